@@ -3,16 +3,25 @@
 //! TODO
 use chrono::NaiveDateTime;
 use ethers_core::{
-    abi::AbiParser,
+    abi::{AbiParser, Event, RawLog},
     types::*,
-    utils::{self, keccak256},
+    utils::{
+        self, keccak256,
+        rlp::{Rlp, RlpStream},
+    },
 };
 use ethers_providers::{Middleware, PendingTransaction};
-use eyre::Result;
-use rustc_hex::{FromHexIter, ToHex};
+use eyre::{Result, WrapErr};
+use rustc_hex::{FromHex, FromHexIter, ToHex};
 use std::str::FromStr;
 
-use foundry_utils::{encode_args, get_func, to_table};
+use foundry_utils::{
+    decode_raw_transaction, detect_proxy, detect_proxy_admin, encode_args, format_token, get_event,
+    get_func, to_table, ProxyKind,
+};
+
+pub mod math;
+pub mod storage;
 
 // TODO: CastContract with common contract initializers? Same for CastProviders?
 
@@ -375,6 +384,210 @@ where
             if to_json { serde_json::to_string(&transaction)? } else { to_table(transaction) };
         Ok(transaction)
     }
+
+    /// Reads and decodes every storage variable of the contract at `who` according to its
+    /// storage `layout`. Mappings are only read for the `keys` given as `(variable, key)` pairs,
+    /// with keys of nested mappings separated by commas.
+    ///
+    /// ```no_run
+    /// use cast::{storage::StorageLayout, Cast};
+    /// use ethers_providers::{Provider, Http};
+    /// use ethers_core::types::Address;
+    /// use std::{str::FromStr, convert::TryFrom};
+    ///
+    /// # async fn foo() -> eyre::Result<()> {
+    /// let provider = Provider::<Http>::try_from("http://localhost:8545")?;
+    /// let cast = Cast::new(provider);
+    /// let addr = Address::from_str("0x6b175474e89094c44da98b954eedeac495271d0f")?;
+    /// let layout = StorageLayout::from_json(&std::fs::read_to_string("out/Dai.json")?)?;
+    /// let keys = vec![("balanceOf".to_string(), format!("{:?}", addr))];
+    /// let state = cast.storage_layout(addr, &layout, &keys, None).await?;
+    /// println!("{}", state);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn storage_layout<T: Into<NameOrAddress> + Clone + Send + Sync>(
+        &self,
+        who: T,
+        layout: &storage::StorageLayout,
+        keys: &[(String, String)],
+        block: Option<BlockId>,
+    ) -> Result<String> {
+        use storage::{Item, Step};
+
+        let mut slots = std::collections::HashMap::new();
+        // decoded depth first, so variables are printed in declaration order
+        let mut stack = layout
+            .variables()?
+            .into_iter()
+            .rev()
+            .map(|var| Item {
+                label: var.label,
+                ty: var.ty,
+                slot: var.slot,
+                offset: var.offset,
+                keys: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        let mut lines = Vec::new();
+        while let Some(item) = stack.pop() {
+            match storage::step(layout, &item, keys, |slot| slots.get(&slot).copied())? {
+                Step::Output(line) => lines.push(line),
+                Step::Read(missing) => {
+                    for slot in missing {
+                        let value = self
+                            .provider
+                            .get_storage_at(who.clone(), H256::from_uint(&slot), block)
+                            .await?;
+                        slots.insert(slot, value);
+                    }
+                    stack.push(item);
+                }
+                Step::Expand(expanded, items) => {
+                    lines.extend(expanded);
+                    stack.extend(items.into_iter().rev());
+                }
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// Fetches and decodes the logs of `event` emitted from `from_block` up to `to_block` (the
+    /// latest block if not given), optionally only by `address`. The range is queried in pages
+    /// of `page_size` blocks, as providers limit how much `eth_getLogs` may return.
+    ///
+    /// ```no_run
+    /// use cast::Cast;
+    /// use ethers_providers::{Provider, Http};
+    /// use ethers_core::types::Address;
+    /// use std::{str::FromStr, convert::TryFrom};
+    ///
+    /// # async fn foo() -> eyre::Result<()> {
+    /// let provider = Provider::<Http>::try_from("http://localhost:8545")?;
+    /// let cast = Cast::new(provider);
+    /// let dai = Address::from_str("0x6b175474e89094c44da98b954eedeac495271d0f")?;
+    /// let event = "Transfer(address indexed,address indexed,uint256)";
+    /// let logs = cast.logs(event, Some(dai), 13000000, Some(13000010), 10000, false).await?;
+    /// println!("{}", logs);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn logs(
+        &self,
+        event: &str,
+        address: Option<Address>,
+        from_block: u64,
+        to_block: Option<u64>,
+        page_size: u64,
+        to_json: bool,
+    ) -> Result<String> {
+        let event = get_event(event)?;
+        let to_block = match to_block {
+            Some(block) => block,
+            None => self.provider.get_block_number().await?.as_u64(),
+        };
+        let page_size = page_size.max(1);
+
+        let mut logs = Vec::new();
+        let mut start = from_block;
+        while start <= to_block {
+            let end = to_block.min(start.saturating_add(page_size - 1));
+            let mut filter =
+                Filter::new().from_block(start).to_block(end).topic0(event.signature());
+            if let Some(address) = address {
+                filter = filter.address(address);
+            }
+            for log in self.provider.get_logs(&filter).await? {
+                logs.push(decode_log(&event, &log)?);
+            }
+            if end == u64::MAX {
+                break
+            }
+            start = end + 1;
+        }
+
+        Ok(if to_json {
+            serde_json::to_string(&logs)?
+        } else {
+            logs.into_iter().map(to_table).collect::<Vec<_>>().join("\n")
+        })
+    }
+
+    /// Returns the implementation of the proxy at `who` and the proxy pattern it was detected
+    /// with. The implementation of beacon proxies is looked up on their beacon.
+    ///
+    /// ```no_run
+    /// use cast::Cast;
+    /// use ethers_providers::{Provider, Http};
+    /// use ethers_core::types::Address;
+    /// use std::{str::FromStr, convert::TryFrom};
+    ///
+    /// # async fn foo() -> eyre::Result<()> {
+    /// let provider = Provider::<Http>::try_from("http://localhost:8545")?;
+    /// let cast = Cast::new(provider);
+    /// let usdc = Address::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")?;
+    /// let (kind, implementation) = cast.implementation(usdc, None).await?;
+    /// println!("{:?} ({})", implementation, kind);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn implementation<T: Into<NameOrAddress> + Clone + Send + Sync>(
+        &self,
+        who: T,
+        block: Option<BlockId>,
+    ) -> Result<(ProxyKind, Address)> {
+        let code = self.provider.get_code(who.clone(), block).await?;
+        let mut slots = std::collections::HashMap::new();
+        for slot in ProxyKind::SLOT_BASED.iter().filter_map(|kind| kind.implementation_slot()) {
+            slots.insert(slot, self.provider.get_storage_at(who.clone(), slot, block).await?);
+        }
+        let (kind, address) = detect_proxy(&code, |slot| slots[&slot])
+            .ok_or_else(|| eyre::eyre!("no proxy implementation found"))?;
+
+        if kind != ProxyKind::Eip1967Beacon {
+            return Ok((kind, address))
+        }
+        let tx = Eip1559TransactionRequest::new()
+            .to(address)
+            .data(utils::id("implementation()").to_vec())
+            .into();
+        let res = self.provider.call(&tx, block).await?;
+        if res.len() < 32 {
+            eyre::bail!("beacon {:?} did not return an implementation", address)
+        }
+        Ok((kind, Address::from_slice(&res[12..32])))
+    }
+
+    /// Returns the admin of the proxy at `who` and the proxy pattern it was detected with
+    ///
+    /// ```no_run
+    /// use cast::Cast;
+    /// use ethers_providers::{Provider, Http};
+    /// use ethers_core::types::Address;
+    /// use std::{str::FromStr, convert::TryFrom};
+    ///
+    /// # async fn foo() -> eyre::Result<()> {
+    /// let provider = Provider::<Http>::try_from("http://localhost:8545")?;
+    /// let cast = Cast::new(provider);
+    /// let usdc = Address::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")?;
+    /// let (kind, admin) = cast.admin(usdc, None).await?;
+    /// println!("{:?} ({})", admin, kind);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn admin<T: Into<NameOrAddress> + Clone + Send + Sync>(
+        &self,
+        who: T,
+        block: Option<BlockId>,
+    ) -> Result<(ProxyKind, Address)> {
+        let mut slots = std::collections::HashMap::new();
+        for slot in ProxyKind::SLOT_BASED.iter().filter_map(|kind| kind.admin_slot()) {
+            if !slots.contains_key(&slot) {
+                slots.insert(slot, self.provider.get_storage_at(who.clone(), slot, block).await?);
+            }
+        }
+        detect_proxy_admin(|slot| slots[&slot]).ok_or_else(|| eyre::eyre!("no proxy admin found"))
+    }
 }
 
 pub struct SimpleCast;
@@ -491,6 +704,132 @@ impl SimpleCast {
         }
     }
 
+    /// Converts a fixed point number with the specified decimals into an integer
+    ///
+    /// ```
+    /// use cast::SimpleCast as Cast;
+    ///
+    /// fn main() -> eyre::Result<()> {
+    ///     assert_eq!(Cast::from_fix(2, "1.5")?, 150.into());
+    ///     assert_eq!(Cast::from_fix(18, "0.1")?, 100000000000000000u64.into());
+    ///     assert!(Cast::from_fix(1, "0.01").is_err());
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn from_fix(decimals: u128, value: &str) -> Result<U256> {
+        math::parse_fixed(value, decimals as usize)
+    }
+
+    /// Converts a decimal number into a binary fixed point number with `bits` fractional bits,
+    /// e.g. 96 for Uniswap's Q64.96
+    ///
+    /// ```
+    /// use cast::SimpleCast as Cast;
+    ///
+    /// fn main() -> eyre::Result<()> {
+    ///     assert_eq!(Cast::to_q(96, "1")?.to_string(), "79228162514264337593543950336");
+    ///     assert_eq!(Cast::to_q(1, "2.5")?, 5.into());
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn to_q(bits: usize, value: &str) -> Result<U256> {
+        math::parse_q(value, bits)
+    }
+
+    /// Converts a binary fixed point number with `bits` fractional bits into a decimal number
+    /// with at most 18 decimals
+    ///
+    /// ```
+    /// use cast::SimpleCast as Cast;
+    /// use ethers_core::types::U256;
+    ///
+    /// fn main() -> eyre::Result<()> {
+    ///     assert_eq!(Cast::from_q(96, U256::from_dec_str("79228162514264337593543950336")?)?, "1");
+    ///     assert_eq!(Cast::from_q(1, 5.into())?, "2.5");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn from_q(bits: usize, value: U256) -> Result<String> {
+        Ok(math::format_q(value, bits, 18))
+    }
+
+    /// Converts a Uniswap v3 tick into its Q64.96 sqrt price
+    ///
+    /// ```
+    /// use cast::SimpleCast as Cast;
+    ///
+    /// fn main() -> eyre::Result<()> {
+    ///     assert_eq!(Cast::tick_to_sqrt_price(0)?.to_string(), "79228162514264337593543950336");
+    ///     assert_eq!(Cast::tick_to_sqrt_price(-887272)?.to_string(), "4295128739");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn tick_to_sqrt_price(tick: i32) -> Result<U256> {
+        math::sqrt_price_at_tick(tick)
+    }
+
+    /// Converts a Uniswap v3 Q64.96 sqrt price into the greatest tick at or below it
+    ///
+    /// ```
+    /// use cast::SimpleCast as Cast;
+    /// use ethers_core::types::U256;
+    ///
+    /// fn main() -> eyre::Result<()> {
+    ///     assert_eq!(Cast::sqrt_price_to_tick(U256::from_dec_str("79228162514264337593543950336")?)?, 0);
+    ///     assert_eq!(Cast::sqrt_price_to_tick(U256::from_dec_str("79228162514264337593543950335")?)?, -1);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn sqrt_price_to_tick(sqrt_price: U256) -> Result<i32> {
+        math::tick_at_sqrt_price(sqrt_price)
+    }
+
+    /// Converts a Uniswap v3 Q64.96 sqrt price into the price of token0 in token1, adjusted for
+    /// their decimals
+    ///
+    /// ```
+    /// use cast::SimpleCast as Cast;
+    /// use ethers_core::types::U256;
+    ///
+    /// fn main() -> eyre::Result<()> {
+    ///     let sqrt_price = U256::from_dec_str("79228162514264337593543950336")?;
+    ///     assert_eq!(Cast::sqrt_price_to_price(sqrt_price, 18, 18)?, "1");
+    ///     assert_eq!(Cast::sqrt_price_to_price(sqrt_price, 18, 6)?, "1000000000000");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn sqrt_price_to_price(sqrt_price: U256, decimals0: u8, decimals1: u8) -> Result<String> {
+        Ok(math::sqrt_price_to_price(sqrt_price, decimals0 as usize, decimals1 as usize, 18))
+    }
+
+    /// Decodes the values packed into a storage slot according to `layout`, a comma separated
+    /// list of Solidity types with optional names, starting with the lowest bits
+    ///
+    /// ```
+    /// use cast::SimpleCast as Cast;
+    ///
+    /// fn main() -> eyre::Result<()> {
+    ///     assert_eq!(Cast::decode_packed("uint8 a,bool b", "0x0102")?, "a: 2\nb: true");
+    ///     assert_eq!(Cast::decode_packed("uint16", "258")?, "258");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn decode_packed(layout: &str, value: &str) -> Result<String> {
+        let value = if value.starts_with("0x") {
+            U256::from_str(value)?
+        } else {
+            U256::from_dec_str(value)?
+        };
+        Ok(math::decode_packed(value, layout)?.join("\n"))
+    }
+
     /// Converts decimal input to hex
     ///
     /// ```
@@ -708,6 +1047,121 @@ impl SimpleCast {
         let calldata = encode_args(&func, args)?;
         Ok(format!("0x{}", calldata.to_hex::<String>()))
     }
+
+    /// Decodes a raw signed transaction and recovers its sender. If `field` is given, only that
+    /// field is returned.
+    ///
+    /// ```
+    /// use cast::SimpleCast as Cast;
+    ///
+    /// fn main() -> eyre::Result<()> {
+    ///     // the example transaction of EIP-155
+    ///     let raw = "0xf86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+    ///     assert_eq!(
+    ///         Cast::decode_tx(raw, Some("from".to_string()), true)?,
+    ///         "\"0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f\""
+    ///     );
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn decode_tx(raw: &str, field: Option<String>, to_json: bool) -> Result<String> {
+        let tx = decode_raw_transaction(&strip_0x(raw).from_hex::<Vec<u8>>()?)?.to_json();
+        let tx = if let Some(ref field) = field {
+            tx.get(field).cloned().ok_or_else(|| eyre::eyre!("field {} not found", field))?
+        } else {
+            tx
+        };
+
+        Ok(if to_json { serde_json::to_string(&tx)? } else { to_table(tx) })
+    }
+
+    /// RLP encodes a JSON value made of hex strings and (nested) arrays of them
+    ///
+    /// ```
+    /// use cast::SimpleCast as Cast;
+    ///
+    /// fn main() -> eyre::Result<()> {
+    ///     assert_eq!(Cast::to_rlp("\"0x\"")?, "0x80");
+    ///     assert_eq!(Cast::to_rlp("[\"0x01\", [\"0x0400\"]]")?, "0xc501c3820400");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn to_rlp(value: &str) -> Result<String> {
+        let value: serde_json::Value = serde_json::from_str(value)?;
+        let mut stream = RlpStream::new();
+        append_rlp(&mut stream, &value)?;
+        Ok(format!("0x{}", stream.out().to_hex::<String>()))
+    }
+
+    /// Decodes RLP encoded data into JSON made of hex strings and (nested) arrays of them
+    ///
+    /// ```
+    /// use cast::SimpleCast as Cast;
+    ///
+    /// fn main() -> eyre::Result<()> {
+    ///     assert_eq!(Cast::from_rlp("0x80")?, "\"0x\"");
+    ///     assert_eq!(Cast::from_rlp("0xc501c3820400")?, "[\"0x01\",[\"0x0400\"]]");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn from_rlp(hex: &str) -> Result<String> {
+        let data = strip_0x(hex).from_hex::<Vec<u8>>()?;
+        Ok(serde_json::to_string(&decode_rlp(&Rlp::new(&data))?)?)
+    }
+}
+
+fn append_rlp(stream: &mut RlpStream, value: &serde_json::Value) -> Result<()> {
+    match value {
+        serde_json::Value::String(hex) => {
+            stream.append(&strip_0x(hex).from_hex::<Vec<u8>>()?);
+        }
+        serde_json::Value::Array(items) => {
+            stream.begin_list(items.len());
+            for item in items {
+                append_rlp(stream, item)?;
+            }
+        }
+        value => eyre::bail!("cannot RLP encode {}, expected a hex string or an array", value),
+    }
+    Ok(())
+}
+
+fn decode_rlp(rlp: &Rlp) -> Result<serde_json::Value> {
+    Ok(if rlp.is_list() {
+        serde_json::Value::Array(rlp.iter().map(|item| decode_rlp(&item)).collect::<Result<_>>()?)
+    } else {
+        serde_json::Value::String(format!("0x{}", rlp.data()?.to_hex::<String>()))
+    })
+}
+
+/// Decodes a log of `event` into JSON. If the signature marks no parameter as `indexed`, the
+/// leading parameters are taken to be indexed, one for every topic after the selector.
+fn decode_log(event: &Event, log: &Log) -> Result<serde_json::Value> {
+    let mut event = event.clone();
+    if !event.inputs.iter().any(|input| input.indexed) {
+        let indexed = log.topics.len().saturating_sub(1);
+        event.inputs.iter_mut().take(indexed).for_each(|input| input.indexed = true);
+    }
+    let decoded = event
+        .parse_log(RawLog { topics: log.topics.clone(), data: log.data.to_vec() })
+        .wrap_err_with(|| format!("could not decode log as a `{}` event", event.name))?;
+
+    let mut args = serde_json::Map::new();
+    for (i, param) in decoded.params.into_iter().enumerate() {
+        let name = if param.name.is_empty() { format!("arg{}", i) } else { param.name };
+        args.insert(name, format_token(&param.value).into());
+    }
+    Ok(serde_json::json!({
+        "address": log.address,
+        "blockNumber": log.block_number,
+        "transactionHash": log.transaction_hash,
+        "logIndex": log.log_index,
+        "event": event.name,
+        "args": args,
+    }))
 }
 
 fn strip_0x(s: &str) -> &str {
@@ -716,7 +1170,10 @@ fn strip_0x(s: &str) -> &str {
 
 #[cfg(test)]
 mod tests {
-    use super::SimpleCast as Cast;
+    use super::{decode_log, SimpleCast as Cast};
+    use ethers_core::types::{Address, Log, H256, U256};
+    use foundry_utils::{detect_proxy, detect_proxy_admin, get_event, ProxyKind};
+    use rustc_hex::FromHex;
 
     #[test]
     fn calldata_uint() {
@@ -733,4 +1190,97 @@ mod tests {
             Cast::calldata("bar(bool)", &["false"]).unwrap().as_str()
         );
     }
+
+    #[test]
+    fn decode_transfer_log() {
+        let from = Address::from_low_u64_be(1);
+        let to = Address::from_low_u64_be(2);
+        let log = Log {
+            address: Address::from_low_u64_be(3),
+            topics: vec![
+                get_event("Transfer(address,address,uint256)").unwrap().signature(),
+                H256::from(from),
+                H256::from(to),
+            ],
+            data: ethers_core::abi::encode(&[ethers_core::abi::Token::Uint(U256::from(1337))])
+                .into(),
+            ..Default::default()
+        };
+
+        // indexed parameters are inferred from the topics
+        let decoded = decode_log(&get_event("Transfer(address,address,uint256)").unwrap(), &log);
+        let decoded = decoded.unwrap();
+        assert_eq!(decoded["event"], "Transfer");
+        assert_eq!(decoded["args"]["arg0"], format!("{:?}", from));
+        assert_eq!(decoded["args"]["arg2"], "1337");
+
+        let event =
+            get_event("event Transfer(address indexed from, address indexed to, uint256 value)");
+        let decoded = decode_log(&event.unwrap(), &log).unwrap();
+        assert_eq!(decoded["args"]["to"], format!("{:?}", to));
+        assert_eq!(decoded["args"]["value"], "1337");
+
+        // too few topics for the indexed parameters
+        let event = get_event("Transfer(address indexed,address indexed,uint256 indexed)");
+        assert!(decode_log(&event.unwrap(), &log).is_err());
+    }
+
+    #[test]
+    fn detect_proxies() {
+        let implementation = Address::from_low_u64_be(0x1234);
+        let empty = |_| H256::zero();
+
+        // minimal proxies have the implementation in their code
+        let mut code = "363d3d373d3d3d363d73".from_hex::<Vec<u8>>().unwrap();
+        code.extend_from_slice(implementation.as_bytes());
+        code.extend("5af43d82803e903d91602b57fd5bf3".from_hex::<Vec<u8>>().unwrap());
+        assert_eq!(detect_proxy(&code, empty), Some((ProxyKind::Eip1167, implementation)));
+        assert_eq!(detect_proxy(&code[1..], empty), None);
+
+        for kind in ProxyKind::SLOT_BASED {
+            let slot = kind.implementation_slot().unwrap();
+            let storage = |s| if s == slot { H256::from(implementation) } else { H256::zero() };
+            assert_eq!(detect_proxy(&[], storage), Some((kind, implementation)));
+        }
+
+        // slots holding something other than an address are not mistaken for proxies
+        assert_eq!(detect_proxy(&[], |_| H256::repeat_byte(0xff)), None);
+
+        let admin_slot = ProxyKind::Zeppelin.admin_slot().unwrap();
+        let storage = |s| if s == admin_slot { H256::from(implementation) } else { H256::zero() };
+        assert_eq!(detect_proxy_admin(storage), Some((ProxyKind::Zeppelin, implementation)));
+        assert_eq!(detect_proxy_admin(empty), None);
+    }
+
+    #[test]
+    fn decode_eip1559_tx() {
+        let raw = "0x02f86b0180843b9aca0085174876e8008252089435353535353535353535353535353535353535350180c080a0bb50e2d89a4ed70663d080659fe0ad4b9bc3e06c17a227433966cb59ceee020da063e1ac797194ad423af7d885f881261c022ad9651e5f05034454df218baa7684";
+        let tx: serde_json::Value =
+            serde_json::from_str(&Cast::decode_tx(raw, None, true).unwrap()).unwrap();
+        assert_eq!(tx["type"], 2);
+        assert_eq!(tx["chainId"], "0x1");
+        assert_eq!(tx["maxFeePerGas"], "0x174876e800");
+        assert_eq!(tx["gasPrice"], serde_json::Value::Null);
+        assert_eq!(tx["to"], "0x3535353535353535353535353535353535353535");
+        assert_eq!(tx["from"], "0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f");
+        assert_eq!(
+            tx["hash"],
+            "0xdf2643bb91947631ccebdd67cc06792a54c16e710b24b174bb2a528c4ff8d5df"
+        );
+    }
+
+    #[test]
+    fn decode_invalid_tx() {
+        assert!(Cast::decode_tx("0x", None, true).is_err());
+        assert!(Cast::decode_tx("0x03c0", None, true).is_err());
+        // EIP-1559 transaction missing its signature
+        assert!(Cast::decode_tx("0x02c80180808080808080", None, true).is_err());
+    }
+
+    #[test]
+    fn rlp_roundtrip() {
+        let value = r#"["0x",["0x01","0x7f","0x80"],[],"0xdeadbeef"]"#;
+        assert_eq!(Cast::from_rlp(&Cast::to_rlp(value).unwrap()).unwrap(), value);
+        assert!(Cast::to_rlp("[1]").is_err());
+    }
 }
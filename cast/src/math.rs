@@ -0,0 +1,268 @@
+//! Fixed-point, Uniswap v3 and bit packing math used by the numeric `cast` commands
+use ethers_core::types::{U256, U512};
+use eyre::Result;
+
+/// The largest tick of Uniswap v3, `TickMath.MAX_TICK`
+pub const MAX_TICK: i32 = 887272;
+
+/// The multipliers of `TickMath.getSqrtRatioAtTick`, i.e. `2^128 / sqrt(1.0001^(2^i))`
+const TICK_RATIOS: [&str; 20] = [
+    "fffcb933bd6fad37aa2d162d1a594001",
+    "fff97272373d413259a46990580e213a",
+    "fff2e50f5f656932ef12357cf3c7fdcc",
+    "ffe5caca7e10e4e61c3624eaa0941cd0",
+    "ffcb9843d60f6159c9db58835c926644",
+    "ff973b41fa98c081472e6896dfb254c0",
+    "ff2ea16466c96a3843ec78b326b52861",
+    "fe5dee046a99a2a811c461f1969c3053",
+    "fcbe86c7900a88aedcffc83b479aa3a4",
+    "f987a7253ac413176f2b074cf7815e54",
+    "f3392b0822b70005940c7a398e4b70f3",
+    "e7159475a2c29b7443b29c7fa6e889d9",
+    "d097f3bdfd2022b8845ad8f792aa5825",
+    "a9f746462d870fdf8a65dc1f90e061e5",
+    "70d869a156d2a1b890bb3df62baf32f7",
+    "31be135f97d08fd981231505542fcfa6",
+    "9aa508b5b7a84e1c677de54f3e99bc9",
+    "5d6af8dedb81196699c329225ee604",
+    "2216e584f5fa1ea926041bedfe98",
+    "48a170391f7dc42444e8fa2",
+];
+
+/// Inserts a decimal point `decimals` digits from the right of `digits`, trimming trailing
+/// zeros of the fraction
+fn insert_point(digits: String, decimals: usize) -> String {
+    let digits = format!("{:0>1$}", digits, decimals + 1);
+    let (int, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        int.to_string()
+    } else {
+        format!("{}.{}", int, fraction)
+    }
+}
+
+/// Parses a decimal number (e.g. `1.5`) into an integer with `decimals` fixed-point decimals.
+/// Fails if the number has more decimals than that.
+pub fn parse_fixed(value: &str, decimals: usize) -> Result<U256> {
+    let (int, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if fraction.len() > decimals {
+        eyre::bail!("{} has more than {} decimals", value, decimals)
+    }
+    let digits = format!("{}{:0<2$}", int, fraction, decimals);
+    Ok(U256::from_dec_str(&digits)?)
+}
+
+/// Formats an integer with `decimals` fixed-point decimals as a decimal number
+pub fn format_fixed(value: U256, decimals: usize) -> String {
+    insert_point(value.to_string(), decimals)
+}
+
+/// Parses a decimal number into a binary fixed-point number with `bits` fractional bits (e.g.
+/// 96 for Uniswap's Q64.96), rounding down
+pub fn parse_q(value: &str, bits: usize) -> Result<U256> {
+    // enough decimals to represent the smallest Q128 increment
+    const DECIMALS: usize = 40;
+    let value = U512::from(parse_fixed(value, DECIMALS)?);
+    let q = (value << bits) / U512::exp10(DECIMALS);
+    U256::try_from(q).map_err(|_| eyre::eyre!("value does not fit into 256 bits"))
+}
+
+/// Formats a binary fixed-point number with `bits` fractional bits as a decimal number with at
+/// most `precision` decimals, rounding down
+pub fn format_q(value: U256, bits: usize, precision: usize) -> String {
+    let value = (U512::from(value) * U512::exp10(precision)) >> bits;
+    insert_point(value.to_string(), precision)
+}
+
+/// Returns `sqrt(1.0001^tick) * 2^96`, exactly as `TickMath.getSqrtRatioAtTick`
+pub fn sqrt_price_at_tick(tick: i32) -> Result<U256> {
+    let abs_tick = tick.unsigned_abs();
+    if abs_tick > MAX_TICK as u32 {
+        eyre::bail!("tick {} is out of the range [-{}, {}]", tick, MAX_TICK, MAX_TICK)
+    }
+
+    let mut ratio = U256::one() << 128;
+    for (i, multiplier) in TICK_RATIOS.iter().enumerate() {
+        if abs_tick & (1 << i) != 0 {
+            let multiplier = U256::from_str_radix(multiplier, 16).expect("valid constant");
+            ratio = if i == 0 { multiplier } else { (ratio * multiplier) >> 128 };
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // round up to 160 bits
+    let rounding = if (ratio & U256::from(u32::MAX)).is_zero() { 0 } else { 1 };
+    Ok((ratio >> 32) + rounding)
+}
+
+/// Returns the greatest tick whose sqrt price is at most `sqrt_price`, as
+/// `TickMath.getTickAtSqrtRatio`
+pub fn tick_at_sqrt_price(sqrt_price: U256) -> Result<i32> {
+    let (min, max) = (sqrt_price_at_tick(-MAX_TICK)?, sqrt_price_at_tick(MAX_TICK)?);
+    if sqrt_price < min || sqrt_price >= max {
+        eyre::bail!("sqrt price {} is out of the range [{}, {})", sqrt_price, min, max)
+    }
+
+    // the sqrt price grows monotonically with the tick
+    let (mut low, mut high) = (-MAX_TICK, MAX_TICK);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if sqrt_price_at_tick(mid)? <= sqrt_price {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Ok(low)
+}
+
+/// Returns the price of token0 in token1 given a Q64.96 sqrt price, adjusted for the decimals
+/// of both tokens, with at most `precision` decimals
+pub fn sqrt_price_to_price(
+    sqrt_price: U256,
+    decimals0: usize,
+    decimals1: usize,
+    precision: usize,
+) -> String {
+    let sqrt_price = U512::from(sqrt_price);
+    let price = (sqrt_price * sqrt_price * U512::exp10(precision + decimals0) /
+        U512::exp10(decimals1)) >>
+        192;
+    insert_point(price.to_string(), precision)
+}
+
+/// Decodes the values packed into `value` according to `layout`, a comma separated list of
+/// Solidity types with optional names (e.g. `uint160 sqrtPriceX96,int24 tick,bool unlocked`).
+/// As in storage, the first value occupies the lowest bits.
+pub fn decode_packed(value: U256, layout: &str) -> Result<Vec<String>> {
+    let mut decoded = Vec::new();
+    let mut offset = 0;
+    for field in layout.split(',').map(str::trim) {
+        let (ty, name) = field.split_once(' ').unwrap_or((field, ""));
+        let size = type_size(ty)?;
+        if offset + size > 256 {
+            eyre::bail!("the layout `{}` does not fit into 256 bits", layout)
+        }
+
+        let raw = (value >> offset) & (U256::MAX >> (256 - size));
+        let formatted = if ty == "bool" {
+            (!raw.is_zero()).to_string()
+        } else if ty == "address" || ty.starts_with("bytes") {
+            format!("0x{:0>1$}", format!("{:x}", raw), size / 4)
+        } else if ty.starts_with("int") && raw.bit(size - 1) {
+            // two's complement
+            format!("-{}", (U256::MAX >> (256 - size)) - raw + 1)
+        } else {
+            raw.to_string()
+        };
+        decoded.push(if name.is_empty() {
+            formatted
+        } else {
+            format!("{}: {}", name.trim(), formatted)
+        });
+        offset += size;
+    }
+    Ok(decoded)
+}
+
+/// Returns the number of bits a value of the Solidity type `ty` takes when packed
+fn type_size(ty: &str) -> Result<usize> {
+    let size = match ty {
+        "bool" => 8,
+        "address" => 160,
+        _ => {
+            let (prefix, bits, unit) = if let Some(bits) = ty.strip_prefix("uint") {
+                ("uint", bits, 1)
+            } else if let Some(bits) = ty.strip_prefix("int") {
+                ("int", bits, 1)
+            } else if let Some(bytes) = ty.strip_prefix("bytes") {
+                ("bytes", bytes, 8)
+            } else {
+                eyre::bail!("unsupported type `{}`", ty)
+            };
+            let size = if bits.is_empty() && prefix != "bytes" {
+                256
+            } else {
+                bits.parse::<usize>().map_err(|_| eyre::eyre!("unsupported type `{}`", ty))? * unit
+            };
+            if size == 0 || size > 256 || size % 8 != 0 {
+                eyre::bail!("unsupported type `{}`", ty)
+            }
+            size
+        }
+    };
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_fixed_point() {
+        assert_eq!(parse_fixed("1.5", 18).unwrap(), U256::from(15) * U256::exp10(17));
+        assert_eq!(parse_fixed("2", 27).unwrap(), U256::from(2) * U256::exp10(27));
+        assert!(parse_fixed("0.123", 2).is_err());
+        assert_eq!(format_fixed(U256::from(15) * U256::exp10(17), 18), "1.5");
+        assert_eq!(format_fixed(1.into(), 27), "0.000000000000000000000000001");
+        assert_eq!(format_fixed(100.into(), 0), "100");
+    }
+
+    #[test]
+    fn converts_q_numbers() {
+        assert_eq!(parse_q("1", 96).unwrap(), U256::one() << 96);
+        assert_eq!(parse_q("0.5", 96).unwrap(), U256::one() << 95);
+        assert_eq!(format_q(U256::from(3) << 95, 96, 18), "1.5");
+        assert_eq!(format_q(1.into(), 96, 10), "0");
+    }
+
+    #[test]
+    fn computes_sqrt_prices() {
+        // `TickMath.MIN_SQRT_RATIO` and `TickMath.MAX_SQRT_RATIO`
+        assert_eq!(sqrt_price_at_tick(-MAX_TICK).unwrap(), 4295128739u64.into());
+        assert_eq!(
+            sqrt_price_at_tick(MAX_TICK).unwrap(),
+            U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap()
+        );
+        assert_eq!(sqrt_price_at_tick(0).unwrap(), U256::one() << 96);
+        assert_eq!(
+            sqrt_price_at_tick(100).unwrap(),
+            U256::from_dec_str("79625275426524748796330556128").unwrap()
+        );
+        assert!(sqrt_price_at_tick(MAX_TICK + 1).is_err());
+    }
+
+    #[test]
+    fn computes_ticks() {
+        let sqrt_price = sqrt_price_at_tick(100).unwrap();
+        assert_eq!(tick_at_sqrt_price(sqrt_price).unwrap(), 100);
+        assert_eq!(tick_at_sqrt_price(sqrt_price - 1).unwrap(), 99);
+        assert_eq!(tick_at_sqrt_price(sqrt_price_at_tick(-MAX_TICK).unwrap()).unwrap(), -MAX_TICK);
+        assert!(tick_at_sqrt_price(sqrt_price_at_tick(MAX_TICK).unwrap()).is_err());
+    }
+
+    #[test]
+    fn computes_prices() {
+        assert_eq!(sqrt_price_to_price(U256::one() << 96, 18, 18, 18), "1");
+        // 1 token0 with 18 decimals for 4 token1 with 6 decimals
+        assert_eq!(sqrt_price_to_price(U256::one() << 97, 18, 6, 18), "4000000000000");
+        assert_eq!(sqrt_price_to_price(U256::one() << 97, 6, 18, 18), "0.000000000004");
+    }
+
+    #[test]
+    fn decodes_packed_values() {
+        // tick -1 at offset 160, then an address-sized value and a flag
+        let value = (U256::from(0xffffffu64) << 160) | U256::from(42) | (U256::one() << 184);
+        assert_eq!(
+            decode_packed(value, "uint160 sqrtPriceX96, int24 tick, bool unlocked").unwrap(),
+            vec!["sqrtPriceX96: 42", "tick: -1", "unlocked: true"]
+        );
+        assert_eq!(decode_packed(U256::from(0xabcd), "bytes1,uint8").unwrap(), vec!["0xcd", "171"]);
+        assert!(decode_packed(U256::zero(), "uint256,bool").is_err());
+        assert!(decode_packed(U256::zero(), "uint7").is_err());
+        assert!(decode_packed(U256::zero(), "string").is_err());
+    }
+}
@@ -0,0 +1,470 @@
+//! Decoding of a contract's storage using the storage layout output by solc
+use ethers_core::{
+    types::{Address, H256, I256, U256},
+    utils::keccak256,
+};
+use eyre::{Result, WrapErr};
+use rustc_hex::{FromHex, ToHex};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+/// The storage layout of a contract, i.e. the `storageLayout` output of solc
+#[derive(Clone, Debug)]
+pub struct StorageLayout {
+    storage: Vec<Value>,
+    types: Map<String, Value>,
+}
+
+impl StorageLayout {
+    /// Reads the storage layout from JSON, which is either the `storageLayout` output of solc or
+    /// an artifact containing it
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut value: Value = serde_json::from_str(json).wrap_err("invalid storage layout")?;
+        if value.get("storageLayout").is_some() {
+            value = value["storageLayout"].take();
+        }
+        let storage = value["storage"]
+            .as_array()
+            .cloned()
+            .ok_or_else(|| eyre::eyre!("no `storage` found in the storage layout"))?;
+        let types = value["types"].as_object().cloned().unwrap_or_default();
+        Ok(Self { storage, types })
+    }
+
+    /// Returns the storage variables, in declaration order
+    pub(crate) fn variables(&self) -> Result<Vec<Variable>> {
+        self.storage.iter().map(Variable::from_json).collect()
+    }
+
+    fn ty(&self, id: &str) -> Result<&Value> {
+        self.types.get(id).ok_or_else(|| eyre::eyre!("unknown type `{}` in storage layout", id))
+    }
+
+    /// Returns the number of bytes a value of the type `id` occupies
+    fn size(&self, id: &str) -> Result<usize> {
+        let ty = self.ty(id)?;
+        ty["numberOfBytes"]
+            .as_str()
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| eyre::eyre!("no size for type `{}` in storage layout", id))
+    }
+
+    /// Returns the human readable label of the type `id`, e.g. `uint256`
+    fn label(&self, id: &str) -> Result<&str> {
+        Ok(self.ty(id)?["label"].as_str().unwrap_or(id))
+    }
+
+    /// Returns how the type `id` is laid out
+    fn kind(&self, id: &str) -> Result<Kind> {
+        let ty = self.ty(id)?;
+        let base = || {
+            ty["base"]
+                .as_str()
+                .map(ToString::to_string)
+                .ok_or_else(|| eyre::eyre!("no base type for `{}`", id))
+        };
+        Ok(match ty["encoding"].as_str() {
+            Some("mapping") => Kind::Mapping {
+                key: ty["key"].as_str().unwrap_or_default().to_string(),
+                value: ty["value"].as_str().unwrap_or_default().to_string(),
+            },
+            Some("dynamic_array") => Kind::DynamicArray { base: base()? },
+            Some("bytes") => Kind::Bytes,
+            _ => {
+                if let Some(members) = ty["members"].as_array() {
+                    Kind::Struct(members.iter().map(Variable::from_json).collect::<Result<_>>()?)
+                } else if ty.get("base").is_some() {
+                    // the length of static arrays is only part of their label, e.g. `uint8[3]`
+                    let label = self.label(id)?;
+                    let len = label
+                        .rsplit('[')
+                        .next()
+                        .and_then(|len| len.trim_end_matches(']').parse().ok())
+                        .ok_or_else(|| eyre::eyre!("no length for static array `{}`", label))?;
+                    Kind::StaticArray { base: base()?, len }
+                } else {
+                    Kind::Value
+                }
+            }
+        })
+    }
+}
+
+/// A storage variable or struct member
+#[derive(Clone, Debug)]
+pub(crate) struct Variable {
+    pub label: String,
+    pub ty: String,
+    pub slot: U256,
+    pub offset: usize,
+}
+
+impl Variable {
+    fn from_json(value: &Value) -> Result<Self> {
+        let field = |name: &str| {
+            value[name].as_str().ok_or_else(|| eyre::eyre!("no `{}` for storage variable", name))
+        };
+        Ok(Self {
+            label: field("label")?.to_string(),
+            ty: field("type")?.to_string(),
+            slot: U256::from_dec_str(field("slot")?)?,
+            offset: value["offset"].as_u64().unwrap_or_default() as usize,
+        })
+    }
+}
+
+enum Kind {
+    Value,
+    Bytes,
+    Struct(Vec<Variable>),
+    StaticArray { base: String, len: usize },
+    DynamicArray { base: String },
+    Mapping { key: String, value: String },
+}
+
+/// A value still to be decoded
+pub(crate) struct Item {
+    /// The path of the value, e.g. `balances[0x..]` or `config.owner`
+    pub label: String,
+    pub ty: String,
+    pub slot: U256,
+    pub offset: usize,
+    /// The keys still to be applied to nested mappings
+    pub keys: Vec<String>,
+}
+
+/// The outcome of decoding one item: either a line of output, slots which need to be read first
+/// or lines of output followed by nested items to decode
+pub(crate) enum Step {
+    Output(String),
+    Read(Vec<U256>),
+    Expand(Vec<String>, Vec<Item>),
+}
+
+/// The maximum number of dynamic array elements which are decoded
+const MAX_ELEMENTS: usize = 64;
+
+/// Decodes `item`, where `read` returns the value of a slot if it was already fetched
+pub(crate) fn step(
+    layout: &StorageLayout,
+    item: &Item,
+    mapping_keys: &[(String, String)],
+    read: impl Fn(U256) -> Option<H256>,
+) -> Result<Step> {
+    Ok(match layout.kind(&item.ty)? {
+        Kind::Value => match read(item.slot) {
+            Some(word) => {
+                let size = layout.size(&item.ty)?;
+                let bytes = &word.as_bytes()[32 - item.offset - size..32 - item.offset];
+                let label = layout.label(&item.ty)?;
+                Step::Output(format!("{} ({}) = {}", item.label, label, format_value(label, bytes)))
+            }
+            None => Step::Read(vec![item.slot]),
+        },
+        Kind::Bytes => {
+            let word = match read(item.slot) {
+                Some(word) => word,
+                None => return Ok(Step::Read(vec![item.slot])),
+            };
+            let label = layout.label(&item.ty)?;
+            let value = U256::from_big_endian(word.as_bytes());
+            let data = if value.bit(0) {
+                // long values store `2 * len + 1` and their data from `keccak256(slot)` on
+                let len = ((value - 1) / 2).as_usize();
+                let start = data_slot(item.slot);
+                let slots = (0..(len + 31) / 32).map(|i| start + i).collect::<Vec<_>>();
+                let words = slots.iter().map(|slot| read(*slot)).collect::<Option<Vec<_>>>();
+                match words {
+                    Some(words) => words.iter().flat_map(|word| word.0).take(len).collect(),
+                    None => return Ok(Step::Read(slots)),
+                }
+            } else {
+                // short values are stored in the high bytes with `2 * len` in the lowest one
+                let len = (word[31] / 2) as usize;
+                word[..len].to_vec()
+            };
+            let formatted = if label == "string" {
+                format!("{:?}", String::from_utf8_lossy(&data))
+            } else {
+                format!("0x{}", data.to_hex::<String>())
+            };
+            Step::Output(format!("{} ({}) = {}", item.label, label, formatted))
+        }
+        Kind::Struct(members) => Step::Expand(
+            vec![],
+            members
+                .into_iter()
+                .map(|member| Item {
+                    label: format!("{}.{}", item.label, member.label),
+                    ty: member.ty,
+                    slot: item.slot + member.slot,
+                    offset: member.offset,
+                    keys: Vec::new(),
+                })
+                .collect(),
+        ),
+        Kind::StaticArray { base, len } => {
+            Step::Expand(vec![], elements(layout, item, &base, item.slot, len)?)
+        }
+        Kind::DynamicArray { base } => {
+            let len = match read(item.slot) {
+                Some(word) => U256::from_big_endian(word.as_bytes()),
+                None => return Ok(Step::Read(vec![item.slot])),
+            };
+            let shown = len.min(MAX_ELEMENTS.into()).as_usize();
+            let mut lines = vec![format!("{}.length = {}", item.label, len)];
+            if len > MAX_ELEMENTS.into() {
+                lines.push(format!("(only the first {} elements are shown)", MAX_ELEMENTS));
+            }
+            Step::Expand(lines, elements(layout, item, &base, data_slot(item.slot), shown)?)
+        }
+        Kind::Mapping { key, value } => {
+            // keys are either given for this mapping, or left over from a parent mapping
+            let paths = if item.keys.is_empty() {
+                mapping_keys
+                    .iter()
+                    .filter(|(name, _)| *name == item.label)
+                    .map(|(_, keys)| keys.split(',').map(|key| key.trim().to_string()).collect())
+                    .collect::<Vec<Vec<_>>>()
+            } else {
+                vec![item.keys.clone()]
+            };
+            if paths.is_empty() {
+                return Ok(Step::Output(format!(
+                    "{} ({}) = <pass `--key {}=<key>` to read entries>",
+                    item.label,
+                    layout.label(&item.ty)?,
+                    item.label
+                )))
+            }
+            Step::Expand(
+                vec![],
+                paths
+                    .into_iter()
+                    .map(|mut keys| {
+                        let first = keys.remove(0);
+                        let slot = mapping_slot(layout.label(&key)?, &first, item.slot)?;
+                        Ok(Item {
+                            label: format!("{}[{}]", item.label, first),
+                            ty: value.clone(),
+                            slot,
+                            offset: 0,
+                            keys,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            )
+        }
+    })
+}
+
+/// Returns the items of `len` array elements of type `base` starting at `slot`
+fn elements(
+    layout: &StorageLayout,
+    item: &Item,
+    base: &str,
+    slot: U256,
+    len: usize,
+) -> Result<Vec<Item>> {
+    let size = layout.size(base)?;
+    Ok((0..len)
+        .map(|i| {
+            // elements are packed into slots if they fit, otherwise each starts a new slot
+            let (slot, offset) = if size <= 32 {
+                let per_slot = 32 / size;
+                (slot + i / per_slot, (i % per_slot) * size)
+            } else {
+                (slot + i * ((size + 31) / 32), 0)
+            };
+            Item {
+                label: format!("{}[{}]", item.label, i),
+                ty: base.to_string(),
+                slot,
+                offset,
+                keys: Vec::new(),
+            }
+        })
+        .collect())
+}
+
+/// Returns the slot at which the data of a dynamic array or long bytes value starts
+fn data_slot(slot: U256) -> U256 {
+    U256::from_big_endian(&keccak256(H256::from_uint(&slot).as_bytes()))
+}
+
+/// Returns the slot of the entry `key` of the mapping at `slot`, whose keys are of type `ty`
+pub(crate) fn mapping_slot(ty: &str, key: &str, slot: U256) -> Result<U256> {
+    let mut preimage = encode_key(ty, key).wrap_err_with(|| format!("invalid {} key", ty))?;
+    preimage.extend_from_slice(H256::from_uint(&slot).as_bytes());
+    Ok(U256::from_big_endian(&keccak256(preimage)))
+}
+
+/// Encodes a mapping key as hashed by solc: value types are padded to 32 bytes, `string` and
+/// `bytes` keys are used as is
+fn encode_key(ty: &str, key: &str) -> Result<Vec<u8>> {
+    let hex = |key: &str| key.strip_prefix("0x").unwrap_or(key).from_hex::<Vec<u8>>();
+    Ok(match ty {
+        "string" => key.as_bytes().to_vec(),
+        "bytes" => hex(key)?,
+        "bool" => H256::from_low_u64_be(key.parse::<bool>()? as u64).0.to_vec(),
+        ty if ty == "address" || ty.starts_with("contract ") => {
+            H256::from(Address::from_str(key)?).0.to_vec()
+        }
+        ty if ty.starts_with("bytes") => {
+            let mut word = hex(key)?;
+            if word.len() > 32 {
+                eyre::bail!("key is longer than 32 bytes")
+            }
+            word.resize(32, 0);
+            word
+        }
+        ty if ty.starts_with("int") => {
+            let value = if let Some(hex) = key.strip_prefix("0x") {
+                I256::from_hex_str(hex)?
+            } else {
+                I256::from_dec_str(key)?
+            };
+            H256::from_uint(&value.into_raw()).0.to_vec()
+        }
+        _ => {
+            let value =
+                if key.starts_with("0x") { U256::from_str(key)? } else { U256::from_dec_str(key)? };
+            H256::from_uint(&value).0.to_vec()
+        }
+    })
+}
+
+/// Formats the big endian `bytes` of a value of the type labeled `ty`
+fn format_value(ty: &str, bytes: &[u8]) -> String {
+    let value = U256::from_big_endian(bytes);
+    if ty == "bool" {
+        (!value.is_zero()).to_string()
+    } else if ty.starts_with("address") || ty.starts_with("contract ") {
+        format!("{:?}", Address::from_slice(&bytes[bytes.len().saturating_sub(20)..]))
+    } else if ty.starts_with("bytes") {
+        format!("0x{}", bytes.to_hex::<String>())
+    } else if ty.starts_with("int") && bytes.first().map_or(false, |byte| byte & 0x80 != 0) {
+        // sign extend to 256 bits
+        let mut word = [0xffu8; 32];
+        word[32 - bytes.len()..].copy_from_slice(bytes);
+        I256::from_raw(U256::from_big_endian(&word)).to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const LAYOUT: &str = r#"{
+        "storageLayout": {
+            "storage": [
+                { "label": "owner", "offset": 0, "slot": "0", "type": "t_address" },
+                { "label": "paused", "offset": 20, "slot": "0", "type": "t_bool" },
+                { "label": "delta", "offset": 21, "slot": "0", "type": "t_int8" },
+                { "label": "balances", "offset": 0, "slot": "1", "type": "t_mapping(t_address,t_uint256)" },
+                { "label": "name", "offset": 0, "slot": "2", "type": "t_string_storage" },
+                { "label": "small", "offset": 0, "slot": "3", "type": "t_array(t_uint128)3_storage" }
+            ],
+            "types": {
+                "t_address": { "encoding": "inplace", "label": "address", "numberOfBytes": "20" },
+                "t_bool": { "encoding": "inplace", "label": "bool", "numberOfBytes": "1" },
+                "t_int8": { "encoding": "inplace", "label": "int8", "numberOfBytes": "1" },
+                "t_uint128": { "encoding": "inplace", "label": "uint128", "numberOfBytes": "16" },
+                "t_uint256": { "encoding": "inplace", "label": "uint256", "numberOfBytes": "32" },
+                "t_string_storage": { "encoding": "bytes", "label": "string", "numberOfBytes": "32" },
+                "t_array(t_uint128)3_storage": { "base": "t_uint128", "encoding": "inplace", "label": "uint128[3]", "numberOfBytes": "64" },
+                "t_mapping(t_address,t_uint256)": { "encoding": "mapping", "key": "t_address", "label": "mapping(address => uint256)", "numberOfBytes": "32", "value": "t_uint256" }
+            }
+        }
+    }"#;
+
+    /// Decodes the whole layout against `storage`, in the same way as `Cast::storage_layout`
+    fn decode(storage: &HashMap<U256, H256>, keys: &[(String, String)]) -> Vec<String> {
+        let layout = StorageLayout::from_json(LAYOUT).unwrap();
+        let mut stack = layout
+            .variables()
+            .unwrap()
+            .into_iter()
+            .rev()
+            .map(|var| Item {
+                label: var.label,
+                ty: var.ty,
+                slot: var.slot,
+                offset: var.offset,
+                keys: vec![],
+            })
+            .collect::<Vec<_>>();
+        let mut lines = vec![];
+        while let Some(item) = stack.pop() {
+            let read = |slot| Some(storage.get(&slot).copied().unwrap_or_default());
+            match step(&layout, &item, keys, read).unwrap() {
+                Step::Output(line) => lines.push(line),
+                Step::Read(_) => unreachable!(),
+                Step::Expand(expanded, items) => {
+                    lines.extend(expanded);
+                    stack.extend(items.into_iter().rev());
+                }
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn decodes_storage() {
+        let owner = Address::from_low_u64_be(0xbeef);
+        let mut slot0 = [0u8; 32];
+        slot0[12..].copy_from_slice(owner.as_bytes());
+        slot0[11] = 1; // paused
+        slot0[10] = 0xfe; // delta = -2
+
+        let mut name = [0u8; 32];
+        name[..5].copy_from_slice(b"forge");
+        name[31] = 10;
+
+        let mut small = [0u8; 32];
+        small[31] = 1;
+        small[15] = 2;
+
+        let balance_slot = mapping_slot("address", &format!("{:?}", owner), 1.into()).unwrap();
+        let storage = HashMap::from([
+            (U256::zero(), H256(slot0)),
+            (U256::from(2), H256(name)),
+            (U256::from(3), H256(small)),
+            (U256::from(4), H256::from_low_u64_be(3)),
+            (balance_slot, H256::from_low_u64_be(100)),
+        ]);
+
+        let keys = vec![("balances".to_string(), format!("{:?}", owner))];
+        assert_eq!(
+            decode(&storage, &keys),
+            vec![
+                format!("owner (address) = {:?}", owner),
+                "paused (bool) = true".to_string(),
+                "delta (int8) = -2".to_string(),
+                format!("balances[{:?}] (uint256) = 100", owner),
+                "name (string) = \"forge\"".to_string(),
+                "small[0] (uint128) = 1".to_string(),
+                "small[1] (uint128) = 2".to_string(),
+                "small[2] (uint128) = 3".to_string(),
+            ]
+        );
+
+        assert_eq!(
+            decode(&storage, &[])[3],
+            "balances (mapping(address => uint256)) = <pass `--key balances=<key>` to read entries>"
+        );
+    }
+
+    #[test]
+    fn computes_mapping_slots() {
+        // keccak256(abi.encode(uint256(1), uint256(0)))
+        assert_eq!(
+            format!("{:?}", H256::from_uint(&mapping_slot("uint256", "1", 0.into()).unwrap())),
+            "0xada5013122d395ba3c54772283fb069b10426056ef8ca54750cb9bb552a59e7d"
+        );
+        assert!(mapping_slot("address", "not an address", 0.into()).is_err());
+    }
+}
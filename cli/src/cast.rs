@@ -1,8 +1,9 @@
 pub mod cmd;
 
+mod debugger;
 mod utils;
 
-use cast::{Cast, SimpleCast};
+use cast::{storage::StorageLayout, Cast, SimpleCast};
 
 mod opts;
 use opts::{
@@ -12,18 +13,29 @@ use opts::{
 
 use ethers::{
     core::types::{BlockId, BlockNumber::Latest},
-    providers::{Middleware, Provider},
-    types::{NameOrAddress, U256},
+    providers::Middleware,
+    types::{Address, NameOrAddress, U256},
 };
+use evm_adapters::runtime;
+use foundry_utils::{explorer_tx_url, DisplayUnits, NativeToken};
 use rustc_hex::ToHex;
-use std::{convert::TryFrom, str::FromStr};
+use std::str::FromStr;
 use structopt::StructOpt;
 
-#[tokio::main]
-async fn main() -> eyre::Result<()> {
+fn main() -> eyre::Result<()> {
+    runtime::block_on(run())
+}
+
+async fn run() -> eyre::Result<()> {
     color_eyre::install()?;
 
     let opts = Opts::from_args();
+    // `--display-units` overrides the unit of the `foundry.toml` in the current directory
+    let configured_units = match opts.display_units {
+        Some(units) => Some(units),
+        None => utils::config_display_units(".")?,
+    };
+    let units = configured_units.unwrap_or_default();
     match opts.sub {
         Subcommands::MaxInt => {
             println!("{}", SimpleCast::max_int()?);
@@ -83,10 +95,41 @@ async fn main() -> eyre::Result<()> {
                 SimpleCast::to_fix(unwrap_or_stdin(decimals)?, U256::from_dec_str(&val)?)?
             );
         }
+        Subcommands::FromFix { decimals, value } => {
+            let val = unwrap_or_stdin(value)?;
+            println!("{}", SimpleCast::from_fix(unwrap_or_stdin(decimals)?, &val)?);
+        }
+        Subcommands::ToQ { value, bits } => {
+            let val = unwrap_or_stdin(value)?;
+            println!("{}", SimpleCast::to_q(bits, &val)?);
+        }
+        Subcommands::FromQ { value, bits } => {
+            let val = unwrap_or_stdin(value)?;
+            println!("{}", SimpleCast::from_q(bits, U256::from_dec_str(&val)?)?);
+        }
+        Subcommands::TickToSqrtPrice { tick } => {
+            println!("{}", SimpleCast::tick_to_sqrt_price(tick)?);
+        }
+        Subcommands::SqrtPriceToTick { sqrt_price } => {
+            println!("{}", SimpleCast::sqrt_price_to_tick(U256::from_dec_str(&sqrt_price)?)?);
+        }
+        Subcommands::SqrtPriceToPrice { sqrt_price, decimals0, decimals1 } => {
+            let sqrt_price = U256::from_dec_str(&sqrt_price)?;
+            println!("{}", SimpleCast::sqrt_price_to_price(sqrt_price, decimals0, decimals1)?);
+        }
+        Subcommands::DecodePacked { layout, value } => {
+            println!("{}", SimpleCast::decode_packed(&layout, &value)?);
+        }
         Subcommands::ToUint256 { value } => {
             let val = unwrap_or_stdin(value)?;
             println!("{}", SimpleCast::to_uint256(&val)?);
         }
+        Subcommands::ToRlp { value } => {
+            println!("{}", SimpleCast::to_rlp(&value)?);
+        }
+        Subcommands::FromRlp { hexdata } => {
+            println!("{}", SimpleCast::from_rlp(&hexdata)?);
+        }
         Subcommands::ToWei { value, unit } => {
             let val = unwrap_or_stdin(value)?;
             println!(
@@ -108,87 +151,130 @@ async fn main() -> eyre::Result<()> {
             );
         }
         Subcommands::Block { rpc_url, block, full, field, to_json } => {
-            let provider = Provider::try_from(rpc_url)?;
-            println!("{}", Cast::new(provider).block(block, full, field, to_json).await?);
+            let provider = runtime::http_provider(&rpc_url)?;
+            let cast = Cast::new(provider);
+            match configured_units.filter(|_| is_wei_field(&field, to_json)) {
+                Some(units) => {
+                    let value = cast.block(block, full, field, true).await?;
+                    println!("{}", format_wei_field(&cast, &value, units).await?);
+                }
+                None => println!("{}", cast.block(block, full, field, to_json).await?),
+            }
         }
         Subcommands::BlockNumber { rpc_url } => {
-            let provider = Provider::try_from(rpc_url)?;
+            let provider = runtime::http_provider(&rpc_url)?;
             println!("{}", Cast::new(provider).block_number().await?);
         }
-        Subcommands::Call { rpc_url, address, sig, args } => {
-            let provider = Provider::try_from(rpc_url)?;
-            println!("{}", Cast::new(provider).call(address, &sig, args).await?);
+        Subcommands::Call { rpc_url, address, sig, args, trace, from } => {
+            let provider = runtime::http_provider(&rpc_url)?;
+            if trace {
+                let to = resolve(&provider, address).await?;
+                let from = from.unwrap_or_default();
+                println!("{}", simulate(rpc_url, from, to, sig, args, units).await?);
+            } else {
+                println!("{}", Cast::new(provider).call(address, &sig, args).await?);
+            }
         }
         Subcommands::Calldata { sig, args } => {
             println!("{}", SimpleCast::calldata(sig, &args)?);
         }
         Subcommands::Chain { rpc_url } => {
-            let provider = Provider::try_from(rpc_url)?;
+            let provider = runtime::http_provider(&rpc_url)?;
             println!("{}", Cast::new(provider).chain().await?);
         }
         Subcommands::ChainId { rpc_url } => {
-            let provider = Provider::try_from(rpc_url)?;
+            let provider = runtime::http_provider(&rpc_url)?;
             println!("{}", Cast::new(provider).chain_id().await?);
         }
         Subcommands::Code { block, who, rpc_url } => {
-            let provider = Provider::try_from(rpc_url)?;
+            let provider = runtime::http_provider(&rpc_url)?;
             println!("{}", Cast::new(provider).code(who, block).await?);
         }
         Subcommands::Namehash { name } => {
             println!("{}", SimpleCast::namehash(&name)?);
         }
         Subcommands::Tx { rpc_url, hash, field, to_json } => {
-            let provider = Provider::try_from(rpc_url)?;
-            println!("{}", Cast::new(&provider).transaction(hash, field, to_json).await?)
+            let provider = runtime::http_provider(&rpc_url)?;
+            let cast = Cast::new(&provider);
+            match configured_units.filter(|_| is_wei_field(&field, to_json)) {
+                Some(units) => {
+                    let value = cast.transaction(hash, field, true).await?;
+                    println!("{}", format_wei_field(&cast, &value, units).await?);
+                }
+                None => println!("{}", cast.transaction(hash, field, to_json).await?),
+            }
         }
-        Subcommands::SendTx { eth, to, sig, cast_async, args } => {
-            let provider = Provider::try_from(eth.rpc_url.as_str())?;
+        Subcommands::DecodeTx { raw_tx, field, to_json } => {
+            println!("{}", SimpleCast::decode_tx(&raw_tx, field, to_json)?);
+        }
+        Subcommands::SendTx { eth, to, sig, args, simulate: true, .. } => {
+            let provider = runtime::http_provider(&eth.rpc_url)?;
             let chain_id = Cast::new(&provider).chain_id().await?;
+            let from = match eth.signer_with(chain_id, provider.clone()).await? {
+                Some(WalletType::Ledger(signer)) => signer.address(),
+                Some(WalletType::Local(signer)) => signer.address(),
+                Some(WalletType::Trezor(signer)) => signer.address(),
+                None => eth.from.expect("No ETH_FROM or signer specified"),
+            };
+            let to = resolve(&provider, to).await?;
+            println!("{}", simulate(eth.rpc_url, from, to, sig, args, units).await?);
+        }
+        Subcommands::SendTx { eth, to, sig, cast_async, simulate: false, args } => {
+            let provider = runtime::http_provider(&eth.rpc_url)?;
+            let chain_id = Cast::new(&provider).chain_id().await?;
+            let links = eth.links(chain_id);
 
             if let Some(signer) = eth.signer_with(chain_id, provider.clone()).await? {
                 match signer {
                     WalletType::Ledger(signer) => {
-                        cast_send(&signer, signer.address(), to, sig, args, cast_async).await?;
+                        cast_send(&signer, signer.address(), to, sig, args, cast_async, links)
+                            .await?;
                     }
                     WalletType::Local(signer) => {
-                        cast_send(&signer, signer.address(), to, sig, args, cast_async).await?;
+                        cast_send(&signer, signer.address(), to, sig, args, cast_async, links)
+                            .await?;
                     }
                     WalletType::Trezor(signer) => {
-                        cast_send(&signer, signer.address(), to, sig, args, cast_async).await?;
+                        cast_send(&signer, signer.address(), to, sig, args, cast_async, links)
+                            .await?;
                     }
                 }
             } else {
                 let from = eth.from.expect("No ETH_FROM or signer specified");
-                cast_send(provider, from, to, sig, args, cast_async).await?;
+                cast_send(provider, from, to, sig, args, cast_async, links).await?;
             }
         }
         Subcommands::Age { block, rpc_url } => {
-            let provider = Provider::try_from(rpc_url)?;
+            let provider = runtime::http_provider(&rpc_url)?;
             println!(
                 "{}",
                 Cast::new(provider).age(block.unwrap_or(BlockId::Number(Latest))).await?
             );
         }
-        Subcommands::Balance { block, who, rpc_url } => {
-            let provider = Provider::try_from(rpc_url)?;
-            println!("{}", Cast::new(provider).balance(who, block).await?);
+        Subcommands::Balance { block, who, rpc_url, native } => {
+            let cast = Cast::new(runtime::http_provider(&rpc_url)?);
+            let balance = cast.balance(who, block).await?;
+            if native {
+                println!("{}", NativeToken::of(cast.chain_id().await?.as_u64()).format(balance));
+            } else {
+                println!("{}", chain_units(&cast, units).await?.format(balance));
+            }
         }
         Subcommands::BaseFee { block, rpc_url } => {
-            let provider = Provider::try_from(rpc_url)?;
-            println!(
-                "{}",
-                Cast::new(provider).base_fee(block.unwrap_or(BlockId::Number(Latest))).await?
-            );
+            let cast = Cast::new(runtime::http_provider(&rpc_url)?);
+            let base_fee = cast.base_fee(block.unwrap_or(BlockId::Number(Latest))).await?;
+            println!("{}", chain_units(&cast, units).await?.format(base_fee));
         }
         Subcommands::GasPrice { rpc_url } => {
-            let provider = Provider::try_from(rpc_url)?;
-            println!("{}", Cast::new(provider).gas_price().await?);
+            let cast = Cast::new(runtime::http_provider(&rpc_url)?);
+            let gas_price = cast.gas_price().await?;
+            println!("{}", chain_units(&cast, units).await?.format(gas_price));
         }
         Subcommands::Keccak { data } => {
             println!("{}", SimpleCast::keccak(&data)?);
         }
         Subcommands::ResolveName { who, rpc_url, verify } => {
-            let provider = Provider::try_from(rpc_url)?;
+            let provider = runtime::http_provider(&rpc_url)?;
             let who = unwrap_or_stdin(who)?;
             let address = provider.resolve_name(&who).await?;
             if verify {
@@ -202,7 +288,7 @@ async fn main() -> eyre::Result<()> {
             println!("{:?}", address);
         }
         Subcommands::LookupAddress { who, rpc_url, verify } => {
-            let provider = Provider::try_from(rpc_url)?;
+            let provider = runtime::http_provider(&rpc_url)?;
             let who = unwrap_or_stdin(who)?;
             let name = provider.lookup_address(who).await?;
             if verify {
@@ -215,13 +301,44 @@ async fn main() -> eyre::Result<()> {
             }
             println!("{}", name);
         }
-        Subcommands::Storage { address, slot, rpc_url, block } => {
-            let provider = Provider::try_from(rpc_url)?;
-            let value = provider.get_storage_at(address, slot, block).await?;
-            println!("{:?}", value);
+        Subcommands::Storage { address, slot, layout, keys, rpc_url, block } => {
+            let provider = runtime::http_provider(&rpc_url)?;
+            match (slot, layout) {
+                (_, Some(layout)) => {
+                    let layout = StorageLayout::from_json(&std::fs::read_to_string(layout)?)?;
+                    let state =
+                        Cast::new(provider).storage_layout(address, &layout, &keys, block).await?;
+                    println!("{}", state);
+                }
+                (Some(slot), None) => {
+                    let value = provider.get_storage_at(address, slot, block).await?;
+                    println!("{:?}", value);
+                }
+                (None, None) => eyre::bail!("either a slot or --layout is required"),
+            }
+        }
+        Subcommands::Implementation { address, block, rpc_url } => {
+            let provider = runtime::http_provider(&rpc_url)?;
+            let (kind, implementation) = Cast::new(provider).implementation(address, block).await?;
+            eprintln!("Detected an {}", kind);
+            println!("{:?}", implementation);
+        }
+        Subcommands::Admin { address, block, rpc_url } => {
+            let provider = runtime::http_provider(&rpc_url)?;
+            let (kind, admin) = Cast::new(provider).admin(address, block).await?;
+            eprintln!("Detected an {}", kind);
+            println!("{:?}", admin);
+        }
+        Subcommands::Logs { event, address, from_block, to_block, page_size, to_json, rpc_url } => {
+            let provider = runtime::http_provider(&rpc_url)?;
+            let cast = Cast::new(provider);
+            println!(
+                "{}",
+                cast.logs(&event, address, from_block, to_block, page_size, to_json).await?
+            );
         }
         Subcommands::Nonce { block, who, rpc_url } => {
-            let provider = Provider::try_from(rpc_url)?;
+            let provider = runtime::http_provider(&rpc_url)?;
             println!("{}", Cast::new(provider).nonce(who, block).await?);
         }
     };
@@ -245,6 +362,119 @@ where
     })
 }
 
+/// Replaces `ether` by the native token of the chain the provider is connected to, e.g. `MATIC`
+async fn chain_units<M: Middleware>(
+    cast: &Cast<M>,
+    units: DisplayUnits,
+) -> eyre::Result<DisplayUnits>
+where
+    M::Error: 'static,
+{
+    if units.name != "ether" {
+        return Ok(units)
+    }
+    Ok(units.on_chain(cast.chain_id().await?.as_u64()))
+}
+
+/// The fields of blocks and transactions which are amounts of wei
+const WEI_FIELDS: [&str; 5] =
+    ["value", "gasPrice", "maxFeePerGas", "maxPriorityFeePerGas", "baseFeePerGas"];
+
+/// Whether the single field requested of a block or transaction is printed in the display units
+fn is_wei_field(field: &Option<String>, to_json: bool) -> bool {
+    !to_json && field.as_deref().map_or(false, |field| WEI_FIELDS.contains(&field))
+}
+
+/// Formats a wei field of a block or transaction, given as JSON, in `units`
+async fn format_wei_field<M: Middleware>(
+    cast: &Cast<M>,
+    value: &str,
+    units: DisplayUnits,
+) -> eyre::Result<String>
+where
+    M::Error: 'static,
+{
+    let wei: U256 = serde_json::from_str(value)?;
+    Ok(chain_units(cast, units).await?.format(wei))
+}
+
+/// Resolves an ENS name to its address
+async fn resolve<M: Middleware>(provider: &M, who: NameOrAddress) -> eyre::Result<Address>
+where
+    M::Error: 'static,
+{
+    Ok(match who {
+        NameOrAddress::Address(address) => address,
+        NameOrAddress::Name(name) => provider.resolve_name(&name).await?,
+    })
+}
+
+/// Runs the call locally against a fork of the latest block of the chain behind `rpc_url`, and
+/// renders every call it made, with its value in `units`, and the storage it changed
+#[cfg(feature = "sputnik-evm")]
+async fn simulate(
+    rpc_url: String,
+    from: Address,
+    to: Address,
+    sig: String,
+    args: Vec<String>,
+    units: DisplayUnits,
+) -> eyre::Result<String> {
+    use evm_adapters::{
+        sputnik::{new_shared_cache, vicinity, Executor, MemCache, SharedBackend, PRECOMPILES_MAP},
+        Evm,
+    };
+    use forge::KnownContracts;
+
+    let provider = runtime::http_provider(&rpc_url)?;
+    let vicinity = vicinity(&provider, None).await?;
+    let func = foundry_utils::get_func(&sig)?;
+    let calldata = foundry_utils::encode_args(&func, &args)?;
+
+    // the called function is the only one that can be decoded
+    let mut known_contracts = KnownContracts::default();
+    let name = format!("{:?}", to);
+    known_contracts.insert(name.clone(), ethers::abi::parse_abi(&[&sig])?, Default::default());
+    known_contracts.insert_deployed(to, name);
+    known_contracts.set_display_units(units.on_chain(vicinity.chain_id.as_u64()));
+
+    // the backend blocks on requests to the provider, so execution must not happen on one of
+    // the runtime's worker threads
+    tokio::task::spawn_blocking(move || {
+        let block = vicinity.block_number.as_u64();
+        let gas_limit = vicinity.block_gas_limit.as_u64();
+        let cache = new_shared_cache(MemCache::default());
+        let throttle = runtime::throttle(Some(&rpc_url));
+        let backend =
+            SharedBackend::new_throttled(provider, throttle, cache, vicinity, Some(block.into()));
+
+        let cfg = sputnik::Config::london();
+        let precompiles = PRECOMPILES_MAP.clone();
+        let mut evm = Executor::new_with_cheatcodes(backend, gas_limit, &cfg, &precompiles, false);
+        let (_, status, gas_used, _) = evm.call_raw(from, to, calldata.into(), 0.into(), false)?;
+
+        Ok(format!(
+            "{}\nStatus: {:?}\nGas used: {}",
+            known_contracts.format_call_trace(&evm.call_trace()),
+            status,
+            gas_used
+        ))
+    })
+    .await?
+}
+
+#[cfg(not(feature = "sputnik-evm"))]
+async fn simulate(
+    _rpc_url: String,
+    _from: Address,
+    _to: Address,
+    _sig: String,
+    _args: Vec<String>,
+    _units: DisplayUnits,
+) -> eyre::Result<String> {
+    eyre::bail!("simulating calls requires the `sputnik-evm` feature")
+}
+
 async fn cast_send<M: Middleware, F: Into<NameOrAddress>, T: Into<NameOrAddress>>(
     provider: M,
     from: F,
@@ -252,6 +482,7 @@ async fn cast_send<M: Middleware, F: Into<NameOrAddress>, T: Into<NameOrAddress>
     sig: String,
     args: Vec<String>,
     cast_async: bool,
+    links: Option<u64>,
 ) -> eyre::Result<()>
 where
     M::Error: 'static,
@@ -261,6 +492,11 @@ where
         cast.send(from, to, if !sig.is_empty() { Some((&sig, args)) } else { None }).await?;
     let tx_hash = *pending_tx;
 
+    // printed to stderr, so that the output can still be piped
+    if let Some(url) = links.and_then(|chain_id| explorer_tx_url(chain_id, tx_hash)) {
+        eprintln!("{}", url);
+    }
+
     if cast_async {
         println!("{}", tx_hash);
     } else {
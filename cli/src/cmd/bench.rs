@@ -0,0 +1,219 @@
+//! Bench command
+
+use crate::cmd::{
+    test::{RunnerCmd, TestArgs},
+    Cmd,
+};
+use ansi_term::Colour;
+use ethers::solc::{ArtifactOutput, Project};
+use eyre::Context;
+use forge::{BenchResult, MultiContractRunnerBuilder, Stats};
+use regex::Regex;
+use std::{collections::BTreeMap, fs, path::PathBuf};
+use structopt::StructOpt;
+
+/// Benchmark results `contract -> (bench name -> BenchResult)`
+type BenchResults = BTreeMap<String, BTreeMap<String, BenchResult>>;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct BenchArgs {
+    /// All test arguments are supported. `--baseline` and `--gas-threshold` (or `--threshold`)
+    /// compare the mean gas of the benchmarks against a baseline previously written with
+    /// `--save-baseline`
+    #[structopt(flatten)]
+    test: TestArgs,
+
+    #[structopt(
+        help = "number of unmeasured iterations to run before each benchmark",
+        long,
+        default_value = "2"
+    )]
+    warmup: usize,
+
+    #[structopt(
+        help = "number of measured iterations of each benchmark",
+        long,
+        default_value = "10"
+    )]
+    iterations: usize,
+
+    #[structopt(
+        help = "write the benchmark results to this file, to be used as a baseline later",
+        long
+    )]
+    save_baseline: Option<PathBuf>,
+}
+
+impl Cmd for BenchArgs {
+    type Output = ();
+
+    fn run(self) -> eyre::Result<()> {
+        let BenchArgs { test, warmup, iterations, save_baseline } = self;
+        let json = test.json;
+        let baseline = test.baseline.clone();
+        let threshold = test.gas_threshold;
+
+        let bencher = Bencher { pattern: test.pattern.clone(), warmup, iterations };
+        let results = test.run_with(bencher)?;
+
+        if json {
+            println!("{}", serde_json::to_string(&results)?);
+        } else {
+            print_results(&results);
+        }
+
+        if let Some(path) = save_baseline {
+            fs::write(&path, serde_json::to_string_pretty(&results)?)
+                .wrap_err(format!("failed to write baseline \"{}\"", path.display()))?;
+        }
+
+        let failures = results.values().flat_map(|b| b.values()).filter(|b| !b.success).count();
+        if failures > 0 {
+            eyre::bail!("Encountered a total of {} failing benchmarks", failures);
+        }
+
+        if let Some(path) = baseline {
+            let content = fs::read_to_string(&path)
+                .wrap_err(format!("failed to read baseline \"{}\"", path.display()))?;
+            let baseline: BenchResults = serde_json::from_str(&content)?;
+            let regressions = compare(&results, &baseline, threshold);
+            if regressions > 0 {
+                eyre::bail!(
+                    "{} benchmarks regressed by more than {}% compared to the baseline",
+                    regressions,
+                    threshold
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs all benchmarks
+struct Bencher {
+    pattern: Regex,
+    warmup: usize,
+    iterations: usize,
+}
+
+impl RunnerCmd for Bencher {
+    type Output = BenchResults;
+
+    fn run_with<A: ArtifactOutput + 'static, S: Clone, E: evm_adapters::Evm<S>>(
+        self,
+        builder: MultiContractRunnerBuilder,
+        project: Project<A>,
+        evm: E,
+    ) -> eyre::Result<Self::Output> {
+        let mut runner = builder.build(project, evm)?;
+        runner.bench(self.pattern, self.warmup, self.iterations)
+    }
+}
+
+fn print_results(results: &BenchResults) {
+    for (i, (contract_name, benches)) in results.iter().enumerate() {
+        if i > 0 {
+            println!()
+        }
+        let term = if benches.len() > 1 { "benchmarks" } else { "benchmark" };
+        println!("Running {} {} for {}", benches.len(), term, contract_name);
+
+        for (name, result) in benches {
+            if result.success {
+                println!(
+                    "{} {} (gas: {}) (time: {})",
+                    Colour::Green.paint("[PASS]"),
+                    name,
+                    fmt_stats(&result.gas, ""),
+                    fmt_stats(&result.time_us, "µs")
+                );
+            } else {
+                let txt = match result.reason {
+                    Some(ref reason) => format!("[FAIL. Reason: {}]", reason),
+                    None => "[FAIL]".to_string(),
+                };
+                println!("{} {}", Colour::Red.paint(txt), name);
+            }
+        }
+    }
+}
+
+fn fmt_stats(stats: &Stats, unit: &str) -> String {
+    format!(
+        "μ: {:.1}{unit}, σ: {:.1}{unit}, p50: {:.1}{unit}, p90: {:.1}{unit}, p99: {:.1}{unit}",
+        stats.mean,
+        stats.stddev,
+        stats.p50,
+        stats.p90,
+        stats.p99,
+        unit = unit
+    )
+}
+
+/// Compares the mean gas of every benchmark against the baseline and prints the changes.
+///
+/// Returns the number of benchmarks whose mean gas increased by more than `threshold` percent
+fn compare(results: &BenchResults, baseline: &BenchResults, threshold: f64) -> usize {
+    println!();
+    println!("Comparing against baseline:");
+
+    let mut regressions = 0;
+    for (contract_name, benches) in results {
+        for (name, result) in benches {
+            let target = match baseline.get(contract_name).and_then(|b| b.get(name)) {
+                Some(target) => target,
+                None => {
+                    println!("{}::{} (no baseline)", contract_name, name);
+                    continue
+                }
+            };
+
+            let change = if target.gas.mean == 0.0 {
+                0.0
+            } else {
+                (result.gas.mean - target.gas.mean) / target.gas.mean * 100.0
+            };
+            let txt = format!("{:+.3}%", change);
+            let txt = if change > threshold {
+                regressions += 1;
+                Colour::Red.paint(format!("{} REGRESSION", txt)).to_string()
+            } else if change < 0.0 {
+                Colour::Green.paint(txt).to_string()
+            } else {
+                txt
+            };
+            println!(
+                "{}::{} (gas: {:.1} -> {:.1} ({}))",
+                contract_name, name, target.gas.mean, result.gas.mean, txt
+            );
+        }
+    }
+    regressions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(gas: f64) -> BenchResults {
+        let result = BenchResult {
+            success: true,
+            iterations: 1,
+            gas: Stats::new(&[gas]),
+            ..Default::default()
+        };
+        BTreeMap::from([(
+            "GreeterTest".to_string(),
+            BTreeMap::from([("benchGreeting()".to_string(), result)]),
+        )])
+    }
+
+    #[test]
+    fn detects_regressions() {
+        assert_eq!(compare(&results(100.0), &results(100.0), 0.0), 0);
+        assert_eq!(compare(&results(99.0), &results(100.0), 0.0), 0);
+        assert_eq!(compare(&results(101.0), &results(100.0), 0.0), 1);
+        assert_eq!(compare(&results(101.0), &results(100.0), 5.0), 0);
+    }
+}
@@ -2,22 +2,36 @@
 
 use ethers::{
     solc::{
-        artifacts::{Optimizer, Settings},
+        artifacts::{BytecodeObject, Optimizer, Settings},
         remappings::Remapping,
         EvmVersion, MinimalCombinedArtifacts, Project, ProjectCompileOutput, ProjectPathsConfig,
         SolcConfig,
     },
     types::Address,
 };
+use rustc_hex::ToHex;
+use serde_json::json;
 use std::{
+    collections::BTreeMap,
+    fs,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
-use crate::{cmd::Cmd, utils};
+use crate::{
+    cmd::{
+        cache::{self, ByteSize},
+        Cmd,
+    },
+    utils::{self, CompiledContract},
+};
 
+#[cfg(feature = "sputnik-evm")]
+use evm_adapters::sputnik::gas::GasOverride;
 #[cfg(feature = "evmodin-evm")]
 use evmodin::util::mocked_host::MockedHost;
+#[cfg(feature = "revm-evm")]
+use revm::SpecId;
 #[cfg(feature = "sputnik-evm")]
 use sputnik::backend::MemoryVicinity;
 use structopt::StructOpt;
@@ -77,11 +91,47 @@ pub struct BuildArgs {
         alias = "hh"
     )]
     pub hardhat: bool,
+
+    #[structopt(
+        help = "additionally write the artifacts in the layout of another tool, so that existing scripts keep working: `hardhat` (one directory per source file, with debug files) or `dapptools` (a single combined `dapp.sol.json`)",
+        long,
+        env = "FORGE_ARTIFACTS_LAYOUT"
+    )]
+    pub artifacts_layout: Option<ArtifactsLayout>,
+
+    #[structopt(
+        help = "the maximum size of the build artifacts and the fork RPC cache together, e.g. `2GiB`. The least recently used entries are evicted after each build once it is exceeded",
+        long,
+        env = "FOUNDRY_CACHE_MAX_SIZE"
+    )]
+    pub cache_max_size: Option<ByteSize>,
+
+    #[structopt(
+        help = "the number of solc processes compiling groups of sources which need different compiler versions in parallel, and of threads writing the artifacts of `--artifacts-layout hardhat`, default being the number of CPUs",
+        long,
+        short = "j",
+        env = "FORGE_JOBS",
+        parse(try_from_str = parse_jobs)
+    )]
+    pub jobs: Option<usize>,
+
+    #[structopt(
+        help = "verify that the build is reproducible: requires a pinned solc (`--no-auto-detect`), writes the artifacts with sorted keys and fails if compiling all sources again produces different bytecode than the artifacts",
+        long,
+        env = "FORGE_DETERMINISTIC"
+    )]
+    pub deterministic: bool,
 }
 
 impl Cmd for BuildArgs {
     type Output = ProjectCompileOutput<MinimalCombinedArtifacts>;
     fn run(self) -> eyre::Result<Self::Output> {
+        if self.deterministic && !self.no_auto_detect {
+            // the automatically detected version depends on which ones are installed
+            eyre::bail!(
+                "deterministic builds need a pinned solc, pass `--no-auto-detect` to use the one in $PATH"
+            )
+        }
         println!("compiling...");
         let project = self.project()?;
         let output = project.compile()?;
@@ -92,12 +142,64 @@ impl Cmd for BuildArgs {
             println!("no files changed, compilation skippped.");
         } else {
             println!("success.");
+            let spans = forge::function_spans(&output.clone().output(), &project.paths.root);
+            forge::write_function_spans(&project, &spans)?;
+        }
+        if self.deterministic {
+            sort_artifact_keys(&project)?;
+            self.verify_reproducible(&project)?;
+            println!("build is reproducible.");
+        }
+        if let Some(ref layout) = self.artifacts_layout {
+            layout.write(&project, self.jobs())?;
+        }
+        if let Some(max_size) = self.cache_max_size {
+            let (evicted, freed) = cache::gc(&project, max_size)?;
+            if evicted > 0 {
+                println!("evicted {} cache entries, freed {}.", evicted, ByteSize(freed));
+            }
         }
         Ok(output)
     }
 }
 
 impl BuildArgs {
+    /// Compiles all sources again without touching the cache or the artifacts, and fails if the
+    /// bytecode of any contract differs from its artifact
+    fn verify_reproducible(&self, project: &Project) -> eyre::Result<()> {
+        let mut opts = self.clone();
+        opts.force = false;
+        let mut fresh = opts.project()?;
+        fresh.cached = false;
+        fresh.no_artifacts = true;
+        let output = fresh.compile()?;
+        if output.has_compiler_errors() {
+            eyre::bail!(output.to_string())
+        }
+
+        // the bytecode of every contract by its source and name
+        let output = serde_json::to_value(output.output())?;
+        let mut compiled = BTreeMap::new();
+        for (source, contracts) in output["contracts"].as_object().into_iter().flatten() {
+            for (name, contract) in contracts.as_object().into_iter().flatten() {
+                let object = |field: &str| {
+                    let object = contract["evm"][field]["object"].as_str().unwrap_or_default();
+                    object.trim_start_matches("0x").to_string()
+                };
+                compiled.insert(
+                    (PathBuf::from(source), name.clone()),
+                    (object("bytecode"), object("deployedBytecode")),
+                );
+            }
+        }
+
+        let differences = bytecode_differences(compiled, utils::read_artifacts(project)?);
+        if !differences.is_empty() {
+            eyre::bail!("the build is not reproducible:\n  {}", differences.join("\n  "))
+        }
+        Ok(())
+    }
+
     /// Determines the source directory within the given root
     fn contracts_path(&self, root: impl AsRef<Path>) -> PathBuf {
         let root = root.as_ref();
@@ -144,6 +246,11 @@ impl BuildArgs {
         }
     }
 
+    /// The number of compiler processes and artifact writing threads to run in parallel
+    pub fn jobs(&self) -> usize {
+        self.jobs.unwrap_or_else(num_cpus::get)
+    }
+
     /// Converts all build arguments to the corresponding project config
     ///
     /// Defaults to DAppTools-style repo layout, but can be customized.
@@ -206,12 +313,24 @@ impl BuildArgs {
             Optimizer { enabled: Some(self.optimize), runs: Some(self.optimize_runs as usize) };
 
         // build the project w/ allowed paths = root and all the libs
-        let solc_settings =
-            Settings { optimizer, evm_version: Some(self.evm_version), ..Default::default() };
+        // the ASTs are read for the source spans of every function
+        let mut output_selection = Settings::default().output_selection;
+        output_selection
+            .entry("*".to_string())
+            .or_default()
+            .insert(String::new(), vec!["ast".to_string()]);
+
+        let solc_settings = Settings {
+            optimizer,
+            evm_version: Some(self.evm_version),
+            output_selection,
+            ..Default::default()
+        };
         let mut builder = Project::builder()
             .paths(paths)
             .allowed_path(&root)
             .allowed_paths(lib_paths)
+            .solc_jobs(self.jobs())
             .solc_config(SolcConfig::builder().settings(solc_settings).build()?);
 
         if self.no_auto_detect {
@@ -230,12 +349,204 @@ impl BuildArgs {
     }
 }
 
+/// A layout in which the artifacts can additionally be written
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArtifactsLayout {
+    /// `<artifacts>/<source>/<name>.json` and `<artifacts>/<source>/<name>.dbg.json`
+    Hardhat,
+    /// `<artifacts>/dapp.sol.json` with all contracts in solc's combined output format
+    Dapptools,
+}
+
+impl FromStr for ArtifactsLayout {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "hardhat" | "hh" => ArtifactsLayout::Hardhat,
+            "dapptools" | "dapp" => ArtifactsLayout::Dapptools,
+            other => eyre::bail!("unknown artifacts layout {}", other),
+        })
+    }
+}
+
+impl ArtifactsLayout {
+    /// Writes the artifacts of the last compilation of the project in this layout, using up to
+    /// `jobs` threads
+    pub fn write(&self, project: &Project, jobs: usize) -> eyre::Result<()> {
+        let out = project.artifacts_path();
+        let contracts = utils::read_artifacts(project)?;
+        // source paths are relative to the root in both layouts
+        let source_name = |source: &Path| {
+            source.strip_prefix(&project.paths.root).unwrap_or(source).display().to_string()
+        };
+
+        match self {
+            ArtifactsLayout::Hardhat => {
+                let contracts = contracts
+                    .into_iter()
+                    .map(|contract| (source_name(&contract.source), contract))
+                    .collect();
+                write_hardhat_artifacts(&out, contracts, jobs)?;
+            }
+            ArtifactsLayout::Dapptools => {
+                let mut combined = BTreeMap::<String, BTreeMap<String, serde_json::Value>>::new();
+                for contract in contracts {
+                    let artifact = json!({
+                        "abi": &contract.artifact.abi,
+                        "evm": {
+                            "bytecode": { "object": bytecode_hex(&contract.artifact.bin) },
+                            "deployedBytecode": {
+                                "object": bytecode_hex(&contract.artifact.bin_runtime)
+                            },
+                        },
+                    });
+                    combined
+                        .entry(source_name(&contract.source))
+                        .or_default()
+                        .insert(contract.name, artifact);
+                }
+                fs::create_dir_all(&out)?;
+                fs::write(
+                    out.join("dapp.sol.json"),
+                    serde_json::to_string(&json!({ "contracts": combined }))?,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares the creation and runtime bytecode of every contract, by its source and name, with its
+/// artifact, and describes every difference
+fn bytecode_differences(
+    mut compiled: BTreeMap<(PathBuf, String), (String, String)>,
+    artifacts: Vec<CompiledContract>,
+) -> Vec<String> {
+    let mut differences = Vec::new();
+    for contract in artifacts {
+        let id = contract.id();
+        match compiled.remove(&(contract.source, contract.name)) {
+            Some((bytecode, deployed)) => {
+                if bytecode != bytecode_hex(&contract.artifact.bin) {
+                    differences.push(format!("{}: creation bytecode differs", id));
+                }
+                if deployed != bytecode_hex(&contract.artifact.bin_runtime) {
+                    differences.push(format!("{}: runtime bytecode differs", id));
+                }
+            }
+            None => differences.push(format!("{}: not produced by the rebuild", id)),
+        }
+    }
+    for (source, name) in compiled.into_keys() {
+        differences.push(format!("{}:{}: has no artifact", source.display(), name));
+    }
+    differences
+}
+
+/// Rewrites every artifact of the project with its keys sorted, so that the files only change if
+/// their contents do
+fn sort_artifact_keys(project: &Project) -> eyre::Result<()> {
+    for entry in fs::read_dir(project.artifacts_path())? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "json") {
+            continue
+        }
+        // maps of `serde_json::Value` are ordered by key
+        let artifact: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        fs::write(&path, serde_json::to_string_pretty(&artifact)?)?;
+    }
+    Ok(())
+}
+
+/// Writes the hardhat artifacts of the contracts, given with the names of their sources. Every
+/// contract is written to its own files, so they are split evenly between `jobs` threads
+fn write_hardhat_artifacts(
+    out: &Path,
+    contracts: Vec<(String, CompiledContract)>,
+    jobs: usize,
+) -> eyre::Result<()> {
+    let jobs = jobs.max(1);
+    let chunk_size = ((contracts.len() + jobs - 1) / jobs).max(1);
+    let writers = contracts
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let chunk = chunk.to_vec();
+            let out = out.to_path_buf();
+            std::thread::spawn(move || {
+                chunk.into_iter().try_for_each(|(source, contract)| {
+                    write_hardhat_artifact(&out, &source, &contract)
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    for writer in writers {
+        writer.join().map_err(|_| eyre::eyre!("artifact writer panicked"))??;
+    }
+    Ok(())
+}
+
+/// Parses the number of `--jobs`, which must be at least 1
+fn parse_jobs(s: &str) -> eyre::Result<usize> {
+    match s.parse()? {
+        0 => eyre::bail!("--jobs must be at least 1"),
+        jobs => Ok(jobs),
+    }
+}
+
+/// Writes a contract's artifact and debug file to `<out>/<source>/<name>.json` and
+/// `<out>/<source>/<name>.dbg.json`
+fn write_hardhat_artifact(
+    out: &Path,
+    source: &str,
+    contract: &CompiledContract,
+) -> eyre::Result<()> {
+    let dir = out.join(source);
+    fs::create_dir_all(&dir)?;
+
+    let artifact = json!({
+        "_format": "hh-sol-artifact-1",
+        "contractName": &contract.name,
+        "sourceName": source,
+        "abi": &contract.artifact.abi,
+        "bytecode": format!("0x{}", bytecode_hex(&contract.artifact.bin)),
+        "deployedBytecode": format!("0x{}", bytecode_hex(&contract.artifact.bin_runtime)),
+        "linkReferences": {},
+        "deployedLinkReferences": {},
+    });
+    fs::write(
+        dir.join(format!("{}.json", contract.name)),
+        serde_json::to_string_pretty(&artifact)?,
+    )?;
+
+    // we don't keep solc's standard JSON input and output around, so there is no build info to
+    // point to
+    let dbg = json!({ "_format": "hh-sol-dbg-1" });
+    fs::write(
+        dir.join(format!("{}.dbg.json", contract.name)),
+        serde_json::to_string_pretty(&dbg)?,
+    )?;
+    Ok(())
+}
+
+/// Returns the bytecode as hex without `0x` prefix, including the placeholders of unlinked
+/// libraries
+fn bytecode_hex(bytecode: &Option<BytecodeObject>) -> String {
+    match bytecode {
+        Some(BytecodeObject::Bytecode(bytes)) => bytes.as_ref().to_hex(),
+        Some(BytecodeObject::Unlinked(code)) => code.trim_start_matches("0x").to_string(),
+        None => String::new(),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum EvmType {
     #[cfg(feature = "sputnik-evm")]
     Sputnik,
     #[cfg(feature = "evmodin-evm")]
     EvmOdin,
+    #[cfg(feature = "revm-evm")]
+    Revm,
 }
 
 impl FromStr for EvmType {
@@ -247,6 +558,8 @@ impl FromStr for EvmType {
             "sputnik" => EvmType::Sputnik,
             #[cfg(feature = "evmodin-evm")]
             "evmodin" => EvmType::EvmOdin,
+            #[cfg(feature = "revm-evm")]
+            "revm" => EvmType::Revm,
             other => eyre::bail!("unknown EVM type {}", other),
         })
     }
@@ -303,6 +616,14 @@ pub struct Env {
 
     #[structopt(help = "the block.gaslimit value during EVM execution", long)]
     pub block_gas_limit: Option<u64>,
+
+    #[cfg(feature = "sputnik-evm")]
+    #[structopt(
+        help = "overrides a gas cost for chains which changed it, either of an opcode with a fixed cost, e.g. `ADD=5`, or of one of the gas parameters of the EVM, e.g. `transaction_call=21000` or `sload_cold=800`",
+        long = "gas-cost",
+        number_of_values = 1
+    )]
+    pub gas_costs: Vec<GasOverride>,
     // TODO: Add configuration option for base fee.
 }
 
@@ -340,4 +661,122 @@ impl Env {
 
         host
     }
+
+    #[cfg(feature = "revm-evm")]
+    pub fn revm_env(&self, spec_id: SpecId) -> revm::Env {
+        let mut env = revm::Env::default();
+
+        env.cfg.chain_id = self.chain_id.into();
+        env.cfg.spec_id = spec_id;
+        env.tx.gas_price = self.gas_price.into();
+        env.block.coinbase = self.block_coinbase;
+        env.block.number = self.block_number.into();
+        env.block.timestamp = self.block_timestamp.into();
+        env.block.difficulty = self.block_difficulty.into();
+        env.block.basefee = self.block_base_fee_per_gas.into();
+        env.block.gas_limit = self.block_gas_limit.unwrap_or(self.gas_limit).into();
+
+        env
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    #[ignore = "needs solc in $PATH"]
+    fn fixture_builds_deterministically() {
+        let root = TempDir::new("deterministic-build").unwrap();
+        fs::create_dir_all(root.path().join("src")).unwrap();
+        fs::write(
+            root.path().join("src/Counter.sol"),
+            "pragma solidity >=0.6.0;\ncontract Counter { uint256 public count; \
+             function increment() public { count += 1; } }\n",
+        )
+        .unwrap();
+        let args = BuildArgs::from_iter([
+            "build",
+            "--root",
+            root.path().to_str().unwrap(),
+            "--no-auto-detect",
+            "--deterministic",
+        ]);
+        // the second build reuses the artifacts of the first, which must still match
+        args.clone().run().unwrap();
+        args.run().unwrap();
+    }
+
+    #[test]
+    fn reports_bytecode_differences() {
+        let contract = |name: &str, bin: &str, bin_runtime: &str| {
+            let mut artifact: MinimalCombinedArtifacts = serde_json::from_value(json!({})).unwrap();
+            artifact.bin = Some(BytecodeObject::Unlinked(bin.to_string()));
+            artifact.bin_runtime = Some(BytecodeObject::Unlinked(bin_runtime.to_string()));
+            let source = PathBuf::from("src/Token.sol");
+            CompiledContract { source, name: name.to_string(), artifact }
+        };
+        let compiled = |entries: &[(&str, &str, &str)]| {
+            entries
+                .iter()
+                .map(|(name, bin, bin_runtime)| {
+                    let key = (PathBuf::from("src/Token.sol"), name.to_string());
+                    (key, (bin.to_string(), bin_runtime.to_string()))
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        let artifacts = vec![contract("Token", "6001", "6002")];
+        assert!(bytecode_differences(compiled(&[("Token", "6001", "6002")]), artifacts).is_empty());
+
+        let artifacts = vec![contract("Token", "6001", "6002"), contract("Old", "60", "60")];
+        let differences = bytecode_differences(
+            compiled(&[("Token", "6001", "6003"), ("New", "60", "60")]),
+            artifacts,
+        );
+        assert_eq!(
+            differences,
+            vec![
+                "src/Token.sol:Token: runtime bytecode differs",
+                "src/Token.sol:Old: not produced by the rebuild",
+                "src/Token.sol:New: has no artifact",
+            ]
+        );
+    }
+
+    #[test]
+    fn jobs_must_be_positive() {
+        assert_eq!(parse_jobs("4").unwrap(), 4);
+        assert!(parse_jobs("0").is_err());
+        assert!(parse_jobs("-1").is_err());
+        assert!(parse_jobs("many").is_err());
+    }
+
+    #[test]
+    fn writes_every_hardhat_artifact_whatever_the_jobs() {
+        let contract = |name: &str| {
+            let contract = CompiledContract {
+                source: PathBuf::from("src/Token.sol"),
+                name: name.to_string(),
+                artifact: serde_json::from_value(json!({})).unwrap(),
+            };
+            ("src/Token.sol".to_string(), contract)
+        };
+        let names = ["A", "B", "C", "D", "E"];
+        // fewer, as many and more threads than contracts
+        for jobs in [1, 2, 5, 8] {
+            let out = TempDir::new("hardhat-artifacts").unwrap();
+            let contracts = names.iter().map(|name| contract(name)).collect();
+            write_hardhat_artifacts(out.path(), contracts, jobs).unwrap();
+            let dir = out.path().join("src/Token.sol");
+            for name in names {
+                let path = dir.join(format!("{}.json", name));
+                let artifact: serde_json::Value =
+                    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+                assert_eq!(artifact["contractName"], name);
+                assert!(dir.join(format!("{}.dbg.json", name)).exists());
+            }
+        }
+    }
 }
@@ -0,0 +1,323 @@
+//! cache command
+
+use crate::cmd::{build::BuildArgs, Cmd};
+use ethers::solc::{cache::SolFilesCache, Project};
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct CacheArgs {
+    #[structopt(subcommand)]
+    pub sub: CacheSubcommands,
+}
+
+#[derive(Debug, Clone, StructOpt)]
+pub enum CacheSubcommands {
+    #[structopt(about = "prints the size of the build artifacts and of the fork RPC cache")]
+    Ls {
+        #[structopt(flatten)]
+        opts: BuildArgs,
+    },
+    #[structopt(
+        about = "evicts the least recently used build artifacts and fork RPC cache entries until the caches fit in `--cache-max-size`"
+    )]
+    Gc {
+        #[structopt(flatten)]
+        opts: BuildArgs,
+    },
+}
+
+impl Cmd for CacheArgs {
+    type Output = ();
+
+    fn run(self) -> eyre::Result<()> {
+        match self.sub {
+            CacheSubcommands::Ls { opts } => {
+                let project = opts.project()?;
+                let entries = cache_entries(&project)?;
+                let (artifacts, rpc): (Vec<_>, Vec<_>) =
+                    entries.iter().partition(|entry| entry.kind == CacheKind::Artifacts);
+                println!(
+                    "build artifacts: {} sources, {}",
+                    artifacts.len(),
+                    ByteSize(total_size(artifacts.into_iter()))
+                );
+                println!(
+                    "fork RPC cache: {} files, {}",
+                    rpc.len(),
+                    ByteSize(total_size(rpc.into_iter()))
+                );
+                let total = ByteSize(total_size(entries.iter()));
+                match opts.cache_max_size {
+                    Some(max_size) => println!("total: {} (max {})", total, max_size),
+                    None => println!("total: {}", total),
+                }
+            }
+            CacheSubcommands::Gc { opts } => {
+                let max_size = opts.cache_max_size.ok_or_else(|| {
+                    eyre::eyre!(
+                        "no maximum cache size, pass `--cache-max-size` or set `FOUNDRY_CACHE_MAX_SIZE`"
+                    )
+                })?;
+                let project = opts.project()?;
+                let (evicted, freed) = gc(&project, max_size)?;
+                println!("evicted {} cache entries, freed {}.", evicted, ByteSize(freed));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A size in bytes, parsed from a number with an optional unit, e.g. `500MB` or `2GiB`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (value, unit) = s.split_at(split);
+        let value: u64 = value.parse().map_err(|_| eyre::eyre!("invalid size: `{}`", s))?;
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" | "k" => 1_000,
+            "mb" | "m" => 1_000_000,
+            "gb" | "g" => 1_000_000_000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            _ => eyre::bail!(
+                "unknown size unit in `{}`, expected one of B, KB, MB, GB, KiB, MiB, GiB",
+                s
+            ),
+        };
+        value
+            .checked_mul(multiplier)
+            .map(Self)
+            .ok_or_else(|| eyre::eyre!("size `{}` is too large", s))
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+        let mut size = self.0 as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            write!(f, "{}B", self.0)
+        } else {
+            write!(f, "{:.1}{}", size, UNITS[unit])
+        }
+    }
+}
+
+/// The directory of the caches which are shared by all projects, `~/.foundry/cache`
+pub fn foundry_cache_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".foundry").join("cache"))
+}
+
+/// The directory where the state fetched from forked chains is cached
+pub fn rpc_cache_dir() -> Option<PathBuf> {
+    foundry_cache_dir().map(|dir| dir.join("rpc"))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheKind {
+    /// The artifacts of the contracts defined in a source file of the project
+    Artifacts,
+    /// A file of the fork RPC cache
+    Rpc,
+}
+
+/// A unit of eviction: either all artifacts of a source file, which are only usable together
+/// with the source's entry in the solc cache, or a single fork RPC cache file
+#[derive(Clone, Debug)]
+pub struct CacheEntry {
+    pub kind: CacheKind,
+    /// The source file for artifacts, the file itself for fork RPC cache entries
+    pub path: PathBuf,
+    pub files: Vec<PathBuf>,
+    pub size: u64,
+    pub last_used: SystemTime,
+}
+
+/// Returns when the file was last used, falling back to the last modification if access times
+/// are not recorded by the filesystem
+fn last_used(metadata: &fs::Metadata) -> SystemTime {
+    let accessed = metadata.accessed().ok();
+    let modified = metadata.modified().ok();
+    accessed.max(modified).unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Collects the build artifacts of the project and the fork RPC cache files
+pub fn cache_entries(project: &Project) -> eyre::Result<Vec<CacheEntry>> {
+    let mut entries = Vec::new();
+
+    if project.paths.cache.exists() {
+        let cache = SolFilesCache::read(&project.paths.cache)?;
+        for (source, entry) in cache.files {
+            let mut artifacts = CacheEntry {
+                kind: CacheKind::Artifacts,
+                path: source,
+                files: Vec::new(),
+                size: 0,
+                last_used: SystemTime::UNIX_EPOCH,
+            };
+            for name in entry.artifacts {
+                // artifacts are written to `<artifacts>/<name>.json`
+                let file = project.artifacts_path().join(format!("{}.json", name));
+                if let Ok(metadata) = fs::metadata(&file) {
+                    artifacts.size += metadata.len();
+                    artifacts.last_used = artifacts.last_used.max(last_used(&metadata));
+                    artifacts.files.push(file);
+                }
+            }
+            entries.push(artifacts);
+        }
+    }
+
+    if let Some(dir) = rpc_cache_dir() {
+        let mut files = Vec::new();
+        walk_files(&dir, &mut files)?;
+        for (file, metadata) in files {
+            entries.push(CacheEntry {
+                kind: CacheKind::Rpc,
+                path: file.clone(),
+                files: vec![file],
+                size: metadata.len(),
+                last_used: last_used(&metadata),
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Recursively collects the files of the directory, if it exists
+fn walk_files(dir: &Path, files: &mut Vec<(PathBuf, fs::Metadata)>) -> eyre::Result<()> {
+    if !dir.is_dir() {
+        return Ok(())
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            walk_files(&entry.path(), files)?;
+        } else {
+            files.push((entry.path(), metadata));
+        }
+    }
+    Ok(())
+}
+
+fn total_size<'a>(entries: impl Iterator<Item = &'a CacheEntry>) -> u64 {
+    entries.map(|entry| entry.size).sum()
+}
+
+/// Returns the indices of the least recently used entries which have to be evicted for the
+/// remaining ones to fit in `max_size`
+pub fn entries_to_evict(entries: &[CacheEntry], max_size: ByteSize) -> Vec<usize> {
+    let mut size = total_size(entries.iter());
+    let mut by_age: Vec<_> = (0..entries.len()).collect();
+    by_age.sort_by_key(|&idx| entries[idx].last_used);
+    by_age
+        .into_iter()
+        .take_while(|&idx| {
+            let evict = size > max_size.0;
+            size = size.saturating_sub(entries[idx].size);
+            evict
+        })
+        .collect()
+}
+
+/// Evicts the least recently used cache entries until the caches fit in `max_size`, returning
+/// the number of evicted entries and the freed bytes.
+///
+/// Evicted sources are removed from the solc cache, so that they are recompiled by the next
+/// build.
+pub fn gc(project: &Project, max_size: ByteSize) -> eyre::Result<(usize, u64)> {
+    let entries = cache_entries(project)?;
+    let evicted = entries_to_evict(&entries, max_size);
+    if evicted.is_empty() {
+        return Ok((0, 0))
+    }
+    let evicted: HashSet<_> = evicted.into_iter().collect();
+
+    // contracts with the same name in different sources share an artifact file, which has to be
+    // kept as long as any of them is
+    let kept: HashSet<_> = entries
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| !evicted.contains(idx))
+        .flat_map(|(_, entry)| entry.files.iter())
+        .collect();
+
+    let mut freed = 0;
+    let mut evicted_sources = Vec::new();
+    for idx in &evicted {
+        let entry = &entries[*idx];
+        for file in entry.files.iter().filter(|file| !kept.contains(file)) {
+            if let Ok(metadata) = fs::metadata(file) {
+                fs::remove_file(file)?;
+                freed += metadata.len();
+            }
+        }
+        if entry.kind == CacheKind::Artifacts {
+            evicted_sources.push(&entry.path);
+        }
+    }
+
+    if !evicted_sources.is_empty() {
+        let mut cache = SolFilesCache::read(&project.paths.cache)?;
+        for source in evicted_sources {
+            cache.files.remove(source);
+        }
+        cache.write(&project.paths.cache)?;
+    }
+
+    Ok((evicted.len(), freed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn can_parse_byte_sizes() {
+        assert_eq!("1024".parse::<ByteSize>().unwrap(), ByteSize(1024));
+        assert_eq!("500MB".parse::<ByteSize>().unwrap(), ByteSize(500_000_000));
+        assert_eq!("2 GiB".parse::<ByteSize>().unwrap(), ByteSize(2 << 30));
+        assert_eq!("10kib".parse::<ByteSize>().unwrap(), ByteSize(10 << 10));
+        assert!("10TB".parse::<ByteSize>().is_err());
+        assert!("MB".parse::<ByteSize>().is_err());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let entry = |size, secs| CacheEntry {
+            kind: CacheKind::Rpc,
+            path: PathBuf::new(),
+            files: Vec::new(),
+            size,
+            last_used: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+        };
+        let entries = vec![entry(100, 3), entry(100, 1), entry(100, 2)];
+        assert_eq!(entries_to_evict(&entries, ByteSize(300)), Vec::<usize>::new());
+        assert_eq!(entries_to_evict(&entries, ByteSize(200)), vec![1]);
+        assert_eq!(entries_to_evict(&entries, ByteSize(150)), vec![1, 2]);
+        assert_eq!(entries_to_evict(&entries, ByteSize(0)), vec![1, 2, 0]);
+    }
+}
@@ -0,0 +1,119 @@
+//! Corpus command
+
+use crate::cmd::Cmd;
+use ethers::{
+    providers::Middleware,
+    types::{Address, Bytes},
+};
+use evm_adapters::runtime;
+use eyre::{ContextCompat, WrapErr};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct CorpusArgs {
+    #[structopt(
+        help = "the node whose blocks the calldata is harvested from",
+        long,
+        env = "ETH_RPC_URL",
+        alias = "rpc-url"
+    )]
+    fork_url: String,
+
+    #[structopt(help = "the contract whose calls are harvested", long)]
+    address: Address,
+
+    #[structopt(help = "the first block to harvest", long)]
+    from_block: u64,
+
+    #[structopt(help = "the last block to harvest [default: --from-block]", long)]
+    to_block: Option<u64>,
+
+    #[structopt(
+        help = "the corpus file the calldata is added to [default: corpus/<address>.json]",
+        long
+    )]
+    out: Option<PathBuf>,
+}
+
+impl Cmd for CorpusArgs {
+    type Output = ();
+
+    fn run(self) -> eyre::Result<()> {
+        let to_block = self.to_block.unwrap_or(self.from_block);
+        if to_block < self.from_block {
+            eyre::bail!("invalid block range {}..={}", self.from_block, to_block);
+        }
+
+        let provider = runtime::http_provider(&self.fork_url)?;
+        let throttle = runtime::throttle(Some(&self.fork_url));
+
+        // only the transactions sent to the contract are harvested, calls made to it by other
+        // contracts are not visible without tracing every transaction
+        let mut harvested = Vec::new();
+        for number in self.from_block..=to_block {
+            let block = runtime::block_on(runtime::retry(&throttle, || {
+                provider.get_block_with_txs(number)
+            }))?
+            .wrap_err_with(|| format!("block {} not found", number))?;
+            harvested.extend(
+                block
+                    .transactions
+                    .into_iter()
+                    .filter(|tx| tx.to == Some(self.address) && tx.input.as_ref().len() >= 4)
+                    .map(|tx| tx.input),
+            );
+        }
+
+        let out = self
+            .out
+            .unwrap_or_else(|| Path::new("corpus").join(format!("{:?}.json", self.address)));
+        let mut corpus = if out.exists() { read_corpus_file(&out)? } else { Vec::new() };
+        let before = corpus.len();
+        let mut seen = corpus.iter().map(|seed| seed.as_ref().to_vec()).collect::<BTreeSet<_>>();
+        corpus.extend(harvested.into_iter().filter(|seed| seen.insert(seed.as_ref().to_vec())));
+
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&out, serde_json::to_string_pretty(&corpus)?)?;
+        println!(
+            "Added {} calls to {:?} from {} blocks to {} ({} in total)",
+            corpus.len() - before,
+            self.address,
+            to_block - self.from_block + 1,
+            out.display(),
+            corpus.len()
+        );
+        Ok(())
+    }
+}
+
+/// Reads a corpus file, or all corpus files in a directory
+pub fn read_corpus(path: &Path) -> eyre::Result<Vec<Bytes>> {
+    if !path.is_dir() {
+        return read_corpus_file(path)
+    }
+    let mut files = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|file| file.extension().map_or(false, |ext| ext == "json"));
+    files.sort();
+    let mut corpus = Vec::new();
+    for file in files {
+        corpus.extend(read_corpus_file(&file)?);
+    }
+    Ok(corpus)
+}
+
+/// Reads the JSON array of hex encoded calldata of a corpus file
+fn read_corpus_file(path: &Path) -> eyre::Result<Vec<Bytes>> {
+    let corpus = fs::read_to_string(path)
+        .wrap_err_with(|| format!("failed to read the corpus {}", path.display()))?;
+    serde_json::from_str(&corpus)
+        .wrap_err_with(|| format!("failed to parse the corpus {}", path.display()))
+}
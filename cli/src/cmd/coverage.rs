@@ -0,0 +1,144 @@
+//! Coverage command
+
+use crate::cmd::{
+    build::BuildArgs,
+    test::{compile_source_locator, RunnerCmd, TestArgs},
+    Cmd,
+};
+use ethers::solc::{ArtifactOutput, Project};
+use eyre::Context;
+use forge::{CoverageReport, MultiContractRunnerBuilder};
+use regex::Regex;
+use std::{fs, path::PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct CoverageArgs {
+    /// All test arguments are supported, e.g. `--match` to only cover some tests
+    #[structopt(flatten)]
+    test: TestArgs,
+
+    #[structopt(
+        help = "the file the LCOV report is written to",
+        long,
+        default_value = "lcov.info"
+    )]
+    lcov: PathBuf,
+
+    #[structopt(
+        help = "also report the coverage of the test contracts (`*.t.sol`) and the libraries",
+        long
+    )]
+    include_tests: bool,
+}
+
+impl Cmd for CoverageArgs {
+    type Output = ();
+
+    fn run(self) -> eyre::Result<()> {
+        let CoverageArgs { test, lcov, include_tests } = self;
+        let coverage = Coverage { pattern: test.pattern.clone(), opts: test.opts.clone() };
+        let (failures, mut report, paths) = test.run_with(coverage)?;
+
+        if !include_tests {
+            report.files.retain(|path, _| {
+                !path.to_string_lossy().ends_with(".t.sol") &&
+                    !paths.libraries.iter().any(|lib| path.starts_with(lib))
+            });
+        }
+        // paths are reported relative to the project root
+        report.files = report
+            .files
+            .into_iter()
+            .map(|(path, file)| {
+                (path.strip_prefix(&paths.root).map(PathBuf::from).unwrap_or(path), file)
+            })
+            .collect();
+
+        print_summary(&report);
+        fs::write(&lcov, report.lcov())
+            .wrap_err(format!("failed to write the LCOV report \"{}\"", lcov.display()))?;
+        println!();
+        println!("Wrote the LCOV report to {}", lcov.display());
+
+        if failures > 0 {
+            eyre::bail!("Encountered a total of {} failing tests", failures);
+        }
+        Ok(())
+    }
+}
+
+/// The paths of the project whose sources are excluded from the report
+struct ProjectPaths {
+    root: PathBuf,
+    libraries: Vec<PathBuf>,
+}
+
+/// Runs all tests with coverage enabled
+struct Coverage {
+    pattern: Regex,
+    /// The project's build arguments, to compile it again with source maps
+    opts: BuildArgs,
+}
+
+impl RunnerCmd for Coverage {
+    type Output = (usize, CoverageReport, ProjectPaths);
+
+    const COVERAGE: bool = true;
+
+    fn run_with<A: ArtifactOutput + 'static, S: Clone, E: evm_adapters::Evm<S>>(
+        self,
+        builder: MultiContractRunnerBuilder,
+        project: Project<A>,
+        evm: E,
+    ) -> eyre::Result<Self::Output> {
+        let paths = ProjectPaths {
+            root: project.paths.root.clone(),
+            libraries: project.paths.libraries.clone(),
+        };
+        let mut runner = builder.coverage(true).build(project, evm)?;
+        let results = runner.test(self.pattern)?;
+        let failures = results.values().flat_map(|tests| tests.values()).filter(|t| !t.success);
+
+        // the cached artifacts do not include source maps
+        let locator = compile_source_locator(&self.opts)?;
+        let report = CoverageReport::new(&locator, &runner.coverage());
+        Ok((failures.count(), report, paths))
+    }
+}
+
+fn print_summary(report: &CoverageReport) {
+    let rows = report
+        .files
+        .iter()
+        .map(|(path, file)| (path.display().to_string(), file.summary()))
+        .chain(std::iter::once(("Total".to_string(), report.summary())))
+        .map(|(name, summary)| {
+            [
+                name,
+                summary.lines.to_string(),
+                summary.branches.to_string(),
+                summary.functions.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let header = ["File", "Lines", "Branches", "Functions"].map(String::from);
+    let mut widths = header.clone().map(|cell| cell.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let print_row = |row: &[String; 4]| {
+        let cells = row.iter().zip(&widths).map(|(cell, width)| format!("{:<1$}", cell, *width));
+        println!("| {} |", cells.collect::<Vec<_>>().join(" | "));
+    };
+
+    print_row(&header);
+    let separator = widths.map(|width| "-".repeat(width));
+    println!("|-{}-|", separator.join("-|-"));
+    for row in &rows {
+        print_row(row);
+    }
+}
@@ -3,21 +3,23 @@
 use crate::{
     cmd::{build::BuildArgs, Cmd},
     opts::{EthereumOpts, WalletType},
+    utils::{self, CompiledContract},
 };
 use ethers::{
-    abi::{Constructor, Contract, Token},
+    abi::{Address, Constructor, Contract, Token},
     prelude::{
         artifacts::{BytecodeObject, Source, Sources},
-        ContractFactory, Http, Middleware, MinimalCombinedArtifacts, Project, ProjectCompileOutput,
-        Provider,
+        ContractFactory, Http, Middleware, MinimalCombinedArtifacts, Project, Provider,
     },
     solc::cache::SolFilesCache,
 };
+use evm_adapters::runtime;
 use eyre::Result;
-use foundry_utils::parse_tokens;
+use foundry_utils::{explorer_address_url, parse_tokens};
+use rustc_hex::{FromHex, ToHex};
 
-use crate::opts::forge::ContractInfo;
-use std::{path::PathBuf, sync::Arc};
+use crate::opts::forge::{ContractInfo, LibraryInfo};
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
 use structopt::StructOpt;
 
 #[derive(Debug, Clone, StructOpt)]
@@ -36,6 +38,24 @@ pub struct CreateArgs {
 
     #[structopt(long, help = "verify on Etherscan")]
     verify: bool,
+
+    #[structopt(
+        long = "libraries",
+        help = "link a deployed library, as `<path>:<libraryname>:<address>` where `<path>:` is optional. The code at the address must match the compiled library",
+        number_of_values = 1
+    )]
+    libraries: Vec<LibraryInfo>,
+}
+
+/// A library to link against, resolved from the compiled artifacts
+struct Library {
+    /// The fully qualified name `<path>:<name>` of the library, which solc uses to derive the
+    /// placeholder in unlinked bytecode
+    id: String,
+    /// The address at which the library is deployed
+    address: Address,
+    /// The expected runtime bytecode of the library
+    runtime: Option<BytecodeObject>,
 }
 
 impl Cmd for CreateArgs {
@@ -60,32 +80,53 @@ impl Cmd for CreateArgs {
             println!("success.");
         }
 
+        let artifacts = compiled.into_artifacts().collect::<BTreeMap<_, _>>();
+
         // Get ABI and BIN
         let (abi, bin) = match self.contract.path {
             Some(ref path) => self.get_artifact_from_path(&project, path.clone())?,
-            None => self.get_artifact_from_name(compiled)?,
+            None => self.get_artifact_from_name(&artifacts)?,
         };
 
         // Add arguments to constructor
-        let provider = Provider::<Http>::try_from(self.eth.rpc_url.as_str())?;
+        let provider = runtime::http_provider(&self.eth.rpc_url)?;
         let params = match abi.constructor {
             Some(ref v) => self.parse_constructor_args(v)?,
             None => vec![],
         };
 
+        // Link the libraries, after making sure that they are actually deployed. Libraries are
+        // identified by their source file, so we need the contracts along with their sources
+        let contracts = utils::read_artifacts(&project)?;
+        let libraries = self.resolve_libraries(&contracts)?;
+        runtime::block_on(verify_libraries(&provider, &libraries))?;
+        let bin = link(bin, &libraries)?;
+        if let BytecodeObject::Unlinked(ref code) = bin {
+            let missing = contracts
+                .iter()
+                .map(CompiledContract::id)
+                .filter(|id| code.contains(&library_placeholder(id)))
+                .collect::<Vec<_>>();
+            eyre::bail!(
+                "{} needs to be linked against libraries which were not provided: {}. Pass their addresses with `--libraries <path>:<libraryname>:<address>`",
+                self.contract.name,
+                missing.join(", ")
+            )
+        }
+
         // Deploy with signer
-        let rt = tokio::runtime::Runtime::new().expect("could not start tokio rt");
-        let chain_id = rt.block_on(provider.get_chainid())?;
-        if let Some(signer) = rt.block_on(self.eth.signer_with(chain_id, provider))? {
+        let chain_id = runtime::block_on(provider.get_chainid())?;
+        let links = self.eth.links(chain_id);
+        if let Some(signer) = runtime::block_on(self.eth.signer_with(chain_id, provider))? {
             match signer {
                 WalletType::Ledger(signer) => {
-                    rt.block_on(self.deploy(abi, bin, params, signer))?;
+                    runtime::block_on(self.deploy(abi, bin, params, signer, links))?;
                 }
                 WalletType::Local(signer) => {
-                    rt.block_on(self.deploy(abi, bin, params, signer))?;
+                    runtime::block_on(self.deploy(abi, bin, params, signer, links))?;
                 }
                 WalletType::Trezor(signer) => {
-                    rt.block_on(self.deploy(abi, bin, params, signer))?;
+                    runtime::block_on(self.deploy(abi, bin, params, signer, links))?;
                 }
             }
         } else {
@@ -102,12 +143,12 @@ impl CreateArgs {
     // contract name?
     fn get_artifact_from_name(
         &self,
-        compiled: ProjectCompileOutput<MinimalCombinedArtifacts>,
+        artifacts: &BTreeMap<String, MinimalCombinedArtifacts>,
     ) -> Result<(Contract, BytecodeObject)> {
         let mut has_found_contract = false;
         let mut contract_artifact = None;
 
-        for (name, artifact) in compiled.into_artifacts() {
+        for (name, artifact) in artifacts {
             let artifact_contract_name = name.split(':').collect::<Vec<_>>()[1];
 
             if artifact_contract_name == self.contract.name {
@@ -115,7 +156,7 @@ impl CreateArgs {
                     eyre::bail!("contract with duplicate name. pass path")
                 }
                 has_found_contract = true;
-                contract_artifact = Some(artifact);
+                contract_artifact = Some(artifact.clone());
             }
         }
 
@@ -173,12 +214,38 @@ impl CreateArgs {
         ))
     }
 
+    /// Finds the artifact of every library passed with `--libraries`
+    fn resolve_libraries(&self, contracts: &[CompiledContract]) -> Result<Vec<Library>> {
+        self.libraries
+            .iter()
+            .map(|lib| {
+                let mut candidates = contracts.iter().filter(|contract| {
+                    contract.name == lib.contract.name &&
+                        lib.contract.path.as_ref().map_or(true, |p| contract.source.ends_with(p))
+                });
+                match (candidates.next(), candidates.next()) {
+                    (Some(contract), None) => Ok(Library {
+                        id: contract.id(),
+                        address: lib.address,
+                        runtime: contract.artifact.bin_runtime.clone(),
+                    }),
+                    (None, _) => eyre::bail!("could not find library {}", lib.contract.name),
+                    (Some(_), Some(_)) => eyre::bail!(
+                        "found multiple libraries named {name}, pass its path as `<path>:{name}:<address>`",
+                        name = lib.contract.name
+                    ),
+                }
+            })
+            .collect()
+    }
+
     async fn deploy<M: Middleware + 'static>(
         self,
         abi: Contract,
         bin: BytecodeObject,
         args: Vec<Token>,
         provider: M,
+        links: Option<u64>,
     ) -> Result<()> {
         let deployer_address =
             provider.default_sender().expect("no sender address set for provider");
@@ -192,6 +259,11 @@ impl CreateArgs {
 
         println!("Deployer: {:?}", deployer_address);
         println!("Deployed to: {:?}", deployed_contract.address());
+        if let Some(url) =
+            links.and_then(|chain_id| explorer_address_url(chain_id, deployed_contract.address()))
+        {
+            println!("Explorer: {}", url);
+        }
 
         Ok(())
     }
@@ -207,3 +279,168 @@ impl CreateArgs {
         parse_tokens(params, true)
     }
 }
+
+/// The placeholder solc puts into unlinked bytecode for the library with the fully qualified name
+/// `<path>:<name>`
+fn library_placeholder(id: &str) -> String {
+    let hash: String = ethers::utils::keccak256(id).to_hex();
+    format!("__${}$__", &hash[..34])
+}
+
+/// Replaces the placeholders of the libraries in the bytecode with their addresses. The bytecode
+/// stays unlinked if it references any other library.
+fn link(bin: BytecodeObject, libraries: &[Library]) -> Result<BytecodeObject> {
+    let mut code = match bin {
+        BytecodeObject::Unlinked(code) => code,
+        linked => return Ok(linked),
+    };
+    for lib in libraries {
+        code =
+            code.replace(&library_placeholder(&lib.id), &lib.address.as_bytes().to_hex::<String>());
+    }
+    if code.contains("__") {
+        return Ok(BytecodeObject::Unlinked(code))
+    }
+    let bytes: Vec<u8> = code.trim_start_matches("0x").from_hex()?;
+    Ok(BytecodeObject::Bytecode(bytes.into()))
+}
+
+/// Makes sure that there is a contract at the address of every library, whose code matches the
+/// compiled library
+async fn verify_libraries(provider: &Provider<Http>, libraries: &[Library]) -> Result<()> {
+    for lib in libraries {
+        let code = provider.get_code(lib.address, None).await?;
+        if code.as_ref().is_empty() {
+            eyre::bail!(
+                "there is no code at the address {:?} of library {}, was it deployed to this chain?",
+                lib.address,
+                lib.id
+            )
+        }
+        // the runtime code of a library linked against other libraries can't be compared
+        if let Some(BytecodeObject::Bytecode(ref expected)) = lib.runtime {
+            let expected = with_library_address(expected.to_vec(), lib.address);
+            if let Some(diff) = code_diff(&expected, code.as_ref()) {
+                eyre::bail!(
+                    "the code at the address {:?} does not match library {}:\n{}",
+                    lib.address,
+                    lib.id,
+                    diff
+                )
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Libraries start with `PUSH20 <address>` to prevent state modifying calls, the address is
+/// filled in when they are deployed
+fn with_library_address(mut code: Vec<u8>, address: Address) -> Vec<u8> {
+    if code.len() > 21 && code[0] == 0x73 && code[1..21].iter().all(|b| *b == 0) {
+        code[1..21].copy_from_slice(address.as_bytes());
+    }
+    code
+}
+
+/// Strips the CBOR encoded metadata which solc appends to the code, because it changes with
+/// e.g. the source paths without affecting the behavior of the code
+fn strip_metadata(code: &[u8]) -> &[u8] {
+    if code.len() < 2 {
+        return code
+    }
+    let len = u16::from_be_bytes([code[code.len() - 2], code[code.len() - 1]]) as usize;
+    match code.len().checked_sub(len + 2) {
+        // the metadata is a CBOR map
+        Some(start) if (0xa0..=0xbf).contains(&code[start]) => &code[..start],
+        _ => code,
+    }
+}
+
+/// Describes where the code differs from the expected code, ignoring their metadata. Returns
+/// `None` if they match
+fn code_diff(expected: &[u8], actual: &[u8]) -> Option<String> {
+    let (expected, actual) = (strip_metadata(expected), strip_metadata(actual));
+    if expected == actual {
+        return None
+    }
+    let offset = expected
+        .iter()
+        .zip(actual)
+        .position(|(a, b)| a != b)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+    let window = |code: &[u8]| -> String {
+        code[offset.min(code.len())..(offset + 16).min(code.len())].to_hex()
+    };
+    Some(format!(
+        "  expected {} bytes, found {} bytes (without metadata)\n  first difference at byte {}:\n  - expected: 0x{}\n  + found:    0x{}",
+        expected.len(),
+        actual.len(),
+        offset,
+        window(expected),
+        window(actual)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_library_placeholders() {
+        assert_eq!(
+            library_placeholder("contracts/Lib.sol:L"),
+            "__$01903783eb9194ebdde2f622e200e5eda4$__"
+        );
+    }
+
+    #[test]
+    fn links_libraries() {
+        let lib = Library {
+            id: "src/Lib.sol:L".to_string(),
+            address: Address::from_low_u64_be(1),
+            runtime: None,
+        };
+        let placeholder = library_placeholder(&lib.id);
+        let bin = BytecodeObject::Unlinked(format!("73{}ff", placeholder));
+        let linked = link(bin, &[lib]).unwrap();
+        let mut expected = vec![0x73];
+        expected.extend(Address::from_low_u64_be(1).as_bytes());
+        expected.push(0xff);
+        assert!(
+            matches!(linked, BytecodeObject::Bytecode(ref code) if code.as_ref() == expected.as_slice())
+        );
+
+        // other libraries are left unlinked
+        let bin = BytecodeObject::Unlinked(format!("73{}", placeholder));
+        assert!(matches!(link(bin, &[]).unwrap(), BytecodeObject::Unlinked(_)));
+    }
+
+    #[test]
+    fn diffs_library_code() {
+        let address = Address::from_low_u64_be(1);
+        let mut compiled = vec![0x73];
+        compiled.extend([0u8; 20]);
+        compiled.extend([0x30, 0x14, 0x60, 0x80]);
+        let mut deployed = vec![0x73];
+        deployed.extend(address.as_bytes());
+        deployed.extend([0x30, 0x14, 0x60, 0x80]);
+
+        let expected = with_library_address(compiled, address);
+        assert_eq!(code_diff(&expected, &deployed), None);
+
+        // metadata is ignored
+        let mut with_metadata = deployed.clone();
+        with_metadata.extend(
+            "a26469706673582212207a0c1fbc3b4e5f2d6e19a3d3c2b6a1f0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b4\
+             64736f6c63430008070033"
+                .from_hex::<Vec<u8>>()
+                .unwrap(),
+        );
+        assert_eq!(code_diff(&expected, &with_metadata), None);
+
+        deployed[22] = 0x00;
+        let diff = code_diff(&expected, &deployed).unwrap();
+        assert!(diff.contains("first difference at byte 22"), "{}", diff);
+        assert!(code_diff(&expected, &[]).is_some());
+    }
+}
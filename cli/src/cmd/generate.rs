@@ -0,0 +1,45 @@
+//! generate command
+
+use crate::cmd::Cmd;
+use evm_adapters::sputnik::cheatcodes::interface;
+use std::{fs, path::PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct GenerateArgs {
+    #[structopt(subcommand)]
+    pub sub: GenerateSubcommands,
+}
+
+#[derive(Debug, Clone, StructOpt)]
+pub enum GenerateSubcommands {
+    #[structopt(
+        about = "generates the Solidity interface of the cheatcodes, or their documentation with `--docs`, from the cheatcodes forge implements"
+    )]
+    VmInterface {
+        #[structopt(help = "the name of the interface", long, default_value = "Vm")]
+        name: String,
+        #[structopt(help = "generate a Markdown list of the cheatcodes instead", long)]
+        docs: bool,
+        #[structopt(help = "the file to write to, default being stdout", long, short)]
+        out: Option<PathBuf>,
+    },
+}
+
+impl Cmd for GenerateArgs {
+    type Output = ();
+
+    fn run(self) -> eyre::Result<()> {
+        match self.sub {
+            GenerateSubcommands::VmInterface { name, docs, out } => {
+                let generated =
+                    if docs { interface::vm_docs() } else { interface::vm_interface(&name) };
+                match out {
+                    Some(out) => fs::write(out, generated)?,
+                    None => print!("{}", generated),
+                }
+            }
+        }
+        Ok(())
+    }
+}
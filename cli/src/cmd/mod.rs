@@ -1,7 +1,15 @@
 //! Subcommands for forge
 
+pub mod bench;
 pub mod build;
+pub mod cache;
+pub mod corpus;
+pub mod coverage;
 pub mod create;
+#[cfg(feature = "sputnik-evm")]
+pub mod generate;
+#[cfg(feature = "sputnik-evm")]
+pub mod replay;
 pub mod snapshot;
 pub mod test;
 pub mod verify;
@@ -0,0 +1,293 @@
+//! Replay command
+
+use crate::cmd::Cmd;
+use ansi_term::Colour;
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{Block, Transaction, TransactionReceipt, H160, H256, U256},
+};
+use evm_adapters::{
+    runtime::{self, Throttle},
+    sputnik::{
+        new_shared_cache, vicinity, Executor, MemCache, SharedBackend, SputnikExecutor,
+        PRECOMPILES_MAP,
+    },
+};
+use eyre::ContextCompat;
+use sputnik::{
+    backend::{Backend, Basic},
+    executor::stack::StackState,
+    Config, ExitReason,
+};
+use std::{cell::Cell, sync::Arc};
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct ReplayArgs {
+    #[structopt(
+        help = "the archive node whose blocks are replayed",
+        long,
+        env = "ETH_RPC_URL",
+        alias = "rpc-url"
+    )]
+    fork_url: String,
+
+    #[structopt(help = "the first block to replay", long)]
+    from_block: u64,
+
+    #[structopt(help = "the last block to replay [default: --from-block]", long)]
+    to_block: Option<u64>,
+
+    #[structopt(help = "print the diverging transactions as JSON", long)]
+    json: bool,
+}
+
+impl Cmd for ReplayArgs {
+    type Output = ();
+
+    fn run(self) -> eyre::Result<()> {
+        let to_block = self.to_block.unwrap_or(self.from_block);
+        if self.from_block == 0 || to_block < self.from_block {
+            eyre::bail!("invalid block range {}..={}", self.from_block, to_block);
+        }
+
+        let provider = runtime::http_provider(&self.fork_url)?;
+        let throttle = runtime::throttle(Some(&self.fork_url));
+
+        let mut replayed = Vec::new();
+        for number in self.from_block..=to_block {
+            let results = replay_block(&provider, &throttle, number)?;
+            if !self.json {
+                for result in results.iter().filter(|result| result.diverged()) {
+                    println!("{}", result);
+                }
+            }
+            replayed.extend(results);
+        }
+
+        let divergences: Vec<_> = replayed.iter().filter(|result| result.diverged()).collect();
+        if self.json {
+            let divergences: Vec<_> = divergences.iter().map(|result| result.to_json()).collect();
+            println!("{}", serde_json::to_string(&divergences)?);
+        } else {
+            println!(
+                "Replayed {} transactions in {} blocks, {} diverged",
+                replayed.len(),
+                to_block - self.from_block + 1,
+                divergences.len()
+            );
+        }
+
+        if !divergences.is_empty() {
+            eyre::bail!("{} transactions diverged from their receipts", divergences.len());
+        }
+        Ok(())
+    }
+}
+
+/// A transaction executed locally, next to the outcome recorded in its receipt
+#[derive(Debug)]
+struct Replayed {
+    block: u64,
+    hash: H256,
+    success: bool,
+    expected_success: bool,
+    gas_used: u64,
+    expected_gas_used: u64,
+}
+
+impl Replayed {
+    fn diverged(&self) -> bool {
+        self.success != self.expected_success || self.gas_used != self.expected_gas_used
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "block": self.block,
+            "transactionHash": self.hash,
+            "success": self.success,
+            "expectedSuccess": self.expected_success,
+            "gasUsed": self.gas_used,
+            "expectedGasUsed": self.expected_gas_used,
+        })
+    }
+}
+
+impl std::fmt::Display for Replayed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = |success| if success { "success" } else { "revert" };
+        write!(f, "{} block {} tx {:?}:", Colour::Red.paint("[DIVERGED]"), self.block, self.hash)?;
+        if self.success != self.expected_success {
+            write!(f, " {} (expected {})", status(self.success), status(self.expected_success))?;
+        }
+        if self.gas_used != self.expected_gas_used {
+            write!(f, " gas used {} (expected {})", self.gas_used, self.expected_gas_used)?;
+        }
+        Ok(())
+    }
+}
+
+/// Executes the transactions of the block on top of the chain's state at its parent
+fn replay_block(
+    provider: &Provider<Http>,
+    throttle: &Arc<Throttle>,
+    number: u64,
+) -> eyre::Result<Vec<Replayed>> {
+    let (vicinity, block, receipts) = runtime::block_on(fetch_block(provider, throttle, number))?;
+
+    let cache = new_shared_cache(MemCache::default());
+    let backend = SharedBackend::new_throttled(
+        provider.clone(),
+        throttle.clone(),
+        cache,
+        vicinity,
+        Some((number - 1).into()),
+    );
+    let backend = TxBackend { backend, origin: Cell::default(), gas_price: Cell::default() };
+
+    let cfg = Config::london();
+    let precompiles = PRECOMPILES_MAP.clone();
+    // the gas of every transaction is accounted by the same gasometer, so it must not run out
+    // before the whole block was replayed
+    let mut evm = Executor::new(u64::MAX, &cfg, &backend, &precompiles);
+
+    let mut replayed = Vec::with_capacity(block.transactions.len());
+    for (tx, receipt) in block.transactions.iter().zip(receipts) {
+        backend.origin.set(tx.from);
+        backend.gas_price.set(tx.gas_price.unwrap_or_default());
+
+        let gasometer = evm.executor.state().metadata().gasometer();
+        let (used_before, refunded_before) = (gasometer.total_used_gas(), gasometer.refunded_gas());
+
+        let success = execute(&mut evm.executor, tx);
+
+        let gasometer = evm.executor.state().metadata().gasometer();
+        let used = gasometer.total_used_gas() - used_before;
+        let refunded = (gasometer.refunded_gas() - refunded_before).max(0) as u64;
+        let gas_used = used - refunded.min(used / cfg.max_refund_quotient);
+
+        replayed.push(Replayed {
+            block: number,
+            hash: tx.hash,
+            success,
+            expected_success: receipt.status.map(|status| status.as_u64() == 1).unwrap_or(true),
+            gas_used,
+            expected_gas_used: receipt.gas_used.unwrap_or_default().as_u64(),
+        });
+    }
+    Ok(replayed)
+}
+
+/// Runs the transaction and returns whether it succeeded.
+///
+/// Fees are not charged, so that balances may be higher than on chain afterwards
+fn execute<S, E: SputnikExecutor<S>>(executor: &mut E, tx: &Transaction) -> bool {
+    let access_list = tx
+        .access_list
+        .clone()
+        .map(|list| list.0.into_iter().map(|item| (item.address, item.storage_keys)).collect())
+        .unwrap_or_default();
+    let gas_limit = tx.gas.as_u64();
+    let reason = match tx.to {
+        Some(to) => {
+            executor
+                .transact_call(tx.from, to, tx.value, tx.input.to_vec(), gas_limit, access_list)
+                .0
+        }
+        None => {
+            executor.transact_create(tx.from, tx.value, tx.input.to_vec(), gas_limit, access_list)
+        }
+    };
+    matches!(reason, ExitReason::Succeed(_))
+}
+
+/// Fetches the block with its transactions and their receipts, and the environment to execute it
+/// in
+async fn fetch_block(
+    provider: &Provider<Http>,
+    throttle: &Throttle,
+    number: u64,
+) -> eyre::Result<(sputnik::backend::MemoryVicinity, Block<Transaction>, Vec<TransactionReceipt>)> {
+    let vicinity = runtime::retry(throttle, || vicinity(provider, Some(number))).await?;
+    let block = runtime::retry(throttle, || provider.get_block_with_txs(number))
+        .await?
+        .wrap_err_with(|| format!("block {} not found", number))?;
+    let mut receipts = Vec::with_capacity(block.transactions.len());
+    for tx in &block.transactions {
+        let receipt = runtime::retry(throttle, || provider.get_transaction_receipt(tx.hash))
+            .await?
+            .wrap_err_with(|| format!("receipt of {:?} not found", tx.hash))?;
+        receipts.push(receipt);
+    }
+    Ok((vicinity, block, receipts))
+}
+
+/// Serves the state of the forked chain, with the origin and gas price of the transaction being
+/// replayed
+struct TxBackend {
+    backend: SharedBackend,
+    origin: Cell<H160>,
+    gas_price: Cell<U256>,
+}
+
+impl Backend for TxBackend {
+    fn gas_price(&self) -> U256 {
+        self.gas_price.get()
+    }
+
+    fn origin(&self) -> H160 {
+        self.origin.get()
+    }
+
+    fn block_hash(&self, number: U256) -> H256 {
+        self.backend.block_hash(number)
+    }
+
+    fn block_number(&self) -> U256 {
+        self.backend.block_number()
+    }
+
+    fn block_coinbase(&self) -> H160 {
+        self.backend.block_coinbase()
+    }
+
+    fn block_timestamp(&self) -> U256 {
+        self.backend.block_timestamp()
+    }
+
+    fn block_difficulty(&self) -> U256 {
+        self.backend.block_difficulty()
+    }
+
+    fn block_gas_limit(&self) -> U256 {
+        self.backend.block_gas_limit()
+    }
+
+    fn block_base_fee_per_gas(&self) -> U256 {
+        self.backend.block_base_fee_per_gas()
+    }
+
+    fn chain_id(&self) -> U256 {
+        self.backend.chain_id()
+    }
+
+    fn exists(&self, address: H160) -> bool {
+        self.backend.exists(address)
+    }
+
+    fn basic(&self, address: H160) -> Basic {
+        self.backend.basic(address)
+    }
+
+    fn code(&self, address: H160) -> Vec<u8> {
+        self.backend.code(address)
+    }
+
+    fn storage(&self, address: H160, index: H256) -> H256 {
+        self.backend.storage(address, index)
+    }
+
+    fn original_storage(&self, address: H160, index: H256) -> Option<H256> {
+        self.backend.original_storage(address, index)
+    }
+}
@@ -7,12 +7,12 @@ use crate::cmd::{
 };
 use ansi_term::Colour;
 use eyre::Context;
-use forge::TestKindGas;
+use forge::{DeploymentCost, TestKindGas};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use std::{
     cmp::Ordering,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::Write,
     fs,
     io::{self, BufRead},
@@ -22,9 +22,9 @@ use std::{
 use structopt::StructOpt;
 
 /// A regex that matches a basic snapshot entry like
-/// `testDeposit() (gas: 58804)`
+/// `testDeposit() (gas: 58804)`, or a gas section entry like `testDeposit():transfer (gas: 2300)`
 pub static RE_BASIC_SNAPSHOT_ENTRY: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"(?P<sig>(\w+)\s*\((.*?)\))\s*\(((gas:)?\s*(?P<gas>\d+)|(μ:\s*(?P<avg>\d+),\s*~:\s*(?P<med>\d+)))\)").unwrap()
+    Regex::new(r"(?P<sig>(\w+)\s*\((.*?)\)(:\S+)?)\s*\(((gas:)?\s*(?P<gas>\d+)|(μ:\s*(?P<avg>\d+),\s*~:\s*(?P<med>\d+)))\)").unwrap()
 });
 
 #[derive(Debug, Clone, StructOpt)]
@@ -59,7 +59,9 @@ impl Cmd for SnapshotArgs {
     fn run(self) -> eyre::Result<()> {
         let outcome = self.test.run()?;
         outcome.ensure_ok()?;
-        let tests = self.config.apply(outcome);
+        let deployments = deployment_entries(&outcome.deployments);
+        let mut tests = snapshot_entries(self.config.apply(outcome));
+        tests.extend(deployments);
 
         if let Some(path) = self.diff {
             let snap = path.as_ref().unwrap_or(&self.snap);
@@ -128,6 +130,8 @@ impl SnapshotConfig {
     fn apply(&self, outcome: TestOutcome) -> Vec<Test> {
         let mut tests = outcome
             .into_tests()
+            // skipped tests stopped early, so their gas usage is not worth recording
+            .filter(|test| !test.result.skipped)
             .filter(|test| self.is_in_gas_range(test.gas_used()))
             .collect::<Vec<_>>();
 
@@ -143,7 +147,9 @@ impl SnapshotConfig {
 
 /// A general entry in a snapshot file
 ///
-/// Has the form `<signature>(gas:? 40181)`
+/// Has the form `<signature>(gas:? 40181)`, where the signature is either a test's signature or,
+/// for gas sections measured within a test, `<test signature>:<section name>`. The cost of
+/// deploying a contract is recorded as `deploy(<contract name>)`
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct SnapshotEntry {
     pub signature: String,
@@ -180,6 +186,41 @@ impl FromStr for SnapshotEntry {
     }
 }
 
+/// Converts the tests into snapshot entries, followed by an entry for every gas section that was
+/// recorded in a test
+fn snapshot_entries(tests: Vec<Test>) -> Vec<SnapshotEntry> {
+    tests
+        .into_iter()
+        .flat_map(|test| {
+            let sections = test
+                .result
+                .gas_sections
+                .iter()
+                .map(|(name, gas)| SnapshotEntry {
+                    signature: format!("{}:{}", test.signature, name),
+                    gas_used: TestKindGas::Standard(*gas),
+                })
+                .collect::<Vec<_>>();
+            std::iter::once(SnapshotEntry {
+                gas_used: test.result.kind.gas_used(),
+                signature: test.signature,
+            })
+            .chain(sections)
+        })
+        .collect()
+}
+
+/// Converts the deployment costs into snapshot entries, which are written after all tests
+fn deployment_entries(deployments: &BTreeMap<String, DeploymentCost>) -> Vec<SnapshotEntry> {
+    deployments
+        .iter()
+        .map(|(name, cost)| SnapshotEntry {
+            signature: format!("deploy({})", name),
+            gas_used: TestKindGas::Standard(cost.gas),
+        })
+        .collect()
+}
+
 /// Reads a list of snapshot entries from a snapshot file
 fn read_snapshot(path: impl AsRef<Path>) -> eyre::Result<Vec<SnapshotEntry>> {
     let path = path.as_ref();
@@ -196,15 +237,15 @@ fn read_snapshot(path: impl AsRef<Path>) -> eyre::Result<Vec<SnapshotEntry>> {
     Ok(entries)
 }
 
-/// Writes a series of tests to a snapshot file
+/// Writes a series of snapshot entries to a snapshot file
 fn write_to_snapshot_file(
-    tests: &[Test],
+    tests: &[SnapshotEntry],
     path: impl AsRef<Path>,
     _format: Option<Format>,
 ) -> eyre::Result<()> {
     let mut out = String::new();
     for test in tests {
-        writeln!(out, "{} {}", test.signature, test.result.kind.gas_used())?;
+        writeln!(out, "{} {}", test.signature, test.gas_used)?;
     }
     Ok(fs::write(path, out)?)
 }
@@ -235,13 +276,13 @@ impl SnapshotDiff {
 /// Compares the set of tests with an existing snapshot
 ///
 /// Returns true all tests match
-fn check(tests: Vec<Test>, snaps: Vec<SnapshotEntry>) -> bool {
+fn check(tests: Vec<SnapshotEntry>, snaps: Vec<SnapshotEntry>) -> bool {
     let snaps = snaps.into_iter().map(|s| (s.signature, s.gas_used)).collect::<HashMap<_, _>>();
     let mut has_diff = false;
 
     for test in tests {
         if let Some(target_gas) = snaps.get(&test.signature).cloned() {
-            let source_gas = test.result.kind.gas_used();
+            let source_gas = test.gas_used;
             if source_gas.gas() != target_gas.gas() {
                 println!(
                     "Diff in \"{}\": consumed \"{}\" gas, expected \"{}\" gas ",
@@ -261,7 +302,7 @@ fn check(tests: Vec<Test>, snaps: Vec<SnapshotEntry>) -> bool {
 }
 
 /// Compare the set of tests with an existing snapshot
-fn diff(tests: Vec<Test>, snaps: Vec<SnapshotEntry>) -> eyre::Result<()> {
+fn diff(tests: Vec<SnapshotEntry>, snaps: Vec<SnapshotEntry>) -> eyre::Result<()> {
     let snaps = snaps.into_iter().map(|s| (s.signature, s.gas_used)).collect::<HashMap<_, _>>();
     let mut diffs = Vec::with_capacity(tests.len());
     for test in tests.into_iter() {
@@ -273,7 +314,7 @@ fn diff(tests: Vec<Test>, snaps: Vec<SnapshotEntry>) -> eyre::Result<()> {
         })?;
 
         diffs.push(SnapshotDiff {
-            source_gas_used: test.result.kind.gas_used(),
+            source_gas_used: test.gas_used,
             signature: test.signature,
             target_gas_used,
         });
@@ -343,6 +384,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn can_parse_gas_section_snapshot_entry() {
+        let s = "testDeposit():transfer (gas: 2300)";
+        let entry = SnapshotEntry::from_str(s).unwrap();
+        assert_eq!(
+            entry,
+            SnapshotEntry {
+                signature: "testDeposit():transfer".to_string(),
+                gas_used: TestKindGas::Standard(2300)
+            }
+        );
+    }
+
+    #[test]
+    fn can_parse_deployment_snapshot_entry() {
+        let s = "deploy(Greeter) (gas: 151200)";
+        let entry = SnapshotEntry::from_str(s).unwrap();
+        assert_eq!(
+            entry,
+            SnapshotEntry {
+                signature: "deploy(Greeter)".to_string(),
+                gas_used: TestKindGas::Standard(151200)
+            }
+        );
+    }
+
     #[test]
     fn can_parse_fuzz_snapshot_entry() {
         let s = "deposit() (μ: 100, ~:200)";
@@ -3,26 +3,42 @@
 use crate::{
     cmd::{
         build::{BuildArgs, Env, EvmType},
+        corpus::read_corpus,
         Cmd,
     },
     utils,
 };
 use ansi_term::Colour;
 use ethers::{
-    providers::Provider,
     solc::{ArtifactOutput, Project},
-    types::{Address, U256},
+    types::{Address, H256, U256},
 };
-use evm_adapters::FAUCET_ACCOUNT;
-use forge::MultiContractRunnerBuilder;
+use evm_adapters::{runtime, FAUCET_ACCOUNT};
+use eyre::Context;
+use forge::{MultiContractRunnerBuilder, SourceLocator};
+use foundry_utils::{DisplayUnits, NativeToken};
 use regex::Regex;
-use std::{collections::BTreeMap, convert::TryFrom, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use structopt::StructOpt;
 
 #[derive(Debug, Clone, StructOpt)]
 pub struct TestArgs {
     #[structopt(help = "print the test results in json format", long, short)]
-    json: bool,
+    pub json: bool,
+
+    #[structopt(
+        help = "print newline-delimited json events (test_started, log, test_finished, suite_finished) while the tests are running, instead of the test results",
+        long,
+        conflicts_with = "json",
+        env = "FORGE_STREAM_JSON"
+    )]
+    pub stream_json: bool,
 
     #[structopt(flatten)]
     env: Env,
@@ -33,15 +49,22 @@ pub struct TestArgs {
         help = "only run test methods matching regex",
         default_value = ".*"
     )]
-    pattern: regex::Regex,
+    pub pattern: regex::Regex,
+
+    #[structopt(
+        help = "run the single standard test matching regex, recording every opcode it executes, and step through it in an interactive debugger",
+        long,
+        conflicts_with_all = &["json", "stream-json"]
+    )]
+    pub debug: Option<regex::Regex>,
 
     #[structopt(flatten)]
-    opts: BuildArgs,
+    pub opts: BuildArgs,
 
     #[structopt(
         long,
         short,
-        help = "the EVM type you want to use (e.g. sputnik, evmodin)",
+        help = "the EVM type you want to use (e.g. sputnik, evmodin, revm)",
         default_value = "sputnik"
     )]
     evm_type: EvmType,
@@ -58,6 +81,12 @@ pub struct TestArgs {
     #[structopt(env = "DAPP_FORK_BLOCK")]
     fork_block_number: Option<u64>,
 
+    #[structopt(
+        help = "do not cache the state fetched from the fork block in ~/.foundry/cache/rpc",
+        long
+    )]
+    no_fork_cache: bool,
+
     #[structopt(
         help = "the initial balance of each deployed test contract",
         long,
@@ -76,55 +105,240 @@ pub struct TestArgs {
     #[structopt(help = "enables the FFI cheatcode", long)]
     ffi: bool,
 
-    #[structopt(help = "verbosity of 'forge test' output (0-3)", long, default_value = "0")]
+    #[structopt(help = "enables the `sleep` and `unixTime` cheatcodes", long)]
+    wall_clock: bool,
+
+    #[structopt(
+        help = "the seed of the values returned by the `randomUint` and `randomAddress` cheatcodes",
+        long,
+        default_value = "0",
+        env = "FORGE_SEED"
+    )]
+    seed: u64,
+
+    #[structopt(
+        help = "a corpus file, or a directory of corpus files, of calldata (e.g. harvested by `forge corpus`) whose arguments seed the fuzz tests",
+        long,
+        env = "FORGE_CORPUS"
+    )]
+    corpus: Option<PathBuf>,
+
+    #[structopt(
+        help = "report unused cheatcode expectations (e.g. an `expectRevert` that was never followed by a call, a `startPrank` without `stopPrank`, or a `mockCall` which was never called) as warnings instead of failing the test",
+        long,
+        env = "FORGE_ALLOW_UNUSED_EXPECTATIONS"
+    )]
+    allow_unused_expectations: bool,
+
+    #[structopt(
+        help = "fail the run if any test calls a deprecated cheatcode, instead of printing a warning",
+        long,
+        env = "FORGE_DENY_DEPRECATED"
+    )]
+    deny_deprecated: bool,
+
+    #[structopt(
+        help = "revert a test as soon as one of its ds-test assertions fails, instead of continuing execution. Also enabled by `abort_on_failed_assertion = true` in the project's `foundry.toml`",
+        long,
+        env = "FORGE_ABORT_ON_FAILED_ASSERTION"
+    )]
+    abort_on_failed_assertion: bool,
+
+    #[structopt(
+        help = "verbosity of 'forge test' output (0-3), 3 prints the decoded call tree of every standard test",
+        long,
+        default_value = "0"
+    )]
     verbosity: u8,
 
+    #[structopt(
+        help = "the maximum number of frames printed of a failing test's revert trace, the innermost ones are kept (0 for no limit). Does not apply to the `--json` output",
+        long,
+        default_value = "32",
+        env = "FORGE_MAX_TRACE_DEPTH"
+    )]
+    max_trace_depth: usize,
+
+    #[structopt(
+        help = "the maximum number of bytes printed of a test's logs with `-vv` (0 for no limit). Does not apply to the `--json` output",
+        long,
+        default_value = "65536",
+        env = "FORGE_MAX_LOG_BYTES"
+    )]
+    max_log_bytes: usize,
+
+    #[structopt(
+        help = "print the gas used by deploying each contract and the size of its code, and its cost at `--gas-price` if set, and the cold and warm state accesses of each test, after the test results",
+        long
+    )]
+    gas_report: bool,
+
+    #[structopt(
+        help = "the unit in which the values and balances of traces and the deployment costs of the gas report are printed: wei, gwei or ether, which is named after the chain's native token (e.g. MATIC on Polygon), optionally with the number of decimals to round to, e.g. `gwei:2`. Defaults to the `display_units` of the project's `foundry.toml`, or to the chain's native token",
+        long,
+        env = "FORGE_DISPLAY_UNITS"
+    )]
+    display_units: Option<DisplayUnits>,
+
+    #[structopt(
+        help = "print the balances, nonces, code and storage slots changed by every failing test, after its revert trace",
+        long
+    )]
+    state_diff: bool,
+
     #[structopt(
         help = "if set to true, the process will exit with an exit code = 0, even if the tests fail",
         long,
         env = "FORGE_ALLOW_FAILURE"
     )]
     allow_failure: bool,
+
+    #[structopt(
+        help = "compare the results against a baseline and report the regressions. For `forge test`, the baseline is the `--json` output of a previous run (e.g. on the main branch), newly failing, newly passing and gas-regressed tests are reported, and only regressions fail the run: the exit code is 1 if any test newly fails and 2 if there are only gas regressions. For `forge bench`, the baseline is a file written with `--save-baseline`, and any benchmark whose mean gas regressed fails the run (the spread of the gas, e.g. its standard deviation or 90th percentile, is not compared)",
+        long
+    )]
+    pub baseline: Option<PathBuf>,
+
+    #[structopt(
+        help = "the maximum allowed increase (in %) of a test's gas usage, or of a benchmark's mean gas, compared to the baseline",
+        long,
+        alias = "threshold",
+        default_value = "0"
+    )]
+    pub gas_threshold: f64,
+}
+
+/// A command which consumes the tests configured by [`TestArgs`], e.g. by running and reporting
+/// them
+pub trait RunnerCmd {
+    type Output;
+
+    /// Whether the command measures coverage, which needs the opcodes executed by the EVM
+    const COVERAGE: bool = false;
+
+    fn run_with<A: ArtifactOutput + 'static, S: Clone, E: evm_adapters::Evm<S>>(
+        self,
+        builder: MultiContractRunnerBuilder,
+        project: Project<A>,
+        evm: E,
+    ) -> eyre::Result<Self::Output>;
 }
 
 impl Cmd for TestArgs {
     type Output = TestOutcome;
 
     fn run(self) -> eyre::Result<Self::Output> {
+        // read the baseline upfront so that we don't run all tests just to fail on a bad path
+        let baseline = self.baseline.as_deref().map(read_baseline).transpose()?;
+        let reporter = TestReporter {
+            pattern: self.debug.clone().unwrap_or_else(|| self.pattern.clone()),
+            debug: self.debug.is_some(),
+            json: self.json,
+            stream_json: self.stream_json,
+            verbosity: self.verbosity,
+            limits: OutputLimits {
+                max_trace_depth: self.max_trace_depth,
+                max_log_bytes: self.max_log_bytes,
+            },
+            gas_report: self.gas_report,
+            gas_price: self.env.gas_price,
+            allow_failure: self.allow_failure,
+            deny_deprecated: self.deny_deprecated,
+            baseline,
+            gas_threshold: self.gas_threshold,
+            opts: self.opts.clone(),
+        };
+        self.run_with(reporter)
+    }
+}
+
+impl TestArgs {
+    /// Sets up the project and the EVM configured by the arguments and hands them to `cmd`
+    pub fn run_with<C: RunnerCmd>(self, cmd: C) -> eyre::Result<C::Output> {
         let TestArgs {
             opts,
             env,
             json,
-            pattern,
+            stream_json,
+            pattern: _,
+            debug,
             evm_type,
             fork_url,
             fork_block_number,
+            no_fork_cache,
             initial_balance,
             sender,
             ffi,
+            wall_clock,
+            seed,
+            corpus,
+            allow_unused_expectations,
+            deny_deprecated: _,
+            abort_on_failed_assertion,
             verbosity,
-            allow_failure,
+            max_trace_depth: _,
+            max_log_bytes: _,
+            gas_report,
+            display_units,
+            state_diff,
+            allow_failure: _,
+            baseline: _,
+            gas_threshold: _,
         } = self;
         // Setup the fuzzer
         // TODO: Add CLI Options to modify the persistence
         let cfg = proptest::test_runner::Config { failure_persistence: None, ..Default::default() };
         let fuzzer = proptest::test_runner::TestRunner::new(cfg);
+        let corpus = corpus.as_deref().map(read_corpus).transpose()?.unwrap_or_default();
 
         // Set up the project
         let project = opts.project()?;
 
+        // all forks of the run share one budget of RPC requests, so that running the tests in
+        // parallel does not get them rate limited by the endpoints
+        let rpc = utils::config_rpc(&project.paths.root)?;
+        runtime::configure_rpc(
+            rpc.max_concurrent_requests.unwrap_or(runtime::DEFAULT_MAX_CONCURRENT_REQUESTS),
+            rpc.rate_limits,
+        );
+
+        // expose the `env` of the config to the env cheatcodes and FFI, without overriding the
+        // variables which are set explicitly
+        utils::set_config_env(&project.paths.root)?;
+
+        // aborting is enabled by either the flag or the config
+        let abort_on_failed_assertion = abort_on_failed_assertion ||
+            utils::config_abort_on_failed_assertion(&project.paths.root)?;
+
+        // `--display-units` overrides the unit of the config
+        let display_units = match display_units {
+            Some(units) => Some(units),
+            None => utils::config_display_units(&project.paths.root)?,
+        };
+
         // prepare the test builder
         let builder = MultiContractRunnerBuilder::default()
             .fuzzer(fuzzer)
             .initial_balance(initial_balance)
-            .sender(sender);
+            .sender(sender)
+            .allow_unused_expectations(allow_unused_expectations)
+            .corpus(corpus)
+            .debug(debug.is_some())
+            .traces(verbosity > 2)
+            // printed with `-vv` and the gas report, and always part of the JSON output
+            .details(verbosity > 1 || json || stream_json || gas_report)
+            .state_diffs(state_diff)
+            .gas_report(gas_report)
+            .display_units(units_on_chain(display_units, env.chain_id));
 
         // run the tests depending on the chosen EVM
         match evm_type {
             #[cfg(feature = "sputnik-evm")]
             EvmType::Sputnik => {
+                use crate::cmd::cache::rpc_cache_dir;
                 use evm_adapters::sputnik::{
-                    vicinity, Executor, ForkMemoryBackend, PRECOMPILES_MAP,
+                    gas::GasOverride, vicinity, DiskCache, Executor, ForkMemoryBackend,
+                    PRECOMPILES_MAP,
                 };
                 use sputnik::backend::{Backend, MemoryBackend};
                 let mut cfg = utils::sputnik_cfg(opts.evm_version);
@@ -133,13 +347,35 @@ impl Cmd for TestArgs {
                 // test smart contracts are likely to be >24kb
                 cfg.create_contract_limit = None;
 
+                // charge the gas costs of the target chain
+                let opcode_gas_costs = GasOverride::apply(&env.gas_costs, &mut cfg);
+
+                // the state of a pinned fork block is cached on disk to only fetch it once
+                let fork_cache = fork_block_number
+                    .filter(|_| !no_fork_cache)
+                    .and_then(|block| Some((block, rpc_cache_dir()?)));
+
                 let vicinity = if let Some(ref url) = fork_url {
-                    let provider = Provider::try_from(url.as_str())?;
-                    let rt = tokio::runtime::Runtime::new().expect("could not start tokio rt");
-                    rt.block_on(vicinity(&provider, fork_block_number))?
+                    let provider = runtime::http_provider(url)?;
+                    let throttle = runtime::throttle(Some(url));
+                    let fetched = runtime::block_on(runtime::retry(&throttle, || {
+                        vicinity(&provider, fork_block_number)
+                    }));
+                    // work offline if the block was cached before
+                    let cached = || {
+                        let (block, dir) = fork_cache.as_ref()?;
+                        DiskCache::read(dir, None, *block).map(|cache| cache.vicinity())
+                    };
+                    match fetched {
+                        Ok(vicinity) => vicinity,
+                        Err(err) => cached().ok_or(err)?,
+                    }
                 } else {
                     env.sputnik_state()
                 };
+                // a fork runs on the chain of its endpoint, whatever `--chain-id` is
+                let units = units_on_chain(display_units, vicinity.chain_id.as_u64());
+                let builder = builder.display_units(units);
                 let mut backend = MemoryBackend::new(&vicinity, Default::default());
                 // max out the balance of the faucet
                 let faucet =
@@ -147,11 +383,21 @@ impl Cmd for TestArgs {
                 faucet.balance = U256::MAX;
 
                 let backend: Box<dyn Backend> = if let Some(ref url) = fork_url {
-                    let provider = Provider::try_from(url.as_str())?;
+                    let provider = runtime::http_provider(url)?;
+                    let throttle = runtime::throttle(Some(url));
                     let init_state = backend.state().clone();
-                    let backend =
-                        ForkMemoryBackend::new(provider, backend, fork_block_number, init_state);
-                    Box::new(backend)
+                    match fork_cache {
+                        Some((block, dir)) => Box::new(ForkMemoryBackend::new_cached(
+                            provider, throttle, backend, block, init_state, dir,
+                        )),
+                        None => Box::new(ForkMemoryBackend::new_throttled(
+                            provider,
+                            throttle,
+                            backend,
+                            fork_block_number,
+                            init_state,
+                        )),
+                    }
                 } else {
                     Box::new(backend)
                 };
@@ -159,15 +405,35 @@ impl Cmd for TestArgs {
 
                 let precompiles = PRECOMPILES_MAP.clone();
                 let evm =
-                    Executor::new_with_cheatcodes(backend, env.gas_limit, &cfg, &precompiles, ffi);
+                    Executor::new_with_cheatcodes(backend, env.gas_limit, &cfg, &precompiles, ffi)
+                        .abort_on_failed_assertion(abort_on_failed_assertion)
+                        .wall_clock(wall_clock)
+                        .rpc_url(fork_url)
+                        .opcode_gas_costs(opcode_gas_costs)
+                        .seed(H256::from_low_u64_be(seed))
+                        .artifacts(project.artifacts_path().clone())
+                        .project_root(project.paths.root.clone());
 
-                test(builder, project, evm, pattern, json, verbosity, allow_failure)
+                cmd.run_with(builder, project, evm)
             }
             #[cfg(feature = "evmodin-evm")]
             EvmType::EvmOdin => {
                 use evm_adapters::evmodin::EvmOdin;
                 use evmodin::tracing::NoopTracer;
 
+                ensure_supported(
+                    "evmodin",
+                    [
+                        ("--fork-url", fork_url.is_some()),
+                        ("--ffi", ffi),
+                        ("--debug", debug.is_some()),
+                        ("--abort-on-failed-assertion", abort_on_failed_assertion),
+                        ("--state-diff", state_diff),
+                        ("--verbosity 3", verbosity > 2),
+                        ("forge coverage", C::COVERAGE),
+                    ],
+                )?;
+
                 let revision = utils::evmodin_cfg(opts.evm_version);
 
                 // TODO: Replace this with a proper host. We'll want this to also be
@@ -175,12 +441,239 @@ impl Cmd for TestArgs {
                 let host = env.evmodin_state();
 
                 let evm = EvmOdin::new(host, env.gas_limit, revision, NoopTracer);
-                test(builder, project, evm, pattern, json, verbosity, allow_failure)
+                cmd.run_with(builder, project, evm)
+            }
+            #[cfg(feature = "revm-evm")]
+            EvmType::Revm => {
+                use evm_adapters::revm::{DatabaseExt, Revm};
+                use revm::InMemoryDB;
+
+                ensure_supported(
+                    "revm",
+                    [
+                        ("--fork-url", fork_url.is_some()),
+                        ("--ffi", ffi),
+                        ("--debug", debug.is_some()),
+                        ("--abort-on-failed-assertion", abort_on_failed_assertion),
+                        ("--state-diff", state_diff),
+                        ("--verbosity 3", verbosity > 2),
+                        ("forge coverage", C::COVERAGE),
+                    ],
+                )?;
+
+                let spec_id = utils::revm_cfg(opts.evm_version);
+
+                let mut db = InMemoryDB::default();
+                // max out the balance of the faucet
+                db.set_balance(*FAUCET_ACCOUNT, U256::MAX);
+
+                let evm = Revm::new(db, env.revm_env(spec_id), env.gas_limit);
+                cmd.run_with(builder, project, evm)
+            }
+        }
+    }
+}
+
+/// The given unit on the chain with the given id, or the whole unit of the chain's native token
+fn units_on_chain(units: Option<DisplayUnits>, chain_id: u64) -> DisplayUnits {
+    units.unwrap_or_else(|| DisplayUnits::native(NativeToken::of(chain_id))).on_chain(chain_id)
+}
+
+/// Runs the tests and prints their results
+struct TestReporter {
+    pattern: Regex,
+    /// Whether the matching test is stepped through in the debugger after running
+    debug: bool,
+    json: bool,
+    stream_json: bool,
+    verbosity: u8,
+    limits: OutputLimits,
+    gas_report: bool,
+    /// The gas price at which the deployment costs of the gas report are priced, 0 to omit them
+    gas_price: u64,
+    allow_failure: bool,
+    deny_deprecated: bool,
+    baseline: Option<Baseline>,
+    gas_threshold: f64,
+    /// The project's build arguments, to compile it again with source maps if a test overflowed
+    opts: BuildArgs,
+}
+
+impl RunnerCmd for TestReporter {
+    type Output = TestOutcome;
+
+    fn run_with<A: ArtifactOutput + 'static, S: Clone, E: evm_adapters::Evm<S>>(
+        self,
+        builder: MultiContractRunnerBuilder,
+        project: Project<A>,
+        evm: E,
+    ) -> eyre::Result<Self::Output> {
+        let units = builder.display_units;
+        let mut outcome = test(
+            builder,
+            project,
+            evm,
+            self.pattern,
+            self.json,
+            self.stream_json,
+            self.verbosity,
+            self.limits,
+            self.allow_failure,
+        )?;
+        outcome.deny_deprecated = self.deny_deprecated;
+        // only the machine-readable output goes to stdout
+        let quiet = self.json || self.stream_json;
+
+        if self.gas_report && !quiet {
+            println!();
+            println!("Deployment costs:");
+            for (name, cost) in &outcome.deployments {
+                let price = match self.gas_price {
+                    0 => String::new(),
+                    price => format!(" (cost: {})", units.format(U256::from(cost.gas) * price)),
+                };
+                println!("  {} (gas: {}) (size: {} bytes){}", name, cost.gas, cost.size, price);
+            }
+            for (name, reason) in &outcome.failed_deployments {
+                println!(
+                    "  {} could not deploy {} on its own: {}",
+                    Colour::Yellow.paint("warning:"),
+                    name,
+                    reason
+                );
+            }
+
+            // cold accesses cost a multiple of warm ones, which often explains gas differences
+            println!();
+            println!("State accesses (EIP-2929):");
+            for (contract, tests) in &outcome.results {
+                for (name, result) in tests.iter().filter(|(_, result)| !result.is_fuzz()) {
+                    println!("  {}::{} ({})", contract, name, result.accesses);
+                }
             }
         }
+
+        if !quiet {
+            print_overflows(&outcome, &self.opts)?;
+            print_rpc_stats();
+        }
+
+        if self.debug {
+            debug(&outcome, &self.opts)?;
+        }
+
+        if let Some(baseline) = self.baseline {
+            let diff = BaselineDiff::new(&outcome.results, &baseline, self.gas_threshold);
+            if !quiet {
+                diff.print();
+            }
+            outcome.baseline_diff = Some(diff);
+        }
+
+        Ok(outcome)
     }
 }
 
+/// Fails loudly instead of ignoring the flags which are set, but not supported by the EVM
+#[cfg(any(feature = "evmodin-evm", feature = "revm-evm"))]
+fn ensure_supported<const N: usize>(evm: &str, flags: [(&str, bool); N]) -> eyre::Result<()> {
+    if let Some((flag, _)) = flags.iter().find(|(_, set)| *set) {
+        eyre::bail!(
+            "{} is not supported by {}, which runs the tests without cheatcodes, console logs, \
+             forking or traces. Use `--evm-type sputnik` instead.",
+            flag,
+            evm
+        )
+    }
+    Ok(())
+}
+
+/// Prints the requests sent to every forked endpoint and their effective rate
+fn print_rpc_stats() {
+    let stats = runtime::rpc_stats();
+    if stats.is_empty() {
+        return
+    }
+    println!();
+    println!("RPC requests:");
+    for stats in stats {
+        let limit = stats.rate_limit.map(|limit| format!(" (limit: {}/s)", limit));
+        println!(
+            "  {}: {} requests, {:.1}/s{}",
+            stats.host,
+            stats.requests,
+            stats.rate,
+            limit.unwrap_or_default()
+        );
+    }
+}
+
+/// Prints the expression every failing test overflowed at
+fn print_overflows(outcome: &TestOutcome, opts: &BuildArgs) -> eyre::Result<()> {
+    let overflows = outcome
+        .results
+        .iter()
+        .flat_map(|(contract, tests)| tests.iter().map(move |test| (contract, test)))
+        .filter(|(_, (_, result))| !result.success)
+        .filter_map(|(contract, (name, result))| {
+            Some((format!("{}::{}", contract, name), result.overflow.as_ref()?))
+        })
+        .collect::<Vec<_>>();
+    if overflows.is_empty() {
+        return Ok(())
+    }
+
+    let locator = compile_source_locator(opts)?;
+    println!();
+    println!("Arithmetic overflows:");
+    for (name, site) in overflows {
+        match locator.locate(site) {
+            Some(location) => println!("  {} at {}", name, location),
+            None => println!("  {} in {:?}", name, site.address),
+        }
+    }
+    Ok(())
+}
+
+/// Steps through the opcodes executed by the single standard test which ran
+fn debug(outcome: &TestOutcome, opts: &BuildArgs) -> eyre::Result<()> {
+    let tests = outcome
+        .results
+        .iter()
+        .flat_map(|(contract, tests)| tests.iter().map(move |test| (contract, test)))
+        .filter(|(_, (_, result))| !result.is_fuzz())
+        .collect::<Vec<_>>();
+    let (contract, (name, result)) = match tests.as_slice() {
+        [test] => *test,
+        [] => eyre::bail!("no standard test matches the `--debug` pattern"),
+        tests => eyre::bail!(
+            "the `--debug` pattern must match a single standard test, but it matches {}",
+            tests.len()
+        ),
+    };
+
+    let locator = compile_source_locator(opts)?;
+    crate::debugger::Debugger::new(
+        format!("{}::{}", contract, name),
+        &result.debug,
+        &result.breakpoints,
+        &locator,
+    )
+    .run()
+}
+
+/// Compiles the project with source maps to locate the executed instructions in the sources
+pub(crate) fn compile_source_locator(opts: &BuildArgs) -> eyre::Result<SourceLocator> {
+    // the cached artifacts do not include source maps, so the sources are compiled again without
+    // touching the cache
+    let mut opts = opts.clone();
+    opts.force = false;
+    let mut project = opts.project()?;
+    project.cached = false;
+    project.no_artifacts = true;
+    SourceLocator::compile(&project)
+}
+
 /// The result of a single test
 #[derive(Debug, Clone)]
 pub struct Test {
@@ -200,16 +693,32 @@ impl Test {
 pub struct TestOutcome {
     /// Whether failures are allowed
     allow_failure: bool,
+    /// Whether calls to deprecated cheatcodes fail the run
+    deny_deprecated: bool,
     /// All test results `contract -> (test name -> TestResult)`
     pub results: BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+    /// The cost of deploying each contract `contract -> DeploymentCost`
+    pub deployments: BTreeMap<String, forge::DeploymentCost>,
+    /// The contracts which could not be deployed for the gas report `contract -> reason`
+    pub failed_deployments: BTreeMap<String, String>,
+    /// The changes compared to the baseline, if one was provided
+    pub baseline_diff: Option<BaselineDiff>,
 }
 
 impl TestOutcome {
     fn new(
         results: BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+        deployments: BTreeMap<String, forge::DeploymentCost>,
         allow_failure: bool,
     ) -> Self {
-        Self { results, allow_failure }
+        Self {
+            results,
+            deployments,
+            failed_deployments: BTreeMap::new(),
+            allow_failure,
+            deny_deprecated: false,
+            baseline_diff: None,
+        }
     }
 
     /// Iterator over all succeeding tests and their names
@@ -234,9 +743,12 @@ impl TestOutcome {
             .map(|(name, result)| Test { signature: name, result })
     }
 
-    /// Checks if there are any failures and failures are disallowed
+    /// Checks if there are any failures and failures are disallowed, or calls to deprecated
+    /// cheatcodes and those are denied. Compared against a baseline, only the failures which are
+    /// not in the baseline and gas regressions fail the run, with a [`BaselineExit`]
     pub fn ensure_ok(&self) -> eyre::Result<()> {
-        if !self.allow_failure {
+        let baseline_exit_code = self.baseline_exit_code();
+        if !self.allow_failure && baseline_exit_code.is_none() {
             let failures = self.failures().count();
             if failures > 0 {
                 let successes = self.successes().count();
@@ -247,7 +759,267 @@ impl TestOutcome {
                 );
             }
         }
-        Ok(())
+        if self.deny_deprecated {
+            let deprecated = self.tests().filter(|(_, t)| !t.deprecations.is_empty()).count();
+            if deprecated > 0 {
+                eyre::bail!(
+                    "{} tests call deprecated cheatcodes, which are denied by `--deny-deprecated`",
+                    deprecated
+                );
+            }
+        }
+        match baseline_exit_code {
+            Some(code) if code != 0 => Err(BaselineExit { code }.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// If the results were compared against a baseline, returns the exit code to use for PR
+    /// gates, see [`BaselineDiff::exit_code`]. Failures that are already present in the baseline
+    /// do not affect it.
+    pub fn baseline_exit_code(&self) -> Option<i32> {
+        let diff = self.baseline_diff.as_ref()?;
+        Some(if self.allow_failure { 0 } else { diff.exit_code() })
+    }
+}
+
+/// The error of a run which regressed compared to its baseline. `forge` exits with its code
+/// instead of printing it, since the changes were printed already
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BaselineExit {
+    /// Either [`EXIT_NEWLY_FAILING`] or [`EXIT_GAS_REGRESSION`]
+    pub code: i32,
+}
+
+impl std::fmt::Display for BaselineExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.code {
+            EXIT_NEWLY_FAILING => write!(f, "tests newly fail compared to the baseline"),
+            _ => write!(f, "gas usage regressed compared to the baseline"),
+        }
+    }
+}
+
+impl std::error::Error for BaselineExit {}
+
+/// The status of a test in a baseline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BaselineTest {
+    pub success: bool,
+    pub gas_used: u64,
+}
+
+/// Test results to compare against `contract -> (test name -> BaselineTest)`
+pub type Baseline = BTreeMap<String, BTreeMap<String, BaselineTest>>;
+
+/// Reads a baseline from the `--json` output of `forge test`.
+///
+/// Only the `success` and `gas_used` fields of every test are read, so that baselines written
+/// by older versions can still be used.
+fn read_baseline(path: &Path) -> eyre::Result<Baseline> {
+    let content = fs::read_to_string(path)
+        .wrap_err(format!("failed to read baseline \"{}\"", path.display()))?;
+    let json: serde_json::Value = serde_json::from_str(&content)
+        .wrap_err(format!("failed to parse baseline \"{}\"", path.display()))?;
+    let contracts = json
+        .as_object()
+        .ok_or_else(|| eyre::eyre!("baseline \"{}\" is not a JSON object", path.display()))?;
+
+    let mut baseline = Baseline::new();
+    for (contract_name, tests) in contracts {
+        let tests = baseline.entry(contract_name.clone()).or_default();
+        for (name, test) in contracts[contract_name].as_object().into_iter().flatten() {
+            let success = test["success"].as_bool().ok_or_else(|| {
+                eyre::eyre!("missing `success` of {}::{} in baseline", contract_name, name)
+            })?;
+            let gas_used = test["gas_used"].as_u64().unwrap_or_default();
+            tests.insert(name.clone(), BaselineTest { success, gas_used });
+        }
+    }
+    Ok(baseline)
+}
+
+/// Exit code when a test fails which passed in (or was missing from) the baseline
+pub const EXIT_NEWLY_FAILING: i32 = 1;
+/// Exit code when no test newly fails, but the gas usage of some test regressed
+pub const EXIT_GAS_REGRESSION: i32 = 2;
+
+/// The changes of the test results compared to a baseline, test names are formatted as
+/// `Contract::test()`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BaselineDiff {
+    /// Tests which fail, but passed in the baseline or are new
+    pub newly_failing: Vec<String>,
+    /// Tests which pass, but failed in the baseline
+    pub newly_passing: Vec<String>,
+    /// Passing tests whose gas usage increased by more than the threshold `(name, before, after)`
+    pub gas_regressions: Vec<(String, u64, u64)>,
+    /// Tests which are not in the baseline
+    pub added: Vec<String>,
+    /// Tests of the baseline which were not run
+    pub removed: Vec<String>,
+}
+
+impl BaselineDiff {
+    pub fn new(
+        results: &BTreeMap<String, BTreeMap<String, forge::TestResult>>,
+        baseline: &Baseline,
+        gas_threshold: f64,
+    ) -> Self {
+        let mut diff = Self::default();
+        for (contract_name, tests) in results {
+            for (name, result) in tests {
+                let id = format!("{}::{}", contract_name, name);
+                let before = baseline.get(contract_name).and_then(|tests| tests.get(name));
+                match before {
+                    None => {
+                        if !result.success {
+                            diff.newly_failing.push(id.clone());
+                        }
+                        diff.added.push(id);
+                    }
+                    Some(before) => match (before.success, result.success) {
+                        (true, false) => diff.newly_failing.push(id),
+                        (false, true) => diff.newly_passing.push(id),
+                        (true, true) => {
+                            let limit = before.gas_used as f64 * (1.0 + gas_threshold / 100.0);
+                            if result.gas_used as f64 > limit {
+                                diff.gas_regressions.push((id, before.gas_used, result.gas_used));
+                            }
+                        }
+                        (false, false) => {}
+                    },
+                }
+            }
+        }
+
+        for (contract_name, tests) in baseline {
+            for name in tests.keys() {
+                let ran =
+                    results.get(contract_name).map_or(false, |tests| tests.contains_key(name));
+                if !ran {
+                    diff.removed.push(format!("{}::{}", contract_name, name));
+                }
+            }
+        }
+        diff
+    }
+
+    /// Returns [`EXIT_NEWLY_FAILING`] if any test newly fails, [`EXIT_GAS_REGRESSION`] if there
+    /// are only gas regressions, and 0 otherwise
+    pub fn exit_code(&self) -> i32 {
+        if !self.newly_failing.is_empty() {
+            EXIT_NEWLY_FAILING
+        } else if !self.gas_regressions.is_empty() {
+            EXIT_GAS_REGRESSION
+        } else {
+            0
+        }
+    }
+
+    /// Prints every category of changes in its own section
+    pub fn print(&self) {
+        println!();
+        println!("Comparing against baseline:");
+
+        if !self.newly_failing.is_empty() {
+            println!("{}", Colour::Red.paint("Newly failing tests:"));
+            for name in &self.newly_failing {
+                println!("  {}", name);
+            }
+        }
+        if !self.newly_passing.is_empty() {
+            println!("{}", Colour::Green.paint("Newly passing tests:"));
+            for name in &self.newly_passing {
+                println!("  {}", name);
+            }
+        }
+        if !self.gas_regressions.is_empty() {
+            println!("{}", Colour::Yellow.paint("Gas regressions:"));
+            for (name, before, after) in &self.gas_regressions {
+                let change = (*after as f64 - *before as f64) / *before as f64 * 100.0;
+                println!("  {} (gas: {} -> {} ({:+.3}%))", name, before, after, change);
+            }
+        }
+        if !self.added.is_empty() {
+            println!("New tests:");
+            for name in &self.added {
+                println!("  {}", name);
+            }
+        }
+        if !self.removed.is_empty() {
+            println!("Removed tests:");
+            for name in &self.removed {
+                println!("  {}", name);
+            }
+        }
+        if self.newly_failing.is_empty() &&
+            self.newly_passing.is_empty() &&
+            self.gas_regressions.is_empty() &&
+            self.added.is_empty() &&
+            self.removed.is_empty()
+        {
+            println!("No changes");
+        }
+    }
+}
+
+/// Limits of the printed test results, so that tests with huge logs or deep traces still produce
+/// readable output. A limit of 0 disables it
+#[derive(Debug, Clone, Copy)]
+pub struct OutputLimits {
+    /// The maximum number of frames printed of a revert trace
+    pub max_trace_depth: usize,
+    /// The maximum number of bytes printed of a test's logs
+    pub max_log_bytes: usize,
+}
+
+impl OutputLimits {
+    /// Returns the lines of the revert trace, innermost frame first, with a marker in place of
+    /// the outermost frames beyond the limit
+    pub fn revert_trace(&self, frames: &[String]) -> Vec<String> {
+        let shown = if self.max_trace_depth == 0 {
+            frames.len()
+        } else {
+            frames.len().min(self.max_trace_depth)
+        };
+        let mut lines: Vec<_> =
+            frames.iter().rev().take(shown).map(|frame| format!("at {}", frame)).collect();
+        if shown < frames.len() {
+            lines.push(format!("… {} outer frames truncated", frames.len() - shown));
+        }
+        lines
+    }
+
+    /// Returns the logs which fit in the limit, with a marker in place of the rest. The log
+    /// which crosses the limit is cut off at a character boundary
+    pub fn logs<'a>(&self, logs: &'a [String]) -> Vec<Cow<'a, str>> {
+        if self.max_log_bytes == 0 {
+            return logs.iter().map(|log| Cow::Borrowed(log.as_str())).collect()
+        }
+        let mut lines = Vec::new();
+        let mut remaining = self.max_log_bytes;
+        for (i, log) in logs.iter().enumerate() {
+            if log.len() <= remaining {
+                remaining -= log.len();
+                lines.push(Cow::Borrowed(log.as_str()));
+                continue
+            }
+            let mut end = remaining;
+            while !log.is_char_boundary(end) {
+                end -= 1;
+            }
+            if end > 0 {
+                lines.push(Cow::Owned(format!("{}…", &log[..end])));
+            }
+            let truncated = log.len() - end + logs[i + 1..].iter().map(String::len).sum::<usize>();
+            lines.push(Cow::Owned(format!(
+                "… {} bytes of logs truncated, use --json to get all of them",
+                truncated
+            )));
+            break
+        }
+        lines
     }
 }
 
@@ -258,17 +1030,32 @@ fn test<A: ArtifactOutput + 'static, S: Clone, E: evm_adapters::Evm<S>>(
     evm: E,
     pattern: Regex,
     json: bool,
+    stream_json: bool,
     verbosity: u8,
+    limits: OutputLimits,
     allow_failure: bool,
 ) -> eyre::Result<TestOutcome> {
     let mut runner = builder.build(project, evm)?;
 
-    let results = runner.test(pattern)?;
+    let results = if stream_json {
+        runner.test_streaming(
+            pattern,
+            &mut |contract, event| match serde_json::to_value(event) {
+                Ok(mut line) => {
+                    line["contract"] = contract.into();
+                    println!("{}", line);
+                }
+                Err(err) => tracing::error!(?err, "could not serialize a test event"),
+            },
+        )?
+    } else {
+        runner.test(pattern)?
+    };
 
     if json {
         let res = serde_json::to_string(&results)?;
         println!("{}", res);
-    } else {
+    } else if !stream_json {
         // Dapptools-style printing of test results
         for (i, (contract_name, tests)) in results.iter().enumerate() {
             if i > 0 {
@@ -280,7 +1067,9 @@ fn test<A: ArtifactOutput + 'static, S: Clone, E: evm_adapters::Evm<S>>(
             }
 
             for (name, result) in tests {
-                let status = if result.success {
+                let status = if result.skipped {
+                    Colour::Yellow.paint("[SKIP]")
+                } else if result.success {
                     Colour::Green.paint("[PASS]")
                 } else {
                     let txt = match (&result.reason, &result.counterexample) {
@@ -303,25 +1092,229 @@ fn test<A: ArtifactOutput + 'static, S: Clone, E: evm_adapters::Evm<S>>(
                 };
 
                 println!("{} {} {}", status, name, result.kind.gas_used());
+
+                for (section, gas) in &result.gas_sections {
+                    println!("  {} (gas: {})", section, gas);
+                }
+
+                for warning in result.warnings.iter().chain(&result.deprecations) {
+                    println!("  {} {}", Colour::Yellow.paint("warning:"), warning);
+                }
+
+                if let Some(counterexample) =
+                    result.counterexample.as_ref().filter(|_| !result.success)
+                {
+                    println!("  reproduce with: {}", counterexample.cast);
+                    println!("  or in the test contract:");
+                    println!("{}", counterexample.solidity);
+                }
+
+                // print where a failing test reverted, innermost frame first
+                if !result.success {
+                    for line in limits.revert_trace(&result.revert_trace) {
+                        println!("    {}", line);
+                    }
+                    if !result.state_diff.is_empty() {
+                        println!("  State diff:");
+                        for line in &result.state_diff {
+                            println!("    {}", line);
+                        }
+                    }
+                }
             }
 
             if verbosity > 1 {
                 println!();
 
                 for (name, result) in tests {
-                    let status = if result.success { "Success" } else { "Failure" };
+                    let status = match (result.skipped, result.success) {
+                        (true, _) => "Skipped",
+                        (false, true) => "Success",
+                        (false, false) => "Failure",
+                    };
                     println!("{}: {}", status, name);
                     println!();
 
-                    for log in &result.logs {
+                    for log in limits.logs(&result.logs) {
                         println!("  {}", log);
                     }
 
+                    for breakpoint in &result.breakpoints {
+                        println!(
+                            "  breakpoint `{}` at step {} in {:?} (depth {})",
+                            breakpoint.label, breakpoint.step, breakpoint.address, breakpoint.depth
+                        );
+                    }
+
+                    for prank in &result.pranks {
+                        let selector = prank.input.as_ref().iter().take(4);
+                        let selector = selector.map(|b| format!("{:02x}", b)).collect::<String>();
+                        println!(
+                            "  {} {:?} called {:?} (0x{}) as {:?} (depth {})",
+                            if prank.persistent { "startPrank" } else { "prank" },
+                            prank.pranker,
+                            prank.target,
+                            selector,
+                            prank.sender,
+                            prank.depth
+                        );
+                    }
+
+                    if verbosity > 2 && !result.traces.is_empty() {
+                        println!("  Traces:");
+                        for line in &result.traces {
+                            println!("    {}", line);
+                        }
+                    }
+
                     println!();
                 }
             }
         }
     }
 
-    Ok(TestOutcome::new(results, allow_failure))
+    let mut outcome = TestOutcome::new(results, runner.deployments().clone(), allow_failure);
+    outcome.failed_deployments = runner.failed_deployments().clone();
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(
+        tests: &[(&str, bool, u64)],
+    ) -> BTreeMap<String, BTreeMap<String, forge::TestResult>> {
+        let tests = tests
+            .iter()
+            .map(|(name, success, gas_used)| {
+                let result = forge::TestResult {
+                    success: *success,
+                    reason: None,
+                    gas_used: *gas_used,
+                    counterexample: None,
+                    logs: vec![],
+                    gas_sections: Default::default(),
+                    warnings: vec![],
+                    deprecations: Default::default(),
+                    revert_trace: vec![],
+                    breakpoints: vec![],
+                    pranks: vec![],
+                    accesses: Default::default(),
+                    traces: vec![],
+                    state_diff: vec![],
+                    overflow: None,
+                    debug: Default::default(),
+                    skipped: false,
+                    kind: forge::TestKind::Standard(*gas_used),
+                };
+                (name.to_string(), result)
+            })
+            .collect();
+        BTreeMap::from([("GreeterTest".to_string(), tests)])
+    }
+
+    fn baseline(tests: &[(&str, bool, u64)]) -> Baseline {
+        let tests = tests
+            .iter()
+            .map(|(name, success, gas_used)| {
+                (name.to_string(), BaselineTest { success: *success, gas_used: *gas_used })
+            })
+            .collect();
+        BTreeMap::from([("GreeterTest".to_string(), tests)])
+    }
+
+    #[test]
+    fn diffs_against_baseline() {
+        let baseline = baseline(&[
+            ("testA()", true, 100),
+            ("testB()", false, 100),
+            ("testC()", true, 100),
+            ("testD()", true, 100),
+            ("testRemoved()", true, 100),
+        ]);
+        let results = results(&[
+            ("testA()", false, 100),
+            ("testB()", true, 100),
+            ("testC()", true, 110),
+            ("testD()", true, 90),
+            ("testNew()", false, 100),
+        ]);
+
+        let diff = BaselineDiff::new(&results, &baseline, 0.0);
+        assert_eq!(
+            diff,
+            BaselineDiff {
+                newly_failing: vec!["GreeterTest::testA()".into(), "GreeterTest::testNew()".into()],
+                newly_passing: vec!["GreeterTest::testB()".into()],
+                gas_regressions: vec![("GreeterTest::testC()".into(), 100, 110)],
+                added: vec!["GreeterTest::testNew()".into()],
+                removed: vec!["GreeterTest::testRemoved()".into()],
+            }
+        );
+        assert_eq!(diff.exit_code(), EXIT_NEWLY_FAILING);
+
+        // the gas regression is within the threshold
+        let diff = BaselineDiff::new(&results, &baseline, 10.0);
+        assert!(diff.gas_regressions.is_empty());
+    }
+
+    #[test]
+    fn gas_regressions_have_their_own_exit_code() {
+        let baseline = baseline(&[("testA()", true, 100), ("testB()", false, 100)]);
+        // failures which are already in the baseline don't fail the gate
+        let diff = BaselineDiff::new(
+            &results(&[("testA()", true, 100), ("testB()", false, 100)]),
+            &baseline,
+            0.0,
+        );
+        assert_eq!(diff.exit_code(), 0);
+
+        let diff = BaselineDiff::new(
+            &results(&[("testA()", true, 101), ("testB()", false, 100)]),
+            &baseline,
+            0.0,
+        );
+        assert_eq!(diff.exit_code(), EXIT_GAS_REGRESSION);
+    }
+
+    #[test]
+    fn baseline_gate_goes_through_ensure_ok() {
+        let baseline = baseline(&[("testA()", true, 100), ("testB()", false, 100)]);
+        let mut outcome = TestOutcome::new(
+            results(&[("testA()", true, 101), ("testB()", false, 100)]),
+            Default::default(),
+            false,
+        );
+        outcome.baseline_diff = Some(BaselineDiff::new(&outcome.results, &baseline, 0.0));
+        let err = outcome.ensure_ok().unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&BaselineExit { code: EXIT_GAS_REGRESSION }));
+
+        // the other checks still apply
+        let test_a = outcome.results.get_mut("GreeterTest").unwrap().get_mut("testA()").unwrap();
+        test_a.deprecations = vec!["`roll` is deprecated".to_string()];
+        outcome.deny_deprecated = true;
+        assert!(outcome.ensure_ok().unwrap_err().downcast_ref::<BaselineExit>().is_none());
+    }
+
+    #[test]
+    fn truncates_logs_and_traces() {
+        let limits = OutputLimits { max_trace_depth: 2, max_log_bytes: 7 };
+        let frames = vec!["Outer.a()".to_string(), "Middle.b()".into(), "Inner.c()".into()];
+        assert_eq!(
+            limits.revert_trace(&frames),
+            vec!["at Inner.c()", "at Middle.b()", "… 1 outer frames truncated"]
+        );
+
+        let logs = vec!["hello".to_string(), "wörld".into(), "again".into()];
+        // the cut falls inside of `ö`, which is kept out
+        assert_eq!(
+            limits.logs(&logs),
+            vec!["hello", "w…", "… 10 bytes of logs truncated, use --json to get all of them"]
+        );
+
+        let unlimited = OutputLimits { max_trace_depth: 0, max_log_bytes: 0 };
+        assert_eq!(unlimited.revert_trace(&frames).len(), 3);
+        assert_eq!(unlimited.logs(&logs), logs);
+    }
 }
@@ -7,11 +7,10 @@ use ethers::{
     abi::{Address, Function, FunctionExt},
     core::types::Chain,
     etherscan::{contract::VerifyContract, Client},
-    prelude::Provider,
     providers::Middleware,
 };
+use evm_adapters::runtime;
 use eyre::ContextCompat;
-use std::convert::TryFrom;
 
 /// Run the verify command to submit the contract's source code for verification on etherscan
 pub async fn run(
@@ -22,7 +21,7 @@ pub async fn run(
 ) -> eyre::Result<()> {
     let etherscan_api_key = utils::etherscan_api_key()?;
     let rpc_url = utils::rpc_url();
-    let provider = Provider::try_from(rpc_url)?;
+    let provider = runtime::http_provider(&rpc_url)?;
     let chain = provider
         .get_chainid()
         .await
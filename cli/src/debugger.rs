@@ -0,0 +1,266 @@
+//! Interactive debugger stepping through the opcodes executed by a test
+
+use crossterm::{
+    event::{self, Event, KeyCode},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use evm_adapters::trace::{Breakpoint, DebugStep, DebugTrace};
+use forge::{SourceLocation, SourceLocator};
+use rustc_hex::ToHex;
+use std::{fs, io};
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph},
+    Frame, Terminal,
+};
+
+/// The number of source lines shown around the current one
+const SOURCE_CONTEXT: usize = 6;
+
+/// The keys understood by the debugger
+const HELP: &str = "[j/k] step  [c/C] next/previous call  [b/B] next/previous breakpoint  \
+                    [g/G] first/last step  [q] quit";
+
+/// Steps through the opcodes recorded for a test in the terminal
+pub struct Debugger<'a> {
+    /// The name of the debugged test
+    name: String,
+    trace: &'a DebugTrace,
+    breakpoints: &'a [Breakpoint],
+    locator: &'a SourceLocator,
+    /// The index of the current step
+    current: usize,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(
+        name: String,
+        trace: &'a DebugTrace,
+        breakpoints: &'a [Breakpoint],
+        locator: &'a SourceLocator,
+    ) -> Self {
+        Self { name, trace, breakpoints, locator, current: 0 }
+    }
+
+    /// Takes over the terminal until the user quits
+    pub fn run(mut self) -> eyre::Result<()> {
+        if self.trace.steps.is_empty() {
+            eyre::bail!("no opcodes were recorded for {}", self.name)
+        }
+
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+        let result = self.event_loop(&mut terminal);
+
+        // restore the terminal even if drawing failed
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+        result
+    }
+
+    fn event_loop<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> eyre::Result<()> {
+        loop {
+            terminal.draw(|f| self.draw(f))?;
+            let key = match event::read()? {
+                Event::Key(key) => key.code,
+                _ => continue,
+            };
+            let last = self.trace.steps.len() - 1;
+            self.current = match key {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('j') | KeyCode::Down | KeyCode::Right => (self.current + 1).min(last),
+                KeyCode::Char('k') | KeyCode::Up | KeyCode::Left => self.current.saturating_sub(1),
+                KeyCode::Char('g') | KeyCode::Home => 0,
+                KeyCode::Char('G') | KeyCode::End => last,
+                KeyCode::Char('c') => self.next_call().unwrap_or(self.current),
+                KeyCode::Char('C') => self.previous_call().unwrap_or(self.current),
+                KeyCode::Char('b') => self.next_breakpoint().unwrap_or(self.current),
+                KeyCode::Char('B') => self.previous_breakpoint().unwrap_or(self.current),
+                _ => self.current,
+            };
+        }
+    }
+
+    fn step(&self) -> &DebugStep {
+        &self.trace.steps[self.current]
+    }
+
+    /// The first step after the current one which enters or leaves a call
+    fn next_call(&self) -> Option<usize> {
+        let depth = self.step().depth;
+        (self.current + 1..self.trace.steps.len()).find(|&i| self.trace.steps[i].depth != depth)
+    }
+
+    /// The last step before the current one which was executed in another call
+    fn previous_call(&self) -> Option<usize> {
+        let depth = self.step().depth;
+        (0..self.current).rev().find(|&i| self.trace.steps[i].depth != depth)
+    }
+
+    /// The step of every breakpoint, i.e. the call to the `breakpoint` cheatcode
+    fn breakpoint_steps(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().map(|breakpoint| breakpoint.step.saturating_sub(1))
+    }
+
+    fn next_breakpoint(&self) -> Option<usize> {
+        self.breakpoint_steps().filter(|&step| step > self.current).min()
+    }
+
+    fn previous_breakpoint(&self) -> Option<usize> {
+        self.breakpoint_steps().filter(|&step| step < self.current).max()
+    }
+
+    fn draw<B: Backend>(&self, f: &mut Frame<B>) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Percentage(45),
+                Constraint::Min(8),
+                Constraint::Length(1),
+            ])
+            .split(f.size());
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+            .split(rows[1]);
+        let state = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+            .split(rows[2]);
+
+        self.draw_header(f, rows[0]);
+        self.draw_opcodes(f, columns[0]);
+        self.draw_source(f, columns[1]);
+        self.draw_stack(f, state[0]);
+        self.draw_memory(f, state[1]);
+        f.render_widget(Paragraph::new(HELP), rows[3]);
+    }
+
+    fn draw_header<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let step = self.step();
+        let mut header = format!(
+            "step {}/{}  depth {}  {:?}  gas left {}",
+            self.current + 1,
+            self.trace.steps.len(),
+            step.depth,
+            step.address,
+            step.gas_left
+        );
+        if let Some(breakpoint) =
+            self.breakpoints.iter().find(|breakpoint| breakpoint.step == self.current + 1)
+        {
+            header.push_str(&format!("  breakpoint {:?}", breakpoint.label));
+        }
+        if let Some(access) = storage_access(step) {
+            header.push_str("  ");
+            header.push_str(&access);
+        }
+        let block = Block::default().borders(Borders::ALL).title(self.name.as_str());
+        f.render_widget(Paragraph::new(header).block(block), area);
+    }
+
+    /// The steps around the current one
+    fn draw_opcodes<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        let start = self.current.saturating_sub(height / 2);
+        let end = (start + height).min(self.trace.steps.len());
+        let lines = (start..end)
+            .map(|i| {
+                let step = &self.trace.steps[i];
+                let mut line = format!("{:>6} {}", step.pc, step.mnemonic());
+                if let Some(data) = step.push_data(&self.trace.codes[step.code]) {
+                    line.push_str(&format!(" 0x{}", data.to_hex::<String>()));
+                }
+                Spans::from(Span::styled(line, highlight(i == self.current)))
+            })
+            .collect::<Vec<_>>();
+        let block = Block::default().borders(Borders::ALL).title("Opcodes");
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    /// The source the current opcode was generated from, if its contract is known
+    fn draw_source<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let step = self.step();
+        let location = self.locator.locate_pc(&self.trace.codes[step.code], step.pc);
+        let (title, lines) = match location {
+            Some(location) => (location.to_string(), source_lines(&location)),
+            None => ("Source".to_string(), vec![Spans::from("no source map for this code")]),
+        };
+        let block = Block::default().borders(Borders::ALL).title(title);
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn draw_stack<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let lines = self
+            .step()
+            .stack
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, value)| Spans::from(format!("{:>3}: {:#066x}", i, value)))
+            .collect::<Vec<_>>();
+        let block = Block::default().borders(Borders::ALL).title("Stack (top first)");
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn draw_memory<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let lines = self
+            .trace
+            .memory(self.step())
+            .chunks(32)
+            .enumerate()
+            .map(|(i, word)| Spans::from(format!("{:#06x}: {}", i * 32, word.to_hex::<String>())))
+            .collect::<Vec<_>>();
+        let block = Block::default().borders(Borders::ALL).title("Memory");
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+/// Describes the slot read or written by an `SLOAD` or `SSTORE`
+fn storage_access(step: &DebugStep) -> Option<String> {
+    let mut stack = step.stack.iter().rev();
+    match step.opcode {
+        0x54 => Some(format!("SLOAD {:#x}", stack.next()?)),
+        0x55 => Some(format!("SSTORE {:#x} = {:#x}", stack.next()?, stack.next()?)),
+        _ => None,
+    }
+}
+
+/// The lines around a source location, falling back to the located snippet if the source file
+/// cannot be read
+fn source_lines(location: &SourceLocation) -> Vec<Spans<'static>> {
+    let source = match fs::read_to_string(&location.path) {
+        Ok(source) => source,
+        Err(_) => return vec![Spans::from(location.snippet.clone())],
+    };
+    let start = location.line.saturating_sub(SOURCE_CONTEXT + 1);
+    source
+        .lines()
+        .enumerate()
+        .skip(start)
+        .take(SOURCE_CONTEXT * 2 + 1)
+        .map(|(i, line)| {
+            let line_number = i + 1;
+            Spans::from(Span::styled(
+                format!("{:>5} {}", line_number, line),
+                highlight(line_number == location.line),
+            ))
+        })
+        .collect()
+}
+
+fn highlight(current: bool) -> Style {
+    if current {
+        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default()
+    }
+}
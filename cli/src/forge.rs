@@ -1,8 +1,9 @@
 pub mod cmd;
+mod debugger;
 mod opts;
 mod utils;
 
-use crate::cmd::Cmd;
+use crate::cmd::{test::BaselineExit, Cmd};
 
 use ethers::solc::{remappings::Remapping, Project, ProjectPathsConfig};
 use opts::forge::{Dependency, FullContractInfo, Opts, Subcommands};
@@ -15,6 +16,17 @@ fn main() -> eyre::Result<()> {
     utils::subscriber();
 
     let opts = Opts::from_args();
+    match run(opts) {
+        // everything was cleaned up already, only the exit code of the baseline gate is left
+        Err(err) => match err.downcast_ref::<BaselineExit>() {
+            Some(exit) => std::process::exit(exit.code),
+            None => Err(err),
+        },
+        Ok(()) => Ok(()),
+    }
+}
+
+fn run(opts: Opts) -> eyre::Result<()> {
     match opts.sub {
         Subcommands::Test(cmd) => {
             let outcome = cmd.run()?;
@@ -25,8 +37,12 @@ fn main() -> eyre::Result<()> {
         }
         Subcommands::VerifyContract { contract, address, constructor_args } => {
             let FullContractInfo { path, name } = contract;
-            let rt = tokio::runtime::Runtime::new().expect("could not start tokio rt");
-            rt.block_on(cmd::verify::run(path, name, address, constructor_args))?;
+            evm_adapters::runtime::block_on(cmd::verify::run(
+                path,
+                name,
+                address,
+                constructor_args,
+            ))?;
         }
         Subcommands::Create(cmd) => {
             cmd.run()?;
@@ -121,6 +137,26 @@ fn main() -> eyre::Result<()> {
         Subcommands::Snapshot(cmd) => {
             cmd.run()?;
         }
+        Subcommands::Bench(cmd) => {
+            cmd.run()?;
+        }
+        Subcommands::Cache(cmd) => {
+            cmd.run()?;
+        }
+        Subcommands::Corpus(cmd) => {
+            cmd.run()?;
+        }
+        Subcommands::Coverage(cmd) => {
+            cmd.run()?;
+        }
+        #[cfg(feature = "sputnik-evm")]
+        Subcommands::Replay(cmd) => {
+            cmd.run()?;
+        }
+        #[cfg(feature = "sputnik-evm")]
+        Subcommands::Generate(cmd) => {
+            cmd.run()?;
+        }
     }
 
     Ok(())
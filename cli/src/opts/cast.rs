@@ -1,9 +1,10 @@
-use std::str::FromStr;
+use std::{path::PathBuf, str::FromStr};
 
 use ethers::types::{Address, BlockId, BlockNumber, NameOrAddress, H256};
 use structopt::StructOpt;
 
 use super::EthereumOpts;
+use foundry_utils::DisplayUnits;
 
 #[derive(Debug, StructOpt)]
 #[structopt(about = "Perform Ethereum RPC calls from the comfort of your command line.")]
@@ -46,16 +47,78 @@ pub enum Subcommands {
     ToDec { hexvalue: Option<String> },
     #[structopt(name = "--to-fix")]
     #[structopt(about = "convert integers into fixed point with specified decimals")]
-    ToFix { decimals: Option<u128>, value: Option<String> },
+    ToFix {
+        #[structopt(help = "the number of decimals, or `wad` (18) or `ray` (27)", parse(try_from_str = parse_decimals))]
+        decimals: Option<u128>,
+        value: Option<String>,
+    },
+    #[structopt(name = "--from-fix")]
+    #[structopt(about = "convert fixed point numbers with specified decimals into integers")]
+    FromFix {
+        #[structopt(help = "the number of decimals, or `wad` (18) or `ray` (27)", parse(try_from_str = parse_decimals))]
+        decimals: Option<u128>,
+        value: Option<String>,
+    },
+    #[structopt(name = "--to-q")]
+    #[structopt(
+        about = "convert a decimal number into a binary fixed point number (Q64.96 by default)"
+    )]
+    ToQ {
+        value: Option<String>,
+        #[structopt(long, help = "the number of fractional bits", default_value = "96")]
+        bits: usize,
+    },
+    #[structopt(name = "--from-q")]
+    #[structopt(
+        about = "convert a binary fixed point number (Q64.96 by default) into a decimal number"
+    )]
+    FromQ {
+        value: Option<String>,
+        #[structopt(long, help = "the number of fractional bits", default_value = "96")]
+        bits: usize,
+    },
     #[structopt(name = "--to-uint256")]
     #[structopt(about = "convert a number into uint256 hex string with 0x prefix")]
     ToUint256 { value: Option<String> },
+    #[structopt(name = "--to-rlp")]
+    #[structopt(
+        about = "RLP encodes a hex string or a JSON array of (nested arrays of) hex strings"
+    )]
+    ToRlp { value: String },
+    #[structopt(name = "--from-rlp")]
+    #[structopt(about = "decodes RLP encoded data into JSON")]
+    FromRlp { hexdata: String },
     #[structopt(name = "--to-wei")]
     #[structopt(about = "convert an ETH amount into wei")]
     ToWei { value: Option<String>, unit: Option<String> },
     #[structopt(name = "--from-wei")]
     #[structopt(about = "convert wei into an ETH amount")]
     FromWei { value: Option<String>, unit: Option<String> },
+    #[structopt(name = "tick-to-sqrt-price")]
+    #[structopt(about = "convert a Uniswap v3 tick into its Q64.96 sqrt price")]
+    TickToSqrtPrice {
+        #[structopt(allow_hyphen_values = true)]
+        tick: i32,
+    },
+    #[structopt(name = "sqrt-price-to-tick")]
+    #[structopt(about = "convert a Uniswap v3 Q64.96 sqrt price into the tick at or below it")]
+    SqrtPriceToTick { sqrt_price: String },
+    #[structopt(name = "sqrt-price-to-price")]
+    #[structopt(
+        about = "convert a Uniswap v3 Q64.96 sqrt price into the price of token0 in token1"
+    )]
+    SqrtPriceToPrice {
+        sqrt_price: String,
+        #[structopt(long, help = "the decimals of token0", default_value = "18")]
+        decimals0: u8,
+        #[structopt(long, help = "the decimals of token1", default_value = "18")]
+        decimals1: u8,
+    },
+    #[structopt(name = "decode-packed")]
+    #[structopt(
+        about = "Decodes the values packed into <value> according to <layout>, e.g. \"uint160 sqrtPriceX96,int24 tick\", starting with the lowest bits"
+    )]
+    DecodePacked { layout: String, value: String },
     #[structopt(name = "block")]
     #[structopt(
         about = "Prints information about <block>. If <field> is given, print only the value of that field"
@@ -86,6 +149,13 @@ pub enum Subcommands {
         args: Vec<String>,
         #[structopt(long, env = "ETH_RPC_URL")]
         rpc_url: String,
+        #[structopt(
+            long,
+            help = "run the call locally against a fork of the chain and print every call it made and the storage it changed"
+        )]
+        trace: bool,
+        #[structopt(long, env = "ETH_FROM", help = "the sender of the call when tracing")]
+        from: Option<Address>,
     },
     #[structopt(about = "Pack a signature and an argument list into hexadecimal calldata.")]
     Calldata {
@@ -123,6 +193,16 @@ pub enum Subcommands {
         #[structopt(long, env = "ETH_RPC_URL")]
         rpc_url: String,
     },
+    #[structopt(name = "decode-tx")]
+    #[structopt(
+        about = "Decodes the raw signed transaction <raw-tx> and recovers its sender. If <field> is given, print only the value of that field"
+    )]
+    DecodeTx {
+        raw_tx: String,
+        field: Option<String>,
+        #[structopt(long = "--json", short = "-j")]
+        to_json: bool,
+    },
     #[structopt(name = "send")]
     #[structopt(about = "Publish a transaction signed by <from> to call <to> with <data>")]
     SendTx {
@@ -134,6 +214,11 @@ pub enum Subcommands {
         args: Vec<String>,
         #[structopt(long, env = "CAST_ASYNC")]
         cast_async: bool,
+        #[structopt(
+            long,
+            help = "do not send the transaction, but run it locally against a fork of the chain and print its trace"
+        )]
+        simulate: bool,
         #[structopt(flatten)]
         eth: EthereumOpts,
     },
@@ -154,6 +239,11 @@ pub enum Subcommands {
         who: NameOrAddress,
         #[structopt(short, long, env = "ETH_RPC_URL")]
         rpc_url: String,
+        #[structopt(
+            long,
+            help = "print the balance in the chain's native token, e.g. `1.5 MATIC`, instead of in wei"
+        )]
+        native: bool,
     },
     #[structopt(name = "basefee")]
     #[structopt(about = "Print the basefee of a block")]
@@ -179,6 +269,28 @@ pub enum Subcommands {
         #[structopt(short, long, env = "ETH_RPC_URL")]
         rpc_url: String,
     },
+    #[structopt(name = "implementation")]
+    #[structopt(
+        about = "Prints the implementation of a proxy, detecting EIP-1167, EIP-1967 (incl. beacons), EIP-1822 and OpenZeppelin proxies"
+    )]
+    Implementation {
+        #[structopt(help = "the proxy address", parse(try_from_str = parse_name_or_address))]
+        address: NameOrAddress,
+        #[structopt(long, short = "-B", help = "the block you want to query, can also be earliest/latest/pending", parse(try_from_str = parse_block_id))]
+        block: Option<BlockId>,
+        #[structopt(long, env = "ETH_RPC_URL")]
+        rpc_url: String,
+    },
+    #[structopt(name = "admin")]
+    #[structopt(about = "Prints the admin of an EIP-1967 or OpenZeppelin proxy")]
+    Admin {
+        #[structopt(help = "the proxy address", parse(try_from_str = parse_name_or_address))]
+        address: NameOrAddress,
+        #[structopt(long, short = "-B", help = "the block you want to query, can also be earliest/latest/pending", parse(try_from_str = parse_block_id))]
+        block: Option<BlockId>,
+        #[structopt(long, env = "ETH_RPC_URL")]
+        rpc_url: String,
+    },
     #[structopt(name = "keccak")]
     #[structopt(about = "Keccak-256 hashes arbitrary data")]
     Keccak { data: String },
@@ -196,6 +308,33 @@ pub enum Subcommands {
         )]
         verify: bool,
     },
+    #[structopt(name = "logs")]
+    #[structopt(
+        about = "Prints the decoded logs of an event, e.g. `Transfer(address,address,uint256)`"
+    )]
+    Logs {
+        #[structopt(
+            long,
+            help = "the event signature, parameters are assumed to be indexed in order unless marked `indexed`"
+        )]
+        event: String,
+        #[structopt(long, help = "only show logs emitted by this address")]
+        address: Option<Address>,
+        #[structopt(long, default_value = "0")]
+        from_block: u64,
+        #[structopt(long, help = "the last block to query, the latest one if not given")]
+        to_block: Option<u64>,
+        #[structopt(
+            long,
+            help = "the number of blocks to query per request",
+            default_value = "10000"
+        )]
+        page_size: u64,
+        #[structopt(long = "--json", short = "-j")]
+        to_json: bool,
+        #[structopt(long, env = "ETH_RPC_URL")]
+        rpc_url: String,
+    },
     #[structopt(name = "lookup-address")]
     #[structopt(about = "Returns the name the provided address resolves to")]
     LookupAddress {
@@ -210,12 +349,32 @@ pub enum Subcommands {
         )]
         verify: bool,
     },
-    #[structopt(name = "storage", about = "Show the raw value of a contract's storage slot")]
+    #[structopt(
+        name = "storage",
+        about = "Show the raw value of a contract's storage slot, or its whole decoded state with --layout"
+    )]
     Storage {
         #[structopt(help = "the contract address", parse(try_from_str = parse_name_or_address))]
         address: NameOrAddress,
-        #[structopt(help = "the storage slot number (hex or number)", parse(try_from_str = parse_slot))]
-        slot: H256,
+        #[structopt(
+            help = "the storage slot number (hex or number)",
+            parse(try_from_str = parse_slot),
+            required_unless = "layout"
+        )]
+        slot: Option<H256>,
+        #[structopt(
+            long,
+            help = "a storage layout or an artifact containing one, used to decode every storage variable",
+            conflicts_with = "slot"
+        )]
+        layout: Option<PathBuf>,
+        #[structopt(
+            long = "key",
+            help = "the keys of a mapping to read, as `<variable>=<key>[,<nested key>...]`",
+            parse(try_from_str = parse_mapping_key),
+            requires = "layout"
+        )]
+        keys: Vec<(String, String)>,
         #[structopt(short, long, env = "ETH_RPC_URL")]
         rpc_url: String,
         #[structopt(
@@ -255,6 +414,20 @@ fn parse_block_id(s: &str) -> eyre::Result<BlockId> {
     })
 }
 
+fn parse_decimals(s: &str) -> eyre::Result<u128> {
+    Ok(match s {
+        "wad" => 18,
+        "ray" => 27,
+        s => s.parse()?,
+    })
+}
+
+fn parse_mapping_key(s: &str) -> eyre::Result<(String, String)> {
+    let (name, keys) =
+        s.split_once('=').ok_or_else(|| eyre::eyre!("expected `<variable>=<key>`, got `{}`", s))?;
+    Ok((name.to_string(), keys.to_string()))
+}
+
 fn parse_slot(s: &str) -> eyre::Result<H256> {
     Ok(if s.starts_with("0x") {
         let padded = format!("{:0>64}", s.strip_prefix("0x").unwrap());
@@ -268,4 +441,11 @@ fn parse_slot(s: &str) -> eyre::Result<H256> {
 pub struct Opts {
     #[structopt(subcommand)]
     pub sub: Subcommands,
+    #[structopt(
+        long,
+        global = true,
+        env = "CAST_DISPLAY_UNITS",
+        help = "the unit in which balances, gas prices, the values and fees of `cast tx` and `cast block` fields and the values of simulated calls are printed: wei, gwei or ether, which is named after the chain's native token (e.g. MATIC on Polygon), optionally with the number of decimals to round to, e.g. `gwei:2`. Defaults to the `display_units` of the `foundry.toml` in the current directory, or to wei"
+    )]
+    pub display_units: Option<DisplayUnits>,
 }
@@ -3,7 +3,7 @@ use structopt::StructOpt;
 use ethers::types::Address;
 use std::{path::PathBuf, str::FromStr};
 
-use crate::cmd::{build::BuildArgs, create, snapshot, test};
+use crate::cmd::{bench, build::BuildArgs, cache, corpus, coverage, create, snapshot, test};
 
 #[derive(Debug, StructOpt)]
 pub struct Opts {
@@ -94,6 +94,38 @@ pub enum Subcommands {
 
     #[structopt(about = "creates a snapshot of each test's gas usage")]
     Snapshot(snapshot::SnapshotArgs),
+
+    #[structopt(
+        about = "repeatedly runs `bench*` functions and reports gas & time statistics. `--baseline` only compares their mean gas"
+    )]
+    Bench(bench::BenchArgs),
+
+    #[structopt(
+        about = "inspects and garbage collects the build artifacts and the fork RPC cache"
+    )]
+    Cache(cache::CacheArgs),
+
+    #[structopt(
+        about = "harvests the calldata of a contract's transactions in a range of blocks into a corpus which seeds the fuzz tests"
+    )]
+    Corpus(corpus::CorpusArgs),
+
+    #[structopt(
+        about = "runs the tests and reports the line, branch and function coverage of the sources, also written as LCOV"
+    )]
+    Coverage(coverage::CoverageArgs),
+
+    #[cfg(feature = "sputnik-evm")]
+    #[structopt(
+        about = "re-executes a range of historical blocks locally and reports every transaction whose status or gas used differs from its receipt"
+    )]
+    Replay(crate::cmd::replay::ReplayArgs),
+
+    #[cfg(feature = "sputnik-evm")]
+    #[structopt(
+        about = "generates code from forge's own definitions, e.g. the cheatcodes interface"
+    )]
+    Generate(crate::cmd::generate::GenerateArgs),
 }
 
 /// Represents the common dapp argument pattern for `<path>:<contractname>` where `<path>:` is
@@ -117,6 +149,30 @@ impl FromStr for ContractInfo {
     }
 }
 
+/// A deployed library to link against, given as `<path>:<libraryname>:<address>` where `<path>:`
+/// is optional.
+#[derive(Clone, Debug)]
+pub struct LibraryInfo {
+    /// The library contract
+    pub contract: ContractInfo,
+    /// The address at which the library is deployed
+    pub address: Address,
+}
+
+impl FromStr for LibraryInfo {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (contract, address) = s
+            .rsplit_once(':')
+            .ok_or_else(|| eyre::eyre!("Expected `<path>:<libraryname>:<address>`, got `{}`", s))?;
+        let address = address
+            .parse()
+            .map_err(|_| eyre::eyre!("Invalid address `{}` of library `{}`", address, contract))?;
+        Ok(Self { contract: contract.parse()?, address })
+    }
+}
+
 /// Represents the common dapp argument pattern `<path>:<contractname>`
 #[derive(Clone, Debug)]
 pub struct FullContractInfo {
@@ -194,6 +250,21 @@ impl FromStr for Dependency {
 mod tests {
     use super::*;
 
+    #[test]
+    fn parses_libraries() {
+        let lib =
+            LibraryInfo::from_str("src/Math.sol:Math:0x0000000000000000000000000000000000000001")
+                .unwrap();
+        assert_eq!(lib.contract.path.as_deref(), Some("src/Math.sol"));
+        assert_eq!(lib.contract.name, "Math");
+        assert_eq!(lib.address, Address::from_low_u64_be(1));
+
+        let lib = LibraryInfo::from_str("Math:0x0000000000000000000000000000000000000001").unwrap();
+        assert_eq!(lib.contract.path, None);
+        assert!(LibraryInfo::from_str("Math").is_err());
+        assert!(LibraryInfo::from_str("Math:0x01zz").is_err());
+    }
+
     #[test]
     fn parses_dependencies() {
         [
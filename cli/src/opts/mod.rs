@@ -1,7 +1,7 @@
 pub mod cast;
 pub mod forge;
 
-use std::{convert::TryFrom, str::FromStr};
+use std::str::FromStr;
 
 use ethers::{
     middleware::SignerMiddleware,
@@ -25,12 +25,25 @@ pub struct EthereumOpts {
 
     #[structopt(flatten)]
     pub wallet: Wallet,
+
+    #[structopt(
+        long = "no-links",
+        alias = "offline",
+        env = "FOUNDRY_NO_LINKS",
+        help = "do not print block explorer links to transactions and addresses"
+    )]
+    pub no_links: bool,
 }
 
 impl EthereumOpts {
+    /// The id of the chain whose block explorer should be linked to, if links are enabled
+    pub fn links(&self, chain_id: U256) -> Option<u64> {
+        (!self.no_links).then(|| chain_id.as_u64())
+    }
+
     #[allow(unused)]
     pub async fn signer(&self, chain_id: U256) -> eyre::Result<Option<WalletType>> {
-        self.signer_with(chain_id, Provider::try_from(self.rpc_url.as_str())?).await
+        self.signer_with(chain_id, evm_adapters::runtime::http_provider(&self.rpc_url)?).await
     }
 
     /// Returns a [`SignerMiddleware`] corresponding to the provided private key, mnemonic or hw
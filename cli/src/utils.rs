@@ -1,7 +1,11 @@
-use ethers::solc::{artifacts::Contract, EvmVersion};
+use ethers::solc::{
+    artifacts::Contract, cache::SolFilesCache, EvmVersion, MinimalCombinedArtifacts, Project,
+};
 
 use eyre::{ContextCompat, WrapErr};
+use foundry_utils::DisplayUnits;
 use std::{
+    collections::BTreeMap,
     env::VarError,
     path::{Path, PathBuf},
     process::Command,
@@ -9,6 +13,8 @@ use std::{
 
 #[cfg(feature = "evmodin-evm")]
 use evmodin::Revision;
+#[cfg(feature = "revm-evm")]
+use revm::SpecId;
 #[cfg(feature = "sputnik-evm")]
 use sputnik::Config;
 
@@ -18,6 +24,9 @@ const LOCAL_RPC_URL: &str = "http://127.0.0.1:8545";
 /// Default Path to where the contract artifacts are stored
 pub const DAPP_JSON: &str = "./out/dapp.sol.json";
 
+/// The config file in the project root
+pub const FOUNDRY_TOML: &str = "foundry.toml";
+
 /// Initializes a tracing Subscriber for logging
 #[allow(dead_code)]
 pub fn subscriber() {
@@ -50,11 +59,267 @@ pub fn rpc_url() -> String {
     std::env::var("ETH_RPC_URL").unwrap_or_else(|_| LOCAL_RPC_URL.to_string())
 }
 
+/// The profile of the project's `foundry.toml` selected with `FOUNDRY_PROFILE`
+/// [default: `default`]
+fn selected_profile() -> String {
+    std::env::var("FOUNDRY_PROFILE").unwrap_or_else(|_| "default".to_string())
+}
+
+/// Reads the `key` of the `[profile.<name>]` tables in the project's `foundry.toml`, for the
+/// default profile and the given profile, in that order. Returns no values if there is no
+/// `foundry.toml`
+fn config_values(
+    root: impl AsRef<Path>,
+    profile: &str,
+    key: &str,
+) -> eyre::Result<Vec<(String, toml::Value)>> {
+    let path = root.as_ref().join(FOUNDRY_TOML);
+    let config = match std::fs::read_to_string(&path) {
+        Ok(config) => config,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).wrap_err_with(|| format!("failed to read {}", path.display())),
+    };
+    let config: toml::Value =
+        config.parse().wrap_err_with(|| format!("failed to parse {}", path.display()))?;
+
+    let mut profiles = vec!["default"];
+    if profile != "default" {
+        profiles.push(profile);
+    }
+
+    Ok(profiles
+        .into_iter()
+        .filter_map(|name| {
+            let value = config.get("profile")?.get(name)?.get(key)?;
+            Some((name.to_string(), value.clone()))
+        })
+        .collect())
+}
+
+/// Reads the `[profile.<name>.<table>]` tables like [`config_values`]
+fn config_tables(
+    root: impl AsRef<Path>,
+    profile: &str,
+    table: &str,
+) -> eyre::Result<Vec<(String, toml::value::Table)>> {
+    config_values(root, profile, table)?
+        .into_iter()
+        .map(|(name, value)| match value {
+            toml::Value::Table(values) => Ok((name, values)),
+            _ => eyre::bail!("`profile.{}.{}` in {} is not a table", name, table, FOUNDRY_TOML),
+        })
+        .collect()
+}
+
+/// Reads the environment variables of the `[profile.<name>.env]` table in the project's
+/// `foundry.toml`, for the profile selected with `FOUNDRY_PROFILE` [default: `default`].
+///
+/// Every other profile inherits the variables of the default profile and can override them.
+/// Returns no variables if there is no `foundry.toml`
+pub fn config_env(root: impl AsRef<Path>) -> eyre::Result<BTreeMap<String, String>> {
+    profile_env(root, &selected_profile())
+}
+
+/// Sets the environment variables of [`config_env`], except for the ones which are already set
+/// explicitly
+pub fn set_config_env(root: impl AsRef<Path>) -> eyre::Result<()> {
+    for (key, value) in config_env(root)? {
+        if std::env::var_os(&key).is_none() {
+            std::env::set_var(key, value);
+        }
+    }
+    Ok(())
+}
+
+/// Reads the environment variables of the given profile, see [`config_env`]
+fn profile_env(root: impl AsRef<Path>, profile: &str) -> eyre::Result<BTreeMap<String, String>> {
+    let mut env = BTreeMap::new();
+    for (name, vars) in config_tables(root, profile, "env")? {
+        for (key, value) in vars {
+            // env cheatcodes parse numbers and booleans from strings anyway
+            let value = match value {
+                toml::Value::String(value) => value,
+                toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                    value.to_string()
+                }
+                _ => eyre::bail!(
+                    "`profile.{}.env.{}` in {} must be a string, number or boolean",
+                    name,
+                    key,
+                    FOUNDRY_TOML
+                ),
+            };
+            env.insert(key, value);
+        }
+    }
+    Ok(env)
+}
+
+/// The RPC request budget of the `[profile.<name>.rpc]` table in the project's `foundry.toml`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RpcConfig {
+    /// How many requests may be in flight at once across all endpoints
+    pub max_concurrent_requests: Option<usize>,
+    /// The maximum requests per second of endpoints by their host
+    pub rate_limits: BTreeMap<String, f64>,
+}
+
+/// Reads the RPC request budget, e.g.
+///
+/// ```toml
+/// [profile.default.rpc]
+/// max_concurrent_requests = 8
+/// rate_limits = { "mainnet.infura.io" = 10 }
+/// ```
+///
+/// Profiles inherit the settings of the default profile like in [`config_env`]
+pub fn config_rpc(root: impl AsRef<Path>) -> eyre::Result<RpcConfig> {
+    profile_rpc(root, &selected_profile())
+}
+
+/// Reads the RPC request budget of the given profile, see [`config_rpc`]
+fn profile_rpc(root: impl AsRef<Path>, profile: &str) -> eyre::Result<RpcConfig> {
+    let mut config = RpcConfig::default();
+    for (name, rpc) in config_tables(root, profile, "rpc")? {
+        if let Some(max) = rpc.get("max_concurrent_requests") {
+            let max = max.as_integer().filter(|max| *max > 0).wrap_err_with(|| {
+                format!(
+                    "`profile.{}.rpc.max_concurrent_requests` in {} must be a positive integer",
+                    name, FOUNDRY_TOML
+                )
+            })?;
+            config.max_concurrent_requests = Some(max as usize);
+        }
+        let limits = match rpc.get("rate_limits") {
+            Some(limits) => limits.as_table().wrap_err_with(|| {
+                format!("`profile.{}.rpc.rate_limits` in {} is not a table", name, FOUNDRY_TOML)
+            })?,
+            None => continue,
+        };
+        for (host, limit) in limits {
+            let limit = match limit {
+                toml::Value::Integer(limit) => Some(*limit as f64),
+                toml::Value::Float(limit) => Some(*limit),
+                _ => None,
+            };
+            // a limit of 0 would stall every request to the host
+            let limit = limit.filter(|limit| *limit > 0.0).wrap_err_with(|| {
+                format!(
+                    "`profile.{}.rpc.rate_limits.{}` in {} must be a positive number of requests \
+                     per second",
+                    name, host, FOUNDRY_TOML
+                )
+            })?;
+            config.rate_limits.insert(host.clone(), limit);
+        }
+    }
+    Ok(config)
+}
+
+/// Reads the unit in which values are displayed, e.g.
+///
+/// ```toml
+/// [profile.default]
+/// display_units = "gwei:2"
+/// ```
+///
+/// Profiles inherit the unit of the default profile like in [`config_env`]
+pub fn config_display_units(root: impl AsRef<Path>) -> eyre::Result<Option<DisplayUnits>> {
+    profile_display_units(root, &selected_profile())
+}
+
+/// Reads the unit in which values are displayed of the given profile, see
+/// [`config_display_units`]
+fn profile_display_units(
+    root: impl AsRef<Path>,
+    profile: &str,
+) -> eyre::Result<Option<DisplayUnits>> {
+    let mut units = None;
+    for (name, value) in config_values(root, profile, "display_units")? {
+        let value = value.as_str().and_then(|value| value.parse().ok()).wrap_err_with(|| {
+            format!(
+                "`profile.{}.display_units` in {} must be a unit like `ether` or `gwei:2`",
+                name, FOUNDRY_TOML
+            )
+        })?;
+        units = Some(value);
+    }
+    Ok(units)
+}
+
+/// Reads whether a test reverts as soon as one of its ds-test assertions fails, e.g.
+///
+/// ```toml
+/// [profile.default]
+/// abort_on_failed_assertion = true
+/// ```
+///
+/// Profiles inherit the setting of the default profile like in [`config_env`]. Returns `false`
+/// if it is not set
+pub fn config_abort_on_failed_assertion(root: impl AsRef<Path>) -> eyre::Result<bool> {
+    profile_abort_on_failed_assertion(root, &selected_profile())
+}
+
+/// Reads whether a test reverts on its first failed assertion for the given profile, see
+/// [`config_abort_on_failed_assertion`]
+fn profile_abort_on_failed_assertion(root: impl AsRef<Path>, profile: &str) -> eyre::Result<bool> {
+    let mut abort = false;
+    for (name, value) in config_values(root, profile, "abort_on_failed_assertion")? {
+        abort = value.as_bool().wrap_err_with(|| {
+            format!(
+                "`profile.{}.abort_on_failed_assertion` in {} must be a boolean",
+                name, FOUNDRY_TOML
+            )
+        })?;
+    }
+    Ok(abort)
+}
+
 /// The path to where the contract artifacts are stored
 pub fn dapp_json_path() -> PathBuf {
     PathBuf::from(DAPP_JSON)
 }
 
+/// A compiled contract along with the source file it is defined in
+#[derive(Clone, Debug)]
+pub struct CompiledContract {
+    /// The path of the source file, as passed to solc
+    pub source: PathBuf,
+    /// The name of the contract
+    pub name: String,
+    pub artifact: MinimalCombinedArtifacts,
+}
+
+impl CompiledContract {
+    /// The fully qualified name of the contract, `<source>:<name>`
+    pub fn id(&self) -> String {
+        format!("{}:{}", self.source.display(), self.name)
+    }
+}
+
+/// Reads the artifacts of the last compilation of the project, using the cache to find out in
+/// which source file each contract is defined
+pub fn read_artifacts(project: &Project) -> eyre::Result<Vec<CompiledContract>> {
+    let cache = SolFilesCache::read(&project.paths.cache)?;
+    // artifacts are written to `<artifacts>/<name>.json`
+    let artifacts = cache
+        .read_artifacts::<MinimalCombinedArtifacts>(project.artifacts_path())?
+        .into_iter()
+        .filter_map(|(path, artifact)| Some((path.file_stem()?.to_str()?.to_string(), artifact)))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut contracts = Vec::new();
+    for (source, entry) in cache.files {
+        for name in entry.artifacts {
+            if let Some(artifact) = artifacts.get(&name) {
+                let artifact = artifact.clone();
+                contracts.push(CompiledContract { source: source.clone(), name, artifact });
+            }
+        }
+    }
+    Ok(contracts)
+}
+
 /// Tries to extract the `Contract` in the `DAPP_JSON` file
 pub fn find_dapp_json_contract(path: &str, name: &str) -> eyre::Result<Contract> {
     let dapp_json = dapp_json_path();
@@ -141,3 +406,173 @@ pub fn evmodin_cfg(evm: EvmVersion) -> Revision {
         _ => panic!("Unsupported EVM version"),
     }
 }
+
+#[cfg(feature = "revm-evm")]
+pub fn revm_cfg(evm: EvmVersion) -> SpecId {
+    match evm {
+        EvmVersion::Istanbul => SpecId::ISTANBUL,
+        EvmVersion::Berlin => SpecId::BERLIN,
+        EvmVersion::London => SpecId::LONDON,
+        _ => panic!("Unsupported EVM version"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    /// Creates a project root whose `foundry.toml` has the given content
+    fn root_with_config(config: &str) -> TempDir {
+        let root = TempDir::new("foundry-config").unwrap();
+        std::fs::write(root.path().join(FOUNDRY_TOML), config).unwrap();
+        root
+    }
+
+    #[test]
+    fn profiles_inherit_the_default_env() {
+        let root = root_with_config(
+            r#"
+            [profile.default.env]
+            A = "a"
+            B = 1
+            C = true
+
+            [profile.ci.env]
+            B = 2.5
+            D = "d"
+            "#,
+        );
+        let env = |profile| profile_env(root.path(), profile).unwrap();
+        let default = env("default");
+        assert_eq!(default["A"], "a");
+        assert_eq!(default["B"], "1");
+        assert_eq!(default["C"], "true");
+        assert!(!default.contains_key("D"));
+
+        let ci = env("ci");
+        assert_eq!(ci["A"], "a");
+        assert_eq!(ci["B"], "2.5");
+        assert_eq!(ci["D"], "d");
+
+        // profiles without an `env` table only have the default variables
+        assert_eq!(env("other"), default);
+    }
+
+    #[test]
+    fn rejects_invalid_env() {
+        let root = root_with_config("[profile.default.env]\nA = [1]\n");
+        assert!(profile_env(root.path(), "default").is_err());
+        let root = root_with_config("[profile.ci]\nenv = 1\n");
+        assert!(profile_env(root.path(), "ci").is_err());
+        // an invalid table of a profile which is not selected is ignored
+        assert!(profile_env(root.path(), "default").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_no_env_without_config() {
+        let root = TempDir::new("foundry-config").unwrap();
+        assert!(profile_env(root.path(), "default").unwrap().is_empty());
+    }
+
+    #[test]
+    fn does_not_override_set_env() {
+        let root = root_with_config(
+            r#"
+            [profile.default.env]
+            FOUNDRY_TEST_CONFIG_ENV_SET = "config"
+            FOUNDRY_TEST_CONFIG_ENV_UNSET = "config"
+            "#,
+        );
+        std::env::set_var("FOUNDRY_TEST_CONFIG_ENV_SET", "explicit");
+        set_config_env(root.path()).unwrap();
+        assert_eq!(std::env::var("FOUNDRY_TEST_CONFIG_ENV_SET").unwrap(), "explicit");
+        assert_eq!(std::env::var("FOUNDRY_TEST_CONFIG_ENV_UNSET").unwrap(), "config");
+    }
+
+    #[test]
+    fn profiles_inherit_the_default_rpc_config() {
+        let root = root_with_config(
+            r#"
+            [profile.default.rpc]
+            max_concurrent_requests = 8
+            rate_limits = { "mainnet.infura.io" = 10, "rpc.ankr.com" = 5 }
+
+            [profile.ci.rpc]
+            rate_limits = { "mainnet.infura.io" = 2.5 }
+            "#,
+        );
+        let default = profile_rpc(root.path(), "default").unwrap();
+        assert_eq!(default.max_concurrent_requests, Some(8));
+        assert_eq!(default.rate_limits["mainnet.infura.io"], 10.0);
+
+        let ci = profile_rpc(root.path(), "ci").unwrap();
+        assert_eq!(ci.max_concurrent_requests, Some(8));
+        assert_eq!(ci.rate_limits["mainnet.infura.io"], 2.5);
+        assert_eq!(ci.rate_limits["rpc.ankr.com"], 5.0);
+
+        let root = TempDir::new("foundry-config").unwrap();
+        assert_eq!(profile_rpc(root.path(), "default").unwrap(), RpcConfig::default());
+    }
+
+    #[test]
+    fn rejects_invalid_rpc_config() {
+        let root = root_with_config("[profile.default.rpc]\nmax_concurrent_requests = 0\n");
+        assert!(profile_rpc(root.path(), "default").is_err());
+        let root = root_with_config("[profile.default.rpc]\nrate_limits = 10\n");
+        assert!(profile_rpc(root.path(), "default").is_err());
+        for limit in ["\"fast\"", "0", "0.0", "-1", "-2.5"] {
+            let config = format!("[profile.default.rpc.rate_limits]\n\"a.io\" = {}\n", limit);
+            let err = profile_rpc(root_with_config(&config).path(), "default").unwrap_err();
+            assert!(err.to_string().contains("must be a positive number"), "{}: {}", limit, err);
+        }
+    }
+
+    #[test]
+    fn profiles_inherit_the_default_display_units() {
+        let root = root_with_config(
+            r#"
+            [profile.default]
+            display_units = "gwei:2"
+
+            [profile.ci]
+            display_units = "ether"
+            "#,
+        );
+        let units = |profile| profile_display_units(root.path(), profile).unwrap().unwrap();
+        assert_eq!(units("default"), "gwei:2".parse().unwrap());
+        assert_eq!(units("ci"), "ether".parse().unwrap());
+        assert_eq!(units("other"), units("default"));
+
+        let root = TempDir::new("foundry-config").unwrap();
+        assert_eq!(profile_display_units(root.path(), "default").unwrap(), None);
+
+        let root = root_with_config("[profile.default]\ndisplay_units = \"lots\"\n");
+        assert!(profile_display_units(root.path(), "default").is_err());
+        let root = root_with_config("[profile.default]\ndisplay_units = 2\n");
+        assert!(profile_display_units(root.path(), "default").is_err());
+    }
+
+    #[test]
+    fn profiles_inherit_abort_on_failed_assertion() {
+        let root = root_with_config(
+            r#"
+            [profile.default]
+            abort_on_failed_assertion = true
+
+            [profile.ci]
+            abort_on_failed_assertion = false
+            "#,
+        );
+        let abort = |profile| profile_abort_on_failed_assertion(root.path(), profile).unwrap();
+        assert!(abort("default"));
+        assert!(!abort("ci"));
+        assert!(abort("other"));
+
+        let root = TempDir::new("foundry-config").unwrap();
+        assert!(!profile_abort_on_failed_assertion(root.path(), "default").unwrap());
+
+        let root = root_with_config("[profile.default]\nabort_on_failed_assertion = \"yes\"\n");
+        assert!(profile_abort_on_failed_assertion(root.path(), "default").is_err());
+    }
+}
@@ -1,55 +1,32 @@
+use crate::runtime::{self, Throttle};
 use ethers::{
     prelude::BlockNumber,
     providers::Middleware,
     types::{Address, Block, BlockId, Bytes, TxHash, H256, U256, U64},
 };
-use tokio::runtime::{Handle, Runtime};
+use std::sync::Arc;
 
-#[allow(clippy::large_enum_variant)]
-#[derive(Debug)]
-pub enum RuntimeOrHandle {
-    Runtime(Runtime),
-    Handle(Handle),
-}
-
-impl RuntimeOrHandle {
-    pub fn new() -> RuntimeOrHandle {
-        match Handle::try_current() {
-            Ok(handle) => RuntimeOrHandle::Handle(handle),
-            Err(_) => RuntimeOrHandle::Runtime(Runtime::new().expect("Failed to start runtime")),
-        }
-    }
-}
-
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 /// Blocking wrapper around an Ethers middleware, for use in synchronous contexts
-/// (powered by a tokio runtime)
+/// (powered by the shared tokio runtime). Requests time out and are retried as configured in
+/// [`runtime`]
 pub struct BlockingProvider<M> {
     provider: M,
-    runtime: RuntimeOrHandle,
-}
-
-impl<M: Clone> Clone for BlockingProvider<M> {
-    fn clone(&self) -> Self {
-        Self { provider: self.provider.clone(), runtime: RuntimeOrHandle::new() }
-    }
+    throttle: Arc<Throttle>,
 }
 
 impl<M: Middleware> BlockingProvider<M>
 where
     M::Error: 'static,
 {
-    /// Constructs the provider. If no tokio runtime exists, it instantiates one as well.
+    /// Constructs the provider.
     pub fn new(provider: M) -> Self {
-        Self { provider, runtime: RuntimeOrHandle::new() }
+        Self::new_throttled(provider, runtime::throttle(None))
     }
 
-    /// Receives a future and runs it to completion.
-    fn block_on<F: std::future::Future>(&self, f: F) -> F::Output {
-        match &self.runtime {
-            RuntimeOrHandle::Runtime(runtime) => runtime.block_on(f),
-            RuntimeOrHandle::Handle(handle) => tokio::task::block_in_place(|| handle.block_on(f)),
-        }
+    /// Constructs the provider, sending its requests within the budget of its endpoint.
+    pub fn new_throttled(provider: M, throttle: Arc<Throttle>) -> Self {
+        Self { provider, throttle }
     }
 
     /// Gets the specified block as well as the chain id concurrently.
@@ -58,12 +35,13 @@ where
         block_id: Option<impl Into<BlockId>>,
     ) -> eyre::Result<(Block<TxHash>, U256)> {
         let block_id = block_id.map(Into::into).unwrap_or(BlockId::Number(BlockNumber::Latest));
-        let f = async {
-            let block = self.provider.get_block(block_id);
-            let chain_id = self.provider.get_chainid();
+        let provider = &self.provider;
+        let f = runtime::retry(&self.throttle, || async move {
+            let block = provider.get_block(block_id);
+            let chain_id = provider.get_chainid();
             tokio::try_join!(block, chain_id)
-        };
-        let (block, chain_id) = self.block_on(f)?;
+        });
+        let (block, chain_id) = runtime::block_on(f)?;
         Ok((block.ok_or_else(|| eyre::eyre!("block {:?} not found", block_id))?, chain_id))
     }
 
@@ -73,25 +51,36 @@ where
         address: Address,
         block_id: Option<BlockId>,
     ) -> eyre::Result<(U256, U256, Bytes)> {
-        let f = async {
-            let balance = self.provider.get_balance(address, block_id);
-            let nonce = self.provider.get_transaction_count(address, block_id);
-            let code = self.provider.get_code(address, block_id);
+        let provider = &self.provider;
+        let f = runtime::retry(&self.throttle, || async move {
+            let balance = provider.get_balance(address, block_id);
+            let nonce = provider.get_transaction_count(address, block_id);
+            let code = provider.get_code(address, block_id);
             tokio::try_join!(balance, nonce, code)
-        };
-        let (balance, nonce, code) = self.block_on(f)?;
+        });
+        let (balance, nonce, code) = runtime::block_on(f)?;
 
         Ok((nonce, balance, code))
     }
 
+    /// Gets the hash of the block with the number, if it exists.
+    pub fn get_block_hash(&self, number: u64) -> eyre::Result<Option<H256>> {
+        let block = BlockId::Number(BlockNumber::Number(number.into()));
+        let block =
+            runtime::block_on(runtime::retry(&self.throttle, || self.provider.get_block(block)))?;
+        Ok(block.and_then(|block| block.hash))
+    }
+
     /// Gets the current block number.
-    pub fn get_block_number(&self) -> Result<U64, M::Error> {
-        self.block_on(self.provider.get_block_number())
+    pub fn get_block_number(&self) -> eyre::Result<U64> {
+        runtime::block_on(runtime::retry(&self.throttle, || self.provider.get_block_number()))
     }
 
     /// Gets the account's balance at the specified block.
-    pub fn get_balance(&self, address: Address, block: Option<BlockId>) -> Result<U256, M::Error> {
-        self.block_on(self.provider.get_balance(address, block))
+    pub fn get_balance(&self, address: Address, block: Option<BlockId>) -> eyre::Result<U256> {
+        runtime::block_on(runtime::retry(&self.throttle, || {
+            self.provider.get_balance(address, block)
+        }))
     }
 
     /// Gets the account's nonce at the specified block.
@@ -99,13 +88,15 @@ where
         &self,
         address: Address,
         block: Option<BlockId>,
-    ) -> Result<U256, M::Error> {
-        self.block_on(self.provider.get_transaction_count(address, block))
+    ) -> eyre::Result<U256> {
+        runtime::block_on(runtime::retry(&self.throttle, || {
+            self.provider.get_transaction_count(address, block)
+        }))
     }
 
     /// Gets the account's code at the specified block.
-    pub fn get_code(&self, address: Address, block: Option<BlockId>) -> Result<Bytes, M::Error> {
-        self.block_on(self.provider.get_code(address, block))
+    pub fn get_code(&self, address: Address, block: Option<BlockId>) -> eyre::Result<Bytes> {
+        runtime::block_on(runtime::retry(&self.throttle, || self.provider.get_code(address, block)))
     }
 
     /// Gets the value at the specified storage slot & block.
@@ -114,7 +105,9 @@ where
         address: Address,
         slot: H256,
         block: Option<BlockId>,
-    ) -> Result<H256, M::Error> {
-        self.block_on(self.provider.get_storage_at(address, slot, block))
+    ) -> eyre::Result<H256> {
+        runtime::block_on(runtime::retry(&self.throttle, || {
+            self.provider.get_storage_at(address, slot, block)
+        }))
     }
 }
@@ -1,6 +1,9 @@
 use crate::Evm;
 
-use ethers::types::{Address, Bytes, U256};
+use ethers::{
+    types::{Address, Bytes, U256},
+    utils::get_contract_address,
+};
 
 use evmodin::{tracing::Tracer, AnalyzedCode, CallKind, Host, Message, Revision, StatusCode};
 
@@ -9,6 +12,10 @@ use eyre::Result;
 // TODO: Check if we can implement this as the base layer of an ethers-provider
 // Middleware stack instead of doing RPC calls.
 /// Wrapper around EVModin which implements the [Evm](`crate::Evm`) trait
+///
+/// Only the EVM itself is supported: there are no cheatcodes, no console logs, no forking and
+/// none of the traces, so the contracts are executed like on chain. Whether calls to other
+/// contracts work depends on the host.
 #[derive(Clone, Debug)]
 pub struct EvmOdin<S, T> {
     pub host: S,
@@ -34,6 +41,8 @@ pub trait HostExt: Host {
     fn set_code(&mut self, address: Address, code: bytes::Bytes);
     /// Sets the account's balance to the provided value.
     fn set_balance(&mut self, address: Address, balance: U256);
+    /// Increments the account's nonce, returning the nonce before.
+    fn increment_nonce(&mut self, address: Address) -> u64;
 }
 
 impl<S: HostExt, Tr: Tracer> Evm<S> for EvmOdin<S, Tr> {
@@ -59,6 +68,10 @@ impl<S: HostExt, Tr: Tracer> Evm<S> for EvmOdin<S, Tr> {
         self.host.set_balance(address, balance)
     }
 
+    fn code(&self, address: Address) -> Bytes {
+        self.host.get_code(&address).cloned().map(Bytes).unwrap_or_default()
+    }
+
     fn reset(&mut self, state: S) {
         self.host = state;
     }
@@ -73,14 +86,45 @@ impl<S: HostExt, Tr: Tracer> Evm<S> for EvmOdin<S, Tr> {
         &self.host
     }
 
-    #[allow(unused)]
     fn deploy(
         &mut self,
         from: Address,
         calldata: Bytes,
         value: U256,
     ) -> Result<(Address, Self::ReturnReason, u64, Vec<String>)> {
-        unimplemented!("Contract deployment is not implemented for evmodin yet")
+        // the host only moves the value of the calls made by contracts
+        if !value.is_zero() {
+            eyre::bail!("evmodin cannot deploy contracts with a value, got {}", value)
+        }
+        let address = get_contract_address(from, self.host.increment_nonce(from));
+
+        let message = Message {
+            sender: from,
+            recipient: address,
+            code_address: address,
+            depth: 0,
+            kind: CallKind::Create,
+            input_data: Default::default(),
+            value,
+            gas: self.gas_limit as i64,
+            is_static: false,
+        };
+
+        // the init code returns the code of the deployed contract
+        let bytecode = AnalyzedCode::analyze(calldata.as_ref());
+        let output =
+            bytecode.execute(&mut self.host, &mut self.tracer, None, message, self.revision);
+        let gas = self.gas_limit - output.gas_left as u64;
+
+        if !Self::is_success(&output.status_code) {
+            tracing::trace!(status = ?output.status_code, "failed");
+            eyre::bail!("deployment reverted, reason: {:?}", output.status_code)
+        }
+        tracing::trace!(status = ?output.status_code, ?address, ?gas, "success");
+        self.host.set_code(address, output.output_data);
+
+        // TODO: Add emitted event logs.
+        Ok((address, output.status_code, gas, vec![]))
     }
 
     /// Runs the selected function
@@ -146,6 +190,12 @@ mod helpers {
             let entry = self.accounts.entry(address).or_insert_with(Default::default);
             entry.balance = amount;
         }
+
+        fn increment_nonce(&mut self, address: Address) -> u64 {
+            let entry = self.accounts.entry(address).or_insert_with(Default::default);
+            entry.nonce += 1;
+            entry.nonce - 1
+        }
     }
 }
 
@@ -156,8 +206,6 @@ mod tests {
     use evmodin::{tracing::NoopTracer, util::mocked_host::MockedHost};
 
     #[test]
-    #[ignore]
-    // TODO: Ignore until we figure out how to deploy stuff in evmodin
     fn evmodin_can_call_vm_directly() {
         let revision = Revision::Istanbul;
         let compiled = COMPILED.find("Greeter").expect("could not find contract");
@@ -170,6 +218,26 @@ mod tests {
         can_call_vm_directly(evm, compiled);
     }
 
+    #[test]
+    fn evmodin_deploys_contracts() {
+        let compiled = COMPILED.find("Greeter").expect("could not find contract");
+        let bytecode = compiled.bytecode().unwrap().clone();
+        let host = MockedHost::default();
+        let mut evm = EvmOdin::new(host, 12_000_000, Revision::Istanbul, NoopTracer);
+
+        let from = Address::repeat_byte(1);
+        let (first, _, gas, _) = evm.deploy(from, bytecode.clone(), 0.into()).unwrap();
+        assert_eq!(first, get_contract_address(from, 0));
+        assert!(gas > 0);
+        assert!(!evm.code(first).as_ref().is_empty());
+
+        // the value cannot be moved, which must not use up a nonce
+        assert!(evm.deploy(from, bytecode.clone(), 1.into()).is_err());
+        let (second, _, _, _) = evm.deploy(from, bytecode, 0.into()).unwrap();
+        assert_eq!(second, get_contract_address(from, 1));
+        assert_eq!(evm.code(second), evm.code(first));
+    }
+
     #[test]
     // TODO: This fails because the cross-contract host does not work.
     #[ignore]
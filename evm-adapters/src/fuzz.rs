@@ -1,11 +1,12 @@
 //! Fuzzing support abstracted over the [`Evm`](crate::Evm) used
-use crate::Evm;
+use crate::{Evm, ExecutorError};
 use ethers::{
     abi::{Function, ParamType, Token, Tokenizable},
     types::{Address, Bytes, Sign, I256, U256},
 };
 use std::{
     cell::{RefCell, RefMut},
+    collections::{BTreeMap, BTreeSet},
     marker::PhantomData,
 };
 
@@ -27,6 +28,9 @@ pub struct FuzzedExecutor<'a, E, S> {
     runner: TestRunner,
     state: PhantomData<S>,
     sender: Address,
+    allow_unused_expectations: bool,
+    types: TypeDefinitions,
+    corpus: Vec<Bytes>,
 }
 
 impl<'a, S, E: Evm<S>> FuzzedExecutor<'a, E, S> {
@@ -37,7 +41,40 @@ impl<'a, S, E: Evm<S>> FuzzedExecutor<'a, E, S> {
 
     /// Instantiates a fuzzed executor EVM given a testrunner
     pub fn new(evm: &'a mut E, runner: TestRunner, sender: Address) -> Self {
-        Self { evm: RefCell::new(evm), runner, state: PhantomData, sender }
+        Self {
+            evm: RefCell::new(evm),
+            runner,
+            state: PhantomData,
+            sender,
+            allow_unused_expectations: false,
+            types: TypeDefinitions::default(),
+            corpus: Vec::new(),
+        }
+    }
+
+    /// If set to `true`, unused cheatcode expectations will be reported as warnings in the
+    /// [`FuzzTestResult`] instead of failing the fuzz case
+    #[must_use]
+    pub fn allow_unused_expectations(mut self, allow: bool) -> Self {
+        self.allow_unused_expectations = allow;
+        self
+    }
+
+    /// Sets the enums and structs declared by the fuzzed contracts, so that enum parameters are
+    /// only fuzzed with valid variants
+    #[must_use]
+    pub fn type_definitions(mut self, types: TypeDefinitions) -> Self {
+        self.types = types;
+        self
+    }
+
+    /// Sets calldata recorded from real usage, e.g. harvested from a chain's history. The
+    /// arguments of every seed which can be decoded as the inputs of the fuzzed function are
+    /// replayed in some of its cases, instead of random values
+    #[must_use]
+    pub fn corpus(mut self, corpus: Vec<Bytes>) -> Self {
+        self.corpus = corpus;
+        self
     }
 
     /// Fuzzes the provided function, assuming it is available at the contract at `address`
@@ -57,7 +94,13 @@ impl<'a, S, E: Evm<S>> FuzzedExecutor<'a, E, S> {
         // fuzz test run.
         S: Clone,
     {
-        let strat = fuzz_calldata(func);
+        let seeds = corpus_seeds(func, &self.corpus);
+        let strat = fuzz_calldata_with(func, &self.types);
+        let strat = if seeds.is_empty() {
+            strat
+        } else {
+            prop_oneof![1 => proptest::sample::select(seeds), CORPUS_RANDOM_WEIGHT => strat].boxed()
+        };
 
         // Snapshot the state before the test starts running
         let pre_test_state = self.evm.borrow().state().clone();
@@ -70,6 +113,12 @@ impl<'a, S, E: Evm<S>> FuzzedExecutor<'a, E, S> {
         let return_reason: RefCell<Option<E::ReturnReason>> = RefCell::new(None);
         let revert_reason = RefCell::new(None);
 
+        // unique unused cheatcode expectations across all cases, only populated if they're
+        // allowed
+        let warnings: RefCell<BTreeSet<String>> = RefCell::new(Default::default());
+        // unique deprecated cheatcodes called across all cases
+        let deprecations: RefCell<BTreeSet<String>> = RefCell::new(Default::default());
+
         let mut runner = self.runner.clone();
         tracing::debug!(func = ?func.name, should_fail, "fuzzing");
         let test_error = runner
@@ -78,9 +127,23 @@ impl<'a, S, E: Evm<S>> FuzzedExecutor<'a, E, S> {
                 // Before each test, we must reset to the initial state
                 evm.reset(pre_test_state.clone());
 
-                let (returndata, reason, gas, _) = evm
-                    .call_raw(self.sender, address, calldata.clone(), 0.into(), false)
-                    .expect("could not make raw evm call");
+                let (returndata, reason, gas, _) =
+                    match evm.call_raw(self.sender, address, calldata.clone(), 0.into(), false) {
+                        Ok(res) => res,
+                        Err(err) => {
+                            // the case is reported as a revert with the executor's error
+                            let err = ExecutorError::RawCall(err).to_string();
+                            let _ = return_reason.borrow_mut().insert(E::revert());
+                            let _ = revert_reason.borrow_mut().insert(err.clone());
+                            return Err(TestCaseError::fail(err))
+                        }
+                    };
+
+                deprecations.borrow_mut().extend(evm.deprecations());
+                if self.allow_unused_expectations {
+                    warnings.borrow_mut().extend(evm.unused_expectations());
+                    evm.clear_expectations();
+                }
 
                 // We must check success before resetting the state, otherwise resetting the state
                 // will also reset the `failed` state variable back to false.
@@ -116,7 +179,12 @@ impl<'a, S, E: Evm<S>> FuzzedExecutor<'a, E, S> {
                 revert_reason: revert_reason.into_inner().expect("Revert error string must be set"),
             });
 
-        FuzzTestResult { cases: FuzzedCases::new(fuzz_cases.into_inner()), test_error }
+        FuzzTestResult {
+            cases: FuzzedCases::new(fuzz_cases.into_inner()),
+            test_error,
+            warnings: warnings.into_inner().into_iter().collect(),
+            deprecations: deprecations.into_inner().into_iter().collect(),
+        }
     }
 }
 
@@ -127,6 +195,10 @@ pub struct FuzzTestResult<Reason> {
     /// if there was a case that resulted in an error, this contains the error and the return
     /// reason of the failed call
     pub test_error: Option<FuzzError<Reason>>,
+    /// Unused cheatcode expectations which were downgraded to warnings
+    pub warnings: Vec<String>,
+    /// The warnings of the deprecated cheatcodes called by any case
+    pub deprecations: Vec<String>,
 }
 
 impl<Reason> FuzzTestResult<Reason> {
@@ -212,25 +284,112 @@ pub struct FuzzCase {
     pub gas: u64,
 }
 
+/// The user-defined types which the ABI of a function does not fully describe, keyed by their
+/// canonical name, e.g. `Contract.Kind`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeDefinitions {
+    /// The number of variants of every enum
+    pub enums: BTreeMap<String, usize>,
+    /// The Solidity types of the members of every struct, in declaration order
+    pub structs: BTreeMap<String, Vec<String>>,
+}
+
+impl TypeDefinitions {
+    /// Adds the definitions of `other`, overriding the ones with the same name
+    pub fn extend(&mut self, other: TypeDefinitions) {
+        self.enums.extend(other.enums);
+        self.structs.extend(other.structs);
+    }
+
+    fn enum_variants(&self, ty: Option<&str>) -> Option<usize> {
+        ty.and_then(|ty| self.enums.get(type_name(ty))).copied()
+    }
+
+    fn struct_members(&self, ty: Option<&str>) -> Option<&[String]> {
+        ty.and_then(|ty| self.structs.get(type_name(ty))).map(Vec::as_slice)
+    }
+}
+
+/// Strips the `enum`/`struct`/`contract` keyword and any data location from a Solidity type, e.g.
+/// `struct C.Pair[] memory` becomes `C.Pair[]`
+fn type_name(ty: &str) -> &str {
+    let ty = ["enum ", "struct ", "contract "]
+        .iter()
+        .find_map(|prefix| ty.strip_prefix(prefix))
+        .unwrap_or(ty);
+    ty.split_whitespace().next().unwrap_or(ty)
+}
+
+/// Returns the Solidity type of the elements of an array type, e.g. `uint8[2]` for `uint8[2][]`
+fn element_type(ty: &str) -> Option<&str> {
+    let ty = type_name(ty);
+    ty.strip_suffix(']').and_then(|ty| ty.rfind('[').map(|idx| &ty[..idx]))
+}
+
 /// Given a function, it returns a proptest strategy which generates valid abi-encoded calldata
 /// for that function's input types.
 pub fn fuzz_calldata(func: &Function) -> impl Strategy<Value = Bytes> + '_ {
+    fuzz_calldata_with(func, &TypeDefinitions::default())
+}
+
+/// Like [`fuzz_calldata`], but uses the given type definitions to only generate valid values for
+/// the enums used by the function's inputs
+pub fn fuzz_calldata_with(func: &Function, types: &TypeDefinitions) -> BoxedStrategy<Bytes> {
     // We need to compose all the strategies generated for each parameter in all
     // possible combinations
-    let strats = func.inputs.iter().map(|input| fuzz_param(&input.kind)).collect::<Vec<_>>();
+    let strats = func
+        .inputs
+        .iter()
+        .map(|input| fuzz_param(&input.kind, input.internal_type.as_deref(), types, false))
+        .collect::<Vec<_>>();
+
+    let func = func.clone();
+    strats
+        .prop_map(move |tokens| {
+            tracing::trace!(input = ?tokens);
+            func.encode_input(&tokens).unwrap().into()
+        })
+        .boxed()
+}
 
-    strats.prop_map(move |tokens| {
-        tracing::trace!(input = ?tokens);
-        func.encode_input(&tokens).unwrap().into()
-    })
+/// For every random case, the number of cases which are drawn from the corpus if it has seeds
+/// for the fuzzed function
+const CORPUS_RANDOM_WEIGHT: u32 = 3;
+
+/// Returns the seeds of the corpus as calldata of the function, keeping the ones whose arguments
+/// are a valid encoding of its inputs, whatever function they were recorded for
+fn corpus_seeds(func: &Function, corpus: &[Bytes]) -> Vec<Bytes> {
+    let types = func.inputs.iter().map(|input| input.kind.clone()).collect::<Vec<_>>();
+    let seeds = corpus
+        .iter()
+        .filter_map(|seed| {
+            let args = seed.as_ref().get(4..)?;
+            let tokens = ethers::abi::decode(&types, args).ok()?;
+            // decoding ignores trailing bytes and dirty padding, which the re-encoding drops
+            if ethers::abi::encode(&tokens) != args {
+                return None
+            }
+            func.encode_input(&tokens).ok()
+        })
+        .collect::<BTreeSet<_>>();
+    seeds.into_iter().map(Bytes::from).collect()
 }
 
 /// The max length of arrays we fuzz for is 256.
 const MAX_ARRAY_LEN: usize = 256;
 
-/// Given an ethabi parameter type, returns a proptest strategy for generating values for that
-/// datatype. Works with ABI Encoder v2 tuples.
-fn fuzz_param(param: &ParamType) -> impl Strategy<Value = Token> {
+/// The max length of dynamic arrays nested in other arrays, which keeps the size of the calldata
+/// in check
+const MAX_NESTED_ARRAY_LEN: usize = 16;
+
+/// Given an ethabi parameter type and its Solidity type, if known, returns a proptest strategy
+/// for generating values for that datatype. Works with ABI Encoder v2 tuples.
+fn fuzz_param(
+    param: &ParamType,
+    ty: Option<&str>,
+    types: &TypeDefinitions,
+    nested: bool,
+) -> BoxedStrategy<Token> {
     match param {
         ParamType::Address => {
             // The key to making this work is the `boxed()` call which type erases everything
@@ -238,48 +397,87 @@ fn fuzz_param(param: &ParamType) -> impl Strategy<Value = Token> {
             any::<[u8; 20]>().prop_map(|x| Address::from_slice(&x).into_token()).boxed()
         }
         ParamType::Bytes => any::<Vec<u8>>().prop_map(|x| Bytes::from(x).into_token()).boxed(),
-        ParamType::Int(n) => match n / 8 {
-            1 => any::<i8>().prop_map(|x| x.into_token()).boxed(),
-            2 => any::<i16>().prop_map(|x| x.into_token()).boxed(),
-            3..=4 => any::<i32>().prop_map(|x| x.into_token()).boxed(),
-            5..=8 => any::<i64>().prop_map(|x| x.into_token()).boxed(),
-            9..=16 => any::<i128>().prop_map(|x| x.into_token()).boxed(),
-            17..=32 => (any::<bool>(), any::<[u8; 32]>())
+        ParamType::Int(n) => match *n {
+            8 => any::<i8>().prop_map(|x| x.into_token()).boxed(),
+            16 => any::<i16>().prop_map(|x| x.into_token()).boxed(),
+            32 => any::<i32>().prop_map(|x| x.into_token()).boxed(),
+            64 => any::<i64>().prop_map(|x| x.into_token()).boxed(),
+            128 => any::<i128>().prop_map(|x| x.into_token()).boxed(),
+            256 => (any::<bool>(), any::<[u8; 32]>())
                 .prop_filter_map("i256s cannot overflow", |(sign, bytes)| {
                     let sign = if sign { Sign::Positive } else { Sign::Negative };
                     I256::checked_from_sign_and_abs(sign, U256::from(bytes)).map(|x| x.into_token())
                 })
                 .boxed(),
+            n if n % 8 == 0 && n < 256 => {
+                // sign-extend the lower `n` bits, so that the value fits in the type
+                any::<[u8; 32]>()
+                    .prop_map(move |x| {
+                        let mask = (U256::one() << n) - 1;
+                        let value = U256::from(&x) & mask;
+                        let value = if value.bit(n - 1) { value | !mask } else { value };
+                        I256::from_raw(value).into_token()
+                    })
+                    .boxed()
+            }
             _ => panic!("unsupported solidity type int{}", n),
         },
-        ParamType::Uint(n) => match n / 8 {
-            1 => any::<u8>().prop_map(|x| x.into_token()).boxed(),
-            2 => any::<u16>().prop_map(|x| x.into_token()).boxed(),
-            3..=4 => any::<u32>().prop_map(|x| x.into_token()).boxed(),
-            5..=8 => any::<u64>().prop_map(|x| x.into_token()).boxed(),
-            9..=16 => any::<u128>().prop_map(|x| x.into_token()).boxed(),
-            17..=32 => any::<[u8; 32]>().prop_map(|x| U256::from(&x).into_token()).boxed(),
+        ParamType::Uint(8) if types.enum_variants(ty).is_some() => {
+            // only the declared variants of an enum can be decoded by the contract
+            let variants = types.enum_variants(ty).unwrap_or_default();
+            (0..variants).prop_map(|x| U256::from(x).into_token()).boxed()
+        }
+        ParamType::Uint(n) => match *n {
+            8 => any::<u8>().prop_map(|x| x.into_token()).boxed(),
+            16 => any::<u16>().prop_map(|x| x.into_token()).boxed(),
+            32 => any::<u32>().prop_map(|x| x.into_token()).boxed(),
+            64 => any::<u64>().prop_map(|x| x.into_token()).boxed(),
+            128 => any::<u128>().prop_map(|x| x.into_token()).boxed(),
+            256 => any::<[u8; 32]>().prop_map(|x| U256::from(&x).into_token()).boxed(),
+            n if n % 8 == 0 && n < 256 => {
+                // keep the lower `n` bits, so that the value fits in the type
+                any::<[u8; 32]>()
+                    .prop_map(move |x| (U256::from(&x) & ((U256::one() << n) - 1)).into_token())
+                    .boxed()
+            }
             _ => panic!("unsupported solidity type uint{}", n),
         },
         ParamType::Bool => any::<bool>().prop_map(|x| x.into_token()).boxed(),
-        ParamType::String => any::<Vec<u8>>()
-            .prop_map(|x| Token::String(unsafe { std::str::from_utf8_unchecked(&x).to_string() }))
-            .boxed(),
-        ParamType::Array(param) => proptest::collection::vec(fuzz_param(param), 0..MAX_ARRAY_LEN)
+        ParamType::String => any::<String>().prop_map(Token::String).boxed(),
+        ParamType::Array(param) => {
+            let len = if nested { MAX_NESTED_ARRAY_LEN } else { MAX_ARRAY_LEN };
+            proptest::collection::vec(
+                fuzz_param(param, ty.and_then(element_type), types, true),
+                0..len,
+            )
             .prop_map(Token::Array)
-            .boxed(),
+            .boxed()
+        }
         ParamType::FixedBytes(size) => (0..*size as u64)
             .map(|_| any::<u8>())
             .collect::<Vec<_>>()
             .prop_map(Token::FixedBytes)
             .boxed(),
-        ParamType::FixedArray(param, size) => (0..*size as u64)
-            .map(|_| fuzz_param(param).prop_map(|param| param.into_token()))
-            .collect::<Vec<_>>()
-            .prop_map(Token::FixedArray)
-            .boxed(),
+        ParamType::FixedArray(param, size) => {
+            let ty = ty.and_then(element_type);
+            (0..*size as u64)
+                .map(|_| fuzz_param(param, ty, types, nested))
+                .collect::<Vec<_>>()
+                .prop_map(Token::FixedArray)
+                .boxed()
+        }
         ParamType::Tuple(params) => {
-            params.iter().map(fuzz_param).collect::<Vec<_>>().prop_map(Token::Tuple).boxed()
+            let members = types.struct_members(ty);
+            params
+                .iter()
+                .enumerate()
+                .map(|(idx, param)| {
+                    let ty = members.and_then(|members| members.get(idx)).map(String::as_str);
+                    fuzz_param(param, ty, types, nested)
+                })
+                .collect::<Vec<_>>()
+                .prop_map(Token::Tuple)
+                .boxed()
         }
     }
 }
@@ -311,4 +509,59 @@ mod tests {
         let revert_reason = error.revert_reason;
         assert_eq!(revert_reason, "fuzztest-revert");
     }
+
+    #[test]
+    fn fuzzes_valid_enums_and_structs() {
+        let compiled = COMPILED.find("FuzzTests").expect("could not find contract");
+        let func = compiled.abi.unwrap().function("testFuzzedOrder").unwrap().clone();
+
+        // without the definitions, the enums are fuzzed with any uint8 and fail to decode
+        let mut evm = vm();
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        assert!(fuzzvm(&mut evm).fuzz(&func, addr, false).is_err());
+
+        let types = TypeDefinitions {
+            enums: BTreeMap::from([("FuzzTests.Kind".to_string(), 3)]),
+            structs: BTreeMap::from([(
+                "FuzzTests.Order".to_string(),
+                ["enum FuzzTests.Kind", "uint24", "int40", "bytes3", "uint8[][]"]
+                    .iter()
+                    .map(|ty| ty.to_string())
+                    .collect(),
+            )]),
+        };
+        let mut evm = vm();
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        let res = fuzzvm(&mut evm).type_definitions(types).fuzz(&func, addr, false);
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn replays_corpus_seeds() {
+        let compiled = COMPILED.find("FuzzTests").expect("could not find contract");
+        let func = compiled.abi.unwrap().function("testFuzzedRevert").unwrap().clone();
+
+        // seeds recorded for any function are replayed if their arguments fit
+        let args = ethers::abi::encode(&[Token::Uint(5.into())]);
+        let seed = [&[0xde, 0xad, 0xbe, 0xef][..], &args].concat();
+        let trailing = [&seed[..], &[0]].concat();
+        let corpus = vec![Bytes::from(seed), Bytes::from(trailing), Bytes::from(vec![0xde])];
+        assert_eq!(
+            corpus_seeds(&func, &corpus),
+            vec![Bytes::from(func.encode_input(&[Token::Uint(5.into())]).unwrap())]
+        );
+
+        // the only succeeding input is never generated randomly
+        let mut evm = vm();
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        assert!(fuzzvm(&mut evm).fuzz(&func, addr, true).is_ok());
+
+        let mut evm = vm();
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        assert!(fuzzvm(&mut evm).corpus(corpus).fuzz(&func, addr, true).is_err());
+    }
 }
@@ -7,26 +7,49 @@ pub mod sputnik;
 #[cfg(feature = "evmodin")]
 pub mod evmodin;
 
+/// Abstraction over [revm](https://github.com/bluealloy/revm)
+#[cfg(feature = "revm")]
+pub mod revm;
+
+#[cfg(feature = "fork-backend")]
 mod blocking_provider;
+#[cfg(feature = "fork-backend")]
 pub use blocking_provider::BlockingProvider;
 
+#[cfg(feature = "fork-backend")]
+pub mod runtime;
+
+#[cfg(feature = "fuzz")]
 pub mod fuzz;
 
+pub mod logs;
+
+pub mod trace;
+use trace::{
+    Breakpoint, CallFrame, CallTrace, CoverageHits, DebugTrace, InternalJumps, PrankedCall,
+    StateDiff,
+};
+
 use ethers::{
-    abi::{Detokenize, Tokenize},
+    abi::{Detokenize, ParamType, Tokenize},
     contract::{decode_function_data, encode_function_data},
-    core::types::{Address, Bytes, U256},
+    core::types::{Address, Bytes, Selector, TransactionRequest, H256, U256},
 };
 
 use foundry_utils::IntoFunction;
 
 use eyre::Result;
 use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
 
 /// The account that we use to fund all the deployed contracts
 pub static FAUCET_ACCOUNT: Lazy<Address> =
     Lazy::new(|| Address::from_slice(&ethers::utils::keccak256("turbodapp faucet")[12..]));
 
+/// The revert reason with which the `skip(true)` cheatcode halts a test, so that the test runner
+/// reports it as skipped
+pub const SKIP_REASON: &str = "FOUNDRY::SKIP";
+
 /// Errors related to the EVM call execution
 #[derive(thiserror::Error, Debug)]
 pub enum EvmError {
@@ -42,6 +65,23 @@ pub enum EvmError {
     Eyre(#[from] eyre::Error),
 }
 
+/// Errors related to setting up or driving an executor, as opposed to the EVM call itself
+#[derive(thiserror::Error, Debug)]
+pub enum ExecutorError {
+    #[error("Block {0} not found")]
+    /// The provider does not know the requested block
+    BlockNotFound(u64),
+    #[error("Block {0} is still pending")]
+    /// The block has no number yet, so it cannot be used as the block environment
+    PendingBlock(u64),
+    #[error("Could not make raw EVM call: {0}")]
+    /// The executor failed before the call could return or revert
+    RawCall(eyre::Error),
+    #[error(transparent)]
+    /// Error returned by the provider
+    Provider(Box<dyn std::error::Error + Send + Sync>),
+}
+
 // TODO: Any reason this should be an async trait?
 /// Low-level abstraction layer for interfacing with various EVMs. Once instantiated, one
 /// only needs to specify the transaction parameters
@@ -54,6 +94,87 @@ pub trait Evm<State> {
 
     fn expected_revert(&self) -> Option<&[u8]>;
 
+    // The cheatcode and tracing hooks below do nothing by default, for EVMs which do not
+    // support them
+
+    /// Returns a description of every cheatcode expectation which was set up but never
+    /// fulfilled, e.g. a dangling `expectRevert` or a `startPrank` without a `stopPrank`
+    fn unused_expectations(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Discards all pending cheatcode expectations
+    fn clear_expectations(&mut self) {}
+
+    /// Returns the gas used by every named section which was measured with the
+    /// `snapshotGasStart` / `snapshotGasEnd` cheatcodes during the last call
+    fn gas_sections(&self) -> BTreeMap<String, u64> {
+        BTreeMap::new()
+    }
+
+    /// Returns a warning for every deprecated cheatcode which was called since the state was
+    /// last reset
+    fn deprecations(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Sets the output types of the known functions by their selector, which are used to return
+    /// zero values of the right shape from calls whose revert was expected by `expectRevert`
+    fn set_return_types(&mut self, _types: BTreeMap<Selector, Vec<ParamType>>) {}
+
+    /// Sets the jumps into and out of internal functions of the known runtime codes, by the hash
+    /// of the code, which are followed to record the internal functions of the revert trace
+    fn set_internal_jumps(&mut self, _jumps: BTreeMap<H256, InternalJumps>) {}
+
+    /// Returns the chain of call frames from the top-level call down to the frame where the
+    /// last call's revert originated. Empty if the last call did not revert.
+    fn revert_trace(&self) -> Vec<CallFrame> {
+        Vec::new()
+    }
+
+    /// Returns the breakpoints hit during the last call, in the order they were hit
+    fn breakpoints(&self) -> Vec<Breakpoint> {
+        Vec::new()
+    }
+
+    /// Returns the calls of the last call whose sender was replaced by `prank` or `startPrank`,
+    /// in the order they were made
+    fn pranks(&self) -> Vec<PrankedCall> {
+        Vec::new()
+    }
+
+    /// Returns every call made by the last call and the storage it changed
+    fn call_trace(&self) -> CallTrace {
+        CallTrace::default()
+    }
+
+    /// Enables recording every opcode executed by the following calls, for the debugger
+    fn set_debug(&mut self, _enabled: bool) {}
+
+    /// Returns the opcodes executed by the last call, if debugging is enabled
+    fn debug_trace(&self) -> DebugTrace {
+        DebugTrace::default()
+    }
+
+    /// Enables counting the executed opcodes and branches of every runtime code, for coverage
+    fn set_coverage(&mut self, _enabled: bool) {}
+
+    /// Returns the opcodes and branches executed by all calls since coverage was enabled
+    fn coverage(&self) -> CoverageHits {
+        CoverageHits::default()
+    }
+
+    /// Returns the changes made to the accounts since the EVM was in the `before` state
+    fn state_diff(&self, _before: &State) -> StateDiff {
+        StateDiff::default()
+    }
+
+    /// Returns the transactions collected by the `broadcast` and `startBroadcast` cheatcodes
+    /// since the EVM was created, to be sent to a real network
+    fn broadcastable_transactions(&self) -> Vec<TransactionRequest> {
+        Vec::new()
+    }
+
     /// Whether a return reason should be considered successful
     fn is_success(reason: &Self::ReturnReason) -> bool;
     /// Whether a return reason should be considered failing
@@ -68,6 +189,9 @@ pub trait Evm<State> {
     /// Sets the balance at the specified address
     fn set_balance(&mut self, address: Address, amount: U256);
 
+    /// Gets the runtime bytecode deployed at the specified address
+    fn code(&self, address: Address) -> Bytes;
+
     /// Resets the EVM's state to the provided value
     fn reset(&mut self, state: State);
 
@@ -166,8 +290,9 @@ pub trait Evm<State> {
                 success = !failed;
             }
         }
-        // check if there is a remaining expected revert
-        if self.expected_revert().is_some() {
+        // check if there are any dangling cheatcode expectations (e.g. an `expectRevert`
+        // which was never followed by a call)
+        if !self.unused_expectations().is_empty() {
             success = false;
         }
 
@@ -244,4 +369,11 @@ mod test_helpers {
 
         // TODO: Add testFail
     }
+
+    /// The mainnet endpoint of the forking tests. They are ignored by default, and run with
+    /// `ETH_RPC_URL=<url> cargo test -- --ignored`
+    #[cfg(feature = "fork-backend")]
+    pub fn rpc_url() -> String {
+        std::env::var("ETH_RPC_URL").expect("the forking tests need ETH_RPC_URL")
+    }
 }
@@ -0,0 +1,87 @@
+//! Decoding of the ds-test log events, which tests print their logs and failed assertions with
+use ethers::{abi::RawLog, contract::EthLogDecode, types::U256};
+
+ethers::contract::abigen!(
+    HevmConsole,
+    r#"[
+            event log(string)
+            event logs                   (bytes)
+            event log_address            (address)
+            event log_bytes32            (bytes32)
+            event log_int                (int)
+            event log_uint               (uint)
+            event log_bytes              (bytes)
+            event log_string             (string)
+            event log_named_address      (string key, address val)
+            event log_named_bytes32      (string key, bytes32 val)
+            event log_named_decimal_int  (string key, int val, uint decimals)
+            event log_named_decimal_uint (string key, uint val, uint decimals)
+            event log_named_int          (string key, int val)
+            event log_named_uint         (string key, uint val)
+            event log_named_bytes        (string key, bytes val)
+            event log_named_string       (string key, string val)
+            ]"#,
+);
+
+/// A ds-test log event which cannot be formatted
+#[derive(thiserror::Error, Debug)]
+pub enum LogError {
+    #[error("{key}: {value} with {decimals} decimals overflows 256 bits")]
+    /// The value of a `log_named_decimal_*` event scaled by its decimals does not fit in 256 bits
+    DecimalOverflow { key: String, value: String, decimals: U256 },
+}
+
+/// Decodes the ds-test log events among the logs and formats them, other events are skipped.
+/// Events which cannot be formatted are replaced by their error
+pub fn decode_logs(logs: impl IntoIterator<Item = RawLog>) -> Vec<String> {
+    logs.into_iter()
+        .filter_map(|log| HevmConsoleEvents::decode_log(&log).ok())
+        .map(|event| format_log(event).unwrap_or_else(|err| err.to_string()))
+        .collect()
+}
+
+/// Scales `val` by `10^decimals`, like `parse_units` does with a whole number
+fn scale(val: U256, decimals: U256) -> Option<U256> {
+    U256::from(10).checked_pow(decimals).and_then(|unit| val.checked_mul(unit))
+}
+
+/// Formats a ds-test log event as a string
+pub fn format_log(event: HevmConsoleEvents) -> Result<String, LogError> {
+    use HevmConsoleEvents::*;
+    Ok(match event {
+        LogsFilter(inner) => format!("{}", inner.0),
+        LogBytesFilter(inner) => format!("{}", inner.0),
+        LogNamedAddressFilter(inner) => format!("{}: {:?}", inner.key, inner.val),
+        LogNamedBytes32Filter(inner) => {
+            format!("{}: 0x{}", inner.key, hex::encode(inner.val))
+        }
+        LogNamedDecimalIntFilter(inner) => {
+            let val = scale(inner.val.unsigned_abs(), inner.decimals).ok_or_else(|| {
+                LogError::DecimalOverflow {
+                    key: inner.key.clone(),
+                    value: inner.val.to_string(),
+                    decimals: inner.decimals,
+                }
+            })?;
+            let sign = if inner.val.is_negative() { "-" } else { "" };
+            format!("{}: {}{:?}", inner.key, sign, val)
+        }
+        LogNamedDecimalUintFilter(inner) => {
+            let val =
+                scale(inner.val, inner.decimals).ok_or_else(|| LogError::DecimalOverflow {
+                    key: inner.key.clone(),
+                    value: inner.val.to_string(),
+                    decimals: inner.decimals,
+                })?;
+            format!("{}: {:?}", inner.key, val)
+        }
+        LogNamedIntFilter(inner) => format!("{}: {:?}", inner.key, inner.val),
+        LogNamedUintFilter(inner) => format!("{}: {:?}", inner.key, inner.val),
+        LogNamedBytesFilter(inner) => {
+            format!("{}: 0x{}", inner.key, hex::encode(inner.val))
+        }
+        LogNamedStringFilter(inner) => format!("{}: {}", inner.key, inner.val),
+
+        e => e.to_string(),
+    })
+}
@@ -0,0 +1,235 @@
+use crate::{logs::decode_logs, Evm};
+
+use ethers::{
+    abi::RawLog,
+    types::{Address, Bytes, U256},
+};
+
+use revm::{
+    AccountInfo, CreateScheme, Database, DatabaseCommit, Env, InMemoryDB, Return, TransactOut,
+    TransactTo, EVM,
+};
+
+use eyre::Result;
+
+/// The intrinsic gas cost of every transaction, which is not reported like in the other EVMs
+const BASE_TX_COST: u64 = 21000;
+
+/// Wrapper around revm which implements the [Evm](`crate::Evm`) trait
+#[derive(Clone, Debug)]
+pub struct Revm<DB> {
+    pub evm: EVM<DB>,
+    pub gas_limit: u64,
+}
+
+impl<DB: DatabaseExt> Revm<DB> {
+    /// Given the initial state, the environment of the transactions and a gas limit
+    pub fn new(db: DB, env: Env, gas_limit: u64) -> Self {
+        let mut evm = EVM::new();
+        evm.env = env;
+        evm.database(db);
+        Self { evm, gas_limit }
+    }
+
+    /// Sets up the environment of a transaction from `from` with the given input and executes it,
+    /// returning the ds-test logs it emitted. The state changes of static calls are discarded.
+    fn transact(
+        &mut self,
+        from: Address,
+        transact_to: TransactTo,
+        data: Bytes,
+        value: U256,
+        is_static: bool,
+    ) -> (Return, TransactOut, u64, Vec<String>) {
+        self.evm.env.tx.caller = from;
+        self.evm.env.tx.transact_to = transact_to;
+        self.evm.env.tx.data = data.0;
+        self.evm.env.tx.value = value;
+        self.evm.env.tx.gas_limit = self.gas_limit;
+        let (status, out, gas, logs) = if is_static {
+            let (status, out, gas, _state, logs) = self.evm.transact();
+            (status, out, gas, logs)
+        } else {
+            self.evm.transact_commit()
+        };
+        let logs =
+            logs.into_iter().map(|log| RawLog { topics: log.topics, data: log.data.to_vec() });
+        (status, out, gas.saturating_sub(BASE_TX_COST), decode_logs(logs))
+    }
+
+    fn db(&self) -> &DB {
+        self.evm.db.as_ref().expect("the database is set on construction")
+    }
+
+    fn db_mut(&mut self) -> &mut DB {
+        self.evm.db.as_mut().expect("the database is set on construction")
+    }
+}
+
+/// Helper trait for exposing additional functionality over revm databases
+pub trait DatabaseExt: Database + DatabaseCommit {
+    /// Gets the bytecode at the specified address, if it was deployed or initialized
+    fn get_code(&self, address: Address) -> Option<Bytes>;
+    /// Sets the bytecode at the specified address to the provided value.
+    fn set_code(&mut self, address: Address, code: Bytes);
+    /// Sets the account's balance to the provided value.
+    fn set_balance(&mut self, address: Address, balance: U256);
+}
+
+impl DatabaseExt for InMemoryDB {
+    fn get_code(&self, address: Address) -> Option<Bytes> {
+        self.cache().get(&address).and_then(|account| account.code.clone()).map(Bytes)
+    }
+
+    fn set_code(&mut self, address: Address, code: Bytes) {
+        let account = self.cache().get(&address).cloned().unwrap_or_default();
+        // the code hash is computed from the code when it is inserted
+        self.insert_cache(address, AccountInfo { code: Some(code.0), ..account });
+    }
+
+    fn set_balance(&mut self, address: Address, balance: U256) {
+        let account = self.cache().get(&address).cloned().unwrap_or_default();
+        self.insert_cache(address, AccountInfo { balance, ..account });
+    }
+}
+
+impl<DB: DatabaseExt + Clone> Evm<DB> for Revm<DB> {
+    type ReturnReason = Return;
+
+    fn revert() -> Self::ReturnReason {
+        Return::Revert
+    }
+
+    fn expected_revert(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn is_success(reason: &Self::ReturnReason) -> bool {
+        matches!(reason, Return::Continue | Return::Stop | Return::Return | Return::SelfDestruct)
+    }
+
+    fn is_fail(reason: &Self::ReturnReason) -> bool {
+        matches!(reason, Return::Revert)
+    }
+
+    fn set_balance(&mut self, address: Address, balance: U256) {
+        self.db_mut().set_balance(address, balance)
+    }
+
+    fn code(&self, address: Address) -> Bytes {
+        self.db().get_code(address).unwrap_or_default()
+    }
+
+    fn reset(&mut self, state: DB) {
+        self.evm.database(state);
+    }
+
+    fn initialize_contracts<I: IntoIterator<Item = (Address, Bytes)>>(&mut self, contracts: I) {
+        contracts.into_iter().for_each(|(address, bytecode)| {
+            self.db_mut().set_code(address, bytecode);
+        })
+    }
+
+    fn state(&self) -> &DB {
+        self.db()
+    }
+
+    fn deploy(
+        &mut self,
+        from: Address,
+        calldata: Bytes,
+        value: U256,
+    ) -> Result<(Address, Self::ReturnReason, u64, Vec<String>)> {
+        let (status, out, gas, logs) =
+            self.transact(from, TransactTo::Create(CreateScheme::Create), calldata, value, false);
+        match out {
+            TransactOut::Create(_, Some(address)) if Self::is_success(&status) => {
+                tracing::trace!(?status, ?address, ?gas, "success");
+                Ok((address, status, gas, logs))
+            }
+            _ => {
+                tracing::trace!(?status, "failed");
+                Err(eyre::eyre!("deployment reverted, reason: {:?}", status))
+            }
+        }
+    }
+
+    /// Runs the selected function
+    fn call_raw(
+        &mut self,
+        from: Address,
+        to: Address,
+        calldata: Bytes,
+        value: U256,
+        is_static: bool,
+    ) -> Result<(Bytes, Self::ReturnReason, u64, Vec<String>)> {
+        let (status, out, gas, logs) =
+            self.transact(from, TransactTo::Call(to), calldata, value, is_static);
+        let retdata = match out {
+            TransactOut::Call(retdata) => retdata,
+            _ => Default::default(),
+        };
+
+        Ok((retdata.into(), status, gas, logs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{can_call_vm_directly, solidity_unit_test, COMPILED};
+
+    fn new_revm() -> Revm<InMemoryDB> {
+        let mut env = Env::default();
+        env.block.gas_limit = U256::MAX;
+        Revm::new(InMemoryDB::default(), env, 12_000_000)
+    }
+
+    #[test]
+    fn revm_can_call_vm_directly() {
+        let compiled = COMPILED.find("Greeter").expect("could not find contract");
+        can_call_vm_directly(new_revm(), compiled);
+    }
+
+    #[test]
+    fn revm_can_call_solidity_unit_test() {
+        let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
+        solidity_unit_test(new_revm(), compiled);
+    }
+
+    #[test]
+    fn revm_returns_ds_test_logs() {
+        let mut evm = new_revm();
+        let compiled = COMPILED.find("DebugLogs").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let (_, _, _, logs) =
+            evm.call::<(), _, _>(Address::zero(), addr, "test_log()", (), 0.into()).unwrap();
+        assert_eq!(logs[0], "Hi");
+        assert_eq!(logs[1], "0x1234");
+    }
+
+    #[test]
+    fn revm_discards_static_calls() {
+        let mut evm = new_revm();
+        let compiled = COMPILED.find("Greeter").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let func = ethers::abi::parse_abi(&["function greet(string)"]).unwrap();
+        let calldata = ethers::contract::encode_function_data(
+            func.function("greet").unwrap(),
+            "hi".to_owned(),
+        )
+        .unwrap();
+        let (_, status, _, _) =
+            evm.call_raw(Address::zero(), addr, calldata, 0.into(), true).unwrap();
+        assert!(Revm::<InMemoryDB>::is_success(&status));
+
+        let (greeting, _, _, _) = evm
+            .call::<String, _, _>(Address::zero(), addr, "greeting()(string)", (), 0.into())
+            .unwrap();
+        assert_eq!(greeting, "");
+    }
+}
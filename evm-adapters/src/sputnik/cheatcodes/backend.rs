@@ -11,6 +11,9 @@ use sputnik::backend::{Backend, Basic};
 /// The cheatcode backend can be composed with other enhanced backends, e.g. the forking
 /// backend. You should always put the cheatcode backend on the highest layer of your
 /// stack of backend middlewares, so that it is always hit first.
+///
+/// If a fork was selected with the `selectFork` cheatcode, the chain state is read from the
+/// fork instead of the inner backend.
 pub struct CheatcodeBackend<B> {
     /// The inner backend type.
     pub backend: B,
@@ -18,6 +21,34 @@ pub struct CheatcodeBackend<B> {
     pub cheats: Cheatcodes,
 }
 
+impl<B: Backend> CheatcodeBackend<B> {
+    /// The backend holding the chain state, i.e. the selected fork if there is one
+    fn chain(&self) -> &dyn Backend {
+        #[cfg(feature = "fork-backend")]
+        if let Some(fork) = self.cheats.forks.active() {
+            return fork
+        }
+        &self.backend
+    }
+
+    /// The backend holding the state of the account, which is the inner backend for accounts
+    /// which persist across forks
+    #[cfg_attr(not(feature = "fork-backend"), allow(unused_variables))]
+    fn account_chain(&self, address: H160) -> &dyn Backend {
+        #[cfg(feature = "fork-backend")]
+        if self.cheats.persistent_accounts.contains(&address) {
+            return &self.backend
+        }
+        self.chain()
+    }
+
+    /// The account of the chain state whose storage is read for the address, which differs if
+    /// the storage of another account was copied to it with `copyStorage`
+    fn storage_owner(&self, address: H160) -> H160 {
+        self.cheats.storage_aliases.get(&address).copied().unwrap_or(address)
+    }
+}
+
 impl<B: Backend> Backend for CheatcodeBackend<B> {
     // TODO: Override the return values based on the values of `self.cheats`
     fn gas_price(&self) -> U256 {
@@ -29,54 +60,59 @@ impl<B: Backend> Backend for CheatcodeBackend<B> {
     }
 
     fn block_hash(&self, number: U256) -> H256 {
-        self.backend.block_hash(number)
+        match self.cheats.block_hashes.get(&number) {
+            Some(hash) => *hash,
+            None => self.chain().block_hash(number),
+        }
     }
 
     fn block_number(&self) -> U256 {
-        self.cheats.block_number.unwrap_or_else(|| self.backend.block_number())
+        self.cheats.block_number.unwrap_or_else(|| self.chain().block_number())
     }
 
     fn block_coinbase(&self) -> H160 {
-        self.backend.block_coinbase()
+        self.chain().block_coinbase()
     }
 
     fn block_timestamp(&self) -> U256 {
-        self.cheats.block_timestamp.unwrap_or_else(|| self.backend.block_timestamp())
+        self.cheats.block_timestamp.unwrap_or_else(|| self.chain().block_timestamp())
     }
 
     fn block_base_fee_per_gas(&self) -> U256 {
-        self.cheats.block_base_fee_per_gas.unwrap_or_else(|| self.backend.block_base_fee_per_gas())
+        self.cheats.block_base_fee_per_gas.unwrap_or_else(|| self.chain().block_base_fee_per_gas())
     }
 
     fn block_difficulty(&self) -> U256 {
-        self.backend.block_difficulty()
+        self.chain().block_difficulty()
     }
 
     fn block_gas_limit(&self) -> U256 {
-        self.backend.block_gas_limit()
+        self.chain().block_gas_limit()
     }
 
     fn chain_id(&self) -> U256 {
-        self.backend.chain_id()
+        self.chain().chain_id()
     }
 
     fn exists(&self, address: H160) -> bool {
-        self.backend.exists(address)
+        self.account_chain(address).exists(address)
     }
 
     fn basic(&self, address: H160) -> Basic {
-        self.backend.basic(address)
+        self.account_chain(address).basic(address)
     }
 
     fn code(&self, address: H160) -> Vec<u8> {
-        self.backend.code(address)
+        self.account_chain(address).code(address)
     }
 
     fn storage(&self, address: H160, index: H256) -> H256 {
-        self.backend.storage(address, index)
+        let owner = self.storage_owner(address);
+        self.account_chain(owner).storage(owner, index)
     }
 
     fn original_storage(&self, address: H160, index: H256) -> Option<H256> {
-        self.backend.original_storage(address, index)
+        let owner = self.storage_owner(address);
+        self.account_chain(owner).original_storage(owner, index)
     }
 }
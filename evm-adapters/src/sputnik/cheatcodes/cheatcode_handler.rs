@@ -1,32 +1,51 @@
 //! Hooks to EVM execution
+#[cfg(feature = "fork-backend")]
+use super::forks;
 use super::{
-    backend::CheatcodeBackend, memory_stackstate_owned::MemoryStackStateOwned, ConsoleCalls,
-    HEVMCalls, HevmConsoleEvents,
+    backend::CheatcodeBackend,
+    interface, json,
+    memory_stackstate_owned::{ExpectedCall, ExpectedEmit, MemoryStackStateOwned, MockedCall},
+    merkle, signing, CheatcodeError, ConsoleCalls, HEVMCalls,
 };
+#[cfg(feature = "fork-backend")]
+use crate::runtime;
 use crate::{
+    logs::{decode_logs, format_log, HevmConsoleEvents},
     sputnik::{Executor, SputnikExecutor},
+    trace::{
+        AccountDiff, Breakpoint, CallFrame, CallTrace, CoverageHits, DebugStep, DebugTrace,
+        InternalJumps, OverflowSite, PrankedCall, RevertTracer, StateAccess, StateDiff,
+    },
     Evm,
 };
 
 use sputnik::{
-    backend::Backend,
+    backend::{Backend, MemoryAccount},
     executor::stack::{
         Log, PrecompileFailure, PrecompileOutput, PrecompileSet, StackExecutor, StackExitKind,
         StackState, StackSubstateMetadata,
     },
     gasometer, Capture, Config, Context, CreateScheme, ExitError, ExitReason, ExitRevert,
-    ExitSucceed, Handler, Runtime, Transfer,
+    ExitSucceed, Handler, Opcode, Runtime, Stack, Transfer,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    ops::Range,
+    path::PathBuf,
+    process::{Command, Output},
+    rc::Rc,
 };
-use std::{process::Command, rc::Rc};
 
 use ethers::{
-    abi::{RawLog, Token},
+    abi::{ParamType, RawLog, Token},
     contract::EthLogDecode,
     core::{abi::AbiDecode, k256::ecdsa::SigningKey, utils},
-    signers::{LocalWallet, Signer},
-    types::{Address, H160, H256, U256},
+    signers::{coins_bip39::English, LocalWallet, MnemonicBuilder, Signer},
+    types::{Address, Selector, TransactionRequest, H160, H256, I256, U256},
 };
 use std::convert::Infallible;
+#[cfg(feature = "cheatcodes-fs")]
+use std::path::Path;
 
 use crate::sputnik::cheatcodes::patch_hardhat_console_log_selector;
 use once_cell::sync::Lazy;
@@ -47,7 +66,8 @@ pub static CONSOLE_ADDRESS: Lazy<Address> = Lazy::new(|| {
 /// For certain cheatcodes, we may internally change the status of the call, i.e. in
 /// `expectRevert`. Solidity will see a successful call and attempt to abi.decode for the called
 /// function. Therefore, we need to populate the return with dummy bytes such that the decode
-/// doesn't fail
+/// doesn't fail. Calls to known functions return the zero values of their output types instead,
+/// see [`expected_revert_output`]
 pub static DUMMY_OUTPUT: [u8; 320] = [0u8; 320];
 
 /// Hooks on live EVM execution and forwards everything else to a Sputnik [`Handler`].
@@ -66,7 +86,77 @@ pub static DUMMY_OUTPUT: [u8; 320] = [0u8; 320];
 pub struct CheatcodeHandler<H> {
     handler: H,
     enable_ffi: bool,
+    /// Whether the `sleep` and `unixTime` cheatcodes, which depend on the host's clock, may be
+    /// called
+    enable_wall_clock: bool,
     console_logs: Vec<String>,
+    tracer: RevertTracer,
+    /// The number of opcodes executed in the current transaction
+    steps: usize,
+    /// The breakpoints hit in the current transaction
+    breakpoints: Vec<Breakpoint>,
+    /// The opcodes executed in the current transaction, if debugging is enabled
+    debug: Option<DebugTrace>,
+    /// The opcodes and branches executed by all transactions, if coverage is enabled
+    coverage: Option<CoverageHits>,
+    /// The calls of the current transaction whose sender was replaced by a prank
+    pranks: Vec<PrankedCall>,
+    /// The depth at which reverts are expected by `expectRevert(bytes,uint64)` during the current
+    /// expecting call, and the revert data of the calls which reverted at that depth
+    nested_reverts: Option<(usize, Vec<Vec<u8>>)>,
+    /// The memory ranges which the frames at a depth may write to besides the scratch space, set
+    /// by `expectSafeMemory` and `expectSafeMemoryCall`
+    allowed_mem_writes: BTreeMap<usize, Vec<Range<u64>>>,
+    /// The size in words of the memory of the executing frames by depth, which tells the memory
+    /// expansion gas of an opcode apart from its own cost while gas metering is paused
+    memory_words: BTreeMap<usize, u64>,
+    /// The caller of the current transaction, which `broadcast()` and `startBroadcast()` send
+    /// transactions from
+    tx_caller: H160,
+    /// The value before the current transaction of every storage slot it wrote to
+    storage_before: BTreeMap<(H160, H256), H256>,
+    /// Whether a failed ds-test assertion should immediately revert the test instead of letting
+    /// it continue
+    abort_on_failed_assertion: bool,
+    /// The first failed assertion of the current transaction, if aborting is enabled
+    failed_assertion: Option<FailedAssertion>,
+    /// Whether execution was halted because of `failed_assertion`
+    aborted: bool,
+    /// The RPC endpoint the state is forked from, used by the `rpc` cheatcode if no fork is
+    /// selected
+    #[cfg_attr(not(feature = "fork-backend"), allow(dead_code))]
+    rpc_url: Option<String>,
+    /// The directory of the project's compiled artifacts, read by the `getCode` and
+    /// `getDeployedCode` cheatcodes
+    #[cfg_attr(not(feature = "cheatcodes-fs"), allow(dead_code))]
+    artifacts: Option<PathBuf>,
+    /// The absolute path of the project's root directory, returned by the `projectRoot` cheatcode
+    #[cfg_attr(not(feature = "cheatcodes-fs"), allow(dead_code))]
+    project_root: Option<PathBuf>,
+    /// The costs charged for opcodes instead of their fixed costs, for chains which changed them
+    opcode_gas_costs: BTreeMap<u8, u64>,
+    /// The seed of the values returned by `randomUint` and `randomAddress`
+    seed: H256,
+    /// The key of the random values of the current transaction, derived from the seed and its
+    /// calldata so that every test and fuzz run gets its own reproducible values
+    random_key: H256,
+    /// The number of random values returned in the current transaction
+    random_count: u64,
+    /// The frames currently executing code, innermost last
+    frames: Vec<ExecutingFrame>,
+    /// Where the first arithmetic overflow of the current transaction happened
+    overflow: Option<OverflowSite>,
+    /// The accesses being recorded since `startStateDiffRecording`, if any
+    access_recording: Option<AccessRecording>,
+    /// The account whose storage reads are recorded while `dealToken` looks for a slot, and the
+    /// slots it read so far
+    storage_reads: Option<(H160, Vec<H256>)>,
+    /// The output types of the known functions by their selector, whose zero values are returned
+    /// by calls whose revert was expected
+    return_types: BTreeMap<Selector, Vec<ParamType>>,
+    /// The jumps into and out of internal functions of the known runtime codes, by the hash of
+    /// the code
+    internal_jumps: BTreeMap<H256, InternalJumps>,
 }
 
 // Forwards everything internally except for the transact_call which is overwritten.
@@ -90,6 +180,174 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
         self.handler.state().expected_revert.as_deref()
     }
 
+    fn unused_expectations(&self) -> Vec<String> {
+        let state = self.state();
+        let mut unused = Vec::new();
+        if let Some(ref expected_revert) = state.expected_revert {
+            if expected_revert.is_empty() {
+                unused.push("`expectRevert()` was not followed by a call".to_string());
+            } else {
+                unused.push(format!(
+                    "`expectRevert(0x{})` was not followed by a call",
+                    hex::encode(expected_revert)
+                ));
+            }
+        }
+        if !state.expected_emits.is_empty() {
+            unused.push("`expectEmit` was not followed by a call emitting the log".to_string());
+        }
+        unused.extend(state.expected_calls.iter().filter_map(ExpectedCall::unmet));
+        for (address, mocks) in &state.mocked_calls {
+            for (calldata, mock) in mocks.iter().filter(|(_, mock)| mock.calls == 0) {
+                unused.push(format!(
+                    "`{}({:?}, 0x{})` was never called",
+                    if mock.reverts { "mockCallRevert" } else { "mockCall" },
+                    address,
+                    hex::encode(calldata)
+                ));
+            }
+        }
+        if let Some(caller) = state.next_msg_sender {
+            unused.push(format!("`prank({:?})` was not followed by a call", caller));
+        }
+        if let Some((_, caller, _)) = state.msg_sender {
+            unused.push(format!("`startPrank({:?})` was never stopped with `stopPrank`", caller));
+        }
+        if let Some(sender) = state.next_broadcast {
+            unused.push(format!("`broadcast({:?})` was not followed by a call", sender));
+        }
+        if let Some((_, sender, _)) = state.broadcast {
+            unused.push(format!(
+                "`startBroadcast({:?})` was never stopped with `stopBroadcast`",
+                sender
+            ));
+        }
+        for name in state.active_gas_sections.keys() {
+            unused.push(format!("`snapshotGasStart(\"{}\")` was never ended", name));
+        }
+        unused
+    }
+
+    fn clear_expectations(&mut self) {
+        let state = self.state_mut();
+        state.expected_revert = None;
+        state.expected_revert_depth = None;
+        state.expected_emits.clear();
+        state.expected_calls.clear();
+        state.mocked_calls.clear();
+        state.next_msg_sender = None;
+        state.msg_sender = None;
+        state.prank_delegate_calls = false;
+        state.prank_creates = false;
+        state.next_broadcast = None;
+        state.broadcast = None;
+        state.active_gas_sections.clear();
+        // gas metering is paused per test, the next one starts metered again
+        state.gas_metering_paused = false;
+    }
+
+    fn gas_sections(&self) -> BTreeMap<String, u64> {
+        self.state().gas_sections.clone()
+    }
+
+    fn deprecations(&self) -> Vec<String> {
+        self.state().deprecations.iter().cloned().collect()
+    }
+
+    fn set_return_types(&mut self, types: BTreeMap<Selector, Vec<ParamType>>) {
+        self.return_types = types;
+    }
+
+    fn set_internal_jumps(&mut self, jumps: BTreeMap<H256, InternalJumps>) {
+        self.internal_jumps = jumps;
+    }
+
+    fn revert_trace(&self) -> Vec<CallFrame> {
+        self.tracer.trace().to_vec()
+    }
+
+    fn breakpoints(&self) -> Vec<Breakpoint> {
+        self.breakpoints.clone()
+    }
+
+    fn pranks(&self) -> Vec<PrankedCall> {
+        self.pranks.clone()
+    }
+
+    fn call_trace(&self) -> CallTrace {
+        // slots which were written to but hold their original value again are left out
+        let storage_diff = self
+            .storage_before
+            .iter()
+            .map(|(&(address, slot), &before)| {
+                ((address, slot), (before, self.handler.storage(address, slot)))
+            })
+            .filter(|(_, (before, after))| before != after)
+            .collect();
+        CallTrace {
+            calls: self.tracer.calls().to_vec(),
+            storage_diff,
+            overflow: self.overflow.clone(),
+        }
+    }
+
+    fn set_debug(&mut self, enabled: bool) {
+        self.debug = enabled.then(DebugTrace::default);
+    }
+
+    fn debug_trace(&self) -> DebugTrace {
+        self.debug.clone().unwrap_or_default()
+    }
+
+    #[cfg(feature = "coverage")]
+    fn set_coverage(&mut self, enabled: bool) {
+        // coverage builds on the frames tracked for the traces
+        self.coverage = enabled.then(CoverageHits::default);
+    }
+
+    #[cfg(not(feature = "coverage"))]
+    fn set_coverage(&mut self, _enabled: bool) {}
+
+    fn coverage(&self) -> CoverageHits {
+        self.coverage.clone().unwrap_or_default()
+    }
+
+    fn state_diff(&self, before: &CheatcodeStackState<'a, B>) -> StateDiff {
+        let touched = match self.state().touched_accounts() {
+            Ok(accounts) => accounts,
+            Err(_) => return StateDiff::default(),
+        };
+        let mut diff = StateDiff::default();
+        for (address, after) in touched {
+            // the placeholder code of the cheatcode addresses is not part of the chain state
+            if address == *CHEATCODE_ADDRESS || address == *CONSOLE_ADDRESS {
+                continue
+            }
+            let basic = before.basic(address);
+            let code = before.code(address);
+            // accounts which were touched since `before` may hold their values of then again
+            let account = AccountDiff {
+                balance: (basic.balance != after.balance).then(|| (basic.balance, after.balance)),
+                nonce: (basic.nonce != after.nonce).then(|| (basic.nonce, after.nonce)),
+                code: (code != after.code).then(|| (code.len(), after.code.len())),
+                storage: after
+                    .storage
+                    .into_iter()
+                    .map(|(slot, value)| (slot, (before.storage(address, slot), value)))
+                    .filter(|(_, (before, after))| before != after)
+                    .collect(),
+            };
+            if !account.is_empty() {
+                diff.accounts.insert(address, account);
+            }
+        }
+        diff
+    }
+
+    fn broadcastable_transactions(&self) -> Vec<TransactionRequest> {
+        self.state().broadcastable_transactions.clone()
+    }
+
     fn gas_left(&self) -> U256 {
         // NB: We do this to avoid `function cannot return without recursing`
         U256::from(self.state().metadata().gasometer().gas())
@@ -104,6 +362,27 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
         gas_limit: u64,
         access_list: Vec<(H160, Vec<H256>)>,
     ) -> (ExitReason, Vec<u8>) {
+        self.tracer.reset();
+        self.steps = 0;
+        self.breakpoints.clear();
+        if let Some(debug) = self.debug.as_mut() {
+            *debug = DebugTrace::default();
+        }
+        self.pranks.clear();
+        self.nested_reverts = None;
+        self.allowed_mem_writes.clear();
+        self.memory_words.clear();
+        self.tx_caller = caller;
+        self.storage_before.clear();
+        self.reset_random(&data);
+        self.failed_assertion = None;
+        self.aborted = false;
+        self.frames.clear();
+        self.overflow = None;
+        self.access_recording = None;
+        // every account and slot is cold at the start of a transaction anyway
+        self.state_mut().cooled_accounts.clear();
+        self.state_mut().warmed_storage.clear();
         let transaction_cost = gasometer::call_transaction_cost(&data, &access_list);
         match self.state_mut().metadata_mut().gasometer_mut().record_transaction(transaction_cost) {
             Ok(()) => (),
@@ -123,6 +402,15 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
 
         let context = Context { caller, address, apparent_value: value };
 
+        self.tracer.enter(CallFrame {
+            address,
+            caller,
+            value,
+            input: data.clone(),
+            create: false,
+            internal_calls: Vec::new(),
+        });
+        let gas_before = self.state().metadata().gasometer().gas();
         match self.call_inner(
             address,
             Some(Transfer { source: caller, target: address, value }),
@@ -133,7 +421,18 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
             false,
             context,
         ) {
-            Capture::Exit((s, v)) => (s, v),
+            Capture::Exit((s, v)) => {
+                let gas_used = self.gas_used_since(gas_before);
+                self.tracer.exit(matches!(s, ExitReason::Succeed(_)), &v, gas_used);
+                match self.failed_assertion.take() {
+                    // surface the failed assertion as the revert reason of the test
+                    Some(failed) if self.aborted && !matches!(s, ExitReason::Succeed(_)) => (
+                        ExitReason::Revert(ExitRevert::Reverted),
+                        ethers::abi::encode(&[Token::String(failed.reason())]),
+                    ),
+                    _ => (s, v),
+                }
+            }
             Capture::Trap(_) => unreachable!(),
         }
     }
@@ -146,6 +445,27 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
         gas_limit: u64,
         access_list: Vec<(H160, Vec<H256>)>,
     ) -> ExitReason {
+        self.tracer.reset();
+        self.steps = 0;
+        self.breakpoints.clear();
+        if let Some(debug) = self.debug.as_mut() {
+            *debug = DebugTrace::default();
+        }
+        self.pranks.clear();
+        self.nested_reverts = None;
+        self.allowed_mem_writes.clear();
+        self.memory_words.clear();
+        self.tx_caller = caller;
+        self.storage_before.clear();
+        self.reset_random(&init_code);
+        self.failed_assertion = None;
+        self.aborted = false;
+        self.frames.clear();
+        self.overflow = None;
+        self.access_recording = None;
+        // every account and slot is cold at the start of a transaction anyway
+        self.state_mut().cooled_accounts.clear();
+        self.state_mut().warmed_storage.clear();
         let transaction_cost = gasometer::create_transaction_cost(&init_code, &access_list);
         match self.state_mut().metadata_mut().gasometer_mut().record_transaction(transaction_cost) {
             Ok(()) => (),
@@ -153,6 +473,16 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
         };
         self.handler.initialize_with_access_list(access_list);
 
+        let address = self.create_address(CreateScheme::Legacy { caller });
+        self.tracer.enter(CallFrame {
+            address,
+            caller,
+            value,
+            input: init_code.clone(),
+            create: true,
+            internal_calls: Vec::new(),
+        });
+        let gas_before = self.state().metadata().gasometer().gas();
         match self.create_inner(
             caller,
             CreateScheme::Legacy { caller },
@@ -161,7 +491,11 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
             Some(gas_limit),
             false,
         ) {
-            Capture::Exit((s, _, _)) => s,
+            Capture::Exit((s, _, v)) => {
+                let gas_used = self.gas_used_since(gas_before);
+                self.tracer.exit(matches!(s, ExitReason::Succeed(_)), &v, gas_used);
+                s
+            }
             Capture::Trap(_) => unreachable!(),
         }
     }
@@ -176,48 +510,257 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> SputnikExecutor<CheatcodeStackState<'
 
     fn logs(&self) -> Vec<String> {
         let logs = self.state().substate.logs().to_vec();
-        logs.into_iter()
-            .filter_map(|log| {
-                // convert to the ethers type
-                let log = RawLog { topics: log.topics, data: log.data };
-                HevmConsoleEvents::decode_log(&log).ok()
+        // convert to the ethers type
+        let logs = logs.into_iter().map(|log| RawLog { topics: log.topics, data: log.data });
+        decode_logs(logs).into_iter().chain(self.console_logs.clone()).collect()
+    }
+}
+
+/// A ds-test assertion which failed while running with `abort_on_failed_assertion`
+#[derive(Clone, Debug, Default)]
+struct FailedAssertion {
+    /// The contract whose assertion failed
+    address: Address,
+    /// The assertion's error message
+    message: String,
+    /// The logs emitted after the error message, e.g. the expected and actual values
+    details: Vec<String>,
+}
+
+impl FailedAssertion {
+    /// Returns the failed assertion if the event is a ds-test assertion error message, i.e.
+    /// `log("Error: ...")` or `log_named_string("Error", ...)`
+    fn from_event(address: Address, event: &HevmConsoleEvents) -> Option<Self> {
+        let message = match event {
+            HevmConsoleEvents::LogFilter(inner) => inner.0.strip_prefix("Error:").map(str::trim),
+            HevmConsoleEvents::LogNamedStringFilter(inner) if inner.key == "Error" => {
+                Some(inner.val.as_str())
+            }
+            _ => None,
+        }?;
+        Some(Self { address, message: message.to_string(), details: Vec::new() })
+    }
+
+    /// The revert reason used when aborting the test
+    fn reason(&self) -> String {
+        if self.details.is_empty() {
+            self.message.clone()
+        } else {
+            format!("{} ({})", self.message, self.details.join(", "))
+        }
+    }
+}
+
+/// A read or write of a storage slot recorded in an [`AccessRecording`]
+#[derive(Clone, Debug, Default)]
+struct StorageAccess {
+    slot: H256,
+    previous_value: H256,
+    /// The value after the access, which is the previous one for reads
+    new_value: H256,
+    is_write: bool,
+}
+
+/// An account called or created while recording an [`AccessRecording`]
+#[derive(Clone, Debug, Default)]
+struct AccountAccess {
+    account: Address,
+    /// The balance before the call, i.e. before any value was transferred
+    old_balance: U256,
+    /// The balance after the call
+    new_balance: U256,
+    reverted: bool,
+    /// The storage accessed by the account's own code, in the order of the accesses
+    storage_accesses: Vec<StorageAccess>,
+}
+
+impl AccountAccess {
+    fn into_token(self) -> Token {
+        let storage_accesses = self
+            .storage_accesses
+            .into_iter()
+            .map(|access| {
+                Token::Tuple(vec![
+                    Token::FixedBytes(access.slot.as_bytes().to_vec()),
+                    Token::FixedBytes(access.previous_value.as_bytes().to_vec()),
+                    Token::FixedBytes(access.new_value.as_bytes().to_vec()),
+                    Token::Bool(access.is_write),
+                ])
             })
-            .map(|event| {
-                use HevmConsoleEvents::*;
-                match event {
-                    LogsFilter(inner) => format!("{}", inner.0),
-                    LogBytesFilter(inner) => format!("{}", inner.0),
-                    LogNamedAddressFilter(inner) => format!("{}: {:?}", inner.key, inner.val),
-                    LogNamedBytes32Filter(inner) => {
-                        format!("{}: 0x{}", inner.key, hex::encode(inner.val))
-                    }
-                    LogNamedDecimalIntFilter(inner) => format!(
-                        "{}: {:?}",
-                        inner.key,
-                        ethers::utils::parse_units(inner.val, inner.decimals.as_u32()).unwrap()
-                    ),
-                    LogNamedDecimalUintFilter(inner) => {
-                        format!(
-                            "{}: {:?}",
-                            inner.key,
-                            ethers::utils::parse_units(inner.val, inner.decimals.as_u32()).unwrap()
-                        )
-                    }
-                    LogNamedIntFilter(inner) => format!("{}: {:?}", inner.key, inner.val),
-                    LogNamedUintFilter(inner) => format!("{}: {:?}", inner.key, inner.val),
-                    LogNamedBytesFilter(inner) => {
-                        format!("{}: 0x{}", inner.key, hex::encode(inner.val))
-                    }
-                    LogNamedStringFilter(inner) => format!("{}: {}", inner.key, inner.val),
+            .collect();
+        Token::Tuple(vec![
+            Token::Address(self.account),
+            Token::Uint(self.old_balance),
+            Token::Uint(self.new_balance),
+            Token::Bool(self.reverted),
+            Token::Array(storage_accesses),
+        ])
+    }
+}
+
+/// The accounts accessed since `startStateDiffRecording`, returned by `stopAndReturnStateDiff`
+#[derive(Clone, Debug, Default)]
+struct AccessRecording {
+    /// The accessed accounts in the order their calls were made
+    accesses: Vec<AccountAccess>,
+    /// The index in `accesses` of every call which has not returned yet, innermost last
+    open: Vec<usize>,
+}
+
+/// The version of the cheatcodes returned by `cheatcodeVersion`, increased whenever cheatcodes are
+/// added or their behavior changes
+const CHEATCODE_VERSION: u64 = 13;
+
+/// Whether a cheatcode with the selector is implemented, and its feature is enabled
+fn is_cheatcode(selector: &[u8]) -> bool {
+    HEVM_ABI.functions().any(|func| {
+        func.short_signature()[..] == *selector &&
+            !DISABLED_CHEATCODES.contains(&func.name.as_str())
+    })
+}
 
-                    e => e.to_string(),
+/// The cheatcodes which revert because the crate was built without their feature
+const DISABLED_CHEATCODES: &[&str] = &[
+    #[cfg(not(feature = "fork-backend"))]
+    "createFork",
+    #[cfg(not(feature = "fork-backend"))]
+    "selectFork",
+    #[cfg(not(feature = "fork-backend"))]
+    "activeFork",
+    #[cfg(not(feature = "fork-backend"))]
+    "rollFork",
+    #[cfg(not(feature = "fork-backend"))]
+    "rpc",
+    #[cfg(not(feature = "cheatcodes-fs"))]
+    "getCode",
+    #[cfg(not(feature = "cheatcodes-fs"))]
+    "getDeployedCode",
+    #[cfg(not(feature = "cheatcodes-fs"))]
+    "dumpState",
+    #[cfg(not(feature = "cheatcodes-fs"))]
+    "loadAllocs",
+    #[cfg(not(feature = "cheatcodes-fs"))]
+    "projectRoot",
+    #[cfg(not(feature = "cheatcodes-fs"))]
+    "readDir",
+    #[cfg(not(feature = "cheatcodes-fs"))]
+    "exists",
+    #[cfg(not(feature = "cheatcodes-fs"))]
+    "isFile",
+    #[cfg(not(feature = "cheatcodes-fs"))]
+    "isDir",
+    #[cfg(not(feature = "cheatcodes-sign"))]
+    "signEd25519",
+    #[cfg(not(feature = "cheatcodes-sign"))]
+    "signBls",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseToml",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlBool",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlUint",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlInt",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlAddress",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlBytes32",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlString",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlBytes",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlBoolArray",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlUintArray",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlIntArray",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlAddressArray",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlBytes32Array",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlStringArray",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlBytesArray",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "parseTomlKeys",
+    #[cfg(not(feature = "cheatcodes-toml"))]
+    "keyExistsToml",
+    #[cfg(not(feature = "cheatcodes-prompt"))]
+    "prompt",
+    #[cfg(not(feature = "cheatcodes-prompt"))]
+    "promptSecret",
+    #[cfg(not(feature = "cheatcodes-prompt"))]
+    "promptUint",
+];
+
+/// The number of program counters kept per frame to locate an overflow with
+const RECENT_PCS: usize = 64;
+
+/// The `Panic(uint256)` code which solc's checked arithmetic reverts with
+const OVERFLOW_PANIC_CODE: u64 = 0x11;
+
+/// A frame executing code, whose program counter is followed opcode by opcode since the
+/// [`Handler`] hooks are not passed it
+#[derive(Clone, Debug, Default)]
+struct ExecutingFrame {
+    /// The program counter of the opcode about to be executed
+    pc: usize,
+    /// The program counters of the last executed opcodes, most recent last
+    recent: VecDeque<usize>,
+    /// The hash of the executed code, if its internal jumps are known
+    code_hash: Option<H256>,
+    /// The index of the executed code in the debug trace, if debugging is enabled
+    debug_code: Option<usize>,
+    /// The hash of the executed runtime code, if coverage is enabled
+    coverage_code: Option<H256>,
+    /// The program counters of the entries of the internal functions being executed, outermost
+    /// first
+    internal_calls: Vec<usize>,
+}
+
+impl ExecutingFrame {
+    /// Moves the program counter past the opcode, which is about to be executed, and follows the
+    /// jumps into and out of internal functions
+    fn step(&mut self, opcode: Opcode, stack: &Stack, jumps: Option<&InternalJumps>) {
+        if self.recent.len() == RECENT_PCS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(self.pc);
+
+        let dest = |n| stack.peek(n).map(|word| U256::from_big_endian(word.as_bytes()));
+        if let (0x56, Some(jumps)) = (opcode.0, jumps) {
+            if jumps.into.contains(&self.pc) {
+                if let Ok(dest) = dest(0) {
+                    self.internal_calls.push(dest.low_u64() as usize);
                 }
-            })
-            .chain(self.console_logs.clone())
-            .collect()
+            } else if jumps.out_of.contains(&self.pc) {
+                self.internal_calls.pop();
+            }
+        }
+        self.pc = match opcode.0 {
+            // JUMP
+            0x56 => dest(0).map(|dest| dest.low_u64() as usize).unwrap_or(self.pc + 1),
+            // JUMPI
+            0x57 => match (dest(0), dest(1)) {
+                (Ok(dest), Ok(condition)) if !condition.is_zero() => dest.low_u64() as usize,
+                _ => self.pc + 1,
+            },
+            // PUSH1..PUSH32 are followed by their immediate
+            push @ 0x60..=0x7f => self.pc + (push - 0x5f) as usize + 1,
+            _ => self.pc + 1,
+        };
     }
 }
 
+/// Returns whether the revert data is `Panic(0x11)`
+fn is_overflow_panic(retdata: &[u8]) -> bool {
+    retdata.len() == 36 &&
+        retdata[..4] == utils::id("Panic(uint256)") &&
+        U256::from_big_endian(&retdata[4..]) == OVERFLOW_PANIC_CODE.into()
+}
+
 /// A [`MemoryStackStateOwned`] state instantiated over a [`CheatcodeBackend`]
 pub type CheatcodeStackState<'a, B> = MemoryStackStateOwned<'a, CheatcodeBackend<B>>;
 
@@ -247,7 +790,39 @@ impl<'a, 'b, B: Backend, P: PrecompileSet>
 
         // create the executor and wrap it with the cheatcode handler
         let executor = StackExecutor::new_with_precompiles(state, config, precompiles);
-        let executor = CheatcodeHandler { handler: executor, enable_ffi, console_logs: Vec::new() };
+        let executor = CheatcodeHandler {
+            handler: executor,
+            enable_ffi,
+            enable_wall_clock: false,
+            console_logs: Vec::new(),
+            tracer: Default::default(),
+            steps: 0,
+            breakpoints: Vec::new(),
+            debug: None,
+            coverage: None,
+            pranks: Vec::new(),
+            nested_reverts: None,
+            allowed_mem_writes: BTreeMap::new(),
+            memory_words: BTreeMap::new(),
+            tx_caller: H160::zero(),
+            storage_before: BTreeMap::new(),
+            abort_on_failed_assertion: false,
+            failed_assertion: None,
+            aborted: false,
+            rpc_url: None,
+            artifacts: None,
+            project_root: None,
+            opcode_gas_costs: BTreeMap::new(),
+            seed: H256::zero(),
+            random_key: H256::zero(),
+            random_count: 0,
+            frames: Vec::new(),
+            overflow: None,
+            access_recording: None,
+            storage_reads: None,
+            return_types: BTreeMap::new(),
+            internal_jumps: BTreeMap::new(),
+        };
 
         let mut evm = Executor::from_executor(executor, gas_limit);
 
@@ -260,198 +835,1873 @@ impl<'a, 'b, B: Backend, P: PrecompileSet>
 
         evm
     }
+
+    /// Sets whether a failed ds-test assertion should immediately revert the test with the
+    /// assertion's error message, instead of continuing execution and only marking the test as
+    /// failed
+    #[must_use]
+    pub fn abort_on_failed_assertion(mut self, abort: bool) -> Self {
+        self.executor.abort_on_failed_assertion = abort;
+        self
+    }
+
+    /// Sets the RPC endpoint the backend forks from, to which the `rpc` cheatcode sends its
+    /// requests unless a fork was selected with `selectFork`
+    #[must_use]
+    pub fn rpc_url(mut self, url: Option<String>) -> Self {
+        self.executor.rpc_url = url;
+        self
+    }
+
+    /// Sets the directory from which the `getCode` and `getDeployedCode` cheatcodes read compiled
+    /// artifacts
+    #[must_use]
+    pub fn artifacts(mut self, path: PathBuf) -> Self {
+        self.executor.artifacts = Some(path);
+        self
+    }
+
+    /// Sets the project's root directory, which tests can build paths from with the
+    /// `projectRoot` cheatcode regardless of where they are run from
+    #[must_use]
+    pub fn project_root(mut self, path: PathBuf) -> Self {
+        self.executor.project_root = Some(path);
+        self
+    }
+
+    /// Sets the costs which are charged for the given opcodes instead of their fixed costs, as
+    /// returned by [`GasOverride::apply`](crate::sputnik::gas::GasOverride::apply)
+    #[must_use]
+    pub fn opcode_gas_costs(mut self, costs: BTreeMap<u8, u64>) -> Self {
+        self.executor.opcode_gas_costs = costs;
+        self
+    }
+
+    /// Sets whether the `sleep` and `unixTime` cheatcodes may be called. Like FFI, they make
+    /// tests depend on their environment, so they are disabled by default
+    #[must_use]
+    pub fn wall_clock(mut self, enabled: bool) -> Self {
+        self.executor.enable_wall_clock = enabled;
+        self
+    }
+
+    /// Sets the seed from which the `randomUint` and `randomAddress` cheatcodes derive their
+    /// values
+    #[must_use]
+    pub fn seed(mut self, seed: H256) -> Self {
+        self.executor.seed = seed;
+        self
+    }
 }
 
-// helper for creating an exit type
-fn evm_error(retdata: &str) -> Capture<(ExitReason, Vec<u8>), Infallible> {
-    Capture::Exit((
-        ExitReason::Revert(ExitRevert::Reverted),
-        ethers::abi::encode(&[Token::String(retdata.to_owned())]),
-    ))
+// helper for reverting with a cheatcode error, which is where it is turned into the revert reason
+// seen by the caller
+fn evm_error(err: impl Into<CheatcodeError>) -> Capture<(ExitReason, Vec<u8>), Infallible> {
+    Capture::Exit((ExitReason::Revert(ExitRevert::Reverted), err.into().encode()))
 }
 
-impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P> {
-    /// Given a transaction's calldata, it tries to parse it a console call and print the call
-    fn console_log(&mut self, input: Vec<u8>) -> Capture<(ExitReason, Vec<u8>), Infallible> {
-        // replacing hardhat style selectors (`uint`) with abigen style (`uint256`)
-        let input = patch_hardhat_console_log_selector(input);
-        let decoded = match ConsoleCalls::decode(&input) {
-            Ok(inner) => inner,
-            Err(err) => return evm_error(&err.to_string()),
-        };
-        self.console_logs.push(decoded.to_string());
-        Capture::Exit((ExitReason::Succeed(ExitSucceed::Stopped), vec![]))
+// helper for checking revert data against the data expected by `expectRevert`
+fn check_revert(expected: &[u8], is_prefix: bool, data: &[u8]) -> Result<(), CheatcodeError> {
+    // custom errors can be matched by their selector only, regardless of their arguments, and
+    // `expectRevert()` matches any revert
+    if is_prefix {
+        return if data.starts_with(expected) {
+            Ok(())
+        } else {
+            Err(CheatcodeError::RevertSelectorMismatch {
+                actual: data[..data.len().min(4)].to_vec(),
+                expected: expected.to_vec(),
+            })
+        }
     }
 
-    /// Given a transaction's calldata, it tries to parse it as an [`HEVM cheatcode`](super::HEVM)
-    /// call and modify the state accordingly.
-    fn apply_cheatcode(
-        &mut self,
-        input: Vec<u8>,
-        msg_sender: H160,
-    ) -> Capture<(ExitReason, Vec<u8>), Infallible> {
-        let mut res = vec![];
+    // revert strings are compared by their message. Data which has the `Error(string)` selector
+    // but does not decode, e.g. from a hand-written assembly revert, is compared byte by byte like
+    // any other revert data
+    let is_revert_string = data.len() >= 4 && data[0..4] == [8, 195, 121, 160];
+    if let Some(decoded_data) = is_revert_string.then(|| decode_revert_string(data)).flatten() {
+        return if decoded_data == expected {
+            Ok(())
+        } else {
+            Err(CheatcodeError::RevertStringMismatch {
+                actual: decoded_data,
+                expected: expected.to_vec(),
+            })
+        }
+    }
 
-        // Get a mutable ref to the state so we can apply the cheats
-        let state = self.state_mut();
-        let decoded = match HEVMCalls::decode(&input) {
-            Ok(inner) => inner,
-            Err(err) => return evm_error(&err.to_string()),
-        };
+    if data == expected {
+        Ok(())
+    } else if is_revert_string {
+        Err(CheatcodeError::MalformedRevertString {
+            actual: data.to_vec(),
+            expected: expected.to_vec(),
+        })
+    } else {
+        Err(CheatcodeError::RevertDataMismatch {
+            actual: data.to_vec(),
+            expected: expected.to_vec(),
+        })
+    }
+}
 
-        match decoded {
-            HEVMCalls::Warp(inner) => {
-                state.backend.cheats.block_timestamp = Some(inner.0);
-            }
-            HEVMCalls::Roll(inner) => {
-                state.backend.cheats.block_number = Some(inner.0);
-            }
-            HEVMCalls::Fee(inner) => {
-                state.backend.cheats.block_base_fee_per_gas = Some(inner.0);
-            }
-            HEVMCalls::Store(inner) => {
-                state.set_storage(inner.0, inner.1.into(), inner.2.into());
-            }
-            HEVMCalls::Load(inner) => {
-                res = state.storage(inner.0, inner.1.into()).0.to_vec();
-            }
-            HEVMCalls::Ffi(inner) => {
-                let args = inner.0;
-                // if FFI is not explicitly enabled at runtime, do not let this be called
-                // (we could have an FFI cheatcode executor instead but feels like
-                // over engineering)
-                if !self.enable_ffi {
-                    return evm_error(
-                        "ffi disabled: run again with --ffi if you want to allow tests to call external scripts",
-                    );
-                }
+// helper for decoding the message of `Error(string)` revert data, `None` if it is malformed
+fn decode_revert_string(data: &[u8]) -> Option<Vec<u8>> {
+    ethers::abi::decode(&[ParamType::Bytes], data.get(4..)?).ok()?.pop()?.into_bytes()
+}
 
-                // execute the command & get the stdout
-                let output = match Command::new(&args[0]).args(&args[1..]).output() {
-                    Ok(res) => res.stdout,
-                    Err(err) => return evm_error(&err.to_string()),
-                };
+/// Returns the data returned by a call whose revert was expected, given the types of its
+/// outputs if the called function is known: their zero values, which decode as the function's
+/// outputs however large or dynamic they are
+pub fn expected_revert_output(output_types: Option<&[ParamType]>) -> Vec<u8> {
+    match output_types {
+        Some(types) => ethers::abi::encode(&types.iter().map(zero_token).collect::<Vec<_>>()),
+        None => DUMMY_OUTPUT.to_vec(),
+    }
+}
 
-                // get the hex string & decode it
-                let output = unsafe { std::str::from_utf8_unchecked(&output) };
-                let decoded = match hex::decode(&output[2..]) {
-                    Ok(res) => res,
-                    Err(err) => return evm_error(&err.to_string()),
-                };
+// helper for the zero value of an ABI type, dynamic arrays and bytes being empty
+fn zero_token(param: &ParamType) -> Token {
+    match param {
+        ParamType::Address => Token::Address(Address::zero()),
+        ParamType::Bytes => Token::Bytes(Vec::new()),
+        ParamType::Int(_) => Token::Int(U256::zero()),
+        ParamType::Uint(_) => Token::Uint(U256::zero()),
+        ParamType::Bool => Token::Bool(false),
+        ParamType::String => Token::String(String::new()),
+        ParamType::Array(_) => Token::Array(Vec::new()),
+        ParamType::FixedBytes(size) => Token::FixedBytes(vec![0; *size]),
+        ParamType::FixedArray(param, size) => {
+            Token::FixedArray(std::iter::repeat_with(|| zero_token(param)).take(*size).collect())
+        }
+        ParamType::Tuple(params) => Token::Tuple(params.iter().map(zero_token).collect()),
+    }
+}
 
-                // encode the data as Bytes
-                res = ethers::abi::encode(&[Token::Bytes(decoded.to_vec())]);
-            }
-            HEVMCalls::Addr(inner) => {
-                let sk = inner.0;
-                if sk.is_zero() {
-                    return evm_error("Bad Cheat Code. Private Key cannot be 0.")
-                }
-                // 256 bit priv key -> 32 byte slice
-                let mut bs: [u8; 32] = [0; 32];
-                sk.to_big_endian(&mut bs);
-                let xsk = match SigningKey::from_bytes(&bs) {
-                    Ok(xsk) => xsk,
-                    Err(err) => return evm_error(&err.to_string()),
-                };
-                let addr = utils::secret_key_to_address(&xsk);
-                res = ethers::abi::encode(&[Token::Address(addr)]);
-            }
-            HEVMCalls::Sign(inner) => {
-                let sk = inner.0;
-                let digest = inner.1;
-                if sk.is_zero() {
-                    return evm_error("Bad Cheat Code. Private Key cannot be 0.")
-                }
-                // 256 bit priv key -> 32 byte slice
-                let mut bs: [u8; 32] = [0; 32];
-                sk.to_big_endian(&mut bs);
+// helper for ABI-encoding a string return value
+/// Returns the offset and size of the memory the opcode is about to write to, if it writes any
+fn memory_write(opcode: Opcode, stack: &Stack) -> Option<(U256, U256)> {
+    let peek = |n| stack.peek(n).ok().map(|value| U256::from_big_endian(value.as_bytes()));
+    match opcode.0 {
+        // MSTORE, MSTORE8
+        0x52 => Some((peek(0)?, 32.into())),
+        0x53 => Some((peek(0)?, 1.into())),
+        // CALLDATACOPY, CODECOPY, RETURNDATACOPY
+        0x37 | 0x39 | 0x3e => Some((peek(0)?, peek(2)?)),
+        // EXTCODECOPY
+        0x3c => Some((peek(1)?, peek(3)?)),
+        // the return data of CALL and CALLCODE, and of DELEGATECALL and STATICCALL
+        0xf1 | 0xf2 => Some((peek(5)?, peek(6)?)),
+        0xf4 | 0xfa => Some((peek(4)?, peek(5)?)),
+        _ => None,
+    }
+}
 
-                let xsk = match SigningKey::from_bytes(&bs) {
-                    Ok(xsk) => xsk,
-                    Err(err) => return evm_error(&err.to_string()),
-                };
-                let wallet = LocalWallet::from(xsk).with_chain_id(self.handler.chain_id().as_u64());
+/// Returns the end of the memory the opcode is about to read or write, which the memory is
+/// expanded to if it is smaller. Accesses of zero bytes end at 0 since they do not expand it
+fn memory_end(opcode: Opcode, stack: &Stack) -> Option<U256> {
+    let peek = |n| stack.peek(n).ok().map(|value| U256::from_big_endian(value.as_bytes()));
+    let end = |offset: U256, size: U256| {
+        if size.is_zero() {
+            U256::zero()
+        } else {
+            offset.saturating_add(size)
+        }
+    };
+    let end = match opcode.0 {
+        // MLOAD
+        0x51 => end(peek(0)?, 32.into()),
+        // SHA3, LOG0-4, RETURN, REVERT
+        0x20 | 0xa0..=0xa4 | 0xf3 | 0xfd => end(peek(0)?, peek(1)?),
+        // CREATE, CREATE2
+        0xf0 | 0xf5 => end(peek(1)?, peek(2)?),
+        // the arguments and return data of CALL and CALLCODE, and of DELEGATECALL and STATICCALL
+        0xf1 | 0xf2 => end(peek(3)?, peek(4)?).max(end(peek(5)?, peek(6)?)),
+        0xf4 | 0xfa => end(peek(2)?, peek(3)?).max(end(peek(4)?, peek(5)?)),
+        _ => {
+            let (offset, size) = memory_write(opcode, stack)?;
+            end(offset, size)
+        }
+    };
+    Some(end)
+}
 
-                // The EVM precompile does not use EIP-155
-                let sig = wallet.sign_hash(digest.into(), false);
+/// The gas charged for a memory of `words` words
+fn memory_gas(words: u64) -> u64 {
+    3 * words + words * words / 512
+}
 
-                let recovered = sig.recover(digest).unwrap();
-                assert_eq!(recovered, wallet.address());
+fn encode_string(s: String) -> Vec<u8> {
+    ethers::abi::encode(&[Token::String(s)])
+}
 
-                let mut r_bytes = [0u8; 32];
-                let mut s_bytes = [0u8; 32];
-                sig.r.to_big_endian(&mut r_bytes);
-                sig.s.to_big_endian(&mut s_bytes);
-                res = ethers::abi::encode(&[Token::Tuple(vec![
-                    Token::Uint(sig.v.into()),
-                    Token::FixedBytes(r_bytes.to_vec()),
-                    Token::FixedBytes(s_bytes.to_vec()),
-                ])]);
-            }
-            HEVMCalls::Prank(inner) => {
-                let caller = inner.0;
-                if let Some((orginal_pranker, caller, depth)) = self.state().msg_sender {
-                    let start_prank_depth = if let Some(depth) = self.state().metadata().depth() {
-                        depth + 1
-                    } else {
-                        0
-                    };
-                    // we allow someone to do a 1 time prank even when startPrank is set if
-                    // and only if we ensure that the startPrank *cannot* be applied to the
-                    // following call
-                    if start_prank_depth == depth && caller == orginal_pranker {
-                        return evm_error("You have an active `startPrank` at this frame depth already. Use either `prank` or `startPrank`, not both");
-                    }
-                }
-                self.state_mut().next_msg_sender = Some(caller);
-            }
-            HEVMCalls::StartPrank(inner) => {
-                // startPrank works by using frame depth to determine whether to overwrite
-                // msg.sender if we set a prank caller at a particular depth, it
-                // will continue to use the prank caller for any subsequent calls
-                // until stopPrank is called.
-                //
-                // We additionally have to store the original message sender of the cheatcode caller
-                // so that we dont apply it to any other addresses when depth ==
-                // prank_depth
-                let caller = inner.0;
-                if self.state().next_msg_sender.is_some() {
-                    return evm_error("You have an active `prank` call already. Use either `prank` or `startPrank`, not both");
-                } else {
-                    self.state_mut().msg_sender = Some((
-                        msg_sender,
-                        caller,
-                        if let Some(depth) = self.state().metadata().depth() {
-                            depth + 1
-                        } else {
-                            0
-                        },
-                    ));
-                }
-            }
-            HEVMCalls::StopPrank(_) => {
-                self.state_mut().msg_sender = None;
-            }
-            HEVMCalls::ExpectRevert(inner) => {
-                if self.state().expected_revert.is_some() {
-                    return evm_error(
-                        "You must call another function prior to expecting a second revert.",
-                    )
-                } else {
-                    self.state_mut().expected_revert = Some(inner.0.to_vec());
+/// Reads the hex encoded `field` of the artifact `id`, which is given as `Contract`,
+/// `Contract.sol` or `path/to/Contract.sol:Contract`
+#[cfg(feature = "cheatcodes-fs")]
+fn artifact_code(
+    artifacts: Option<&Path>,
+    id: &str,
+    field: &str,
+) -> Result<Vec<u8>, CheatcodeError> {
+    let artifacts = artifacts.ok_or(CheatcodeError::NoArtifacts)?;
+    let name = match id.rsplit_once(':') {
+        Some((_, name)) => name,
+        None => Path::new(id).file_stem().and_then(|stem| stem.to_str()).unwrap_or(id),
+    };
+    // artifacts are written to `<artifacts>/<name>.json`
+    let path = artifacts.join(format!("{}.json", name));
+    let display = path.display().to_string();
+    let artifact = std::fs::read_to_string(&path).map_err(|source| CheatcodeError::Io {
+        action: "read artifact",
+        path: display.clone(),
+        source,
+    })?;
+    let artifact: serde_json::Value = serde_json::from_str(&artifact).map_err(|source| {
+        CheatcodeError::JsonFile { action: "read artifact", path: display, source }
+    })?;
+    let code = artifact[field].as_str().ok_or_else(|| CheatcodeError::MissingArtifactField {
+        field: field.to_string(),
+        path: path.clone(),
+    })?;
+    hex::decode(code.trim_start_matches("0x"))
+        .map_err(|_| CheatcodeError::UnlinkedArtifact(id.to_string()))
+}
+
+/// Lists the entries of the directory, sorted so that tests iterate over them in a stable order.
+/// Each entry is prefixed with `path`, so that it can be read relative to the same directory
+#[cfg(feature = "cheatcodes-fs")]
+fn read_dir(path: &str) -> Result<Vec<String>, CheatcodeError> {
+    let mut entries = std::fs::read_dir(path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|source| CheatcodeError::Io {
+            action: "read directory",
+            path: path.to_string(),
+            source,
+        })?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Writes the accounts as a geth-style allocs file, which maps every address to its balance,
+/// nonce, code and storage
+#[cfg(feature = "cheatcodes-fs")]
+fn write_allocs(
+    path: &str,
+    accounts: &BTreeMap<H160, MemoryAccount>,
+) -> Result<(), CheatcodeError> {
+    let allocs = accounts
+        .iter()
+        .map(|(address, account)| {
+            let storage = account
+                .storage
+                .iter()
+                .map(|(slot, value)| (format!("{:?}", slot), serde_json::json!(value)))
+                .collect::<serde_json::Map<_, _>>();
+            let account = serde_json::json!({
+                "balance": account.balance,
+                "nonce": account.nonce,
+                "code": format!("0x{}", hex::encode(&account.code)),
+                "storage": storage,
+            });
+            (format!("{:?}", address), account)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let allocs = serde_json::to_string_pretty(&allocs).map_err(|source| {
+        CheatcodeError::JsonFile { action: "write", path: path.to_string(), source }
+    })?;
+    std::fs::write(path, allocs).map_err(|source| CheatcodeError::Io {
+        action: "write",
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Reads a geth-style allocs file, as written by [`write_allocs`]. Numbers may be hex strings,
+/// decimal strings or JSON numbers, and missing fields are left empty
+#[cfg(feature = "cheatcodes-fs")]
+fn read_allocs(path: &str) -> Result<BTreeMap<H160, MemoryAccount>, CheatcodeError> {
+    let allocs = std::fs::read_to_string(path).map_err(|source| CheatcodeError::Io {
+        action: "read",
+        path: path.to_string(),
+        source,
+    })?;
+    let allocs: BTreeMap<String, serde_json::Value> =
+        serde_json::from_str(&allocs).map_err(|source| CheatcodeError::JsonFile {
+            action: "parse",
+            path: path.to_string(),
+            source,
+        })?;
+
+    let invalid = |address: &str, field: &'static str| CheatcodeError::InvalidAlloc {
+        path: path.to_string(),
+        address: address.to_string(),
+        field,
+    };
+    let number = |value: &serde_json::Value| match value {
+        serde_json::Value::Null => Some(U256::zero()),
+        serde_json::Value::Number(n) => n.as_u64().map(U256::from),
+        serde_json::Value::String(s) => match s.strip_prefix("0x") {
+            Some(hex) => U256::from_str_radix(hex, 16).ok(),
+            None => U256::from_dec_str(s).ok(),
+        },
+        _ => None,
+    };
+    let word = |value: &serde_json::Value| {
+        let mut word = H256::zero();
+        number(value)?.to_big_endian(word.as_bytes_mut());
+        Some(word)
+    };
+
+    allocs
+        .iter()
+        .map(|(address, alloc)| {
+            let hex_address = address.strip_prefix("0x").unwrap_or(address);
+            let account_address = hex::decode(hex_address)
+                .ok()
+                .filter(|bytes| bytes.len() == 20)
+                .map(|bytes| H160::from_slice(&bytes))
+                .ok_or_else(|| CheatcodeError::InvalidAllocsAddress {
+                    path: path.to_string(),
+                    address: address.clone(),
+                })?;
+            let balance = number(&alloc["balance"]).ok_or_else(|| invalid(address, "balance"))?;
+            let nonce = number(&alloc["nonce"]).ok_or_else(|| invalid(address, "nonce"))?;
+            let code = match alloc["code"].as_str() {
+                Some(code) => hex::decode(code.strip_prefix("0x").unwrap_or(code))
+                    .map_err(|_| invalid(address, "code"))?,
+                None => Vec::new(),
+            };
+            let storage = alloc["storage"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(slot, value)| {
+                    let slot = word(&serde_json::Value::String(slot.clone()));
+                    slot.zip(word(value)).ok_or_else(|| invalid(address, "storage"))
+                })
+                .collect::<Result<_, _>>()?;
+            Ok((account_address, MemoryAccount { nonce, balance, storage, code }))
+        })
+        .collect()
+}
+
+/// Asks for input on the terminal, without echoing it if it is `secret`. Prompts are refused if
+/// stdin is not a terminal or `CI` is set, so that scripts fail instead of waiting forever
+#[cfg(feature = "cheatcodes-prompt")]
+fn prompt(message: &str, secret: bool) -> Result<String, CheatcodeError> {
+    if !atty::is(atty::Stream::Stdin) || std::env::var_os("CI").is_some() {
+        return Err(CheatcodeError::NonInteractive(message.to_string()))
+    }
+    let input = if secret {
+        rpassword::prompt_password_stderr(&format!("{} ", message))
+    } else {
+        eprint!("{} ", message);
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input).map(|_| input)
+    };
+    let input = input.map_err(CheatcodeError::ReadInput)?;
+    Ok(input.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+/// Runs the first argument as a command with the remaining ones as its arguments
+fn run_ffi(args: &[String]) -> Result<Output, CheatcodeError> {
+    let (program, args) = args.split_first().ok_or(CheatcodeError::FfiNoCommand)?;
+    Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|source| CheatcodeError::FfiSpawn { program: program.clone(), source })
+}
+
+/// Reads the environment variable `key` and returns its value ABI-encoded as `ty`
+fn env(key: &str, ty: ParamType) -> Result<Vec<u8>, CheatcodeError> {
+    let val = std::env::var(key)
+        .map_err(|source| CheatcodeError::EnvVar { key: key.to_string(), source })?;
+    let tokens = foundry_utils::parse_tokens(std::iter::once((&ty, val.as_str())), true)
+        .map_err(|_| CheatcodeError::EnvParse { key: key.to_string(), ty: ty.clone() })?;
+    Ok(ethers::abi::encode(&tokens))
+}
+
+impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P> {
+    /// Returns the account or storage slot which the opcode is about to access, and whether it is
+    /// still cold. Must be called before the opcode is charged for, which warms it up
+    fn state_access(
+        &self,
+        context: &Context,
+        opcode: Opcode,
+        stack: &Stack,
+    ) -> Option<StateAccess> {
+        let peek_address = |n| stack.peek(n).ok().map(H160::from);
+        let (name, address, slot) = match opcode.0 {
+            0x54 => ("SLOAD", context.address, Some(stack.peek(0).ok()?)),
+            0x55 => ("SSTORE", context.address, Some(stack.peek(0).ok()?)),
+            0x31 => ("BALANCE", peek_address(0)?, None),
+            0x3b => ("EXTCODESIZE", peek_address(0)?, None),
+            0x3c => ("EXTCODECOPY", peek_address(0)?, None),
+            0x3f => ("EXTCODEHASH", peek_address(0)?, None),
+            0xf1 => ("CALL", peek_address(1)?, None),
+            0xf2 => ("CALLCODE", peek_address(1)?, None),
+            0xf4 => ("DELEGATECALL", peek_address(1)?, None),
+            0xfa => ("STATICCALL", peek_address(1)?, None),
+            _ => return None,
+        };
+        Some(StateAccess {
+            opcode: name.to_string(),
+            address,
+            slot,
+            cold: self.is_cold(address, slot),
+        })
+    }
+
+    /// Returns the hash of the code if its internal jumps are known
+    #[cfg(feature = "tracing")]
+    fn internal_jumps_hash(&self, code: &[u8]) -> Option<H256> {
+        if self.internal_jumps.is_empty() {
+            return None
+        }
+        Some(H256::from(utils::keccak256(code)))
+            .filter(|hash| self.internal_jumps.contains_key(hash))
+    }
+
+    /// Internal calls are only followed for the traces
+    #[cfg(not(feature = "tracing"))]
+    fn internal_jumps_hash(&self, _code: &[u8]) -> Option<H256> {
+        None
+    }
+    /// Returns the gas used by the current frame's gasometer since it had `gas_before` left, i.e.
+    /// the gas used by a call or creation it just made
+    fn gas_used_since(&self, gas_before: u64) -> u64 {
+        gas_before.saturating_sub(self.state().metadata().gasometer().gas())
+    }
+
+    /// Records the internal functions the current frame is executing in the revert trace, before
+    /// it makes a call or creates a contract
+    fn record_internal_calls(&mut self) {
+        if let Some(frame) = self.frames.last() {
+            self.tracer.set_internal_calls(frame.internal_calls.clone());
+        }
+    }
+
+    /// Records a call to or the creation of `account` if a state diff is being recorded
+    fn enter_account_access(&mut self, account: Address) {
+        let old_balance = self.state().basic(account).balance;
+        if let Some(diff) = self.access_recording.as_mut() {
+            diff.open.push(diff.accesses.len());
+            diff.accesses.push(AccountAccess { account, old_balance, ..Default::default() });
+        }
+    }
+
+    /// Completes the record of the innermost call or creation which has not returned yet
+    fn exit_account_access(&mut self, success: bool) {
+        let state = self.handler.state();
+        if let Some(diff) = self.access_recording.as_mut() {
+            if let Some(index) = diff.open.pop() {
+                let access = &mut diff.accesses[index];
+                access.new_balance = state.basic(access.account).balance;
+                access.reverted = !success;
+            }
+        }
+    }
+
+    /// Records the storage slot the opcode is about to read or write in the state diff, as an
+    /// access of the innermost recorded call
+    fn record_storage_access(&mut self, context: &Context, opcode: Opcode, stack: &Stack) {
+        let is_write = match opcode.0 {
+            0x54 => false,
+            0x55 => true,
+            _ => return,
+        };
+        let slot = match stack.peek(0) {
+            Ok(slot) => slot,
+            Err(_) => return,
+        };
+        let previous_value = self.handler.storage(context.address, slot);
+        let new_value = if is_write { stack.peek(1).unwrap_or_default() } else { previous_value };
+        let diff = self.access_recording.as_mut().expect("recording a state diff");
+        if let Some(&index) = diff.open.last() {
+            diff.accesses[index].storage_accesses.push(StorageAccess {
+                slot,
+                previous_value,
+                new_value,
+                is_write,
+            });
+        }
+    }
+
+    /// Fails if the opcode is about to write to memory outside of the ranges which the current
+    /// frame was restricted to by `expectSafeMemory` or `expectSafeMemoryCall`. The scratch space
+    /// and the free memory pointer below 0x60 may always be written to
+    fn check_memory_write(&self, opcode: Opcode, stack: &Stack) -> Result<(), CheatcodeError> {
+        let depth = self.state().metadata().depth().unwrap_or_default();
+        let ranges = match self.allowed_mem_writes.get(&depth) {
+            Some(ranges) => ranges,
+            None => return Ok(()),
+        };
+        let (offset, size) = match memory_write(opcode, stack) {
+            Some((offset, size)) if !size.is_zero() => (offset, size),
+            _ => return Ok(()),
+        };
+        let end = offset.saturating_add(size);
+        let allowed = std::iter::once(&(0..0x60))
+            .chain(ranges)
+            .any(|range| offset >= range.start.into() && end <= range.end.into());
+        if allowed {
+            return Ok(())
+        }
+        let ranges = std::iter::once(&(0..0x60))
+            .chain(ranges)
+            .map(|range| format!("[{:#x}, {:#x})", range.start, range.end))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(CheatcodeError::UnsafeMemoryWrite { offset, size, ranges })
+    }
+
+    /// Calls a view function of `token` which returns a `uint256`, on behalf of `caller`
+    fn call_uint_view(
+        &mut self,
+        caller: H160,
+        token: H160,
+        calldata: Vec<u8>,
+    ) -> Result<U256, CheatcodeError> {
+        let context = Context { address: token, caller, apparent_value: U256::zero() };
+        match self.call_inner(token, None, calldata, None, true, false, false, context) {
+            Capture::Exit((ExitReason::Succeed(_), data)) if data.len() >= 32 => {
+                Ok(U256::from_big_endian(&data[..32]))
+            }
+            Capture::Exit(_) => Err(CheatcodeError::TokenCallFailed(token)),
+            Capture::Trap(_) => unreachable!("Trap is Infallible"),
+        }
+    }
+
+    /// Finds the storage slot of `token` whose value is returned by the view function, by
+    /// writing to every slot it reads until the function returns the written value. The slots
+    /// are tried starting with the last read, since mappings are usually read last
+    fn find_slot(
+        &mut self,
+        caller: H160,
+        token: H160,
+        calldata: Vec<u8>,
+    ) -> Result<H256, CheatcodeError> {
+        self.storage_reads = Some((token, Vec::new()));
+        let res = self.call_uint_view(caller, token, calldata.clone());
+        let (_, reads) = self.storage_reads.take().unwrap_or_default();
+        res?;
+
+        // an unlikely value, so that the function cannot return it by chance
+        let probe = H256::from(utils::keccak256(b"foundry dealToken probe"));
+        let mut tried = BTreeSet::new();
+        for slot in reads.into_iter().rev().filter(|slot| tried.insert(*slot)) {
+            let original = self.state().storage(token, slot);
+            self.state_mut().set_storage(token, slot, probe);
+            let value = self.call_uint_view(caller, token, calldata.clone());
+            self.state_mut().set_storage(token, slot, original);
+            if matches!(value, Ok(value) if value == U256::from(probe.as_bytes())) {
+                return Ok(slot)
+            }
+        }
+        Err(CheatcodeError::TokenSlotNotFound(token))
+    }
+
+    /// Sets the token balance of `to`, either in the given slot of the balance mapping or in the
+    /// slot `balanceOf(to)` is found to read, optionally adjusting the total supply by the change
+    fn deal_token(
+        &mut self,
+        caller: H160,
+        token: H160,
+        to: H160,
+        amount: U256,
+        adjust_total_supply: bool,
+        mapping_slot: Option<U256>,
+    ) -> Result<(), CheatcodeError> {
+        let balance_of =
+            [&utils::id("balanceOf(address)")[..], &ethers::abi::encode(&[Token::Address(to)])]
+                .concat();
+        let slot = match mapping_slot {
+            // solidity stores `mapping[key]` at `keccak256(key . slot)`
+            Some(slot) => H256::from(utils::keccak256(ethers::abi::encode(&[
+                Token::Address(to),
+                Token::Uint(slot),
+            ]))),
+            None => self.find_slot(caller, token, balance_of)?,
+        };
+        let word = |value: U256| {
+            let mut word = H256::zero();
+            value.to_big_endian(word.as_bytes_mut());
+            word
+        };
+        let old_balance = U256::from(self.state().storage(token, slot).as_bytes());
+        self.state_mut().set_storage(token, slot, word(amount));
+
+        if adjust_total_supply {
+            let total_supply = utils::id("totalSupply()").to_vec();
+            let slot = self.find_slot(caller, token, total_supply)?;
+            let total = U256::from(self.state().storage(token, slot).as_bytes());
+            let total = if amount >= old_balance {
+                total.checked_add(amount - old_balance)
+            } else {
+                total.checked_sub(old_balance - amount)
+            }
+            .ok_or(CheatcodeError::TotalSupplyOverflow)?;
+            self.state_mut().set_storage(token, slot, word(total));
+        }
+        Ok(())
+    }
+
+    /// The depth of the frames which calls from the current frame create
+    fn next_depth(&self) -> usize {
+        if let Some(depth) = self.state().metadata().depth() {
+            depth + 1
+        } else {
+            0
+        }
+    }
+
+    /// Makes every call made by `caller` at the current depth, until `stopPrank`, use `sender` as
+    /// its `msg.sender`, and optionally its delegatecalls and creates too
+    fn start_prank(
+        &mut self,
+        caller: H160,
+        sender: H160,
+        delegate_calls: bool,
+        creates: bool,
+    ) -> Result<(), CheatcodeError> {
+        if self.state().next_msg_sender.is_some() {
+            return Err(CheatcodeError::PrankActive)
+        }
+        // startPrank works by using frame depth to determine whether to overwrite msg.sender if
+        // we set a prank caller at a particular depth, it will continue to use the prank caller
+        // for any subsequent calls until stopPrank is called.
+        //
+        // We additionally have to store the original message sender of the cheatcode caller so
+        // that we dont apply it to any other addresses when depth == prank_depth
+        let depth = if let Some(depth) = self.state().metadata().depth() { depth + 1 } else { 0 };
+        let state = self.state_mut();
+        state.msg_sender = Some((caller, sender, depth));
+        state.prank_delegate_calls = delegate_calls;
+        state.prank_creates = creates;
+        Ok(())
+    }
+
+    /// Makes the next call or create, or with `persistent` every one of them made by `caller`
+    /// at the current depth, a transaction sent from `sender`
+    fn start_broadcast(
+        &mut self,
+        caller: H160,
+        sender: H160,
+        persistent: bool,
+    ) -> Result<(), CheatcodeError> {
+        if self.state().next_broadcast.is_some() || self.state().broadcast.is_some() {
+            return Err(CheatcodeError::BroadcastActive)
+        }
+        if persistent {
+            let depth = self.next_depth();
+            self.state_mut().broadcast = Some((caller, sender, depth));
+        } else {
+            self.state_mut().next_broadcast = Some(sender);
+        }
+        Ok(())
+    }
+
+    /// Returns the account a call or create made by `caller` is broadcast from, if any
+    fn broadcaster(&mut self, caller: H160) -> Option<H160> {
+        if let Some(sender) = self.state_mut().next_broadcast.take() {
+            return Some(sender)
+        }
+        match self.state().broadcast {
+            Some((original_caller, sender, depth))
+                if original_caller == caller && depth == self.next_depth() =>
+            {
+                Some(sender)
+            }
+            _ => None,
+        }
+    }
+
+    /// Collects a transaction sent from `sender` with its current nonce
+    fn record_broadcast(&mut self, sender: H160, to: Option<H160>, value: U256, data: Vec<u8>) {
+        let nonce = self.state().basic(sender).nonce;
+        let mut tx = TransactionRequest::new().from(sender).value(value).data(data).nonce(nonce);
+        if let Some(to) = to {
+            tx = tx.to(to);
+        }
+        self.state_mut().broadcastable_transactions.push(tx);
+    }
+
+    /// Expects the next log emitted by the calling contract to be emitted by its next call
+    fn expect_emit(&mut self, check_topics: [bool; 4], check_data: bool, emitter: Option<H160>) {
+        let depth = self.state().metadata().depth();
+        self.state_mut().expected_emits.push(ExpectedEmit {
+            depth,
+            log: None,
+            check_topics,
+            check_data,
+            emitter,
+            found: false,
+        });
+    }
+
+    /// Fills in the next expected log if it was emitted by the contract which expects it, and
+    /// otherwise marks the first expected log which was not emitted yet as found if it matches
+    fn record_emit(&mut self, address: H160, log: RawLog) {
+        let depth = self.state().metadata().depth();
+        let expected_emits = &mut self.state_mut().expected_emits;
+        if let Some(expected) = expected_emits
+            .iter_mut()
+            .find(|expected| expected.log.is_none() && expected.depth == depth)
+        {
+            expected.log = Some(log);
+        } else if let Some(expected) =
+            expected_emits.iter_mut().find(|expected| expected.log.is_some() && !expected.found)
+        {
+            if expected.matches(address, &log) {
+                expected.found = true;
+            }
+        }
+    }
+
+    /// Removes the expected logs of the contract at the depth once its call returned, returning
+    /// whether all of them were emitted
+    fn expected_emits_found(&mut self, depth: Option<usize>) -> bool {
+        let expected_emits = &mut self.state_mut().expected_emits;
+        let (done, pending): (Vec<_>, Vec<_>) = expected_emits
+            .drain(..)
+            .partition(|expected| expected.depth == depth && expected.log.is_some());
+        *expected_emits = pending;
+        done.iter().all(|expected| expected.found)
+    }
+
+    /// Starts the random values of a transaction with the given calldata
+    fn reset_random(&mut self, data: &[u8]) {
+        self.random_key = utils::keccak256([self.seed.as_bytes(), data].concat()).into();
+        self.random_count = 0;
+    }
+
+    /// Returns the next random value of the current transaction, which is the hash of the
+    /// transaction's key and the number of values returned before
+    fn next_random(&mut self) -> U256 {
+        let count = self.random_count.to_be_bytes();
+        self.random_count += 1;
+        U256::from(utils::keccak256([self.random_key.as_bytes(), &count].concat()))
+    }
+
+    /// Signs the digest with the secp256k1 private key `sk` and returns the ABI-encoded
+    /// `(v, r, s)` signature
+    fn sign_digest(&self, sk: U256, digest: [u8; 32]) -> Result<Vec<u8>, CheatcodeError> {
+        if sk.is_zero() {
+            return Err(CheatcodeError::ZeroPrivateKey)
+        }
+        // 256 bit priv key -> 32 byte slice
+        let mut bs: [u8; 32] = [0; 32];
+        sk.to_big_endian(&mut bs);
+
+        let xsk = SigningKey::from_bytes(&bs).map_err(CheatcodeError::InvalidPrivateKey)?;
+        let wallet = LocalWallet::from(xsk).with_chain_id(self.handler.chain_id().as_u64());
+
+        // The EVM precompile does not use EIP-155
+        let sig = wallet.sign_hash(digest.into(), false);
+
+        if sig.recover(digest).ok() != Some(wallet.address()) {
+            return Err(CheatcodeError::InvalidSignature)
+        }
+
+        let mut r_bytes = [0u8; 32];
+        let mut s_bytes = [0u8; 32];
+        sig.r.to_big_endian(&mut r_bytes);
+        sig.s.to_big_endian(&mut s_bytes);
+        Ok(ethers::abi::encode(&[Token::Tuple(vec![
+            Token::Uint(sig.v.into()),
+            Token::FixedBytes(r_bytes.to_vec()),
+            Token::FixedBytes(s_bytes.to_vec()),
+        ])]))
+    }
+
+    /// Given a transaction's calldata, it tries to parse it a console call and print the call
+    fn console_log(&mut self, input: Vec<u8>) -> Capture<(ExitReason, Vec<u8>), Infallible> {
+        // replacing hardhat style selectors (`uint`) with abigen style (`uint256`)
+        let input = patch_hardhat_console_log_selector(input);
+        let decoded = match ConsoleCalls::decode(&input) {
+            Ok(inner) => inner,
+            Err(err) => return evm_error(err),
+        };
+        self.console_logs.push(decoded.to_string());
+        Capture::Exit((ExitReason::Succeed(ExitSucceed::Stopped), vec![]))
+    }
+
+    /// Given a transaction's calldata, it tries to parse it as an [`HEVM cheatcode`](super::HEVM)
+    /// call and modify the state accordingly.
+    fn apply_cheatcode(
+        &mut self,
+        input: Vec<u8>,
+        msg_sender: H160,
+    ) -> Capture<(ExitReason, Vec<u8>), Infallible> {
+        let mut res = vec![];
+
+        // Get a mutable ref to the state so we can apply the cheats
+        let state = self.state_mut();
+        let decoded = match HEVMCalls::decode(&input) {
+            Ok(inner) => inner,
+            Err(_) if input.len() < 4 || !is_cheatcode(&input[..4]) => {
+                let selector = input.get(..4).unwrap_or(&input).to_vec();
+                return evm_error(CheatcodeError::UnknownCheatcode(selector))
+            }
+            Err(err) => return evm_error(err),
+        };
+        if let Some(deprecation) = interface::deprecation(&input[..4]) {
+            state.deprecations.insert(deprecation.to_string());
+        }
+
+        match decoded {
+            HEVMCalls::Warp(inner) => {
+                state.backend.cheats.block_timestamp = Some(inner.0);
+            }
+            HEVMCalls::Roll(inner) => {
+                state.backend.cheats.block_number = Some(inner.0);
+            }
+            HEVMCalls::WarpBy(inner) => {
+                match state.backend.block_timestamp().checked_add(inner.0) {
+                    Some(timestamp) => state.backend.cheats.block_timestamp = Some(timestamp),
+                    None => {
+                        return evm_error(CheatcodeError::Overflow {
+                            cheatcode: "warpBy",
+                            value: "timestamp",
+                        })
+                    }
+                }
+            }
+            HEVMCalls::RollBy(inner) => match state.backend.block_number().checked_add(inner.0) {
+                Some(number) => state.backend.cheats.block_number = Some(number),
+                None => {
+                    return evm_error(CheatcodeError::Overflow {
+                        cheatcode: "rollBy",
+                        value: "block number",
+                    })
+                }
+            },
+            HEVMCalls::GetBlockTimestamp(_) => {
+                res = ethers::abi::encode(&[Token::Uint(state.backend.block_timestamp())]);
+            }
+            HEVMCalls::GetBlockNumber(_) => {
+                res = ethers::abi::encode(&[Token::Uint(state.backend.block_number())]);
+            }
+            HEVMCalls::Fee(inner) => {
+                state.backend.cheats.block_base_fee_per_gas = Some(inner.0);
+            }
+            HEVMCalls::SetBlockhash(inner) => {
+                state.backend.cheats.block_hashes.insert(inner.0, inner.1.into());
+            }
+            HEVMCalls::Store(inner) => {
+                state.set_storage(inner.0, inner.1.into(), inner.2.into());
+            }
+            HEVMCalls::Load(inner) => {
+                res = state.storage(inner.0, inner.1.into()).0.to_vec();
+            }
+            HEVMCalls::Ffi(inner) => {
+                let args = inner.0;
+                // if FFI is not explicitly enabled at runtime, do not let this be called
+                // (we could have an FFI cheatcode executor instead but feels like
+                // over engineering)
+                if !self.enable_ffi {
+                    return evm_error(CheatcodeError::FfiDisabled)
+                }
+
+                // execute the command & get the stdout
+                let output = match run_ffi(&args) {
+                    Ok(output) => output,
+                    Err(err) => return evm_error(err),
+                };
+                if !output.status.success() {
+                    return evm_error(CheatcodeError::FfiFailed {
+                        status: output.status,
+                        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                    })
+                }
+
+                // 0x-prefixed hex output is decoded, anything else is returned as an ABI-encoded
+                // string
+                let output = match String::from_utf8(output.stdout) {
+                    Ok(output) => output,
+                    Err(err) => return evm_error(err),
+                };
+                let output = output.trim();
+                let decoded = match output.strip_prefix("0x").map(hex::decode) {
+                    Some(Ok(decoded)) => decoded,
+                    _ => ethers::abi::encode(&[Token::String(output.to_string())]),
+                };
+
+                // encode the data as Bytes
+                res = ethers::abi::encode(&[Token::Bytes(decoded)]);
+            }
+            HEVMCalls::TryFfi(inner) => {
+                if !self.enable_ffi {
+                    return evm_error(CheatcodeError::FfiDisabled)
+                }
+                let output = match run_ffi(&inner.0) {
+                    Ok(output) => output,
+                    Err(err) => return evm_error(err),
+                };
+                // processes killed by a signal have no exit code
+                let exit_code = output.status.code().unwrap_or(-1);
+                res = ethers::abi::encode(&[Token::Tuple(vec![
+                    Token::Int(I256::from(exit_code as i64).into_raw()),
+                    Token::Bytes(output.stdout),
+                    Token::Bytes(output.stderr),
+                ])]);
+            }
+            HEVMCalls::Addr(inner) => {
+                let sk = inner.0;
+                if sk.is_zero() {
+                    return evm_error(CheatcodeError::ZeroPrivateKey)
+                }
+                // 256 bit priv key -> 32 byte slice
+                let mut bs: [u8; 32] = [0; 32];
+                sk.to_big_endian(&mut bs);
+                let xsk = match SigningKey::from_bytes(&bs) {
+                    Ok(xsk) => xsk,
+                    Err(err) => return evm_error(CheatcodeError::InvalidPrivateKey(err)),
+                };
+                let addr = utils::secret_key_to_address(&xsk);
+                res = ethers::abi::encode(&[Token::Address(addr)]);
+            }
+            HEVMCalls::DeriveKey(inner) => {
+                // derives the key at `m/44'/60'/0'/0/{index}`, same as most wallets
+                let wallet = match MnemonicBuilder::<English>::default()
+                    .phrase(inner.0.as_str())
+                    .index(inner.1)
+                    .and_then(|builder| builder.build())
+                {
+                    Ok(wallet) => wallet,
+                    Err(err) => return evm_error(err),
+                };
+                let sk = U256::from_big_endian(wallet.signer().to_bytes().as_slice());
+                res = ethers::abi::encode(&[Token::Uint(sk)]);
+            }
+            HEVMCalls::Sign(inner) => match self.sign_digest(inner.0, inner.1) {
+                Ok(sig) => res = sig,
+                Err(err) => return evm_error(err),
+            },
+            HEVMCalls::SignTypedData(inner) => {
+                let digest = signing::eip712_digest(inner.1, inner.2);
+                match self.sign_digest(inner.0, digest) {
+                    Ok(sig) => res = sig,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-sign")]
+            HEVMCalls::SignEd25519(inner) => {
+                let (public, r, s) = signing::sign_ed25519(inner.0, &inner.1);
+                res = ethers::abi::encode(&[
+                    Token::FixedBytes(public.to_vec()),
+                    Token::FixedBytes(r.to_vec()),
+                    Token::FixedBytes(s.to_vec()),
+                ]);
+            }
+            #[cfg(feature = "cheatcodes-sign")]
+            HEVMCalls::SignBls(inner) => {
+                let mut key = [0u8; 32];
+                inner.0.to_big_endian(&mut key);
+                match signing::sign_bls(key, &inner.1) {
+                    Ok((public, signature)) => {
+                        res = ethers::abi::encode(&[Token::Bytes(public), Token::Bytes(signature)])
+                    }
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::Prank(inner) => {
+                let caller = inner.0;
+                if let Some((orginal_pranker, caller, depth)) = self.state().msg_sender {
+                    let start_prank_depth = if let Some(depth) = self.state().metadata().depth() {
+                        depth + 1
+                    } else {
+                        0
+                    };
+                    // we allow someone to do a 1 time prank even when startPrank is set if
+                    // and only if we ensure that the startPrank *cannot* be applied to the
+                    // following call
+                    if start_prank_depth == depth && caller == orginal_pranker {
+                        return evm_error(CheatcodeError::StartPrankActive)
+                    }
+                }
+                self.state_mut().next_msg_sender = Some(caller);
+            }
+            HEVMCalls::StartPrank0(inner) => {
+                if let Err(err) = self.start_prank(msg_sender, inner.0, false, false) {
+                    return evm_error(err)
+                }
+            }
+            HEVMCalls::StartPrank1(inner) => {
+                if let Err(err) = self.start_prank(msg_sender, inner.0, inner.1, inner.2) {
+                    return evm_error(err)
+                }
+            }
+            HEVMCalls::StopPrank(_) => {
+                self.state_mut().msg_sender = None;
+                self.state_mut().prank_delegate_calls = false;
+                self.state_mut().prank_creates = false;
+            }
+            HEVMCalls::ExpectRevert0(inner) => {
+                if self.state().expected_revert.is_some() {
+                    return evm_error(CheatcodeError::ExpectRevertActive)
+                } else {
+                    self.state_mut().expected_revert = Some(inner.0.to_vec());
+                    self.state_mut().expected_revert_is_prefix = false;
+                }
+            }
+            HEVMCalls::ExpectRevert1(inner) => {
+                if self.state().expected_revert.is_some() {
+                    return evm_error(CheatcodeError::ExpectRevertActive)
+                } else {
+                    self.state_mut().expected_revert = Some(inner.0.to_vec());
+                    self.state_mut().expected_revert_is_prefix = true;
+                }
+            }
+            HEVMCalls::ExpectRevert2(_) => {
+                if self.state().expected_revert.is_some() {
+                    return evm_error(CheatcodeError::ExpectRevertActive)
+                } else {
+                    self.state_mut().expected_revert = Some(Vec::new());
+                    self.state_mut().expected_revert_is_prefix = true;
+                }
+            }
+            HEVMCalls::ExpectRevert3(inner) => {
+                if self.state().expected_revert.is_some() {
+                    return evm_error(CheatcodeError::ExpectRevertActive)
+                } else {
+                    // only the calls made by the caller consume the expectation, like `startPrank`
+                    let depth = if let Some(depth) = self.state().metadata().depth() {
+                        depth + 1
+                    } else {
+                        0
+                    };
+                    self.state_mut().expected_revert = Some(inner.0.to_vec());
+                    self.state_mut().expected_revert_is_prefix = false;
+                    self.state_mut().expected_revert_depth = Some((depth, inner.1 as usize));
+                }
+            }
+            HEVMCalls::ExpectCall0(inner) => {
+                state.expected_calls.push(ExpectedCall {
+                    address: inner.0,
+                    calldata: inner.1.to_vec(),
+                    count: None,
+                    calls: 0,
+                });
+            }
+            HEVMCalls::ExpectCall1(inner) => {
+                state.expected_calls.push(ExpectedCall {
+                    address: inner.0,
+                    calldata: inner.1.to_vec(),
+                    count: Some(inner.2),
+                    calls: 0,
+                });
+            }
+            HEVMCalls::MockCall(inner) => {
+                let mock = MockedCall { reverts: false, data: inner.2.to_vec(), calls: 0 };
+                state.mocked_calls.entry(inner.0).or_default().insert(inner.1.to_vec(), mock);
+            }
+            HEVMCalls::MockCallRevert(inner) => {
+                let mock = MockedCall { reverts: true, data: inner.2.to_vec(), calls: 0 };
+                state.mocked_calls.entry(inner.0).or_default().insert(inner.1.to_vec(), mock);
+            }
+            HEVMCalls::ClearMockedCalls(_) => {
+                state.mocked_calls.clear();
+            }
+            HEVMCalls::ExpectEmit0(inner) => {
+                self.expect_emit([true, inner.0, inner.1, inner.2], inner.3, None);
+            }
+            HEVMCalls::ExpectEmit1(inner) => {
+                self.expect_emit([true, inner.0, inner.1, inner.2], inner.3, Some(inner.4));
+            }
+            HEVMCalls::ExpectEmitAnonymous0(inner) => {
+                self.expect_emit([inner.0, inner.1, inner.2, inner.3], inner.4, None);
+            }
+            HEVMCalls::ExpectEmitAnonymous1(inner) => {
+                self.expect_emit([inner.0, inner.1, inner.2, inner.3], inner.4, Some(inner.5));
+            }
+            HEVMCalls::Deal(inner) => {
+                let who = inner.0;
+                let value = inner.1;
+                state.reset_balance(who);
+                state.deposit(who, value);
+            }
+            HEVMCalls::DealToken0(inner) => {
+                if let Err(err) =
+                    self.deal_token(msg_sender, inner.0, inner.1, inner.2, false, None)
+                {
+                    return evm_error(err)
+                }
+            }
+            HEVMCalls::DealToken1(inner) => {
+                if let Err(err) =
+                    self.deal_token(msg_sender, inner.0, inner.1, inner.2, inner.3, None)
+                {
+                    return evm_error(err)
+                }
+            }
+            HEVMCalls::DealToken2(inner) => {
+                if let Err(err) =
+                    self.deal_token(msg_sender, inner.0, inner.1, inner.2, inner.3, Some(inner.4))
+                {
+                    return evm_error(err)
+                }
+            }
+            HEVMCalls::Etch(inner) => {
+                let who = inner.0;
+                let code = inner.1;
+                state.set_code(who, code.to_vec());
+            }
+            HEVMCalls::EnvBool(inner) => match env(&inner.0, ParamType::Bool) {
+                Ok(encoded) => res = encoded,
+                Err(err) => return evm_error(err),
+            },
+            HEVMCalls::EnvUint(inner) => match env(&inner.0, ParamType::Uint(256)) {
+                Ok(encoded) => res = encoded,
+                Err(err) => return evm_error(err),
+            },
+            HEVMCalls::EnvInt(inner) => match env(&inner.0, ParamType::Int(256)) {
+                Ok(encoded) => res = encoded,
+                Err(err) => return evm_error(err),
+            },
+            HEVMCalls::EnvAddress(inner) => match env(&inner.0, ParamType::Address) {
+                Ok(encoded) => res = encoded,
+                Err(err) => return evm_error(err),
+            },
+            HEVMCalls::EnvBytes32(inner) => match env(&inner.0, ParamType::FixedBytes(32)) {
+                Ok(encoded) => res = encoded,
+                Err(err) => return evm_error(err),
+            },
+            HEVMCalls::EnvString(inner) => match env(&inner.0, ParamType::String) {
+                Ok(encoded) => res = encoded,
+                Err(err) => return evm_error(err),
+            },
+            HEVMCalls::EnvBytes(inner) => match env(&inner.0, ParamType::Bytes) {
+                Ok(encoded) => res = encoded,
+                Err(err) => return evm_error(err),
+            },
+            HEVMCalls::SnapshotGasStart(inner) => {
+                let name = inner.0;
+                let depth = self.state().metadata().depth().unwrap_or_default();
+                let gas_left = self.state().metadata().gasometer().gas();
+                if self.state().active_gas_sections.contains_key(&name) {
+                    return evm_error(CheatcodeError::GasSectionStarted(name))
+                }
+                self.state_mut().active_gas_sections.insert(name, (gas_left, depth));
+            }
+            HEVMCalls::SnapshotGasEnd(inner) => {
+                let name = inner.0;
+                let depth = self.state().metadata().depth().unwrap_or_default();
+                let gas_left = self.state().metadata().gasometer().gas();
+                let (start_gas_left, start_depth) =
+                    match self.state_mut().active_gas_sections.remove(&name) {
+                        Some(section) => section,
+                        None => return evm_error(CheatcodeError::GasSectionNotStarted(name)),
+                    };
+                // the gas left is tracked per call frame, so the two measurements are only
+                // comparable if they were taken in the same frame
+                if start_depth != depth {
+                    return evm_error(CheatcodeError::GasSectionFrame(name))
+                }
+                let gas_used = start_gas_left.saturating_sub(gas_left);
+                self.state_mut().gas_sections.insert(name, gas_used);
+                res = ethers::abi::encode(&[Token::Uint(gas_used.into())]);
+            }
+            HEVMCalls::ExpectSafeMemory(inner) => {
+                let depth = self.state().metadata().depth().unwrap_or_default();
+                self.allowed_mem_writes.entry(depth).or_default().push(inner.0..inner.1);
+            }
+            HEVMCalls::ExpectSafeMemoryCall(inner) => {
+                let depth = self.state().metadata().depth().map_or(0, |depth| depth + 1);
+                self.allowed_mem_writes.entry(depth).or_default().push(inner.0..inner.1);
+            }
+            HEVMCalls::Breakpoint(inner) => {
+                let depth = self.state().metadata().depth().unwrap_or_default();
+                self.breakpoints.push(Breakpoint {
+                    label: inner.0,
+                    address: msg_sender,
+                    depth,
+                    step: self.steps,
+                });
+            }
+            HEVMCalls::Label(inner) => {
+                state.labels.insert(inner.0, inner.1);
+            }
+            HEVMCalls::GetLabel(inner) => {
+                let label = state.labels.get(&inner.0).cloned();
+                res = encode_string(label.unwrap_or_else(|| format!("{:?}", inner.0)));
+            }
+            HEVMCalls::Broadcast0(_) => {
+                let sender = self.tx_caller;
+                if let Err(err) = self.start_broadcast(msg_sender, sender, false) {
+                    return evm_error(err)
+                }
+            }
+            HEVMCalls::Broadcast1(inner) => {
+                if let Err(err) = self.start_broadcast(msg_sender, inner.0, false) {
+                    return evm_error(err)
+                }
+            }
+            HEVMCalls::StartBroadcast0(_) => {
+                let sender = self.tx_caller;
+                if let Err(err) = self.start_broadcast(msg_sender, sender, true) {
+                    return evm_error(err)
+                }
+            }
+            HEVMCalls::StartBroadcast1(inner) => {
+                if let Err(err) = self.start_broadcast(msg_sender, inner.0, true) {
+                    return evm_error(err)
+                }
+            }
+            HEVMCalls::StopBroadcast(_) => {
+                self.state_mut().broadcast = None;
+            }
+            HEVMCalls::RandomUint0(_) => {
+                res = ethers::abi::encode(&[Token::Uint(self.next_random())]);
+            }
+            HEVMCalls::RandomUint1(inner) => {
+                let (min, max) = (inner.0, inner.1);
+                if min > max {
+                    return evm_error(CheatcodeError::InvalidRange)
+                }
+                let random = self.next_random();
+                let value = match (max - min).checked_add(1.into()) {
+                    Some(range) => min + random % range,
+                    // the range covers every uint256
+                    None => random,
+                };
+                res = ethers::abi::encode(&[Token::Uint(value)]);
+            }
+            HEVMCalls::RandomAddress(_) => {
+                let mut random = [0u8; 32];
+                self.next_random().to_big_endian(&mut random);
+                res = ethers::abi::encode(&[Token::Address(Address::from_slice(&random[12..]))]);
+            }
+            HEVMCalls::Skip(inner) => {
+                if inner.0 {
+                    return evm_error(CheatcodeError::Skip)
+                }
+            }
+            HEVMCalls::PauseGasMetering(_) => {
+                state.gas_metering_paused = true;
+            }
+            HEVMCalls::ResumeGasMetering(_) => {
+                state.gas_metering_paused = false;
+            }
+            // `toString` is overloaded, so abigen suffixes each variant with its index
+            HEVMCalls::ToString0(inner) => {
+                res = encode_string(utils::to_checksum(&inner.0, None));
+            }
+            HEVMCalls::ToString1(inner) => {
+                res = encode_string(format!("0x{}", hex::encode(inner.0)));
+            }
+            HEVMCalls::ToString2(inner) => {
+                res = encode_string(format!("0x{}", hex::encode(inner.0)));
+            }
+            HEVMCalls::ToString3(inner) => {
+                res = encode_string(inner.0.to_string());
+            }
+            HEVMCalls::ToString4(inner) => {
+                res = encode_string(inner.0.to_string());
+            }
+            HEVMCalls::ToString5(inner) => {
+                res = encode_string(I256::from_raw(inner.0).to_string());
+            }
+            HEVMCalls::ParseJson(inner) => match json::parse_json(&inner.0, &inner.1) {
+                Ok(encoded) => res = ethers::abi::encode(&[Token::Bytes(encoded)]),
+                Err(err) => return evm_error(err),
+            },
+            HEVMCalls::ParseJsonBool(inner) => {
+                match json::parse_json_as(&inner.0, &inner.1, ParamType::Bool) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonUint(inner) => {
+                match json::parse_json_as(&inner.0, &inner.1, ParamType::Uint(256)) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonInt(inner) => {
+                match json::parse_json_as(&inner.0, &inner.1, ParamType::Int(256)) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonAddress(inner) => {
+                match json::parse_json_as(&inner.0, &inner.1, ParamType::Address) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonBytes32(inner) => {
+                match json::parse_json_as(&inner.0, &inner.1, ParamType::FixedBytes(32)) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonString(inner) => {
+                match json::parse_json_as(&inner.0, &inner.1, ParamType::String) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonBytes(inner) => {
+                match json::parse_json_as(&inner.0, &inner.1, ParamType::Bytes) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonBoolArray(inner) => {
+                match json::parse_json_array_as(&inner.0, &inner.1, ParamType::Bool) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonUintArray(inner) => {
+                match json::parse_json_array_as(&inner.0, &inner.1, ParamType::Uint(256)) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonIntArray(inner) => {
+                match json::parse_json_array_as(&inner.0, &inner.1, ParamType::Int(256)) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonAddressArray(inner) => {
+                match json::parse_json_array_as(&inner.0, &inner.1, ParamType::Address) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonBytes32Array(inner) => {
+                match json::parse_json_array_as(&inner.0, &inner.1, ParamType::FixedBytes(32)) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonStringArray(inner) => {
+                match json::parse_json_array_as(&inner.0, &inner.1, ParamType::String) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonBytesArray(inner) => {
+                match json::parse_json_array_as(&inner.0, &inner.1, ParamType::Bytes) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::ParseJsonKeys(inner) => match json::parse_json_keys(&inner.0, &inner.1) {
+                Ok(encoded) => res = encoded,
+                Err(err) => return evm_error(err),
+            },
+            HEVMCalls::KeyExists(inner) => match json::key_exists(&inner.0, &inner.1) {
+                Ok(exists) => res = ethers::abi::encode(&[Token::Bool(exists)]),
+                Err(err) => return evm_error(err),
+            },
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseToml(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json(&json, &inner.1))
+                {
+                    Ok(encoded) => res = ethers::abi::encode(&[Token::Bytes(encoded)]),
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlBool(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json_as(&json, &inner.1, ParamType::Bool))
+                {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlUint(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json_as(&json, &inner.1, ParamType::Uint(256)))
+                {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlInt(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json_as(&json, &inner.1, ParamType::Int(256)))
+                {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlAddress(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json_as(&json, &inner.1, ParamType::Address))
+                {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlBytes32(inner) => {
+                match json::toml_to_json(&inner.0).and_then(|json| {
+                    json::parse_json_as(&json, &inner.1, ParamType::FixedBytes(32))
+                }) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlString(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json_as(&json, &inner.1, ParamType::String))
+                {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlBytes(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json_as(&json, &inner.1, ParamType::Bytes))
+                {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlBoolArray(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json_array_as(&json, &inner.1, ParamType::Bool))
+                {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlUintArray(inner) => {
+                match json::toml_to_json(&inner.0).and_then(|json| {
+                    json::parse_json_array_as(&json, &inner.1, ParamType::Uint(256))
+                }) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlIntArray(inner) => {
+                match json::toml_to_json(&inner.0).and_then(|json| {
+                    json::parse_json_array_as(&json, &inner.1, ParamType::Int(256))
+                }) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlAddressArray(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json_array_as(&json, &inner.1, ParamType::Address))
+                {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlBytes32Array(inner) => {
+                match json::toml_to_json(&inner.0).and_then(|json| {
+                    json::parse_json_array_as(&json, &inner.1, ParamType::FixedBytes(32))
+                }) {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlStringArray(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json_array_as(&json, &inner.1, ParamType::String))
+                {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlBytesArray(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json_array_as(&json, &inner.1, ParamType::Bytes))
+                {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::ParseTomlKeys(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::parse_json_keys(&json, &inner.1))
+                {
+                    Ok(encoded) => res = encoded,
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-toml")]
+            HEVMCalls::KeyExistsToml(inner) => {
+                match json::toml_to_json(&inner.0)
+                    .and_then(|json| json::key_exists(&json, &inner.1))
+                {
+                    Ok(exists) => res = ethers::abi::encode(&[Token::Bool(exists)]),
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "fork-backend")]
+            HEVMCalls::CreateFork(inner) => {
+                if inner.1 > U256::from(u64::MAX) {
+                    return evm_error(CheatcodeError::InvalidForkBlock(inner.1))
+                }
+                match state.backend.cheats.forks.create(&inner.0, inner.1.as_u64()) {
+                    Ok(id) => res = ethers::abi::encode(&[Token::Uint(id.into())]),
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "fork-backend")]
+            HEVMCalls::SelectFork(inner) => {
+                let id =
+                    if inner.0 > U256::from(usize::MAX) { usize::MAX } else { inner.0.as_usize() };
+                let mut changes = match state.touched_accounts() {
+                    Ok(accounts) => accounts,
+                    Err(err) => return evm_error(CheatcodeError::State(err)),
+                };
+                let from = state.backend.cheats.forks.active_id();
+                if let Err(err) = state.backend.cheats.forks.select(id) {
+                    return evm_error(err)
+                }
+                // the test contract and the sender keep their state on every fork
+                let persistent = [msg_sender, state.origin()];
+                state.backend.cheats.persistent_accounts.extend(persistent);
+                if from != Some(id) {
+                    changes.retain(|address, _| !persistent.contains(address));
+                    state.swap_fork_changes(from, Some(id), changes);
+                }
+            }
+            #[cfg(feature = "fork-backend")]
+            HEVMCalls::ActiveFork(_) => match state.backend.cheats.forks.active_id() {
+                Some(id) => res = ethers::abi::encode(&[Token::Uint(id.into())]),
+                None => return evm_error(CheatcodeError::NoActiveFork),
+            },
+            #[cfg(feature = "fork-backend")]
+            HEVMCalls::RollFork0(inner) => {
+                if inner.0 > U256::from(u64::MAX) {
+                    return evm_error(CheatcodeError::InvalidForkBlock(inner.0))
+                }
+                if let Err(err) = state.backend.cheats.forks.roll(inner.0.as_u64()) {
+                    return evm_error(err)
+                }
+                // the block environment is now taken from the new block
+                state.backend.cheats.block_number = None;
+                state.backend.cheats.block_timestamp = None;
+                state.backend.cheats.block_base_fee_per_gas = None;
+            }
+            #[cfg(feature = "fork-backend")]
+            HEVMCalls::RollFork1(inner) => {
+                if let Err(err) = state.backend.cheats.forks.roll_to_transaction(inner.0.into()) {
+                    return evm_error(err)
+                }
+                state.backend.cheats.block_number = None;
+                state.backend.cheats.block_timestamp = None;
+                state.backend.cheats.block_base_fee_per_gas = None;
+            }
+            #[cfg(feature = "cheatcodes-fs")]
+            HEVMCalls::GetCode(inner) => {
+                match artifact_code(self.artifacts.as_deref(), &inner.0, "bin") {
+                    Ok(code) => res = ethers::abi::encode(&[Token::Bytes(code)]),
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-fs")]
+            HEVMCalls::GetDeployedCode(inner) => {
+                match artifact_code(self.artifacts.as_deref(), &inner.0, "bin-runtime") {
+                    Ok(code) => res = ethers::abi::encode(&[Token::Bytes(code)]),
+                    Err(err) => return evm_error(err),
+                }
+            }
+            #[cfg(feature = "cheatcodes-fs")]
+            HEVMCalls::DumpState(inner) => {
+                let mut accounts = match state.touched_accounts() {
+                    Ok(accounts) => accounts,
+                    Err(err) => return evm_error(CheatcodeError::State(err)),
+                };
+                // the placeholder code of the cheatcode addresses is not part of the chain state
+                accounts.remove(&*CHEATCODE_ADDRESS);
+                accounts.remove(&*CONSOLE_ADDRESS);
+                if let Err(err) = write_allocs(&inner.0, &accounts) {
+                    return evm_error(err)
+                }
+            }
+            #[cfg(feature = "cheatcodes-fs")]
+            HEVMCalls::LoadAllocs(inner) => {
+                let accounts = match read_allocs(&inner.0) {
+                    Ok(accounts) => accounts,
+                    Err(err) => return evm_error(err),
+                };
+                for (address, account) in accounts {
+                    // nonces can only be incremented
+                    let nonce = state.basic(address).nonce;
+                    if account.nonce < nonce || account.nonce > u64::MAX.into() {
+                        return evm_error(CheatcodeError::NonceDecrease {
+                            address,
+                            from: nonce,
+                            to: account.nonce,
+                        })
+                    }
+                    for _ in 0..(account.nonce - nonce).as_u64() {
+                        state.inc_nonce(address);
+                    }
+                    state.reset_balance(address);
+                    state.deposit(address, account.balance);
+                    state.set_code(address, account.code);
+                    for (slot, value) in account.storage {
+                        state.set_storage(address, slot, value);
+                    }
+                }
+            }
+            #[cfg(feature = "cheatcodes-fs")]
+            HEVMCalls::ProjectRoot(_) => match self.project_root {
+                Some(ref root) => res = encode_string(root.to_string_lossy().into_owned()),
+                None => return evm_error(CheatcodeError::NoProjectRoot),
+            },
+            #[cfg(feature = "cheatcodes-fs")]
+            HEVMCalls::ReadDir(inner) => match read_dir(&inner.0) {
+                Ok(entries) => {
+                    let entries = entries.into_iter().map(Token::String).collect();
+                    res = ethers::abi::encode(&[Token::Array(entries)]);
+                }
+                Err(err) => return evm_error(err),
+            },
+            #[cfg(feature = "cheatcodes-fs")]
+            HEVMCalls::Exists(inner) => {
+                res = ethers::abi::encode(&[Token::Bool(Path::new(&inner.0).exists())]);
+            }
+            #[cfg(feature = "cheatcodes-fs")]
+            HEVMCalls::IsFile(inner) => {
+                res = ethers::abi::encode(&[Token::Bool(Path::new(&inner.0).is_file())]);
+            }
+            #[cfg(feature = "cheatcodes-fs")]
+            HEVMCalls::IsDir(inner) => {
+                res = ethers::abi::encode(&[Token::Bool(Path::new(&inner.0).is_dir())]);
+            }
+            #[cfg(feature = "cheatcodes-prompt")]
+            HEVMCalls::Prompt(inner) => match prompt(&inner.0, false) {
+                Ok(input) => res = encode_string(input),
+                Err(err) => return evm_error(err),
+            },
+            #[cfg(feature = "cheatcodes-prompt")]
+            HEVMCalls::PromptSecret(inner) => match prompt(&inner.0, true) {
+                Ok(input) => res = encode_string(input),
+                Err(err) => return evm_error(err),
+            },
+            #[cfg(feature = "cheatcodes-prompt")]
+            HEVMCalls::PromptUint(inner) => {
+                let input = match prompt(&inner.0, false) {
+                    Ok(input) => input,
+                    Err(err) => return evm_error(err),
+                };
+                let input = input.trim();
+                let value = match input.strip_prefix("0x") {
+                    Some(hex) => U256::from_str_radix(hex, 16).ok(),
+                    None => U256::from_dec_str(input).ok(),
+                };
+                match value {
+                    Some(value) => res = ethers::abi::encode(&[Token::Uint(value)]),
+                    None => return evm_error(CheatcodeError::InvalidUint(input.to_owned())),
+                }
+            }
+            HEVMCalls::CopyStorage(inner) => {
+                let (from, to) = (inner.0, inner.1);
+                if from != to {
+                    let accounts = match state.touched_accounts() {
+                        Ok(accounts) => accounts,
+                        Err(err) => return evm_error(CheatcodeError::State(err)),
+                    };
+                    // slots which were never written are read from the chain state of `from`,
+                    // which can not be enumerated if it is forked
+                    let owner = state.backend.cheats.storage_aliases.get(&from).copied();
+                    state.backend.cheats.storage_aliases.insert(to, owner.unwrap_or(from));
+                    // the storage of a contract created in the test is not read from the backend
+                    state.unreset_storage(to);
+                    // the slots written to `to` so far would shadow the ones of `from`
+                    let written =
+                        |address| accounts.get(&address).map(|account| account.storage.clone());
+                    for slot in written(to).unwrap_or_default().into_keys() {
+                        let value = state.storage(from, slot);
+                        state.set_storage(to, slot, value);
+                    }
+                    for (slot, value) in written(from).unwrap_or_default() {
+                        state.set_storage(to, slot, value);
+                    }
+                }
+            }
+            HEVMCalls::ClearStorage(inner) => {
+                // a storage reset is not undone by later reads falling back to the chain state
+                state.backend.cheats.storage_aliases.remove(&inner.0);
+                state.reset_storage(inner.0);
+            }
+            HEVMCalls::ResetAccount(inner) => {
+                let who = inner.0;
+                state.backend.cheats.storage_aliases.remove(&who);
+                state.reset_storage(who);
+                state.set_code(who, Vec::new());
+                state.reset_balance(who);
+            }
+            HEVMCalls::Cool(inner) => {
+                state.cool(inner.0);
+            }
+            HEVMCalls::CheatcodeVersion(_) => {
+                res = ethers::abi::encode(&[Token::Uint(CHEATCODE_VERSION.into())]);
+            }
+            HEVMCalls::Supports(inner) => {
+                res = ethers::abi::encode(&[Token::Bool(is_cheatcode(&inner.0))]);
+            }
+            HEVMCalls::StartStateDiffRecording(_) => {
+                self.access_recording = Some(AccessRecording::default());
+            }
+            HEVMCalls::StopAndReturnStateDiff(_) => {
+                let mut diff = match self.access_recording.take() {
+                    Some(diff) => diff,
+                    None => return evm_error(CheatcodeError::NoStateDiff),
+                };
+                // calls which have not returned yet are reported with their current balance
+                for index in diff.open {
+                    let access = &mut diff.accesses[index];
+                    access.new_balance = self.state().basic(access.account).balance;
+                }
+                let accesses = diff.accesses.into_iter().map(AccountAccess::into_token).collect();
+                res = ethers::abi::encode(&[Token::Array(accesses)]);
+            }
+            HEVMCalls::Sleep(inner) => {
+                if !self.enable_wall_clock {
+                    return evm_error(CheatcodeError::WallClockDisabled)
+                }
+                std::thread::sleep(std::time::Duration::from_millis(inner.0.low_u64()));
+            }
+            HEVMCalls::UnixTime(_) => {
+                if !self.enable_wall_clock {
+                    return evm_error(CheatcodeError::WallClockDisabled)
+                }
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                res = ethers::abi::encode(&[Token::Uint(now.as_millis().into())]);
+            }
+            HEVMCalls::GetMerkleRoot(inner) => {
+                let leaves = inner.0.into_iter().map(H256::from).collect::<Vec<_>>();
+                match merkle::root(&leaves, inner.1) {
+                    Ok(root) => res = ethers::abi::encode(&[Token::FixedBytes(root.0.to_vec())]),
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::GetMerkleProof(inner) => {
+                let leaves = inner.0.into_iter().map(H256::from).collect::<Vec<_>>();
+                let index =
+                    if inner.1 > U256::from(usize::MAX) { usize::MAX } else { inner.1.as_usize() };
+                match merkle::proof(&leaves, index, inner.2) {
+                    Ok(proof) => {
+                        let proof = proof
+                            .into_iter()
+                            .map(|node| Token::FixedBytes(node.0.to_vec()))
+                            .collect();
+                        res = ethers::abi::encode(&[Token::Array(proof)]);
+                    }
+                    Err(err) => return evm_error(err),
+                }
+            }
+            HEVMCalls::VerifyMerkleProof(inner) => {
+                let proof = inner.1.into_iter().map(H256::from).collect::<Vec<_>>();
+                let index =
+                    if inner.3 > U256::from(usize::MAX) { usize::MAX } else { inner.3.as_usize() };
+                let valid = merkle::verify(inner.0.into(), &proof, inner.2.into(), index, inner.4);
+                res = ethers::abi::encode(&[Token::Bool(valid)]);
+            }
+            HEVMCalls::DecodeTx(inner) => {
+                let tx = match foundry_utils::decode_raw_transaction(&inner.0) {
+                    Ok(tx) => tx,
+                    Err(err) => return evm_error(CheatcodeError::InvalidTransaction(err)),
+                };
+                res = ethers::abi::encode(&[
+                    Token::Uint(tx.tx_type.into()),
+                    Token::Address(tx.from),
+                    Token::Address(tx.to.unwrap_or_default()),
+                    Token::Uint(tx.nonce),
+                    Token::Uint(tx.gas),
+                    Token::Uint(tx.value),
+                    Token::Bytes(tx.data.to_vec()),
+                    Token::Uint(tx.chain_id.unwrap_or_default().as_u64().into()),
+                ]);
+            }
+            #[cfg(feature = "fork-backend")]
+            HEVMCalls::Rpc(inner) => {
+                let (provider, throttle) = match state.backend.cheats.forks.active_provider() {
+                    Some((provider, throttle)) => (provider.clone(), throttle.clone()),
+                    None => match self.rpc_url.as_deref() {
+                        Some(url) => match runtime::http_provider(url) {
+                            Ok(provider) => (provider, runtime::throttle(Some(url))),
+                            Err(err) => return evm_error(CheatcodeError::Provider(err)),
+                        },
+                        None => return evm_error(CheatcodeError::NoRpcEndpoint),
+                    },
+                };
+                match forks::rpc(&provider, &throttle, &inner.0, &inner.1) {
+                    Ok(result) => res = ethers::abi::encode(&[Token::Bytes(result)]),
+                    Err(err) => return evm_error(err),
                 }
             }
-            HEVMCalls::Deal(inner) => {
-                let who = inner.0;
-                let value = inner.1;
-                state.reset_balance(who);
-                state.deposit(who, value);
+            #[cfg(not(feature = "fork-backend"))]
+            HEVMCalls::CreateFork(_) |
+            HEVMCalls::SelectFork(_) |
+            HEVMCalls::ActiveFork(_) |
+            HEVMCalls::RollFork0(_) |
+            HEVMCalls::RollFork1(_) |
+            HEVMCalls::Rpc(_) => return evm_error(CheatcodeError::FeatureDisabled("fork-backend")),
+            #[cfg(not(feature = "cheatcodes-fs"))]
+            HEVMCalls::GetCode(_) |
+            HEVMCalls::GetDeployedCode(_) |
+            HEVMCalls::DumpState(_) |
+            HEVMCalls::LoadAllocs(_) |
+            HEVMCalls::ProjectRoot(_) |
+            HEVMCalls::ReadDir(_) |
+            HEVMCalls::Exists(_) |
+            HEVMCalls::IsFile(_) |
+            HEVMCalls::IsDir(_) => {
+                return evm_error(CheatcodeError::FeatureDisabled("cheatcodes-fs"))
             }
-            HEVMCalls::Etch(inner) => {
-                let who = inner.0;
-                let code = inner.1;
-                state.set_code(who, code.to_vec());
+            #[cfg(not(feature = "cheatcodes-sign"))]
+            HEVMCalls::SignEd25519(_) | HEVMCalls::SignBls(_) => {
+                return evm_error(CheatcodeError::FeatureDisabled("cheatcodes-sign"))
+            }
+            #[cfg(not(feature = "cheatcodes-toml"))]
+            HEVMCalls::ParseToml(_) |
+            HEVMCalls::ParseTomlBool(_) |
+            HEVMCalls::ParseTomlUint(_) |
+            HEVMCalls::ParseTomlInt(_) |
+            HEVMCalls::ParseTomlAddress(_) |
+            HEVMCalls::ParseTomlBytes32(_) |
+            HEVMCalls::ParseTomlString(_) |
+            HEVMCalls::ParseTomlBytes(_) |
+            HEVMCalls::ParseTomlBoolArray(_) |
+            HEVMCalls::ParseTomlUintArray(_) |
+            HEVMCalls::ParseTomlIntArray(_) |
+            HEVMCalls::ParseTomlAddressArray(_) |
+            HEVMCalls::ParseTomlBytes32Array(_) |
+            HEVMCalls::ParseTomlStringArray(_) |
+            HEVMCalls::ParseTomlBytesArray(_) |
+            HEVMCalls::ParseTomlKeys(_) |
+            HEVMCalls::KeyExistsToml(_) => {
+                return evm_error(CheatcodeError::FeatureDisabled("cheatcodes-toml"))
+            }
+            #[cfg(not(feature = "cheatcodes-prompt"))]
+            HEVMCalls::Prompt(_) | HEVMCalls::PromptSecret(_) | HEVMCalls::PromptUint(_) => {
+                return evm_error(CheatcodeError::FeatureDisabled("cheatcodes-prompt"))
             }
         };
 
@@ -462,12 +2712,53 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
     // NB: This function is copy-pasted from uptream's `execute`, adjusted so that we call the
     // Runtime with our own handler
     pub fn execute(&mut self, runtime: &mut Runtime) -> ExitReason {
-        match runtime.run(self) {
-            Capture::Exit(s) => s,
-            Capture::Trap(_) => unreachable!("Trap is Infallible"),
+        if self.debug.is_none() {
+            return match runtime.run(self) {
+                Capture::Exit(s) => s,
+                Capture::Trap(_) => unreachable!("Trap is Infallible"),
+            }
+        }
+        // the runtime is stepped through one opcode at a time, so that the state of the machine
+        // can be recorded before each of them
+        loop {
+            self.record_debug_step(runtime);
+            match runtime.step(self) {
+                Ok(()) => continue,
+                Err(Capture::Exit(s)) => return s,
+                Err(Capture::Trap(_)) => unreachable!("Trap is Infallible"),
+            }
         }
     }
 
+    /// Records the opcode the runtime is about to execute in the debug trace
+    fn record_debug_step(&mut self, runtime: &Runtime) {
+        let machine = runtime.machine();
+        let (pc, (opcode, stack)) = match (machine.position(), machine.inspect()) {
+            (Ok(pc), Some(inspect)) => (*pc, inspect),
+            _ => return,
+        };
+        let step = DebugStep {
+            depth: self.state().metadata().depth().unwrap_or_default(),
+            address: runtime.context().address,
+            code: self.frames.last().and_then(|frame| frame.debug_code).unwrap_or_default(),
+            pc,
+            opcode: opcode.0,
+            gas_left: self.state().metadata().gasometer().gas(),
+            stack: stack.data().iter().map(|word| U256::from_big_endian(word.as_bytes())).collect(),
+            memory: 0,
+        };
+        if let Some(debug) = self.debug.as_mut() {
+            debug.push(step, machine.memory().data());
+        }
+    }
+
+    /// Adds the code a new frame is about to execute to the debug trace, returning its index
+    fn record_debug_code(&mut self, code: &[u8]) -> Option<usize> {
+        let debug = self.debug.as_mut()?;
+        debug.codes.push(code.to_vec());
+        Some(debug.codes.len() - 1)
+    }
+
     // NB: This function is copy-pasted from uptream's call_inner
     #[allow(clippy::too_many_arguments)]
     fn call_inner(
@@ -577,8 +2868,33 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
         // each cfg is about 200 bytes, is this a lot to clone? why does this error
         // not manifest upstream?
         let config = self.config().clone();
+        let code_hash = self.internal_jumps_hash(&code);
+        let debug_code = self.record_debug_code(&code);
+        let coverage_code = self.coverage.as_mut().map(|coverage| {
+            let hash = H256::from(utils::keccak256(&code));
+            coverage.codes.entry(hash).or_insert_with(|| code.clone());
+            hash
+        });
         let mut runtime = Runtime::new(Rc::new(code), Rc::new(input), context, &config);
+        self.frames.push(ExecutingFrame {
+            code_hash,
+            debug_code,
+            coverage_code,
+            ..Default::default()
+        });
         let reason = self.execute(&mut runtime);
+        let frame = self.frames.pop().unwrap_or_default();
+        self.tracer.set_internal_calls(frame.internal_calls);
+        if self.overflow.is_none() &&
+            matches!(reason, ExitReason::Revert(_)) &&
+            is_overflow_panic(&runtime.machine().return_value())
+        {
+            self.overflow = Some(OverflowSite {
+                address: code_address,
+                code: self.state().code(code_address),
+                pcs: frame.recent.into(),
+            });
+        }
         // // log::debug!(target: "evm", "Call execution using address {}: {:?}", code_address,
         // reason);
         match reason {
@@ -700,9 +3016,14 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> CheatcodeStackExecutor<'a, 'b, B, P>
         }
 
         let config = self.config().clone();
+        let debug_code = self.record_debug_code(&init_code);
         let mut runtime = Runtime::new(Rc::new(init_code), Rc::new(Vec::new()), context, &config);
 
+        // constructors are not located in the runtime code, but their program counter still has
+        // to be kept apart from the creating frame's
+        self.frames.push(ExecutingFrame { debug_code, ..Default::default() });
         let reason = self.execute(&mut runtime);
+        self.frames.pop();
         // log::debug!(target: "evm", "Create execution using address {}: {:?}", address, reason);
 
         match reason {
@@ -787,15 +3108,49 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> Handler for CheatcodeStackExecutor<'a
             self.console_log(input)
         } else {
             // modify execution context depending on the cheatcode
-            let expected_revert = self.state_mut().expected_revert.take();
+            let curr_depth =
+                if let Some(depth) = self.state().metadata().depth() { depth + 1 } else { 0 };
+            // `expectRevert(bytes,uint64)` is only consumed by a call of the expecting contract,
+            // and may expect the revert of one of the calls nested below it
+            let expected_revert = match self.state().expected_revert_depth {
+                Some((depth, _)) if depth != curr_depth => None,
+                _ => self.state_mut().expected_revert.take(),
+            };
+            let expected_revert_is_prefix = self.state().expected_revert_is_prefix;
+            let nested_revert_depth = match expected_revert {
+                Some(_) => self.state_mut().expected_revert_depth.take(),
+                None => None,
+            }
+            .map(|(_, below)| curr_depth + below)
+            .filter(|depth| *depth > curr_depth);
+            if let Some(depth) = nested_revert_depth {
+                self.nested_reverts = Some((depth, Vec::new()));
+            }
             let mut new_context = context;
             let mut new_transfer = transfer;
 
-            // handle `startPrank` - see apply_cheatcodes for more info
+            // every applied prank is recorded, so that tests can be audited for the senders
+            // they actually exercise
+            let pranked = |pranker, sender, persistent| PrankedCall {
+                pranker,
+                sender,
+                target: code_address,
+                input: input.clone().into(),
+                depth: curr_depth,
+                persistent,
+            };
+
+            // handle `startPrank` - see apply_cheatcodes for more info. A delegatecall runs in the
+            // context of the pranking contract, so it is recognized by its address instead
+            let is_delegate_call = code_address != new_context.address;
             if let Some((original_msg_sender, permanent_caller, depth)) = self.state().msg_sender {
-                let curr_depth =
-                    if let Some(depth) = self.state().metadata().depth() { depth + 1 } else { 0 };
-                if curr_depth == depth && new_context.caller == original_msg_sender {
+                if curr_depth == depth &&
+                    (new_context.caller == original_msg_sender ||
+                        (self.state().prank_delegate_calls &&
+                            is_delegate_call &&
+                            new_context.address == original_msg_sender))
+                {
+                    self.pranks.push(pranked(original_msg_sender, permanent_caller, true));
                     new_context.caller = permanent_caller;
 
                     if let Some(t) = &new_transfer {
@@ -810,6 +3165,7 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> Handler for CheatcodeStackExecutor<'a
 
             // handle normal `prank`
             if let Some(caller) = self.state_mut().next_msg_sender.take() {
+                self.pranks.push(pranked(new_context.caller, caller, false));
                 new_context.caller = caller;
 
                 if let Some(t) = &new_transfer {
@@ -818,58 +3174,113 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> Handler for CheatcodeStackExecutor<'a
                 }
             }
 
+            // handle `broadcast` and `startBroadcast`: the call is collected as a transaction
+            // and made from the broadcasting account, whose nonce it uses up
+            if let Some(sender) = self.broadcaster(new_context.caller) {
+                let value = new_transfer.as_ref().map(|t| t.value).unwrap_or_default();
+                self.record_broadcast(sender, Some(code_address), value, input.clone());
+                self.state_mut().inc_nonce(sender);
+
+                new_context.caller = sender;
+                if let Some(t) = &new_transfer {
+                    new_transfer =
+                        Some(Transfer { source: sender, target: t.target, value: t.value });
+                }
+            }
+
+            // the zero values of the called function's outputs are returned if the call reverts
+            // as expected, so that the caller can decode them
+            let output_types = expected_revert.as_ref().and_then(|_| {
+                let selector: Selector = input.get(..4)?.try_into().ok()?;
+                self.return_types.get(&selector).cloned()
+            });
+
             // perform the call
-            let res = self.call_inner(
-                code_address,
-                new_transfer,
-                input,
-                target_gas,
-                is_static,
-                true,
-                true,
-                new_context,
-            );
+            let depth = self.state().metadata().depth();
+            self.record_internal_calls();
+            self.tracer.enter(CallFrame {
+                address: code_address,
+                caller: new_context.caller,
+                value: new_context.apparent_value,
+                input: input.clone(),
+                create: false,
+                internal_calls: Vec::new(),
+            });
+            self.enter_account_access(new_context.address);
+            for expected in self.state_mut().expected_calls.iter_mut() {
+                if expected.address == code_address && input.starts_with(&expected.calldata) {
+                    expected.calls += 1;
+                }
+            }
+            // mocked calls return or revert right away, without executing any code
+            let mock = self.state_mut().mocked_call(code_address, &input).map(|mock| {
+                mock.calls += 1;
+                mock.clone()
+            });
+            let gas_before = self.state().metadata().gasometer().gas();
+            let res = match mock {
+                Some(MockedCall { reverts: true, data, .. }) => {
+                    Capture::Exit((ExitReason::Revert(ExitRevert::Reverted), data))
+                }
+                Some(MockedCall { reverts: false, data, .. }) => {
+                    Capture::Exit((ExitReason::Succeed(ExitSucceed::Returned), data))
+                }
+                None => self.call_inner(
+                    code_address,
+                    new_transfer,
+                    input,
+                    target_gas,
+                    is_static,
+                    true,
+                    true,
+                    new_context,
+                ),
+            };
+            // the memory writes of a frame are only restricted until it returns
+            self.allowed_mem_writes.remove(&curr_depth);
+            self.memory_words.remove(&curr_depth);
+            if let Capture::Exit((ref reason, ref retdata)) = res {
+                let gas_used = self.gas_used_since(gas_before);
+                self.tracer.exit(matches!(reason, ExitReason::Succeed(_)), retdata, gas_used);
+                self.exit_account_access(matches!(reason, ExitReason::Succeed(_)));
+                if let (ExitReason::Revert(_), Some((revert_depth, reverts))) =
+                    (reason, &mut self.nested_reverts)
+                {
+                    if *revert_depth == curr_depth {
+                        reverts.push(retdata.clone());
+                    }
+                }
+                if !self.expected_emits_found(depth) {
+                    return evm_error(CheatcodeError::ExpectedEmitNotFound)
+                }
+            }
 
             if let Some(expected_revert) = expected_revert {
+                let nested_reverts = match nested_revert_depth {
+                    Some(_) => self.nested_reverts.take().map(|(_, reverts)| reverts),
+                    None => None,
+                };
                 let final_res = match res {
                     Capture::Exit((ExitReason::Revert(_e), data)) => {
-                        if data.len() >= 4 && data[0..4] == [8, 195, 121, 160] {
-                            // its a revert string
-                            let decoded_data =
-                                ethers::abi::decode(&[ethers::abi::ParamType::Bytes], &data[4..])
-                                    .expect("String error code, but not actual string");
-                            let decoded_data = decoded_data[0]
-                                .clone()
-                                .into_bytes()
-                                .expect("Can never fail because it is bytes");
-                            if decoded_data == *expected_revert {
-                                return Capture::Exit((
-                                    ExitReason::Succeed(ExitSucceed::Returned),
-                                    DUMMY_OUTPUT.to_vec(),
-                                ))
-                            } else {
-                                return evm_error(&*format!(
-                                    "Error != expected error: '{}' != '{}'",
-                                    String::from_utf8_lossy(&decoded_data[..]),
-                                    String::from_utf8_lossy(&expected_revert)
-                                ))
+                        // with a nested depth, any call which reverted at that depth may match,
+                        // whatever the expecting call reverted with
+                        let mut result = Err(CheatcodeError::DidNotRevert);
+                        for data in nested_reverts.unwrap_or_else(|| vec![data]) {
+                            result =
+                                check_revert(&expected_revert, expected_revert_is_prefix, &data);
+                            if result.is_ok() {
+                                break
                             }
                         }
-
-                        if data == *expected_revert {
-                            Capture::Exit((
+                        match result {
+                            Ok(()) => Capture::Exit((
                                 ExitReason::Succeed(ExitSucceed::Returned),
-                                DUMMY_OUTPUT.to_vec(),
-                            ))
-                        } else {
-                            evm_error(&*format!(
-                                "Error data != expected error data: 0x{} != 0x{}",
-                                hex::encode(data),
-                                hex::encode(expected_revert)
-                            ))
+                                expected_revert_output(output_types.as_deref()),
+                            )),
+                            Err(err) => evm_error(err),
                         }
                     }
-                    _ => evm_error("Expected revert call did not revert"),
+                    _ => evm_error(CheatcodeError::DidNotRevert),
                 };
                 final_res
             } else {
@@ -962,10 +3373,43 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> Handler for CheatcodeStackExecutor<'a
     }
 
     fn set_storage(&mut self, address: H160, index: H256, value: H256) -> Result<(), ExitError> {
-        self.handler.set_storage(address, index, value)
+        if !self.storage_before.contains_key(&(address, index)) {
+            let before = self.handler.storage(address, index);
+            self.storage_before.insert((address, index), before);
+        }
+        self.handler.set_storage(address, index, value)?;
+
+        // ds-test's `fail()` is the first storage write after an assertion's error logs, so
+        // this is where we halt if the test should not continue after a failed assertion
+        match self.failed_assertion {
+            Some(ref failed) if !self.aborted && failed.address == address => {
+                self.aborted = true;
+                Err(ExitError::Other("ds-test assertion failed".into()))
+            }
+            _ => Ok(()),
+        }
     }
 
     fn log(&mut self, address: H160, topics: Vec<H256>, data: Vec<u8>) -> Result<(), ExitError> {
+        if self.abort_on_failed_assertion && !self.aborted {
+            let log = RawLog { topics: topics.clone(), data: data.clone() };
+            if let Ok(event) = HevmConsoleEvents::decode_log(&log) {
+                match self.failed_assertion {
+                    // collect the expected / actual values logged after the error message
+                    Some(ref mut failed) => {
+                        if failed.address == address &&
+                            FailedAssertion::from_event(address, &event).is_none()
+                        {
+                            let detail = format_log(event).unwrap_or_else(|err| err.to_string());
+                            failed.details.push(detail.trim().to_string());
+                        }
+                    }
+                    None => self.failed_assertion = FailedAssertion::from_event(address, &event),
+                }
+            }
+        }
+        self.record_emit(address, RawLog { topics: topics.clone(), data: data.clone() });
+        self.tracer.log(RawLog { topics: topics.clone(), data: data.clone() });
         self.handler.log(address, topics, data)
     }
 
@@ -981,22 +3425,174 @@ impl<'a, 'b, B: Backend, P: PrecompileSet> Handler for CheatcodeStackExecutor<'a
         init_code: Vec<u8>,
         target_gas: Option<u64>,
     ) -> Capture<(ExitReason, Option<H160>, Vec<u8>), Self::CreateInterrupt> {
-        self.handler.create(caller, scheme, value, init_code, target_gas)
+        // handle `startPrank` with creates: the contract is deployed by the pranked sender, from
+        // whose nonce its address is derived
+        let curr_depth =
+            if let Some(depth) = self.state().metadata().depth() { depth + 1 } else { 0 };
+        let (caller, scheme) = match self.state().msg_sender {
+            Some((original_msg_sender, permanent_caller, depth))
+                if self.state().prank_creates &&
+                    curr_depth == depth &&
+                    caller == original_msg_sender =>
+            {
+                let scheme = match scheme {
+                    CreateScheme::Legacy { .. } => {
+                        CreateScheme::Legacy { caller: permanent_caller }
+                    }
+                    CreateScheme::Create2 { code_hash, salt, .. } => {
+                        CreateScheme::Create2 { caller: permanent_caller, code_hash, salt }
+                    }
+                    scheme => scheme,
+                };
+                self.pranks.push(PrankedCall {
+                    pranker: original_msg_sender,
+                    sender: permanent_caller,
+                    target: self.create_address(scheme),
+                    input: init_code.clone().into(),
+                    depth: curr_depth,
+                    persistent: true,
+                });
+                (permanent_caller, scheme)
+            }
+            _ => (caller, scheme),
+        };
+        let (caller, scheme) = match (self.broadcaster(caller), scheme) {
+            (Some(sender), CreateScheme::Legacy { .. }) => {
+                self.record_broadcast(sender, None, value, init_code.clone());
+                (sender, CreateScheme::Legacy { caller: sender })
+            }
+            // an account can only deploy to deterministic addresses through a factory contract
+            (Some(_), _) => {
+                return Capture::Exit((
+                    ExitReason::Revert(ExitRevert::Reverted),
+                    None,
+                    encode_string("`create2` cannot be broadcast".to_string()),
+                ))
+            }
+            (None, scheme) => (caller, scheme),
+        };
+        let address = self.create_address(scheme);
+        self.record_internal_calls();
+        self.tracer.enter(CallFrame {
+            address,
+            caller,
+            value,
+            input: init_code.clone(),
+            create: true,
+            internal_calls: Vec::new(),
+        });
+        self.enter_account_access(address);
+        let gas_before = self.state().metadata().gasometer().gas();
+        let res = self.handler.create(caller, scheme, value, init_code, target_gas);
+        self.allowed_mem_writes.remove(&curr_depth);
+        self.memory_words.remove(&curr_depth);
+        if let Capture::Exit((ref reason, _, ref retdata)) = res {
+            let gas_used = self.gas_used_since(gas_before);
+            self.tracer.exit(matches!(reason, ExitReason::Succeed(_)), retdata, gas_used);
+            self.exit_account_access(matches!(reason, ExitReason::Succeed(_)));
+        }
+        res
     }
 
     fn pre_validate(
         &mut self,
         context: &Context,
-        opcode: sputnik::Opcode,
-        stack: &sputnik::Stack,
+        opcode: Opcode,
+        stack: &Stack,
     ) -> Result<(), ExitError> {
-        self.handler.pre_validate(context, opcode, stack)
+        self.steps += 1;
+        if let (Some(coverage), Some(frame)) = (self.coverage.as_mut(), self.frames.last()) {
+            if let Some(code) = frame.coverage_code {
+                coverage.hit(code, frame.pc);
+                if opcode.0 == 0x57 {
+                    let jumped = stack.peek(1).map_or(false, |condition| !condition.is_zero());
+                    coverage.branch(code, frame.pc, jumped);
+                }
+            }
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            let jumps = frame.code_hash.and_then(|hash| self.internal_jumps.get(&hash));
+            frame.step(opcode, stack, jumps);
+        }
+        if self.access_recording.is_some() {
+            self.record_storage_access(context, opcode, stack);
+        }
+        if !self.allowed_mem_writes.is_empty() {
+            self.check_memory_write(opcode, stack)
+                .map_err(|err| ExitError::Other(err.to_string().into()))?;
+        }
+        if let Some((account, ref mut reads)) = self.storage_reads {
+            if opcode.0 == 0x54 && context.address == account {
+                reads.extend(stack.peek(0));
+            }
+        }
+        // before EIP-2929 all accesses cost the same
+        let access = if self.config().increase_state_access_gas {
+            self.state_access(context, opcode, stack)
+        } else {
+            None
+        };
+        if let Some(ref access) = access {
+            self.tracer.access(access.clone());
+        }
+        let gas_left = self.state().metadata().gasometer().gas();
+        self.handler.pre_validate(context, opcode, stack)?;
+        // once charged for, an account or slot made cold by `cool` is warm again
+        if let Some(access) = access {
+            self.state_mut().warm(access.address, access.slot);
+        }
+        // the opcode could pay for the memory expansion, so the memory is small enough
+        let depth = self.state().metadata().depth().unwrap_or_default();
+        let words_before = self.memory_words.get(&depth).copied().unwrap_or_default();
+        let words = match memory_end(opcode, stack) {
+            Some(end) if end <= U256::from(u32::MAX) => words_before.max((end.as_u64() + 31) / 32),
+            _ => words_before,
+        };
+        if words != words_before {
+            self.memory_words.insert(depth, words);
+        }
+        if self.state().gas_metering_paused {
+            // give back what the opcode cost, so that the gas left stays the same while metering
+            // is paused. The memory expansion gas is not part of the used gas the stipend is
+            // taken from, so it is still charged
+            let charged = gas_left - self.state().metadata().gasometer().gas();
+            let cost = charged.saturating_sub(memory_gas(words) - memory_gas(words_before));
+            self.state_mut().metadata_mut().gasometer_mut().record_stipend(cost)?;
+        } else if let Some(&cost) = self.opcode_gas_costs.get(&opcode.0) {
+            // charge the overridden cost instead of the fixed one
+            let charged = gas_left - self.state().metadata().gasometer().gas();
+            let gasometer = self.state_mut().metadata_mut().gasometer_mut();
+            if cost > charged {
+                gasometer.record_cost(cost - charged)?;
+            } else {
+                gasometer.record_stipend(charged - cost)?;
+            }
+        }
+        Ok(())
     }
 }
 
-#[cfg(test)]
+// the Solidity tests exercise every cheatcode and trace, so they need all the default features
+#[cfg(all(
+    test,
+    feature = "fork-backend",
+    feature = "fuzz",
+    feature = "cheatcodes-fs",
+    feature = "cheatcodes-sign",
+    feature = "cheatcodes-toml",
+    feature = "cheatcodes-prompt",
+    feature = "tracing"
+))]
 mod tests {
-    use crate::{fuzz::FuzzedExecutor, sputnik::helpers::vm, test_helpers::COMPILED, Evm};
+    use crate::{
+        fuzz::FuzzedExecutor,
+        sputnik::{
+            helpers::{new_backend, new_vicinity, vm},
+            CustomPrecompiles, PRECOMPILES_MAP,
+        },
+        test_helpers::COMPILED,
+        Evm,
+    };
 
     use super::*;
 
@@ -1080,7 +3676,52 @@ mod tests {
 
     #[test]
     fn cheatcodes() {
+        // the `env*` cheatcode tests read these
+        std::env::set_var("FOUNDRY_CHEATCODES_BOOL", "true");
+        std::env::set_var("FOUNDRY_CHEATCODES_UINT", "1337");
+        std::env::set_var("FOUNDRY_CHEATCODES_INT", "-1337");
+        std::env::set_var(
+            "FOUNDRY_CHEATCODES_ADDRESS",
+            "0x7109709ECfa91a80626fF3989D68f67F5b1DD12D",
+        );
+        std::env::set_var(
+            "FOUNDRY_CHEATCODES_BYTES32",
+            "0x0000000000000000000000000000000000000000000000000000000000000539",
+        );
+        std::env::set_var("FOUNDRY_CHEATCODES_STRING", "hello world");
+        std::env::set_var("FOUNDRY_CHEATCODES_BYTES", "0x1337");
+        std::env::set_var("FOUNDRY_CHEATCODES_MALFORMED", "not a number");
+
         let mut evm = vm();
+
+        // the `getCode` and `getDeployedCode` cheatcode tests use the artifact of `Greeter`
+        let artifacts = std::env::temp_dir().join("foundry-cheatcodes-artifacts");
+        std::fs::create_dir_all(&artifacts).unwrap();
+        let greeter = COMPILED.find("Greeter").expect("could not find contract");
+        let artifact = serde_json::json!({
+            "bin": greeter.bytecode().unwrap(),
+            "bin-runtime": greeter.bin_runtime.unwrap(),
+        });
+        std::fs::write(artifacts.join("Greeter.json"), artifact.to_string()).unwrap();
+        evm.executor.artifacts = Some(artifacts);
+        // the `projectRoot` cheatcode test reads this crate's files through it
+        evm.executor.project_root = Some(PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+        evm.executor.enable_wall_clock = true;
+        // calls to `ExpectRevert` which revert as expected return its functions' output types
+        let target = COMPILED.find("ExpectRevert").expect("could not find contract");
+        evm.set_return_types(
+            target
+                .abi
+                .as_ref()
+                .unwrap()
+                .functions()
+                .map(|func| {
+                    let outputs = func.outputs.iter().map(|param| param.kind.clone()).collect();
+                    (func.short_signature(), outputs)
+                })
+                .collect(),
+        );
+
         let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
         let (addr, _, _, _) =
             evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
@@ -1121,6 +3762,501 @@ mod tests {
         }
     }
 
+    #[test]
+    #[ignore = "needs ETH_RPC_URL"]
+    fn copies_forked_storage() {
+        use crate::{
+            runtime,
+            sputnik::{helpers::new_backend, vicinity, ForkMemoryBackend, PRECOMPILES_MAP},
+            test_helpers::rpc_url,
+        };
+        use ethers::providers::{Http, Provider};
+
+        let provider = Provider::<Http>::try_from(rpc_url()).unwrap();
+        let block = Some(13292465);
+        let vicinity = runtime::block_on(vicinity(&provider, block)).unwrap();
+        let backend = new_backend(&vicinity, Default::default());
+        let backend = ForkMemoryBackend::new(provider, backend, block, Default::default());
+        let cfg = Config::london();
+        let mut evm =
+            Executor::new_with_cheatcodes(backend, 30_000_000, &cfg, &*PRECOMPILES_MAP, false);
+
+        let compiled = COMPILED.find("ForkedCheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        let (_, reason, _, _) = evm
+            .call::<(), _, _>(Address::zero(), addr, "testCopyForkedStorage()", (), 0.into())
+            .unwrap();
+        assert!(evm.check_success(addr, &reason, false));
+    }
+
+    #[test]
+    #[ignore = "needs ETH_RPC_URL"]
+    fn isolates_forks() {
+        use crate::test_helpers::rpc_url;
+
+        let mut evm = vm();
+        let compiled = COMPILED.find("ForkedCheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        let (_, reason, _, _) = evm
+            .call::<(), _, _>(
+                Address::zero(),
+                addr,
+                "testForkIsolation(string)",
+                rpc_url(),
+                0.into(),
+            )
+            .unwrap();
+        assert!(evm.check_success(addr, &reason, false));
+    }
+
+    #[test]
+    fn unused_expectations() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let (_, reason, _, _) = evm
+            .call::<(), _, _>(Address::zero(), addr, "testFailDanglingStartPrank()", (), 0.into())
+            .unwrap();
+        assert_eq!(
+            evm.unused_expectations(),
+            vec![format!(
+                "`startPrank({:?})` was never stopped with `stopPrank`",
+                Address::from_low_u64_be(1337)
+            )]
+        );
+        assert!(!evm.check_success(addr, &reason, false));
+
+        // downgrading them lets the test pass
+        evm.clear_expectations();
+        assert!(evm.unused_expectations().is_empty());
+        assert!(evm.check_success(addr, &reason, false));
+    }
+
+    #[test]
+    fn unused_mocked_calls() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let (_, reason, _, _) = evm
+            .call::<(), _, _>(Address::zero(), addr, "testFailUnusedMockCall()", (), 0.into())
+            .unwrap();
+        let unused = evm.unused_expectations();
+        assert_eq!(unused.len(), 1);
+        assert!(unused[0].starts_with("`mockCallRevert(0x"));
+        let selector = hex::encode(ethers::utils::id("stringErr2(uint256)"));
+        assert!(unused[0].ends_with(&format!(", 0x{})` was never called", selector)));
+        // fails `testFail`, so the test passes
+        assert!(evm.check_success(addr, &reason, true));
+
+        evm.clear_expectations();
+        assert!(evm.unused_expectations().is_empty());
+    }
+
+    #[test]
+    fn gas_sections() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        evm.call::<(), _, _>(Address::zero(), addr, "testSnapshotGas()", (), 0.into()).unwrap();
+        let sections = evm.gas_sections();
+        assert_eq!(sections.keys().collect::<Vec<_>>(), vec!["cold", "warm"]);
+        // a cold SSTORE costs more than a warm one
+        assert!(sections["cold"] > sections["warm"]);
+    }
+
+    #[test]
+    fn dumps_state() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let path = std::env::temp_dir().join("foundry-cheatcodes-allocs.json");
+        let path = path.to_str().unwrap().to_string();
+        evm.call::<(), _, _>(
+            Address::zero(),
+            addr,
+            "dumpStateTo(string)",
+            (path.clone(),),
+            0.into(),
+        )
+        .unwrap();
+
+        let allocs: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let dealt = &allocs[format!("{:?}", Address::from_low_u64_be(0x1337))];
+        assert_eq!(dealt["balance"], serde_json::json!(U256::exp10(18)));
+
+        let test = &allocs[format!("{:?}", addr)];
+        assert_eq!(test["code"], format!("0x{}", hex::encode(evm.code(addr))));
+        let slot = format!("{:?}", H256::from_low_u64_be(1337));
+        assert_eq!(test["storage"][slot], serde_json::json!(H256::from_low_u64_be(1)));
+
+        assert!(allocs.get(format!("{:?}", *CHEATCODE_ADDRESS)).is_none());
+    }
+
+    #[test]
+    fn expected_revert_output_matches_output_types() {
+        let types = [ParamType::Array(Box::new(ParamType::Uint(256))), ParamType::String];
+        let output = expected_revert_output(Some(&types));
+        let decoded = ethers::abi::decode(&types, &output).unwrap();
+        assert_eq!(decoded, vec![Token::Array(Vec::new()), Token::String(String::new())]);
+
+        let types = [ParamType::FixedArray(Box::new(ParamType::Uint(256)), 12)];
+        assert!(ethers::abi::decode(&types, &expected_revert_output(Some(&types))).is_ok());
+        assert_eq!(expected_revert_output(None), DUMMY_OUTPUT.to_vec());
+    }
+
+    #[test]
+    fn wall_clock_is_disabled_by_default() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let err =
+            evm.call::<(), _, _>(Address::zero(), addr, "testSleep()", (), 0.into()).unwrap_err();
+        match err {
+            crate::EvmError::Execution { reason, .. } => {
+                assert_eq!(reason, CheatcodeError::WallClockDisabled.to_string())
+            }
+            _ => panic!("unexpected error variant"),
+        }
+    }
+
+    #[test]
+    fn loads_allocs() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        evm.call::<(), _, _>(Address::zero(), addr, "testLoadAllocs()", (), 0.into()).unwrap();
+        let loaded = Address::from_low_u64_be(0x1338);
+        assert_eq!(evm.state().basic(loaded).nonce, 2.into());
+        assert_eq!(evm.code(loaded).to_vec(), hex::decode("602a60005260206000f3").unwrap());
+    }
+
+    #[test]
+    fn annotates_cold_and_warm_accesses() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        evm.call::<(), _, _>(Address::zero(), addr, "testSnapshotGas()", (), 0.into()).unwrap();
+        let trace = evm.call_trace();
+        let sstores = trace.calls[0]
+            .accesses
+            .iter()
+            .filter(|access| access.opcode == "SSTORE")
+            .map(|access| (access.address, access.cold))
+            .collect::<Vec<_>>();
+        // `gasSectionSlot` is written twice
+        assert_eq!(sstores, vec![(addr, true), (addr, false)]);
+
+        let summary = trace.access_summary();
+        assert_eq!((summary.cold_slots, summary.warm_slots), (1, 1));
+        // the cheatcode address is called four times
+        assert_eq!(summary.cold_accounts, 1);
+        assert_eq!(summary.warm_accounts, 3);
+    }
+
+    #[test]
+    fn records_the_call_tree() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        evm.call::<(), _, _>(Address::zero(), addr, "testExpectSafeMemoryCall()", (), 0.into())
+            .unwrap();
+        let trace = evm.call_trace();
+        let (root, call) = trace.roots().next().unwrap();
+        assert_eq!((call.frame.address, call.frame.caller), (addr, Address::zero()));
+
+        // the `MemoryWriter` is deployed and called twice, cheatcode calls are not traced
+        let children = trace.children(root).map(|(_, call)| call).collect::<Vec<_>>();
+        assert_eq!(children.len(), 3);
+        assert!(children[0].frame.create);
+        let writer = children[0].frame.address;
+        let selector = ethers::utils::id("write(uint256)");
+        for call in &children[1..] {
+            assert_eq!((call.frame.address, call.frame.caller), (writer, addr));
+            assert_eq!(call.frame.selector(), Some(selector));
+            assert!(call.success);
+        }
+        assert!(children.iter().all(|call| call.gas_used > 0));
+        assert!(children.iter().map(|call| call.gas_used).sum::<u64>() < call.gas_used);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        evm.call::<(), _, _>(Address::zero(), addr, "testBreakpoint()", (), 0.into()).unwrap();
+        let breakpoints = evm.breakpoints();
+        assert_eq!(
+            breakpoints.iter().map(|b| b.label.as_str()).collect::<Vec<_>>(),
+            vec!["start", "end"]
+        );
+        assert!(breakpoints.iter().all(|b| b.address == addr));
+        assert_eq!(breakpoints[0].depth, breakpoints[1].depth);
+        assert!(breakpoints[0].step < breakpoints[1].step);
+
+        let trace = evm.call_trace();
+        assert_eq!(trace.calls.len(), 1);
+        assert!(trace.calls[0].success);
+        assert_eq!(trace.calls[0].frame.address, addr);
+        // `gasSectionSlot` was set to 1
+        let (_, after) = trace.storage_diff.values().next().unwrap();
+        assert_eq!(*after, H256::from_low_u64_be(1));
+
+        // breakpoints are only kept for the last call
+        evm.call::<(), _, _>(Address::zero(), addr, "testSkipFalse()", (), 0.into()).unwrap();
+        assert!(evm.breakpoints().is_empty());
+    }
+
+    #[test]
+    fn records_coverage() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        evm.set_coverage(true);
+
+        // hits accumulate over transactions
+        for _ in 0..2 {
+            evm.call::<(), _, _>(Address::zero(), addr, "testBreakpoint()", (), 0.into()).unwrap();
+        }
+        let coverage = evm.coverage();
+        let code = H256::from(utils::keccak256(evm.code(addr)));
+        assert_eq!(coverage.codes[&code], evm.code(addr).to_vec());
+        assert_eq!(coverage.pcs[&code][&0], 2);
+        // the dispatcher branches on the selector
+        assert!(coverage.branches[&code].values().any(|&(taken, not_taken)| taken + not_taken > 0));
+    }
+
+    #[test]
+    fn diffs_state() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("Greeter").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        let before = evm.state().clone();
+        evm.call::<(), _, _>(Address::zero(), addr, "greet(string)", "hi".to_owned(), 0.into())
+            .unwrap();
+        let diff = evm.state_diff(&before);
+        // short strings are stored in a single slot along with their length
+        let greeter = &diff.accounts[&addr];
+        assert_eq!(greeter.storage.len(), 1);
+        let (old, new) = greeter.storage[&H256::zero()];
+        assert_eq!(old, H256::zero());
+        assert_eq!(&new[..2], b"hi");
+        assert_eq!((greeter.balance, greeter.code), (None, None));
+
+        assert!(evm.state_diff(evm.state()).accounts.is_empty());
+    }
+
+    #[test]
+    fn runs_custom_precompiles() {
+        let block_number = U256::from(42);
+        let precompiles = CustomPrecompiles::new(PRECOMPILES_MAP.clone()).with(
+            // Arbitrum's `ArbSys`
+            Address::from_low_u64_be(0x64),
+            move |_input, _gas_limit, _context, _is_static| {
+                let mut output = [0u8; 32];
+                block_number.to_big_endian(&mut output);
+                Ok(PrecompileOutput {
+                    exit_status: ExitSucceed::Returned,
+                    output: output.to_vec(),
+                    cost: 100,
+                    logs: Vec::new(),
+                })
+            },
+        );
+        let (cfg, vicinity) = (Config::london(), new_vicinity());
+        let backend = new_backend(&vicinity, Default::default());
+        let mut evm = Executor::new_with_cheatcodes(backend, 30_000_000, &cfg, &precompiles, false);
+
+        // `arbBlockNumber()`
+        let (output, reason, _, _) = evm
+            .call_raw(
+                Address::zero(),
+                Address::from_low_u64_be(0x64),
+                vec![0xa3, 0xb1, 0xb3, 0x1d].into(),
+                0.into(),
+                false,
+            )
+            .unwrap();
+        assert!(matches!(reason, ExitReason::Succeed(_)));
+        assert_eq!(U256::from_big_endian(&output), block_number);
+
+        // the extended precompiles are still available
+        let (output, _, _, _) = evm
+            .call_raw(
+                Address::zero(),
+                Address::from_low_u64_be(4),
+                vec![1, 2, 3].into(),
+                0.into(),
+                false,
+            )
+            .unwrap();
+        assert_eq!(output.to_vec(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn records_debug_steps() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        // nothing is recorded unless debugging
+        evm.call::<(), _, _>(Address::zero(), addr, "testBreakpoint()", (), 0.into()).unwrap();
+        assert!(evm.debug_trace().steps.is_empty());
+
+        evm.set_debug(true);
+        evm.call::<(), _, _>(Address::zero(), addr, "testBreakpoint()", (), 0.into()).unwrap();
+        let debug = evm.debug_trace();
+        let first = &debug.steps[0];
+        assert_eq!((first.depth, first.address, first.pc), (0, addr, 0));
+        assert_eq!(debug.codes[first.code], evm.code(addr).to_vec());
+        assert!(debug.steps.windows(2).all(|steps| steps[0].gas_left >= steps[1].gas_left));
+        // the memory starts out empty, and is only copied when an opcode changed it
+        assert!(debug.memory(first).is_empty());
+        assert!(debug.memories.len() < debug.steps.len());
+
+        // breakpoints point at the call of the cheatcode
+        for breakpoint in evm.breakpoints() {
+            assert_eq!(debug.steps[breakpoint.step - 1].mnemonic(), "CALL");
+        }
+    }
+
+    #[test]
+    fn records_pranks() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        let sender = Address::from_low_u64_be(1337);
+
+        evm.call::<(), _, _>(Address::zero(), addr, "testPrankStart()", (), 0.into()).unwrap();
+        let pranks = evm.pranks();
+        // the third call is made after `stopPrank`
+        assert_eq!(pranks.len(), 2);
+        assert!(pranks.iter().all(|prank| prank.persistent));
+        assert!(pranks.iter().all(|prank| prank.pranker == addr && prank.sender == sender));
+        assert_eq!(pranks[0].target, pranks[1].target);
+        assert_eq!(pranks[0].depth, 1);
+        assert_eq!(pranks[0].input.as_ref()[..4], utils::id("bar(address)"));
+
+        evm.call::<(), _, _>(Address::zero(), addr, "testPrank()", (), 0.into()).unwrap();
+        let pranks = evm.pranks();
+        assert_eq!(pranks.len(), 1);
+        assert!(!pranks[0].persistent);
+        assert_eq!(pranks[0].sender, sender);
+
+        // the deployment is pranked, the calls to the deployed contract are made after
+        // `stopPrank`
+        evm.call::<(), _, _>(Address::zero(), addr, "testPrankStartCreate()", (), 0.into())
+            .unwrap();
+        let pranks = evm.pranks();
+        assert_eq!(pranks.len(), 1);
+        assert_eq!(pranks[0].sender, sender);
+        assert_eq!(pranks[0].target, ethers::utils::get_contract_address(sender, 0));
+    }
+
+    #[test]
+    fn broadcasts() {
+        let mut evm = vm();
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+
+        evm.call::<(), _, _>(Address::zero(), addr, "testStartBroadcast()", (), 0.into()).unwrap();
+        let sender = Address::from_low_u64_be(1337);
+        let txs = evm.broadcastable_transactions();
+        assert_eq!(txs.len(), 2);
+        assert!(txs.iter().all(|tx| tx.from == Some(sender)));
+
+        // the `Prank` deployment and the call made to it right after
+        assert_eq!(txs[0].to, None);
+        assert_eq!(txs[0].nonce, Some(0.into()));
+        let deployed = ethers::utils::get_contract_address(sender, 0);
+        assert_eq!(txs[1].to, Some(deployed.into()));
+        assert_eq!(txs[1].nonce, Some(1.into()));
+
+        // transactions are collected across calls
+        evm.call::<(), _, _>(Address::zero(), addr, "testBroadcast()", (), 0.into()).unwrap();
+        assert_eq!(evm.broadcastable_transactions().len(), 3);
+        assert!(evm.unused_expectations().is_empty());
+    }
+
+    #[test]
+    fn random_values_are_seeded() {
+        let compiled = COMPILED.find("CheatCodes").expect("could not find contract");
+        let random_values = |seed: u64| {
+            let mut evm = vm().seed(H256::from_low_u64_be(seed));
+            let (addr, _, _, _) = evm
+                .deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into())
+                .unwrap();
+            (0..2)
+                .map(|_| {
+                    let calldata = ethers::utils::id("randomValue()").to_vec().into();
+                    let (retdata, _, _, _) =
+                        evm.call_raw(Address::zero(), addr, calldata, 0.into(), false).unwrap();
+                    U256::from_big_endian(&retdata)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let values = random_values(1);
+        // every call gets the same values, so that tests are reproducible
+        assert_eq!(values[0], values[1]);
+        assert_eq!(values, random_values(1));
+        assert_ne!(values, random_values(2));
+    }
+
+    #[test]
+    fn aborts_on_failed_assertion() {
+        let compiled = COMPILED.find("Assertions").expect("could not find contract");
+
+        // by default, execution continues after a failed assertion
+        let mut evm = vm();
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        let (_, status, _, logs) = evm
+            .call::<(), _, _>(Address::zero(), addr, "testFailTwoAssertions()", (), 0.into())
+            .unwrap();
+        assert!(logs.contains(&"unreachable".to_owned()));
+        assert!(evm.check_success(addr, &status, true));
+
+        let mut evm = vm().abort_on_failed_assertion(true);
+        let (addr, _, _, _) =
+            evm.deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into()).unwrap();
+        let err = evm
+            .call::<(), _, _>(Address::zero(), addr, "testFailTwoAssertions()", (), 0.into())
+            .unwrap_err();
+        let reason = match err {
+            crate::EvmError::Execution { reason, .. } => reason,
+            _ => panic!("unexpected error"),
+        };
+        assert_eq!(reason, "a == b not satisfied [uint] (Expected: 2, Actual: 1)");
+    }
+
     #[test]
     fn ffi_fails_if_disabled() {
         let mut evm = vm();
@@ -0,0 +1,315 @@
+//! Errors of the cheatcodes, which are returned to the calling contract as `Error(string)` reverts
+use std::{env::VarError, io, path::PathBuf, process::ExitStatus, string::FromUtf8Error};
+
+use ethers::{
+    abi::{ParamType, Token},
+    contract::AbiError,
+    core::k256::ecdsa,
+    signers::WalletError,
+    types::{Address, H256, U256},
+};
+use sputnik::ExitError;
+
+use crate::SKIP_REASON;
+
+/// The error of a cheatcode call, or of an expectation which was set up by a cheatcode
+#[derive(thiserror::Error, Debug)]
+pub enum CheatcodeError {
+    #[error(
+        "Unknown cheatcode 0x{}, use `supports` to check whether a cheatcode is available",
+        hex::encode(.0)
+    )]
+    /// The selector does not belong to any cheatcode
+    UnknownCheatcode(Vec<u8>),
+    #[error(transparent)]
+    /// The calldata could not be decoded as the arguments of the cheatcode
+    Decode(#[from] AbiError),
+    #[error(
+        "ffi disabled: run again with --ffi if you want to allow tests to call external scripts"
+    )]
+    /// `ffi` was called without `--ffi`
+    FfiDisabled,
+    #[error("ffi command failed with {status}: {}", .stderr.trim())]
+    /// The `ffi` command exited with a non-zero status
+    FfiFailed { status: ExitStatus, stderr: String },
+    #[error("ffi output is not UTF-8: {0}")]
+    /// The `ffi` command wrote something which is not text to stdout
+    FfiOutput(#[from] FromUtf8Error),
+    #[error(
+        "wall clock disabled: run again with --wall-clock if you want to allow tests to sleep and read the time"
+    )]
+    /// `sleep` or `unixTime` was called without `--wall-clock`
+    WallClockDisabled,
+    #[error("`{cheatcode}` overflowed the {value}")]
+    /// Advancing a value of the block environment overflowed it
+    Overflow { cheatcode: &'static str, value: &'static str },
+    #[error("Bad Cheat Code. Private Key cannot be 0.")]
+    /// A private key of zero was passed
+    ZeroPrivateKey,
+    #[error(
+        "You have an active `startPrank` at this frame depth already. Use either `prank` or `startPrank`, not both"
+    )]
+    /// `prank` was called while a `startPrank` would apply to the same call
+    StartPrankActive,
+    #[error(
+        "You have an active `prank` call already. Use either `prank` or `startPrank`, not both"
+    )]
+    /// `startPrank` was called while a `prank` was still pending
+    PrankActive,
+    #[error("You must call another function prior to expecting a second revert.")]
+    /// `expectRevert` was called while another revert was still expected
+    ExpectRevertActive,
+    #[error("This cheatcode requires the `{0}` feature, which evm-adapters was built without")]
+    /// The cheatcode belongs to a disabled feature of the crate
+    FeatureDisabled(&'static str),
+    #[error("Gas section `{0}` has already been started")]
+    /// `snapshotGasStart` was called twice with the same name
+    GasSectionStarted(String),
+    #[error("Gas section `{0}` was never started")]
+    /// `snapshotGasEnd` was called without a matching `snapshotGasStart`
+    GasSectionNotStarted(String),
+    #[error("Gas section `{0}` must be started and ended in the same call frame")]
+    /// `snapshotGasStart` and `snapshotGasEnd` were called in different call frames
+    GasSectionFrame(String),
+    #[error("randomUint: min must be less than or equal to max")]
+    /// `randomUint` was called with an empty range
+    InvalidRange,
+    #[error("{}", SKIP_REASON)]
+    /// `skip(true)` halts the test, which the runner reports as skipped
+    Skip,
+    #[error("Invalid fork block number {0}")]
+    /// The block number does not fit in a `u64`
+    InvalidForkBlock(U256),
+    #[error("No fork is active")]
+    /// A fork cheatcode was called before a fork was selected
+    NoActiveFork,
+    #[error("Failed to collect the state: {0:?}")]
+    /// The touched accounts could not be read from the state
+    State(ExitError),
+    #[error("Cannot change the nonce of {address:?} from {from} to {to}")]
+    /// Nonces can only be incremented
+    NonceDecrease { address: Address, from: U256, to: U256 },
+    #[error("No project root configured")]
+    /// `projectRoot` was called without a project root
+    NoProjectRoot,
+    #[error("`{0}` is not a valid uint256")]
+    /// The input of `promptUint` is not a number
+    InvalidUint(String),
+    #[error("No state diff is being recorded")]
+    /// `stopAndReturnStateDiff` was called without `startStateDiffRecording`
+    NoStateDiff,
+    #[error("{0:#}")]
+    /// The raw transaction could not be decoded
+    InvalidTransaction(eyre::Report),
+    #[error("No RPC endpoint: select a fork or run with --fork-url")]
+    /// `rpc` was called without a fork or a fork url
+    NoRpcEndpoint,
+    #[error("Log != expected log")]
+    /// The call did not emit the log expected by `expectEmit`
+    ExpectedEmitNotFound,
+    #[error("Expected revert call did not revert")]
+    /// The call expected to revert by `expectRevert` succeeded
+    DidNotRevert,
+    #[error(
+        "Error selector != expected selector: 0x{} != 0x{}",
+        hex::encode(.actual),
+        hex::encode(.expected)
+    )]
+    /// The selector of the revert data is not the expected one
+    RevertSelectorMismatch { actual: Vec<u8>, expected: Vec<u8> },
+    #[error(
+        "Error != expected error: '{}' != '{}'",
+        String::from_utf8_lossy(.actual),
+        String::from_utf8_lossy(.expected)
+    )]
+    /// The revert string is not the expected one
+    RevertStringMismatch { actual: Vec<u8>, expected: Vec<u8> },
+    #[error(
+        "Error data != expected error data: 0x{} != 0x{}",
+        hex::encode(.actual),
+        hex::encode(.expected)
+    )]
+    /// The revert data is not the expected one
+    RevertDataMismatch { actual: Vec<u8>, expected: Vec<u8> },
+    #[error(
+        "Error data != expected error data: 0x{} != 0x{} (the error data has the `Error(string)` selector but does not encode a string)",
+        hex::encode(.actual),
+        hex::encode(.expected)
+    )]
+    /// The revert data claims to be a revert string but cannot be decoded as one, and is not the
+    /// expected revert data either
+    MalformedRevertString { actual: Vec<u8>, expected: Vec<u8> },
+    #[error(
+        "Memory write of {size} bytes at offset {offset:#x} is not allowed, safe ranges are {ranges}"
+    )]
+    /// The frame wrote to memory outside of the ranges allowed by `expectSafeMemory` or
+    /// `expectSafeMemoryCall`
+    UnsafeMemoryWrite { offset: U256, size: U256, ranges: String },
+    #[error("ffi needs at least the command to run")]
+    /// `ffi` was called without any arguments
+    FfiNoCommand,
+    #[error("Failed to run `{program}`: {source}")]
+    /// The `ffi` command could not be started
+    FfiSpawn { program: String, source: io::Error },
+    #[error("Invalid private key: {0}")]
+    /// The private key is not a valid secp256k1 key
+    InvalidPrivateKey(ecdsa::Error),
+    #[error(transparent)]
+    /// No key could be derived from the mnemonic
+    Wallet(#[from] WalletError),
+    #[error("The signature does not recover the signer")]
+    /// The signature of a `sign` cheatcode could not be verified
+    InvalidSignature,
+    #[error("Invalid BLS private key, it must be in the range [1, r)")]
+    /// The private key of `signBls` is zero or not smaller than the group order
+    InvalidBlsKey,
+    #[error(
+        "You have an active broadcast already. Use either `broadcast` or `startBroadcast`, and call `stopBroadcast` before starting another one"
+    )]
+    /// `broadcast` or `startBroadcast` was called while another broadcast was active
+    BroadcastActive,
+    #[error("Failed to call token {0:?}")]
+    /// A view function of the token of `dealToken` failed
+    TokenCallFailed(Address),
+    #[error("Could not find the storage slot read by token {0:?}")]
+    /// None of the slots read by a view function of the token of `dealToken` holds its result
+    TokenSlotNotFound(Address),
+    #[error("Total supply over- or underflowed")]
+    /// `dealToken` would over- or underflow the total supply
+    TotalSupplyOverflow,
+    #[error("Failed to get environment variable `{key}`: {source}")]
+    /// The environment variable is not set or not unicode
+    EnvVar { key: String, source: VarError },
+    #[error("Failed to parse environment variable `{key}` as type `{ty}`")]
+    /// The environment variable is not a value of the requested type
+    EnvParse { key: String, ty: ParamType },
+    #[error("Cannot prompt for `{0}` in a non-interactive environment")]
+    /// A prompt cheatcode was called without a terminal, or on CI
+    NonInteractive(String),
+    #[error("Failed to read the input: {0}")]
+    /// The input of a prompt could not be read
+    ReadInput(io::Error),
+    #[error("No artifacts directory configured")]
+    /// `getCode` or `getDeployedCode` was called without an artifacts directory
+    NoArtifacts,
+    #[error("Failed to {action} `{path}`: {source}")]
+    /// A file or directory could not be read or written
+    Io { action: &'static str, path: String, source: io::Error },
+    #[error("Failed to {action} `{path}`: {source}")]
+    /// A file is not valid JSON, or JSON could not be written to it
+    JsonFile { action: &'static str, path: String, source: serde_json::Error },
+    #[error("No `{field}` found in artifact `{}`", .path.display())]
+    /// The artifact has no code of the requested kind
+    MissingArtifactField { field: String, path: PathBuf },
+    #[error("The code of `{0}` is not valid hex, does it need linking?")]
+    /// The code of the artifact has unlinked library placeholders
+    UnlinkedArtifact(String),
+    #[error("Invalid address `{address}` in `{path}`")]
+    /// An account of an allocs file is not keyed by an address
+    InvalidAllocsAddress { path: String, address: String },
+    #[error("Invalid `{field}` of account `{address}` in `{path}`")]
+    /// A field of an account of an allocs file is malformed
+    InvalidAlloc { path: String, address: String, field: &'static str },
+    #[error("Failed to parse JSON: {0}")]
+    /// The document of a `parseJson*` cheatcode is not valid JSON
+    ParseJson(serde_json::Error),
+    #[cfg(feature = "cheatcodes-toml")]
+    #[error("Failed to parse TOML: {0}")]
+    /// The document of a `parseToml*` cheatcode is not valid TOML
+    ParseToml(toml::de::Error),
+    #[error("Key `{0}` not found in JSON")]
+    /// The key does not exist in the document
+    JsonKeyNotFound(String),
+    #[error("Cannot ABI-encode a JSON `null`")]
+    /// `null` has no Solidity type
+    JsonNull,
+    #[error("Cannot ABI-encode a JSON array whose elements have different types")]
+    /// The elements of an array do not share a Solidity type
+    JsonMixedArray,
+    #[error("Cannot ABI-encode JSON number `{0}` exactly, pass it as a string instead")]
+    /// The number is fractional or does not fit in 64 bits, which JSON parsers store as an `f64`
+    JsonNumber(String),
+    #[error("JSON value at `{key}` is not {expected}")]
+    /// The value at the key is not of the requested kind
+    JsonType { key: String, expected: String },
+    #[error("Failed to parse JSON value at `{key}` as type `{ty}`")]
+    /// The value at the key is not a value of the requested type
+    JsonParse { key: String, ty: ParamType },
+    #[error("Cannot build a Merkle tree without leaves")]
+    /// A Merkle cheatcode was called without leaves
+    NoMerkleLeaves,
+    #[error("Leaf index {index} is out of bounds of {leaves} leaves")]
+    /// `getMerkleProof` was called for a leaf which does not exist
+    MerkleIndex { index: usize, leaves: usize },
+    #[error("{0}")]
+    /// The RPC URL is invalid
+    Provider(eyre::Report),
+    #[error("Failed to fork at block {block}: {err}")]
+    /// The block could not be fetched from the endpoint
+    Fork { block: u64, err: eyre::Report },
+    #[error("Fork {0} does not exist")]
+    /// `selectFork` was called with an id which `createFork` did not return
+    UnknownFork(usize),
+    #[error("Failed to get transaction {hash:?}: {err}")]
+    /// The transaction of `rollFork` could not be fetched
+    TransactionLookup { hash: H256, err: eyre::Report },
+    #[error("Transaction {0:?} not found")]
+    /// The transaction of `rollFork` does not exist
+    TransactionNotFound(H256),
+    #[error("Transaction {0:?} is still pending")]
+    /// The transaction of `rollFork` was not mined yet
+    TransactionPending(H256),
+    #[error("Failed to parse RPC params `{params}`: {source}")]
+    /// The params of `rpc` are not valid JSON
+    RpcParams { params: String, source: serde_json::Error },
+    #[error("RPC request `{method}` failed: {err}")]
+    /// The request of `rpc` failed
+    RpcFailed { method: String, err: eyre::Report },
+}
+
+impl CheatcodeError {
+    /// ABI-encodes the error as an `Error(string)` revert reason, as returned to the caller
+    pub fn encode(&self) -> Vec<u8> {
+        ethers::abi::encode(&[Token::String(self.to_string())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_revert_reasons() {
+        let err = CheatcodeError::RevertSelectorMismatch {
+            actual: vec![0xde, 0xad, 0xbe, 0xef],
+            expected: vec![0x12, 0x34, 0x56, 0x78],
+        };
+        assert_eq!(
+            err.to_string(),
+            "Error selector != expected selector: 0xdeadbeef != 0x12345678"
+        );
+        let decoded = ethers::abi::decode(&[ethers::abi::ParamType::String], &err.encode())
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(decoded, Token::String(err.to_string()));
+
+        assert_eq!(CheatcodeError::Skip.to_string(), SKIP_REASON);
+        assert_eq!(
+            CheatcodeError::Overflow { cheatcode: "warpBy", value: "timestamp" }.to_string(),
+            "`warpBy` overflowed the timestamp"
+        );
+        let err = CheatcodeError::MalformedRevertString {
+            actual: vec![0x08, 0xc3, 0x79, 0xa0],
+            expected: b"a".to_vec(),
+        };
+        assert!(err
+            .to_string()
+            .starts_with("Error data != expected error data: 0x08c379a0 != 0x61"));
+        assert_eq!(
+            CheatcodeError::JsonType { key: ".a".to_string(), expected: "an array".to_string() }
+                .to_string(),
+            "JSON value at `.a` is not an array"
+        );
+    }
+}
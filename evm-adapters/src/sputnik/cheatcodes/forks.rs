@@ -0,0 +1,311 @@
+//! Forks of remote chains created with the `createFork` cheatcode
+use crate::{
+    runtime::{self, Throttle},
+    sputnik::{new_shared_cache, vicinity, MemCache, SharedBackend, SharedCache},
+};
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::H256,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use super::CheatcodeError;
+
+/// The forks created so far by any test, see [`SharedForks`]
+static FORKS: Lazy<Mutex<SharedForks>> = Lazy::new(Default::default);
+
+/// The most forks kept in [`FORKS`]
+const MAX_SHARED_FORKS: usize = 64;
+
+/// The endpoint, block environment and state block of a fork
+type ForkKey = (String, u64, u64);
+
+/// The forks created so far by any test, by their [`ForkKey`].
+///
+/// Forks only hold the remote state fetched so far, the changes of a test stay in its own state
+/// on top of the fork. Every test forking the same endpoint at the same block therefore shares
+/// one fork, and each remote value is fetched once per run instead of once per test.
+///
+/// The forks live until the end of the run, except that the oldest one is dropped once there are
+/// more than [`MAX_SHARED_FORKS`], e.g. when tests roll through many blocks. The tests using a
+/// dropped fork keep it, but later tests fetch its state again.
+#[derive(Debug, Default)]
+struct SharedForks {
+    forks: HashMap<ForkKey, Fork>,
+    /// The keys of `forks` from the oldest to the newest
+    order: VecDeque<ForkKey>,
+}
+
+impl SharedForks {
+    fn get(&self, key: &ForkKey) -> Option<&Fork> {
+        self.forks.get(key)
+    }
+
+    /// Adds the fork unless another one was added for the key meanwhile, and returns the fork of
+    /// the key
+    fn insert(&mut self, key: ForkKey, fork: Fork) -> Fork {
+        if let Some(fork) = self.forks.get(&key) {
+            return fork.clone()
+        }
+        if self.order.len() == MAX_SHARED_FORKS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.forks.remove(&oldest);
+            }
+        }
+        self.order.push_back(key.clone());
+        self.forks.insert(key, fork.clone());
+        fork
+    }
+}
+
+/// A fork of a remote chain
+#[derive(Clone, Debug)]
+struct Fork {
+    url: String,
+    provider: Provider<Http>,
+    throttle: Arc<Throttle>,
+    backend: SharedBackend,
+    /// The remote state fetched so far by `backend`
+    cache: SharedCache<MemCache>,
+}
+
+impl Fork {
+    /// Forks the chain behind `url` with the block environment of `env_block` and the state of
+    /// `state_block`, or returns the fork another test created for them
+    fn shared(url: &str, env_block: u64, state_block: u64) -> Result<Self, CheatcodeError> {
+        let key = (url.to_string(), env_block, state_block);
+        if let Some(fork) = FORKS.lock().get(&key) {
+            return Ok(fork.clone())
+        }
+        // not locked while fetching, so that forks of other blocks are created meanwhile
+        let fork = Self::new(url, env_block, state_block)?;
+        Ok(FORKS.lock().insert(key, fork))
+    }
+
+    fn new(url: &str, env_block: u64, state_block: u64) -> Result<Self, CheatcodeError> {
+        let provider = runtime::http_provider(url).map_err(CheatcodeError::Provider)?;
+        let throttle = runtime::throttle(Some(url));
+        let vicinity =
+            runtime::block_on(runtime::retry(&throttle, || vicinity(&provider, Some(env_block))))
+                .map_err(|err| CheatcodeError::Fork { block: env_block, err })?;
+        let cache = new_shared_cache(MemCache::default());
+        let backend = SharedBackend::new_throttled(
+            provider.clone(),
+            throttle.clone(),
+            cache.clone(),
+            vicinity,
+            Some(state_block.into()),
+        );
+        Ok(Self { url: url.to_string(), provider, throttle, backend, cache })
+    }
+}
+
+/// The forks created during a test, identified by the order in which they were created
+#[derive(Clone, Debug, Default)]
+pub struct Forks {
+    forks: Vec<Fork>,
+    /// The id of the selected fork, if any
+    active: Option<usize>,
+}
+
+impl Forks {
+    /// Forks the chain behind `url` at `block` and returns the id of the new fork. The fork is
+    /// not selected.
+    pub fn create(&mut self, url: &str, block: u64) -> Result<usize, CheatcodeError> {
+        self.forks.push(Fork::shared(url, block, block)?);
+        Ok(self.forks.len() - 1)
+    }
+
+    /// Re-anchors the selected fork at `block`
+    pub fn roll(&mut self, block: u64) -> Result<(), CheatcodeError> {
+        let fork = self.active_mut()?;
+        *fork = Fork::shared(&fork.url, block, block)?;
+        Ok(())
+    }
+
+    /// Re-anchors the selected fork at the block of the transaction `hash`, with the state at
+    /// the start of that block. The transactions preceding it in the block are not replayed.
+    pub fn roll_to_transaction(&mut self, hash: H256) -> Result<(), CheatcodeError> {
+        let fork = self.active_mut()?;
+        let provider = &fork.provider;
+        let tx =
+            runtime::block_on(runtime::retry(&fork.throttle, || provider.get_transaction(hash)))
+                .map_err(|err| CheatcodeError::TransactionLookup { hash, err })?
+                .ok_or(CheatcodeError::TransactionNotFound(hash))?;
+        let block = tx.block_number.ok_or(CheatcodeError::TransactionPending(hash))?.as_u64();
+        *fork = Fork::shared(&fork.url, block, block.saturating_sub(1))?;
+        Ok(())
+    }
+
+    /// Selects the fork with the given id, whose chain state is used from now on
+    pub fn select(&mut self, id: usize) -> Result<(), CheatcodeError> {
+        if id >= self.forks.len() {
+            return Err(CheatcodeError::UnknownFork(id))
+        }
+        self.active = Some(id);
+        Ok(())
+    }
+
+    /// Returns the id of the selected fork
+    pub fn active_id(&self) -> Option<usize> {
+        self.active
+    }
+
+    /// Returns the selected fork
+    pub fn active(&self) -> Option<&SharedBackend> {
+        self.active.map(|id| &self.forks[id].backend)
+    }
+
+    /// Returns the provider of the selected fork and the request budget of its endpoint
+    pub fn active_provider(&self) -> Option<(&Provider<Http>, &Arc<Throttle>)> {
+        self.active.map(|id| (&self.forks[id].provider, &self.forks[id].throttle))
+    }
+
+    fn active_mut(&mut self) -> Result<&mut Fork, CheatcodeError> {
+        match self.active {
+            Some(id) => Ok(&mut self.forks[id]),
+            None => Err(CheatcodeError::NoActiveFork),
+        }
+    }
+}
+
+/// Sends a raw JSON-RPC request, with `params` being a JSON array.
+///
+/// Returns the decoded bytes if the result is a hex string, otherwise the result as JSON text.
+pub fn rpc(
+    provider: &Provider<Http>,
+    throttle: &Throttle,
+    method: &str,
+    params: &str,
+) -> Result<Vec<u8>, CheatcodeError> {
+    let params: serde_json::Value = serde_json::from_str(params)
+        .map_err(|source| CheatcodeError::RpcParams { params: params.to_string(), source })?;
+    let result: serde_json::Value =
+        runtime::block_on(runtime::retry(throttle, || provider.request(method, params.clone())))
+            .map_err(|err| CheatcodeError::RpcFailed { method: method.to_string(), err })?;
+    Ok(match result {
+        serde_json::Value::String(ref s) if s.starts_with("0x") => match hex::decode(&s[2..]) {
+            Ok(bytes) => bytes,
+            Err(_) => result.to_string().into_bytes(),
+        },
+        result => result.to_string().into_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sputnik::{cheatcodes::backend::CheatcodeBackend, helpers::new_vicinity};
+    use sputnik::backend::{Backend, MemoryBackend};
+
+    #[test]
+    fn cannot_select_unknown_fork() {
+        let mut forks = Forks::default();
+        assert!(forks.select(0).is_err());
+        assert_eq!(forks.active_id(), None);
+        assert!(forks.roll(1).is_err());
+    }
+
+    #[test]
+    #[ignore = "needs ETH_RPC_URL"]
+    fn selected_fork_replaces_chain_state() {
+        use crate::test_helpers::rpc_url;
+
+        let vicinity = new_vicinity();
+        let backend = MemoryBackend::new(&vicinity, Default::default());
+        let mut backend = CheatcodeBackend { backend, cheats: Default::default() };
+
+        let url = &rpc_url();
+        let id = backend.cheats.forks.create(url, 13292465).unwrap();
+        assert_eq!(id, 0);
+        // other tests forking the same block share the fork
+        Forks::default().create(url, 13292465).unwrap();
+        assert!(FORKS.lock().get(&(url.to_string(), 13292465, 13292465)).is_some());
+        assert_eq!(backend.block_number(), vicinity.block_number);
+
+        backend.cheats.forks.select(id).unwrap();
+        // https://etherscan.io/block/13292465
+        assert_eq!(backend.block_number(), 13292465.into());
+        assert_eq!(backend.block_timestamp(), 1632539668.into());
+        assert_eq!(backend.chain_id(), 1.into());
+
+        backend.cheats.forks.roll(13292466).unwrap();
+        assert_eq!(backend.block_number(), 13292466.into());
+        assert_eq!(backend.cheats.forks.active_id(), Some(id));
+
+        let (provider, throttle) = backend.cheats.forks.active_provider().unwrap();
+        assert_eq!(rpc(provider, throttle, "eth_chainId", "[]").unwrap(), vec![1]);
+        assert!(rpc(provider, throttle, "eth_chainId", "not json").is_err());
+    }
+
+    #[test]
+    #[ignore = "needs ETH_RPC_URL"]
+    fn tests_share_forks_but_not_their_changes() {
+        use crate::{
+            sputnik::cheatcodes::{memory_stackstate_owned::MemoryStackStateOwned, Cheatcodes},
+            test_helpers::rpc_url,
+        };
+        use ethers::types::Address;
+        use sputnik::executor::stack::StackSubstateMetadata;
+
+        let url = &rpc_url();
+        let mut forks = [Forks::default(), Forks::default()];
+        for forks in &mut forks {
+            let id = forks.create(url, 13292465).unwrap();
+            forks.select(id).unwrap();
+        }
+        // both fetch the remote state into the same cache
+        assert!(Arc::ptr_eq(&forks[0].forks[0].cache, &forks[1].forks[0].cache));
+
+        let config = sputnik::Config::istanbul();
+        let vicinity = new_vicinity();
+        let [mut first, second] = forks.map(|forks| {
+            let backend = MemoryBackend::new(&vicinity, Default::default());
+            let cheats = Cheatcodes { forks, ..Default::default() };
+            let backend = CheatcodeBackend { backend, cheats };
+            MemoryStackStateOwned::new(StackSubstateMetadata::new(u64::MAX, &config), backend)
+        });
+
+        // WETH
+        let weth: Address = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".parse().unwrap();
+        let balance = second.basic(weth).balance;
+        assert!(!balance.is_zero());
+
+        // the changes of a test are written on top of the fork, not into it
+        first.deposit(weth, 1.into());
+        assert_eq!(first.basic(weth).balance, balance + 1);
+        assert_eq!(second.basic(weth).balance, balance);
+        assert_eq!(second.backend.basic(weth).balance, balance);
+    }
+
+    #[test]
+    fn shared_forks_are_bounded() {
+        // nothing is fetched until the fork is read from
+        let url = "http://localhost:8545";
+        let provider = runtime::http_provider(url).unwrap();
+        let cache = new_shared_cache(MemCache::default());
+        let backend = SharedBackend::new(provider.clone(), cache.clone(), new_vicinity(), None);
+        let throttle = runtime::throttle(None);
+        let fork = Fork { url: url.to_string(), provider, throttle, backend, cache };
+        let key = |block| (url.to_string(), block, block);
+
+        let mut forks = SharedForks::default();
+        for block in 0..MAX_SHARED_FORKS as u64 + 1 {
+            forks.insert(key(block), fork.clone());
+        }
+        assert_eq!(forks.forks.len(), MAX_SHARED_FORKS);
+        assert!(forks.get(&key(0)).is_none());
+        assert!(forks.get(&key(1)).is_some());
+        assert!(forks.get(&key(MAX_SHARED_FORKS as u64)).is_some());
+
+        // inserting a fork again keeps the one which is already there
+        let before = forks.order.clone();
+        forks.insert(key(1), fork);
+        assert_eq!(forks.order, before);
+    }
+}
@@ -0,0 +1,428 @@
+//! Generation of the Solidity interface of the cheatcodes from the `HEVM` bindings, so that the
+//! interface tests and scripts compile against can not drift apart from the handler
+use super::HEVM_ABI;
+use ethers::{
+    abi::{Function, ParamType},
+    utils::id,
+};
+use std::fmt;
+
+/// A struct which cheatcodes return, declared at the top of the interface
+struct StructDef {
+    name: &'static str,
+    doc: &'static str,
+    /// The Solidity type and name of every field
+    fields: &'static [(&'static str, &'static str)],
+}
+
+const STRUCTS: &[StructDef] = &[
+    StructDef {
+        name: "StorageAccess",
+        doc: "A read or write of a storage slot recorded by `startStateDiffRecording`",
+        fields: &[
+            ("bytes32", "slot"),
+            ("bytes32", "previousValue"),
+            ("bytes32", "newValue"),
+            ("bool", "isWrite"),
+        ],
+    },
+    StructDef {
+        name: "AccountAccess",
+        doc: "An account called or created while recording a state diff, with the storage its code accessed",
+        fields: &[
+            ("address", "account"),
+            ("uint256", "oldBalance"),
+            ("uint256", "newBalance"),
+            ("bool", "reverted"),
+            ("StorageAccess[]", "storageAccesses"),
+        ],
+    },
+    StructDef {
+        name: "FfiResult",
+        doc: "The result of a command run by `tryFfi`",
+        fields: &[("int32", "exitCode"), ("bytes", "stdout"), ("bytes", "stderr")],
+    },
+];
+
+/// Every cheatcode's signature in the order of the interface, with the comment preceding it.
+/// Overloads and closely related cheatcodes share the comment of the first one
+const CHEATCODES: &[(&str, &str)] = &[
+    ("warp(uint256)", "Set block.timestamp (newTimestamp)"),
+    ("roll(uint256)", "Set block.height (newHeight)"),
+    ("warpBy(uint256)", "Advances block.timestamp by a number of seconds, on top of any earlier `warp`, (seconds)"),
+    ("rollBy(uint256)", "Advances block.height by a number of blocks, on top of any earlier `roll`, (blocks)"),
+    ("getBlockTimestamp()", "Returns block.timestamp, including the override of `warp`"),
+    ("getBlockNumber()", "Returns block.number, including the override of `roll`"),
+    ("fee(uint256)", "Set block.basefee (newBasefee)"),
+    ("setBlockhash(uint256,bytes32)", "Sets the hash blockhash returns for a block number, regardless of whether it is one of the 256 most recent blocks, (blockNumber, blockHash)"),
+    ("load(address,bytes32)", "Loads a storage slot from an address (who, slot)"),
+    ("store(address,bytes32,bytes32)", "Stores a value to an address' storage slot, (who, slot, value)"),
+    ("sign(uint256,bytes32)", "Signs data, (privateKey, digest) => (r, v, s)"),
+    ("signTypedData(uint256,bytes32,bytes32)", "Signs the EIP-712 digest of typed data, (privateKey, domainSeparator, structHash) => (v, r, s)"),
+    ("signEd25519(bytes32,bytes)", "Signs data with an ed25519 key, (privateKey, message) => (publicKey, r, s)"),
+    ("signBls(uint256,bytes)", "Signs data with a BLS12-381 key, returning the compressed G1 public key and G2 signature, (privateKey, message) => (publicKey, signature)"),
+    ("addr(uint256)", "Gets address for a given private key, (privateKey) => (address)"),
+    ("deriveKey(string,uint32)", "Derives a private key from a mnemonic at `m/44'/60'/0'/0/{index}`, (mnemonic, index) => (privateKey)"),
+    ("ffi(string[])", "Performs a foreign function call via terminal, (stringInputs) => (result)"),
+    ("tryFfi(string[])", "Like `ffi`, but returns the exit code and the raw stdout and stderr instead of failing, (args) => (result)"),
+    ("prank(address)", "Sets the *next* call's msg.sender to be the input address"),
+    ("startPrank(address)", "Sets all subsequent calls' msg.sender to be the input address until `stopPrank` is called"),
+    ("startPrank(address,bool,bool)", "Like `startPrank`, optionally also for delegatecalls, whose msg.sender becomes the input address, and contract creations, which the input address deploys"),
+    ("stopPrank()", "Resets subsequent calls' msg.sender to be `address(this)`"),
+    ("deal(address,uint256)", "Sets an address' balance, (who, newBalance)"),
+    ("dealToken(address,address,uint256)", "Sets an address' ERC20 balance, finding the slot of its balance by probing the slots `balanceOf` reads, (token, who, newBalance)"),
+    ("dealToken(address,address,uint256,bool)", "Like `dealToken`, optionally adjusting `totalSupply` by the change of the balance, (token, who, newBalance, adjustTotalSupply)"),
+    ("dealToken(address,address,uint256,bool,uint256)", "Like `dealToken`, with the slot of the token's balance mapping instead of probing, (token, who, newBalance, adjustTotalSupply, mappingSlot)"),
+    ("etch(address,bytes)", "Sets an address' code, (who, newCode)"),
+    ("expectRevert(bytes)", "Expects an error on next call"),
+    ("expectRevert(bytes4)", "Expects the next call to revert with a custom error with the given selector, whatever its arguments"),
+    ("expectRevert()", "Expects the next call to revert, whatever the revert data"),
+    ("expectRevert(bytes,uint64)", "Expects an error on the next call of the current contract, from a call the given number of levels below it"),
+    ("expectEmit(bool,bool,bool,bool)", "Expects the next call to emit the log emitted after this, comparing the selected topics and data, (checkTopic1, checkTopic2, checkTopic3, checkData)"),
+    ("expectEmit(bool,bool,bool,bool,address)", "Like `expectEmit`, but the log has to be emitted by the given address, (checkTopic1, checkTopic2, checkTopic3, checkData, emitter)"),
+    ("expectEmitAnonymous(bool,bool,bool,bool,bool)", "Expects the next call to emit the anonymous log emitted after this, whose topic0 is not the event selector, (checkTopic0, checkTopic1, checkTopic2, checkTopic3, checkData)"),
+    ("expectEmitAnonymous(bool,bool,bool,bool,bool,address)", "Like `expectEmitAnonymous`, but the log has to be emitted by the given address, (checkTopic0, checkTopic1, checkTopic2, checkTopic3, checkData, emitter)"),
+    ("expectCall(address,bytes)", "Expects a call to an address whose calldata starts with the given calldata before the test ends, (where, data)"),
+    ("expectCall(address,bytes,uint64)", "Expects exactly `count` calls to an address whose calldata starts with the given calldata before the test ends, (where, data, count)"),
+    ("mockCall(address,bytes,bytes)", "Makes calls to an address whose calldata starts with the given calldata return the given data without executing, (where, data, retdata)"),
+    ("mockCallRevert(address,bytes,bytes)", "Like `mockCall`, but the calls revert with the given data, (where, data, revertData)"),
+    ("clearMockedCalls()", "Removes all mocked calls"),
+    ("envBool(string)", "Reads an environment variable and parses it as the given type, (name) => (value)"),
+    ("envUint(string)", ""),
+    ("envInt(string)", ""),
+    ("envAddress(string)", ""),
+    ("envBytes32(string)", ""),
+    ("envString(string)", ""),
+    ("envBytes(string)", ""),
+    ("snapshotGasStart(string)", "Starts measuring the gas used by a named section of the current call frame, (name)"),
+    ("snapshotGasEnd(string)", "Stops measuring a named gas section and records it, (name) => (gasUsed)"),
+    ("pauseGasMetering()", "Stops using gas for executed opcodes until `resumeGasMetering` is called"),
+    ("resumeGasMetering()", "Uses gas for executed opcodes again"),
+    ("skip(bool)", "Stops the test and reports it as skipped if the condition is true, (skip)"),
+    ("breakpoint(string)", "Records a labeled breakpoint which the debugger can jump to, (label)"),
+    ("label(address,string)", "Labels an address, (address, label)"),
+    ("getLabel(address)", "Returns the label of an address, or the address itself if it has none, (address) => (label)"),
+    ("expectSafeMemory(uint64,uint64)", "Only allows the calling frame to write to memory below 0x60 or within [min, max), (min, max)"),
+    ("expectSafeMemoryCall(uint64,uint64)", "Only allows the next call to write to memory below 0x60 or within [min, max), (min, max)"),
+    ("broadcast()", "Collects the next call or create as a transaction sent from the caller of the test, or\nfrom the given account, (sender)"),
+    ("broadcast(address)", ""),
+    ("startBroadcast()", "Collects every following call and create as a transaction until `stopBroadcast`, (sender)"),
+    ("startBroadcast(address)", ""),
+    ("stopBroadcast()", "Stops collecting transactions"),
+    ("randomUint()", "Returns a random uint256, optionally within [min, max], (min, max) => (value)"),
+    ("randomUint(uint256,uint256)", ""),
+    ("randomAddress()", "Returns a random address"),
+    ("toString(address)", "Converts the value to its canonical string representation, (value) => (string)"),
+    ("toString(bytes)", ""),
+    ("toString(bytes32)", ""),
+    ("toString(bool)", ""),
+    ("toString(uint256)", ""),
+    ("toString(int256)", ""),
+    ("parseJson(string,string)", "Reads the value at a JSON-path-like key (e.g. `.a.b[0]`) and ABI-encodes it with an inferred type, (json, key) => (encoded)"),
+    ("parseJsonBool(string,string)", "Reads the value at a JSON-path-like key as the given type, (json, key) => (value)"),
+    ("parseJsonUint(string,string)", ""),
+    ("parseJsonInt(string,string)", ""),
+    ("parseJsonAddress(string,string)", ""),
+    ("parseJsonBytes32(string,string)", ""),
+    ("parseJsonString(string,string)", ""),
+    ("parseJsonBytes(string,string)", ""),
+    ("parseJsonBoolArray(string,string)", "Reads the array at a JSON-path-like key as an array of the given type, (json, key) => (values)"),
+    ("parseJsonUintArray(string,string)", ""),
+    ("parseJsonIntArray(string,string)", ""),
+    ("parseJsonAddressArray(string,string)", ""),
+    ("parseJsonBytes32Array(string,string)", ""),
+    ("parseJsonStringArray(string,string)", ""),
+    ("parseJsonBytesArray(string,string)", ""),
+    ("parseJsonKeys(string,string)", "Returns the keys of the object at a JSON-path-like key, sorted, (json, key) => (keys)"),
+    ("keyExists(string,string)", "Returns whether a JSON-path-like key exists in a JSON document, (json, key) => (exists)"),
+    ("parseToml(string,string)", "Reads the value at a JSON-path-like key of a TOML document and ABI-encodes it with an inferred type, (toml, key) => (encoded)"),
+    ("parseTomlBool(string,string)", "Reads the value at a JSON-path-like key of a TOML document as the given type, (toml, key) => (value)"),
+    ("parseTomlUint(string,string)", ""),
+    ("parseTomlInt(string,string)", ""),
+    ("parseTomlAddress(string,string)", ""),
+    ("parseTomlBytes32(string,string)", ""),
+    ("parseTomlString(string,string)", ""),
+    ("parseTomlBytes(string,string)", ""),
+    ("parseTomlBoolArray(string,string)", "Reads the array at a JSON-path-like key of a TOML document as an array of the given type, (toml, key) => (values)"),
+    ("parseTomlUintArray(string,string)", ""),
+    ("parseTomlIntArray(string,string)", ""),
+    ("parseTomlAddressArray(string,string)", ""),
+    ("parseTomlBytes32Array(string,string)", ""),
+    ("parseTomlStringArray(string,string)", ""),
+    ("parseTomlBytesArray(string,string)", ""),
+    ("parseTomlKeys(string,string)", "Returns the keys of the table at a JSON-path-like key of a TOML document, sorted, (toml, key) => (keys)"),
+    ("keyExistsToml(string,string)", "Returns whether a JSON-path-like key exists in a TOML document, (toml, key) => (exists)"),
+    ("createFork(string,uint256)", "Forks the chain behind an RPC URL at a block, (rpcUrl, block) => (forkId)"),
+    ("selectFork(uint256)", "Reads the chain state from a fork created with `createFork` from now on, (forkId)"),
+    ("activeFork()", "Returns the id of the selected fork"),
+    ("rollFork(uint256)", "Re-anchors the selected fork at a block, or at the start of the block of a transaction, (blockNumber) / (txHash)"),
+    ("rollFork(bytes32)", ""),
+    ("rpc(string,string)", "Sends a raw JSON-RPC request to the selected fork (or the `--fork-url`) and returns the result, (method, params) => (result)"),
+    ("decodeTx(bytes)", "Decodes a raw signed transaction and recovers its sender, with `to` being zero for contract creations"),
+    ("getCode(string)", "Returns the creation bytecode of a compiled artifact, (artifact) => (code), e.g. `getCode(\"Contract.sol:Contract\")`"),
+    ("getDeployedCode(string)", "Returns the runtime bytecode of a compiled artifact, (artifact) => (code)"),
+    ("dumpState(string)", "Writes every account modified so far, with the storage slots written to, as a geth-style allocs JSON file, (path)"),
+    ("loadAllocs(string)", "Applies the balance, nonce, code and storage of every account in a geth-style allocs JSON file, (path)"),
+    ("projectRoot()", "Returns the absolute path of the project's root directory"),
+    ("readDir(string)", "Lists the entries of a directory sorted by path, each prefixed with the directory, (path) => (entries)"),
+    ("exists(string)", "Whether a file or directory exists at the path, (path) => (exists)"),
+    ("isFile(string)", "Whether the path points to a file, (path) => (isFile)"),
+    ("isDir(string)", "Whether the path points to a directory, (path) => (isDir)"),
+    ("copyStorage(address,address)", "Replaces the storage of an account with a copy of another account's storage, (from, to)"),
+    ("prompt(string)", "Asks for a value on the terminal, e.g. in an interactive script. Fails if not run interactively, (message) => (input)"),
+    ("promptSecret(string)", "Like `prompt`, but without echoing the input, e.g. for private keys"),
+    ("promptUint(string)", "Like `prompt`, but parses the input as a decimal or 0x-prefixed hex number"),
+    ("clearStorage(address)", "Wipes the storage of an account, (who)"),
+    ("resetAccount(address)", "Wipes the storage, code and balance of an account, as if it self-destructed, (who)"),
+    ("cool(address)", "Marks an account and all of its storage as not accessed in the transaction, so the next access is cold (EIP-2929), (who)"),
+    ("cheatcodeVersion()", "Returns the version of the cheatcodes, which increases whenever cheatcodes are added or change"),
+    ("supports(bytes4)", "Whether the cheatcode with the selector is available, e.g. `supports(Vm.cool.selector)`, (selector) => (supported)"),
+    ("sleep(uint256)", "Sleeps for the given number of milliseconds of wall-clock time, (ms). Requires --wall-clock"),
+    ("unixTime()", "Returns the host's current time in milliseconds since the Unix epoch. Requires --wall-clock"),
+    ("startStateDiffRecording()", "Starts recording the accounts every call and contract creation accesses, with their balances and storage accesses"),
+    ("stopAndReturnStateDiff()", "Stops recording and returns the accounts accessed since `startStateDiffRecording`, in the order they were called"),
+    ("getMerkleRoot(bytes32[],bool)", "Builds a keccak256 Merkle tree over the leaves and returns its root, (leaves, sortPairs) => (root)"),
+    ("getMerkleProof(bytes32[],uint256,bool)", "Returns the proof of the leaf at the given index, (leaves, index, sortPairs) => (proof)"),
+    ("verifyMerkleProof(bytes32,bytes32[],bytes32,uint256,bool)", "Verifies a Merkle proof, (root, proof, leaf, index, sortPairs) => (valid)"),
+];
+
+/// A cheatcode which is still supported but is going to be removed or to change its behavior
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deprecation {
+    /// The signature of the cheatcode, which has to be in [`CHEATCODES`]
+    pub signature: &'static str,
+    /// What to use instead, e.g. "use `expectEmit(bool,bool,bool,bool,address)` instead"
+    pub replacement: &'static str,
+}
+
+impl fmt::Display for Deprecation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` is deprecated, {}", self.signature, self.replacement)
+    }
+}
+
+/// The deprecated cheatcodes. Calls to them are reported as warnings by `forge test`, which fails
+/// the run instead with `--deny-deprecated`
+const DEPRECATED: &[Deprecation] = &[];
+
+/// Returns the deprecation of the cheatcode with the selector, if it is deprecated
+pub fn deprecation(selector: &[u8]) -> Option<&'static Deprecation> {
+    DEPRECATED.iter().find(|deprecation| id(deprecation.signature) == selector)
+}
+
+/// Renders the Solidity interface of all cheatcodes, e.g. `Vm` for forge-std
+pub fn vm_interface(name: &str) -> String {
+    let mut out = String::new();
+    out.push_str("// SPDX-License-Identifier: UNLICENSED\n");
+    out.push_str("pragma solidity >=0.8.0;\n");
+    out.push_str("pragma experimental ABIEncoderV2;\n\n");
+    out.push_str(&format!("interface {} {{\n", name));
+    for def in STRUCTS {
+        out.push_str(&format!("    // {}\n    struct {} {{\n", def.doc, def.name));
+        for (ty, field) in def.fields {
+            out.push_str(&format!("        {} {};\n", ty, field));
+        }
+        out.push_str("    }\n");
+    }
+    out.push('\n');
+    for (func, doc) in cheatcodes() {
+        for line in doc.lines() {
+            out.push_str(&format!("    // {}\n", line));
+        }
+        if let Some(deprecation) = deprecation(&func.short_signature()) {
+            out.push_str(&format!("    /// @custom:deprecated {}\n", deprecation.replacement));
+        }
+        out.push_str(&format!("    {}\n", declaration(func)));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Renders a Markdown list of all cheatcodes with their descriptions
+pub fn vm_docs() -> String {
+    let mut out = String::new();
+    let mut cheatcodes = cheatcodes().peekable();
+    while let Some((func, doc)) = cheatcodes.next() {
+        // the functions sharing a comment are listed together
+        let mut group = vec![declaration(func)];
+        while let Some((func, "")) = cheatcodes.peek() {
+            group.push(declaration(func));
+            cheatcodes.next();
+        }
+        let group = group.iter().map(|decl| format!("`{}`", decl)).collect::<Vec<_>>();
+        out.push_str(&format!("- {}", group.join(",\n  ")));
+        if !doc.is_empty() {
+            out.push_str(&format!(": {}", doc.replace('\n', " ")));
+        }
+        if let Some(deprecation) = deprecation(&func.short_signature()) {
+            out.push_str(&format!(" **Deprecated**, {}", deprecation.replacement));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// The bindings of every cheatcode in the order of the interface, with their comments
+fn cheatcodes() -> impl Iterator<Item = (&'static Function, &'static str)> {
+    CHEATCODES.iter().map(|(signature, doc)| {
+        let func = HEVM_ABI
+            .functions()
+            .find(|func| input_signature(func) == *signature)
+            .unwrap_or_else(|| panic!("`{}` is not a cheatcode", signature));
+        (func, *doc)
+    })
+}
+
+/// The signature the selector of a function is derived from, e.g. `store(address,bytes32,bytes32)`
+fn input_signature(func: &Function) -> String {
+    let inputs = func.inputs.iter().map(|param| param.kind.to_string()).collect::<Vec<_>>();
+    format!("{}({})", func.name, inputs.join(","))
+}
+
+/// Renders the declaration of a cheatcode, e.g.
+/// `function load(address, bytes32) external returns (bytes32);`
+fn declaration(func: &Function) -> String {
+    let params = |params: &[ethers::abi::Param], location: &str| {
+        params
+            .iter()
+            .map(|param| {
+                let ty = solidity_type(&param.kind);
+                if is_reference(&param.kind) {
+                    format!("{} {}", ty, location)
+                } else {
+                    ty
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut decl = format!("function {}({}) external", func.name, params(&func.inputs, "calldata"));
+    if !func.outputs.is_empty() {
+        decl.push_str(&format!(" returns ({})", params(&func.outputs, "memory")));
+    }
+    decl.push(';');
+    decl
+}
+
+/// Whether values of the type need a data location
+fn is_reference(kind: &ParamType) -> bool {
+    matches!(
+        kind,
+        ParamType::Bytes |
+            ParamType::String |
+            ParamType::Array(_) |
+            ParamType::FixedArray(_, _) |
+            ParamType::Tuple(_)
+    )
+}
+
+/// The Solidity name of the type, with tuples named after the struct with the same fields
+fn solidity_type(kind: &ParamType) -> String {
+    match kind {
+        ParamType::Array(inner) => format!("{}[]", solidity_type(inner)),
+        ParamType::FixedArray(inner, len) => format!("{}[{}]", solidity_type(inner), len),
+        ParamType::Tuple(_) => {
+            let canonical = kind.to_string();
+            STRUCTS
+                .iter()
+                .find(|def| canonical_struct(def) == canonical)
+                .map(|def| def.name.to_string())
+                .unwrap_or_else(|| panic!("no struct is declared for `{}`", canonical))
+        }
+        _ => kind.to_string(),
+    }
+}
+
+/// The canonical tuple type of a struct, e.g. `(bytes32,bool)`
+fn canonical_struct(def: &StructDef) -> String {
+    let fields = def
+        .fields
+        .iter()
+        .map(|(ty, _)| {
+            let base = ty.trim_end_matches("[]");
+            let suffix = &ty[base.len()..];
+            match STRUCTS.iter().find(|def| def.name == base) {
+                Some(def) => format!("{}{}", canonical_struct(def), suffix),
+                None => ty.to_string(),
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("({})", fields.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn every_cheatcode_is_in_the_interface() {
+        let bindings = HEVM_ABI.functions().map(input_signature).collect::<BTreeSet<_>>();
+        let interface =
+            CHEATCODES.iter().map(|(signature, _)| signature.to_string()).collect::<BTreeSet<_>>();
+        assert_eq!(bindings, interface);
+        // panics if a struct is missing
+        vm_interface("Vm");
+    }
+
+    #[test]
+    fn test_interface_matches_bindings() {
+        // the interface the cheatcode tests are compiled against
+        let source = include_str!("../../../testdata/CheatCodes.sol");
+        let start = source.find("interface Hevm {").unwrap();
+        let end = start + source[start..].find("\n}\n").unwrap();
+        let declared = source[start..end]
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("function "))
+            .map(|decl| {
+                let (name, rest) = decl.split_once('(').unwrap();
+                let params = rest.split_once(')').unwrap().0;
+                // drop data locations and names
+                let types = params
+                    .split(',')
+                    .filter_map(|param| param.split_whitespace().next())
+                    .collect::<Vec<_>>();
+                format!("{}({})", name, types.join(","))
+            })
+            .collect::<BTreeSet<_>>();
+        let bindings = HEVM_ABI.functions().map(input_signature).collect::<BTreeSet<_>>();
+        assert_eq!(declared, bindings);
+    }
+
+    #[test]
+    fn deprecated_cheatcodes_exist() {
+        for deprecation in DEPRECATED {
+            assert!(
+                CHEATCODES.iter().any(|(signature, _)| *signature == deprecation.signature),
+                "`{}` is not a cheatcode",
+                deprecation.signature
+            );
+            assert!(!deprecation.replacement.is_empty());
+        }
+    }
+
+    #[test]
+    fn formats_deprecations() {
+        let getter = Deprecation {
+            signature: "getBlockNumber()",
+            replacement: "read `block.number` instead",
+        };
+        assert_eq!(
+            getter.to_string(),
+            "`getBlockNumber()` is deprecated, read `block.number` instead"
+        );
+        assert_eq!(deprecation(&id("warp(uint256)")), None);
+    }
+
+    #[test]
+    fn renders_declarations() {
+        let interface = vm_interface("Vm");
+        assert!(interface.contains("    // Loads a storage slot from an address (who, slot)\n    function load(address, bytes32) external returns (bytes32);\n"));
+        assert!(interface
+            .contains("    function ffi(string[] calldata) external returns (bytes memory);\n"));
+        assert!(interface.contains(
+            "    function stopAndReturnStateDiff() external returns (AccountAccess[] memory);\n"
+        ));
+        assert!(interface.contains("        StorageAccess[] storageAccesses;\n"));
+    }
+}
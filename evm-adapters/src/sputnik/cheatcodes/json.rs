@@ -0,0 +1,380 @@
+//! Helpers for the `parseJson*` cheatcodes, which the `parseToml*` cheatcodes share by converting
+//! TOML to JSON first
+use ethers::{
+    abi::{ParamType, Token},
+    types::{Address, I256},
+};
+use serde_json::Value;
+
+use super::CheatcodeError;
+
+/// Converts a JSON-path-like key (e.g. `.a.b[0]`, `$.a.b` or `.`) to a JSON pointer (`/a/b/0`)
+fn to_pointer(key: &str) -> String {
+    let key = key.strip_prefix('$').unwrap_or(key);
+    let mut pointer = String::with_capacity(key.len());
+    for c in key.chars() {
+        match c {
+            '.' | '[' => pointer.push('/'),
+            ']' => {}
+            '~' => pointer.push_str("~0"),
+            '/' => pointer.push_str("~1"),
+            c => pointer.push(c),
+        }
+    }
+    // the root key `.` maps to the empty pointer, which selects the whole document
+    if pointer == "/" {
+        pointer.clear();
+    }
+    pointer
+}
+
+/// Parses the `json` document
+fn parse(json: &str) -> Result<Value, CheatcodeError> {
+    serde_json::from_str(json).map_err(CheatcodeError::ParseJson)
+}
+
+/// Converts the `toml` document to a JSON document. Datetimes become strings
+#[cfg(feature = "cheatcodes-toml")]
+pub fn toml_to_json(toml: &str) -> Result<String, CheatcodeError> {
+    let value: toml::Value = toml.parse().map_err(CheatcodeError::ParseToml)?;
+    Ok(from_toml(value).to_string())
+}
+
+#[cfg(feature = "cheatcodes-toml")]
+fn from_toml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => i.into(),
+        toml::Value::Float(f) => f.into(),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(values) => Value::Array(values.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => {
+            Value::Object(table.into_iter().map(|(key, value)| (key, from_toml(value))).collect())
+        }
+    }
+}
+
+/// Returns the value found at `key` in the `json` document
+fn lookup(json: &str, key: &str) -> Result<Value, CheatcodeError> {
+    parse(json)?
+        .pointer_mut(&to_pointer(key))
+        .map(Value::take)
+        .ok_or_else(|| CheatcodeError::JsonKeyNotFound(key.to_string()))
+}
+
+/// Converts a JSON value to a token, inferring its Solidity type:
+///
+/// * numbers become `uint256` (or `int256` if negative)
+/// * `0x`-prefixed strings become `address` (20 bytes), `bytes32` (32 bytes) or `bytes`
+/// * arrays become dynamic arrays, whose elements must all have the same type, and objects
+///   become tuples, ordered by key
+fn to_token(value: &Value) -> Result<Token, CheatcodeError> {
+    Ok(match value {
+        Value::Null => return Err(CheatcodeError::JsonNull),
+        Value::Bool(b) => Token::Bool(*b),
+        Value::Number(num) => {
+            if let Some(num) = num.as_u64() {
+                Token::Uint(num.into())
+            } else if let Some(num) = num.as_i64() {
+                Token::Int(I256::from(num).into_raw())
+            } else {
+                // serde_json stores every other number as an `f64`, which e.g. cannot represent
+                // wei amounts exactly
+                return Err(CheatcodeError::JsonNumber(num.to_string()))
+            }
+        }
+        Value::String(s) => match s.strip_prefix("0x").map(hex::decode) {
+            Some(Ok(bytes)) if bytes.len() == 20 => Token::Address(Address::from_slice(&bytes)),
+            Some(Ok(bytes)) if bytes.len() == 32 => Token::FixedBytes(bytes),
+            Some(Ok(bytes)) => Token::Bytes(bytes),
+            _ => Token::String(s.clone()),
+        },
+        Value::Array(values) => {
+            let tokens = values.iter().map(to_token).collect::<Result<Vec<_>, _>>()?;
+            merge_all(tokens.iter()).ok_or(CheatcodeError::JsonMixedArray)?;
+            Token::Array(tokens)
+        }
+        Value::Object(map) => Token::Tuple(map.values().map(to_token).collect::<Result<_, _>>()?),
+    })
+}
+
+/// Returns a token which has the type of both `a` and `b`, or `None` if their types differ. The
+/// elements of an empty array have any type, so e.g. `[]` and `[1]` merge to `[1]`
+fn merge_types(a: &Token, b: &Token) -> Option<Token> {
+    match (a, b) {
+        (Token::Array(a), Token::Array(b)) => {
+            Some(Token::Array(merge_all(a.iter().chain(b))?.into_iter().collect()))
+        }
+        (Token::Tuple(a), Token::Tuple(b)) if a.len() == b.len() => Some(Token::Tuple(
+            a.iter().zip(b).map(|(a, b)| merge_types(a, b)).collect::<Option<_>>()?,
+        )),
+        (Token::Tuple(_), Token::Tuple(_)) => None,
+        (Token::FixedBytes(x), Token::FixedBytes(y)) if x.len() != y.len() => None,
+        _ if std::mem::discriminant(a) == std::mem::discriminant(b) => Some(a.clone()),
+        _ => None,
+    }
+}
+
+/// Merges the types of all `tokens`, see [`merge_types`]. Returns `Some(None)` if there are none
+fn merge_all<'a>(mut tokens: impl Iterator<Item = &'a Token>) -> Option<Option<Token>> {
+    match tokens.next() {
+        Some(first) => {
+            tokens.try_fold(first.clone(), |merged, token| merge_types(&merged, token)).map(Some)
+        }
+        None => Some(None),
+    }
+}
+
+/// Returns the ABI-encoded value found at `key` in the `json` document, inferring its type
+pub fn parse_json(json: &str, key: &str) -> Result<Vec<u8>, CheatcodeError> {
+    let token = to_token(&lookup(json, key)?)?;
+    Ok(ethers::abi::encode(&[token]))
+}
+
+/// Converts the JSON value found at `key` to a token of type `ty`, which has to be a string,
+/// number or bool
+fn value_as(key: &str, value: Value, ty: &ParamType) -> Result<Token, CheatcodeError> {
+    let value = match value {
+        Value::String(s) => s,
+        Value::Number(num)
+            if num.is_f64() && matches!(ty, ParamType::Uint(_) | ParamType::Int(_)) =>
+        {
+            return Err(CheatcodeError::JsonNumber(num.to_string()))
+        }
+        value @ (Value::Number(_) | Value::Bool(_)) => value.to_string(),
+        _ => {
+            return Err(CheatcodeError::JsonType {
+                key: key.to_string(),
+                expected: format!("a `{}`", ty),
+            })
+        }
+    };
+    foundry_utils::parse_tokens(std::iter::once((ty, value.as_str())), true)
+        .ok()
+        .and_then(|mut tokens| tokens.pop())
+        .ok_or_else(|| CheatcodeError::JsonParse { key: key.to_string(), ty: ty.clone() })
+}
+
+/// Returns the value found at `key` in the `json` document ABI-encoded as `ty`
+pub fn parse_json_as(json: &str, key: &str, ty: ParamType) -> Result<Vec<u8>, CheatcodeError> {
+    let token = value_as(key, lookup(json, key)?, &ty)?;
+    Ok(ethers::abi::encode(&[token]))
+}
+
+/// Returns the array found at `key` in the `json` document ABI-encoded as `ty[]`
+pub fn parse_json_array_as(
+    json: &str,
+    key: &str,
+    ty: ParamType,
+) -> Result<Vec<u8>, CheatcodeError> {
+    let values = match lookup(json, key)? {
+        Value::Array(values) => values,
+        _ => {
+            return Err(CheatcodeError::JsonType {
+                key: key.to_string(),
+                expected: "an array".to_string(),
+            })
+        }
+    };
+    let tokens = values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| value_as(&format!("{}[{}]", key, i), value, &ty))
+        .collect::<Result<_, _>>()?;
+    Ok(ethers::abi::encode(&[Token::Array(tokens)]))
+}
+
+/// Returns whether `key` exists in the `json` document
+pub fn key_exists(json: &str, key: &str) -> Result<bool, CheatcodeError> {
+    Ok(parse(json)?.pointer(&to_pointer(key)).is_some())
+}
+
+/// Returns the keys of the object found at `key` in the `json` document, ordered like the
+/// members of the tuples returned by [`parse_json`], ABI-encoded as `string[]`
+pub fn parse_json_keys(json: &str, key: &str) -> Result<Vec<u8>, CheatcodeError> {
+    let keys = match lookup(json, key)? {
+        Value::Object(map) => map.keys().map(|key| Token::String(key.clone())).collect(),
+        _ => {
+            return Err(CheatcodeError::JsonType {
+                key: key.to_string(),
+                expected: "an object".to_string(),
+            })
+        }
+    };
+    Ok(ethers::abi::encode(&[Token::Array(keys)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON: &str = r#"{
+        "a": { "b": [1, "0x0000000000000000000000000000000000000001"] },
+        "neg": -5,
+        "name": "forge",
+        "ok": true
+    }"#;
+
+    #[test]
+    fn converts_keys_to_pointers() {
+        assert_eq!(to_pointer("."), "");
+        assert_eq!(to_pointer("$"), "");
+        assert_eq!(to_pointer(".a.b[0]"), "/a/b/0");
+        assert_eq!(to_pointer("$.a.b"), "/a/b");
+        assert_eq!(to_pointer(".a/b"), "/a~1b");
+    }
+
+    #[test]
+    fn infers_types() {
+        assert_eq!(
+            parse_json(JSON, ".a.b[0]").unwrap(),
+            ethers::abi::encode(&[Token::Uint(1.into())])
+        );
+        assert_eq!(
+            parse_json(JSON, ".a.b[1]").unwrap(),
+            ethers::abi::encode(&[Token::Address(Address::from_low_u64_be(1))])
+        );
+        assert_eq!(
+            parse_json(JSON, ".neg").unwrap(),
+            ethers::abi::encode(&[Token::Int(I256::from(-5i64).into_raw())])
+        );
+        assert_eq!(
+            parse_json(JSON, ".name").unwrap(),
+            ethers::abi::encode(&[Token::String("forge".to_string())])
+        );
+        assert!(parse_json(JSON, ".missing").is_err());
+        assert_eq!(
+            parse_json("[[], [1], [2, 3]]", ".").unwrap(),
+            ethers::abi::encode(&[Token::Array(vec![
+                Token::Array(vec![]),
+                Token::Array(vec![Token::Uint(1.into())]),
+                Token::Array(vec![Token::Uint(2.into()), Token::Uint(3.into())]),
+            ])])
+        );
+    }
+
+    #[test]
+    fn rejects_mixed_arrays() {
+        assert!(matches!(parse_json(JSON, ".a.b"), Err(CheatcodeError::JsonMixedArray)));
+        assert!(matches!(parse_json("[1, -1]", "."), Err(CheatcodeError::JsonMixedArray)));
+        // the types of nested arrays are compared across empty arrays
+        assert!(matches!(
+            parse_json(r#"[[1], [], ["a"]]"#, "."),
+            Err(CheatcodeError::JsonMixedArray)
+        ));
+        assert!(matches!(
+            parse_json(r#"[{"a": 1}, {"a": true}]"#, "."),
+            Err(CheatcodeError::JsonMixedArray)
+        ));
+    }
+
+    #[test]
+    fn rejects_lossy_numbers() {
+        // above `u64::MAX`, so serde_json parses it as `1e20`
+        let json = r#"{"wei": 100000000000000000000, "quoted": "100000000000000000000"}"#;
+        assert!(matches!(parse_json(json, ".wei"), Err(CheatcodeError::JsonNumber(_))));
+        assert!(matches!(
+            parse_json_as(json, ".wei", ParamType::Uint(256)),
+            Err(CheatcodeError::JsonNumber(_))
+        ));
+        assert_eq!(
+            parse_json_as(json, ".quoted", ParamType::Uint(256)).unwrap(),
+            ethers::abi::encode(&[Token::Uint(ethers::types::U256::exp10(20))])
+        );
+        assert!(matches!(parse_json("1.5", "."), Err(CheatcodeError::JsonNumber(_))));
+        assert_eq!(
+            parse_json(&u64::MAX.to_string(), ".").unwrap(),
+            ethers::abi::encode(&[Token::Uint(u64::MAX.into())])
+        );
+        assert_eq!(
+            parse_json(&i64::MIN.to_string(), ".").unwrap(),
+            ethers::abi::encode(&[Token::Int(I256::from(i64::MIN).into_raw())])
+        );
+    }
+
+    #[test]
+    fn parses_typed_values() {
+        assert_eq!(
+            parse_json_as(JSON, ".ok", ParamType::Bool).unwrap(),
+            ethers::abi::encode(&[Token::Bool(true)])
+        );
+        assert_eq!(
+            parse_json_as(JSON, ".neg", ParamType::Int(256)).unwrap(),
+            ethers::abi::encode(&[Token::Int(I256::from(-5i64).into_raw())])
+        );
+        assert!(parse_json_as(JSON, ".name", ParamType::Uint(256)).is_err());
+        assert!(parse_json_as(JSON, ".a", ParamType::Uint(256)).is_err());
+    }
+
+    #[test]
+    fn parses_arrays_and_keys() {
+        let json = r#"{"ids": [1, "2"], "owners": ["0x0000000000000000000000000000000000000001"]}"#;
+        assert_eq!(
+            parse_json_array_as(json, ".ids", ParamType::Uint(256)).unwrap(),
+            ethers::abi::encode(&[Token::Array(vec![
+                Token::Uint(1.into()),
+                Token::Uint(2.into())
+            ])])
+        );
+        assert_eq!(
+            parse_json_array_as(json, ".owners", ParamType::Address).unwrap(),
+            ethers::abi::encode(&[Token::Array(vec![Token::Address(Address::from_low_u64_be(1))])])
+        );
+        assert_eq!(
+            parse_json_array_as(json, ".owners", ParamType::Uint(256)).unwrap_err().to_string(),
+            "Failed to parse JSON value at `.owners[0]` as type `uint256`"
+        );
+        assert!(parse_json_array_as(JSON, ".name", ParamType::String).is_err());
+
+        assert!(key_exists(JSON, ".a.b[1]").unwrap());
+        assert!(!key_exists(JSON, ".a.c").unwrap());
+        assert!(key_exists("{", ".a").is_err());
+
+        assert_eq!(
+            parse_json_keys(JSON, ".").unwrap(),
+            ethers::abi::encode(&[Token::Array(
+                ["a", "name", "neg", "ok"]
+                    .iter()
+                    .map(|key| Token::String(key.to_string()))
+                    .collect()
+            )])
+        );
+        assert!(parse_json_keys(JSON, ".name").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "cheatcodes-toml")]
+    fn parses_toml() {
+        let toml = r#"
+            name = "forge"
+            released = 2021-12-01
+
+            [mainnet]
+            weth = "0x0000000000000000000000000000000000000001"
+            chain_id = 1
+            fees = [30, 5]
+        "#;
+        let json = toml_to_json(toml).unwrap();
+        assert_eq!(
+            parse_json_as(&json, ".mainnet.weth", ParamType::Address).unwrap(),
+            ethers::abi::encode(&[Token::Address(Address::from_low_u64_be(1))])
+        );
+        assert_eq!(
+            parse_json(&json, ".mainnet.chain_id").unwrap(),
+            ethers::abi::encode(&[Token::Uint(1.into())])
+        );
+        assert_eq!(
+            parse_json_array_as(&json, ".mainnet.fees", ParamType::Uint(256)).unwrap(),
+            ethers::abi::encode(&[Token::Array(vec![
+                Token::Uint(30.into()),
+                Token::Uint(5.into())
+            ])])
+        );
+        assert_eq!(
+            parse_json_as(&json, ".released", ParamType::String).unwrap(),
+            ethers::abi::encode(&[Token::String("2021-12-01".to_string())])
+        );
+        assert!(toml_to_json("name = ").is_err());
+    }
+}
@@ -1,10 +1,101 @@
 use sputnik::{
-    backend::{Backend, Basic},
+    backend::{Apply, Backend, Basic, MemoryAccount},
     executor::stack::{MemoryStackSubstate, StackState, StackSubstateMetadata},
     ExitError, Transfer,
 };
 
-use ethers::types::{H160, H256, U256};
+use ethers::{
+    abi::RawLog,
+    types::{TransactionRequest, H160, H256, U256},
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A log expected to be emitted by the next call of the contract which called `expectEmit`
+#[derive(Clone, Debug)]
+pub struct ExpectedEmit {
+    /// The depth of the contract which expects the log
+    pub depth: Option<usize>,
+    /// The log the contract emitted after `expectEmit`, which the next call has to emit as well
+    pub log: Option<RawLog>,
+    /// Which of the topics are compared with the emitted log's
+    pub check_topics: [bool; 4],
+    /// Whether the data is compared with the emitted log's
+    pub check_data: bool,
+    /// The address which has to emit the log, any if `None`
+    pub emitter: Option<H160>,
+    /// Whether the log was emitted
+    pub found: bool,
+}
+
+impl ExpectedEmit {
+    /// Whether the log emitted by the address matches the expected log. The number of topics has
+    /// to be the same, which for anonymous events is all that tells them apart besides the
+    /// checked topics
+    pub fn matches(&self, address: H160, log: &RawLog) -> bool {
+        let expected = match self.log {
+            Some(ref expected) => expected,
+            None => return false,
+        };
+        if self.emitter.map_or(false, |emitter| emitter != address) {
+            return false
+        }
+        if expected.topics.len() != log.topics.len() {
+            return false
+        }
+        let topics_match = expected
+            .topics
+            .iter()
+            .zip(&log.topics)
+            .zip(self.check_topics)
+            .all(|((expected, actual), check)| !check || expected == actual);
+        topics_match && (!self.check_data || expected.data == log.data)
+    }
+}
+
+/// Calls expected to be made by `expectCall`
+#[derive(Clone, Debug)]
+pub struct ExpectedCall {
+    /// The called address
+    pub address: H160,
+    /// The calldata the calls have to start with
+    pub calldata: Vec<u8>,
+    /// How many matching calls have to be made, at least one if `None`
+    pub count: Option<u64>,
+    /// How many matching calls were made
+    pub calls: u64,
+}
+
+impl ExpectedCall {
+    /// Describes the expectation if it was not met by the calls made so far
+    pub fn unmet(&self) -> Option<String> {
+        match self.count {
+            None if self.calls == 0 => Some(format!(
+                "`expectCall({:?}, 0x{})` was not followed by a matching call",
+                self.address,
+                hex::encode(&self.calldata)
+            )),
+            Some(count) if count != self.calls => Some(format!(
+                "`expectCall({:?}, 0x{}, {})` was matched by {} calls",
+                self.address,
+                hex::encode(&self.calldata),
+                count,
+                self.calls
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// The result of a call mocked by `mockCall` or `mockCallRevert`
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockedCall {
+    /// Whether the call reverts with the data instead of returning it
+    pub reverts: bool,
+    /// The returned or revert data
+    pub data: Vec<u8>,
+    /// How many calls the mock matched so far
+    pub calls: u64,
+}
 
 /// This struct implementation is copied from [upstream](https://github.com/rust-blockchain/evm/blob/5ecf36ce393380a89c6f1b09ef79f686fe043624/src/executor/stack/state.rs#L412) and modified to own the Backend type.
 ///
@@ -16,14 +107,128 @@ pub struct MemoryStackStateOwned<'config, B> {
     pub backend: B,
     pub substate: MemoryStackSubstate<'config>,
     pub expected_revert: Option<Vec<u8>>,
+    /// Whether `expected_revert` only has to prefix the revert data, as for custom error
+    /// selectors. An empty prefix matches any revert.
+    pub expected_revert_is_prefix: bool,
+    /// Set by `expectRevert(bytes,uint64)`: the depth of the calls of the expecting contract,
+    /// which are the only ones to consume `expected_revert`, and how many levels below such a
+    /// call the expected revert has to happen
+    pub expected_revert_depth: Option<(usize, usize)>,
+    /// The logs expected by `expectEmit` and `expectEmitAnonymous`, in the order they have to be
+    /// emitted
+    pub expected_emits: Vec<ExpectedEmit>,
     pub next_msg_sender: Option<H160>,
     pub msg_sender: Option<(H160, H160, usize)>,
+    /// Whether `msg_sender` also applies to the delegatecalls of the pranking contract, which
+    /// then see the pranked sender as their `msg.sender`
+    pub prank_delegate_calls: bool,
+    /// Whether `msg_sender` also applies to the contracts created by the pranking contract, which
+    /// are then deployed by the pranked sender
+    pub prank_creates: bool,
+    /// Gas sections started with `snapshotGasStart`, mapped to the gas left and the depth at
+    /// which they were started
+    pub active_gas_sections: BTreeMap<String, (u64, usize)>,
+    /// Gas used by every section which was ended with `snapshotGasEnd`
+    pub gas_sections: BTreeMap<String, u64>,
+    /// Whether opcodes are executed without using gas, as set by `pauseGasMetering`
+    pub gas_metering_paused: bool,
+    /// The warnings of the deprecated cheatcodes which were called
+    pub deprecations: BTreeSet<String>,
+    /// The sender of the next call or create, which is collected as a transaction, as set by
+    /// `broadcast`
+    pub next_broadcast: Option<H160>,
+    /// Like `msg_sender`, the contract and depth whose calls and creates are collected as
+    /// transactions sent from the broadcasting account, as set by `startBroadcast`
+    pub broadcast: Option<(H160, H160, usize)>,
+    /// Every call and create collected by `broadcast` and `startBroadcast`, in execution order
+    pub broadcastable_transactions: Vec<TransactionRequest>,
+    /// Accounts made cold again by `cool` which were not accessed since
+    pub cooled_accounts: BTreeSet<H160>,
+    /// The storage slots of the accounts made cold again by `cool` which were accessed since
+    pub warmed_storage: BTreeMap<H160, BTreeSet<H256>>,
+    /// The calls mocked by `mockCall` and `mockCallRevert`, by the called address and the
+    /// calldata they match
+    pub mocked_calls: BTreeMap<H160, BTreeMap<Vec<u8>, MockedCall>>,
+    /// The calls expected by `expectCall`, which are checked when the test ends
+    pub expected_calls: Vec<ExpectedCall>,
+    /// The labels of addresses, as set by `label`
+    pub labels: BTreeMap<H160, String>,
+    /// The accounts changed on every fork which is not selected, by fork id (`None` for the state
+    /// the test started on), which are swapped back in when the fork is selected again
+    pub fork_changes: BTreeMap<Option<usize>, BTreeMap<H160, MemoryAccount>>,
 }
 
 impl<'config, B: Backend> MemoryStackStateOwned<'config, B> {
     pub fn deposit(&mut self, address: H160, value: U256) {
         self.substate.deposit(address, value, &self.backend);
     }
+
+    /// Returns every account which was modified on top of the backend, including the changes of
+    /// the calls which are still executing, with the storage slots which were written to.
+    /// Deleted accounts are left out
+    pub fn touched_accounts(&self) -> Result<BTreeMap<H160, MemoryAccount>, ExitError> {
+        // the substate can only be deconstructed at the top level, so the open calls are
+        // committed into a copy of it first
+        let mut substate = self.substate.clone();
+        while substate.metadata().depth().is_some() {
+            substate.exit_commit()?;
+        }
+
+        let (applies, _) = substate.deconstruct(&self.backend);
+        let mut accounts = BTreeMap::new();
+        for apply in applies {
+            if let Apply::Modify { address, basic, code, storage, .. } = apply {
+                let account = MemoryAccount {
+                    nonce: basic.nonce,
+                    balance: basic.balance,
+                    storage: storage.into_iter().collect(),
+                    code: code.unwrap_or_else(|| self.code(address)),
+                };
+                accounts.insert(address, account);
+            }
+        }
+        Ok(accounts)
+    }
+
+    /// Swaps the accounts changed on the fork `from` out of the substate and the ones changed on
+    /// the fork `to` in, once `to` was selected, so that the changes made on one fork are not
+    /// seen on another. `changes` are the accounts changed so far, see [`Self::touched_accounts`]
+    pub fn swap_fork_changes(
+        &mut self,
+        from: Option<usize>,
+        to: Option<usize>,
+        changes: BTreeMap<H160, MemoryAccount>,
+    ) {
+        // the substate can not forget accounts, so they are overwritten with the state of `to`
+        for (address, account) in &changes {
+            let basic = self.backend.basic(*address);
+            let code = self.backend.code(*address);
+            let local = self.substate.account_mut(*address, &self.backend);
+            local.basic = basic;
+            local.code = Some(code);
+            local.reset = false;
+            for slot in account.storage.keys() {
+                let value = self.backend.storage(*address, *slot);
+                self.substate.set_storage(*address, *slot, value);
+            }
+        }
+        for (address, account) in self.fork_changes.remove(&to).unwrap_or_default() {
+            let local = self.substate.account_mut(address, &self.backend);
+            local.basic = Basic { balance: account.balance, nonce: account.nonce };
+            local.code = Some(account.code);
+            for (slot, value) in account.storage {
+                self.substate.set_storage(address, slot, value);
+            }
+        }
+        self.fork_changes.insert(from, changes);
+    }
+
+    /// Reads the storage slots of the account which were not written since it was created (or
+    /// its storage was reset) from the backend again, e.g. the slots `copyStorage` aliased to the
+    /// storage of another account
+    pub fn unreset_storage(&mut self, address: H160) {
+        self.substate.account_mut(address, &self.backend).reset = false;
+    }
 }
 
 impl<'config, B: Backend> MemoryStackStateOwned<'config, B> {
@@ -32,8 +237,59 @@ impl<'config, B: Backend> MemoryStackStateOwned<'config, B> {
             backend,
             substate: MemoryStackSubstate::new(metadata),
             expected_revert: None,
+            expected_revert_is_prefix: false,
+            expected_revert_depth: None,
+            expected_emits: Vec::new(),
             next_msg_sender: None,
             msg_sender: None,
+            prank_delegate_calls: false,
+            prank_creates: false,
+            active_gas_sections: BTreeMap::new(),
+            gas_sections: BTreeMap::new(),
+            gas_metering_paused: false,
+            deprecations: BTreeSet::new(),
+            next_broadcast: None,
+            broadcast: None,
+            broadcastable_transactions: Vec::new(),
+            cooled_accounts: BTreeSet::new(),
+            warmed_storage: BTreeMap::new(),
+            mocked_calls: BTreeMap::new(),
+            expected_calls: Vec::new(),
+            labels: BTreeMap::new(),
+            fork_changes: BTreeMap::new(),
+        }
+    }
+
+    /// Marks the account and all of its storage as not accessed in this transaction. The
+    /// substate's access lists can only grow, so this is tracked on top of them
+    pub fn cool(&mut self, address: H160) {
+        self.cooled_accounts.insert(address);
+        self.warmed_storage.insert(address, BTreeSet::new());
+    }
+
+    /// Returns the mock of the call to the address with the calldata. A mock matches if its
+    /// calldata is a prefix of the call's, and the longest matching calldata wins, so that a mock
+    /// of a selector can be overridden for specific arguments
+    pub fn mocked_call(&mut self, address: H160, input: &[u8]) -> Option<&mut MockedCall> {
+        self.mocked_calls
+            .get_mut(&address)?
+            .range_mut(..=input.to_vec())
+            .rev()
+            .find(|(calldata, _)| input.starts_with(calldata))
+            .map(|(_, mock)| mock)
+    }
+
+    /// Records an access of the account or storage slot, warming it up again if it was cooled
+    pub fn warm(&mut self, address: H160, slot: Option<H256>) {
+        match slot {
+            Some(slot) => {
+                if let Some(warmed) = self.warmed_storage.get_mut(&address) {
+                    warmed.insert(slot);
+                }
+            }
+            None => {
+                self.cooled_accounts.remove(&address);
+            }
         }
     }
 }
@@ -137,11 +393,14 @@ impl<'config, B: Backend> StackState<'config> for MemoryStackStateOwned<'config,
     }
 
     fn is_cold(&self, address: H160) -> bool {
-        self.substate.is_cold(address)
+        self.cooled_accounts.contains(&address) || self.substate.is_cold(address)
     }
 
     fn is_storage_cold(&self, address: H160, key: H256) -> bool {
-        self.substate.is_storage_cold(address, key)
+        match self.warmed_storage.get(&address) {
+            Some(warmed) => !warmed.contains(&key),
+            None => self.substate.is_storage_cold(address, key),
+        }
     }
 
     fn inc_nonce(&mut self, address: H160) {
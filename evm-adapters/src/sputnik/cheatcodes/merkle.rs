@@ -0,0 +1,107 @@
+//! Helpers for the Merkle tree cheatcodes
+use ethers::{types::H256, utils::keccak256};
+
+use super::CheatcodeError;
+
+/// Hashes two nodes, ordering them first if `sorted` (as expected by OpenZeppelin's
+/// `MerkleProof`)
+fn hash_pair(a: H256, b: H256, sorted: bool) -> H256 {
+    let (a, b) = if sorted && b < a { (b, a) } else { (a, b) };
+    keccak256([a.as_bytes(), b.as_bytes()].concat()).into()
+}
+
+/// Returns the levels of the tree built over `leaves`, from the leaves up to the root. A node
+/// without a sibling is hashed with the zero hash, so that each level has half the nodes (rounded
+/// up) of the one below.
+fn levels(leaves: &[H256], sorted: bool) -> Result<Vec<Vec<H256>>, CheatcodeError> {
+    if leaves.is_empty() {
+        return Err(CheatcodeError::NoMerkleLeaves)
+    }
+    let mut levels = vec![leaves.to_vec()];
+    while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+        let parents = level
+            .chunks(2)
+            .map(|pair| hash_pair(pair[0], pair.get(1).copied().unwrap_or_default(), sorted))
+            .collect();
+        levels.push(parents);
+    }
+    Ok(levels)
+}
+
+/// Returns the root of the tree built over `leaves`
+pub fn root(leaves: &[H256], sorted: bool) -> Result<H256, CheatcodeError> {
+    Ok(levels(leaves, sorted)?.pop().expect("at least one level")[0])
+}
+
+/// Returns the siblings of the leaf at `index` on the path to the root, bottom up
+pub fn proof(leaves: &[H256], index: usize, sorted: bool) -> Result<Vec<H256>, CheatcodeError> {
+    if index >= leaves.len() {
+        return Err(CheatcodeError::MerkleIndex { index, leaves: leaves.len() })
+    }
+    let levels = levels(leaves, sorted)?;
+    let mut index = index;
+    let mut proof = Vec::with_capacity(levels.len() - 1);
+    for level in &levels[..levels.len() - 1] {
+        proof.push(level.get(index ^ 1).copied().unwrap_or_default());
+        index /= 2;
+    }
+    Ok(proof)
+}
+
+/// Returns whether `proof` proves that `leaf` is at `index` in the tree with the given `root`.
+/// The index is only used to order the nodes of unsorted trees.
+pub fn verify(root: H256, proof: &[H256], leaf: H256, index: usize, sorted: bool) -> bool {
+    let mut index = index;
+    let mut node = leaf;
+    for sibling in proof {
+        node = if index % 2 == 0 {
+            hash_pair(node, *sibling, sorted)
+        } else {
+            hash_pair(*sibling, node, sorted)
+        };
+        index /= 2;
+    }
+    node == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: u64) -> Vec<H256> {
+        (1..=n).map(H256::from_low_u64_be).collect()
+    }
+
+    #[test]
+    fn single_leaf_is_the_root() {
+        let leaves = leaves(1);
+        assert_eq!(root(&leaves, false).unwrap(), leaves[0]);
+        assert!(proof(&leaves, 0, false).unwrap().is_empty());
+        assert!(root(&[], false).is_err());
+    }
+
+    #[test]
+    fn hashes_pairs() {
+        let leaves = leaves(3);
+        let left = hash_pair(leaves[0], leaves[1], false);
+        let right = hash_pair(leaves[2], H256::zero(), false);
+        assert_eq!(root(&leaves, false).unwrap(), hash_pair(left, right, false));
+        assert_eq!(proof(&leaves, 2, false).unwrap(), vec![H256::zero(), left]);
+        assert!(proof(&leaves, 3, false).is_err());
+    }
+
+    #[test]
+    fn proofs_verify() {
+        for sorted in [false, true] {
+            for n in 1..=9 {
+                let leaves = leaves(n);
+                let root = root(&leaves, sorted).unwrap();
+                for (index, leaf) in leaves.iter().enumerate() {
+                    let proof = proof(&leaves, index, sorted).unwrap();
+                    assert!(verify(root, &proof, *leaf, index, sorted));
+                    assert!(!verify(root, &proof, H256::repeat_byte(0xff), index, sorted));
+                }
+            }
+        }
+    }
+}
@@ -7,8 +7,26 @@ use std::collections::HashMap;
 
 pub use cheatcode_handler::CheatcodeHandler;
 
+pub mod error;
+pub use error::CheatcodeError;
+
 pub mod backend;
 
+mod json;
+
+mod merkle;
+
+mod signing;
+
+#[cfg(feature = "fork-backend")]
+pub mod forks;
+#[cfg(feature = "fork-backend")]
+use forks::Forks;
+#[cfg(feature = "fork-backend")]
+use std::collections::BTreeSet;
+
+pub mod interface;
+
 use ethers::types::{Address, Selector, H256, U256};
 use once_cell::sync::Lazy;
 use sputnik::backend::{Backend, MemoryAccount, MemoryBackend};
@@ -23,8 +41,20 @@ pub struct Cheatcodes {
     pub block_timestamp: Option<U256>,
     /// The overriden basefee
     pub block_base_fee_per_gas: Option<U256>,
+    /// The overriden hashes of blocks by their number
+    pub block_hashes: HashMap<U256, H256>,
     /// The overriden storage slots
     pub accounts: HashMap<Address, MemoryAccount>,
+    /// The forks created with `createFork`
+    #[cfg(feature = "fork-backend")]
+    pub forks: Forks,
+    /// Accounts whose state is read from the inner backend whichever fork is selected, e.g. the
+    /// test contract which selected a fork
+    #[cfg(feature = "fork-backend")]
+    pub persistent_accounts: BTreeSet<Address>,
+    /// Accounts whose storage is read from another account of the chain state, since their
+    /// storage was replaced with it by `copyStorage`
+    pub storage_aliases: HashMap<Address, Address>,
 }
 
 /// Extension trait over [`Backend`] which provides additional methods for interacting with the
@@ -46,43 +76,146 @@ ethers::contract::abigen!(
     r#"[
             roll(uint256)
             warp(uint256)
+            warpBy(uint256)
+            rollBy(uint256)
+            getBlockTimestamp()(uint256)
+            getBlockNumber()(uint256)
             fee(uint256)
+            setBlockhash(uint256,bytes32)
             store(address,bytes32,bytes32)
             load(address,bytes32)(bytes32)
             ffi(string[])(bytes)
+            tryFfi(string[])((int32,bytes,bytes))
             addr(uint256)(address)
             sign(uint256,bytes32)(uint8,bytes32,bytes32)
+            signTypedData(uint256,bytes32,bytes32)(uint8,bytes32,bytes32)
+            deriveKey(string,uint32)(uint256)
             prank(address)
             startPrank(address)
+            startPrank(address,bool,bool)
             stopPrank()
             deal(address,uint256)
+            dealToken(address,address,uint256)
+            dealToken(address,address,uint256,bool)
+            dealToken(address,address,uint256,bool,uint256)
             etch(address,bytes)
             expectRevert(bytes)
+            expectRevert(bytes4)
+            expectRevert()
+            expectRevert(bytes,uint64)
+            expectEmit(bool,bool,bool,bool)
+            expectEmit(bool,bool,bool,bool,address)
+            expectEmitAnonymous(bool,bool,bool,bool,bool)
+            expectEmitAnonymous(bool,bool,bool,bool,bool,address)
+            expectCall(address,bytes)
+            expectCall(address,bytes,uint64)
+            mockCall(address,bytes,bytes)
+            mockCallRevert(address,bytes,bytes)
+            clearMockedCalls()
+            envBool(string)(bool)
+            envUint(string)(uint256)
+            envInt(string)(int256)
+            envAddress(string)(address)
+            envBytes32(string)(bytes32)
+            envString(string)(string)
+            envBytes(string)(bytes)
+            snapshotGasStart(string)
+            snapshotGasEnd(string)(uint256)
+            pauseGasMetering()
+            resumeGasMetering()
+            skip(bool)
+            breakpoint(string)
+            label(address,string)
+            getLabel(address)(string)
+            expectSafeMemory(uint64,uint64)
+            expectSafeMemoryCall(uint64,uint64)
+            broadcast()
+            broadcast(address)
+            startBroadcast()
+            startBroadcast(address)
+            stopBroadcast()
+            randomUint()(uint256)
+            randomUint(uint256,uint256)(uint256)
+            randomAddress()(address)
+            toString(address)(string)
+            toString(bytes)(string)
+            toString(bytes32)(string)
+            toString(bool)(string)
+            toString(uint256)(string)
+            toString(int256)(string)
+            parseJson(string,string)(bytes)
+            parseJsonBool(string,string)(bool)
+            parseJsonUint(string,string)(uint256)
+            parseJsonInt(string,string)(int256)
+            parseJsonAddress(string,string)(address)
+            parseJsonBytes32(string,string)(bytes32)
+            parseJsonString(string,string)(string)
+            parseJsonBytes(string,string)(bytes)
+            parseJsonBoolArray(string,string)(bool[])
+            parseJsonUintArray(string,string)(uint256[])
+            parseJsonIntArray(string,string)(int256[])
+            parseJsonAddressArray(string,string)(address[])
+            parseJsonBytes32Array(string,string)(bytes32[])
+            parseJsonStringArray(string,string)(string[])
+            parseJsonBytesArray(string,string)(bytes[])
+            parseJsonKeys(string,string)(string[])
+            keyExists(string,string)(bool)
+            parseToml(string,string)(bytes)
+            parseTomlBool(string,string)(bool)
+            parseTomlUint(string,string)(uint256)
+            parseTomlInt(string,string)(int256)
+            parseTomlAddress(string,string)(address)
+            parseTomlBytes32(string,string)(bytes32)
+            parseTomlString(string,string)(string)
+            parseTomlBytes(string,string)(bytes)
+            parseTomlBoolArray(string,string)(bool[])
+            parseTomlUintArray(string,string)(uint256[])
+            parseTomlIntArray(string,string)(int256[])
+            parseTomlAddressArray(string,string)(address[])
+            parseTomlBytes32Array(string,string)(bytes32[])
+            parseTomlStringArray(string,string)(string[])
+            parseTomlBytesArray(string,string)(bytes[])
+            parseTomlKeys(string,string)(string[])
+            keyExistsToml(string,string)(bool)
+            createFork(string,uint256)(uint256)
+            selectFork(uint256)
+            activeFork()(uint256)
+            rollFork(uint256)
+            rollFork(bytes32)
+            rpc(string,string)(bytes)
+            decodeTx(bytes)(uint8,address,address,uint256,uint256,uint256,bytes,uint256)
+            getCode(string)(bytes)
+            getDeployedCode(string)(bytes)
+            dumpState(string)
+            loadAllocs(string)
+            projectRoot()(string)
+            readDir(string)(string[])
+            exists(string)(bool)
+            isFile(string)(bool)
+            isDir(string)(bool)
+            copyStorage(address,address)
+            prompt(string)(string)
+            promptSecret(string)(string)
+            promptUint(string)(uint256)
+            clearStorage(address)
+            resetAccount(address)
+            cool(address)
+            cheatcodeVersion()(uint256)
+            supports(bytes4)(bool)
+            sleep(uint256)
+            unixTime()(uint256)
+            startStateDiffRecording()
+            stopAndReturnStateDiff()((address,uint256,uint256,bool,(bytes32,bytes32,bytes32,bool)[])[])
+            signEd25519(bytes32,bytes)(bytes32,bytes32,bytes32)
+            signBls(uint256,bytes)(bytes,bytes)
+            getMerkleRoot(bytes32[],bool)(bytes32)
+            getMerkleProof(bytes32[],uint256,bool)(bytes32[])
+            verifyMerkleProof(bytes32,bytes32[],bytes32,uint256,bool)(bool)
     ]"#,
 );
-pub use hevm_mod::HEVMCalls;
+pub use hevm_mod::{HEVMCalls, HEVM_ABI};
 
-ethers::contract::abigen!(
-    HevmConsole,
-    r#"[
-            event log(string)
-            event logs                   (bytes)
-            event log_address            (address)
-            event log_bytes32            (bytes32)
-            event log_int                (int)
-            event log_uint               (uint)
-            event log_bytes              (bytes)
-            event log_string             (string)
-            event log_named_address      (string key, address val)
-            event log_named_bytes32      (string key, bytes32 val)
-            event log_named_decimal_int  (string key, int val, uint decimals)
-            event log_named_decimal_uint (string key, uint val, uint decimals)
-            event log_named_int          (string key, int val)
-            event log_named_uint         (string key, uint val)
-            event log_named_bytes        (string key, bytes val)
-            event log_named_string       (string key, string val)
-            ]"#,
-);
+pub use crate::logs::{HevmConsole, HevmConsoleEvents};
 
 // Bindings for hardhat console
 ethers::contract::abigen!(Console, "./testdata/console.json",);
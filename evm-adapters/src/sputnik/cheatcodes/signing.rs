@@ -0,0 +1,101 @@
+//! Helpers for the `signTypedData`, `signEd25519` and `signBls` cheatcodes
+#[cfg(feature = "cheatcodes-sign")]
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+
+#[cfg(feature = "cheatcodes-sign")]
+use super::CheatcodeError;
+
+/// The domain separation tag of the proof of possession scheme used by the Ethereum beacon chain
+#[cfg(feature = "cheatcodes-sign")]
+const BLS_DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// Computes the EIP-712 digest `keccak256("\x19\x01" ‖ domainSeparator ‖ structHash)` of typed
+/// structured data, which is what gets signed e.g. for `permit`
+pub fn eip712_digest(domain_separator: [u8; 32], struct_hash: [u8; 32]) -> [u8; 32] {
+    ethers::utils::keccak256([&[0x19, 0x01][..], &domain_separator, &struct_hash].concat())
+}
+
+/// Signs `message` with the ed25519 secret key `key` and returns the public key and the
+/// signature `(R, S)`, which is the format expected by Solidity ed25519 verifiers
+#[cfg(feature = "cheatcodes-sign")]
+pub fn sign_ed25519(key: [u8; 32], message: &[u8]) -> ([u8; 32], [u8; 32], [u8; 32]) {
+    // any 32 bytes are a valid secret key
+    let secret = SecretKey::from_bytes(&key).expect("the secret key has 32 bytes");
+    let public = PublicKey::from(&secret);
+    let signature = Keypair { secret, public }.sign(message).to_bytes();
+    let mut r = [0u8; 32];
+    let mut s = [0u8; 32];
+    r.copy_from_slice(&signature[..32]);
+    s.copy_from_slice(&signature[32..]);
+    (public.to_bytes(), r, s)
+}
+
+/// Signs `message` with the BLS12-381 secret key `key` (big endian) and returns the compressed
+/// public key (48 bytes, G1) and signature (96 bytes, G2), as used by the beacon chain deposit
+/// contract
+#[cfg(feature = "cheatcodes-sign")]
+pub fn sign_bls(key: [u8; 32], message: &[u8]) -> Result<(Vec<u8>, Vec<u8>), CheatcodeError> {
+    let key =
+        blst::min_pk::SecretKey::from_bytes(&key).map_err(|_| CheatcodeError::InvalidBlsKey)?;
+    let signature = key.sign(message, BLS_DST, &[]);
+    Ok((key.sk_to_pk().compress().to_vec(), signature.compress().to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_eip712_digest() {
+        // the `Mail` example of EIP-712
+        let domain_separator =
+            hex::decode("f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f")
+                .unwrap();
+        let struct_hash =
+            hex::decode("c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e")
+                .unwrap();
+        let digest =
+            eip712_digest(domain_separator.try_into().unwrap(), struct_hash.try_into().unwrap());
+        assert_eq!(
+            hex::encode(digest),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cheatcodes-sign")]
+    fn signs_ed25519() {
+        // test 1 of RFC 8032
+        let key = hex::decode("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60")
+            .unwrap();
+        let (public, r, s) = sign_ed25519(key.try_into().unwrap(), b"");
+        assert_eq!(
+            hex::encode(public),
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+        assert_eq!(
+            hex::encode([r, s].concat()),
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cheatcodes-sign")]
+    fn signs_bls() {
+        let mut key = [0u8; 32];
+        key[31] = 1;
+        let (public, signature) = sign_bls(key, b"hello").unwrap();
+        assert_eq!(public.len(), 48);
+        assert_eq!(signature.len(), 96);
+
+        let public = blst::min_pk::PublicKey::uncompress(&public).unwrap();
+        let signature = blst::min_pk::Signature::uncompress(&signature).unwrap();
+        assert_eq!(
+            signature.verify(true, b"hello", BLS_DST, &[], &public, true),
+            blst::BLST_ERROR::BLST_SUCCESS
+        );
+
+        assert!(sign_bls([0u8; 32], b"hello").is_err());
+        assert!(sign_bls([0xff; 32], b"hello").is_err());
+    }
+}
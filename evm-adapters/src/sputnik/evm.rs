@@ -1,6 +1,15 @@
-use crate::{Evm, FAUCET_ACCOUNT};
+use crate::{
+    trace::{
+        Breakpoint, CallFrame, CallTrace, CoverageHits, DebugTrace, InternalJumps, PrankedCall,
+        StateDiff,
+    },
+    Evm, FAUCET_ACCOUNT,
+};
 
-use ethers::types::{Address, Bytes, U256};
+use ethers::{
+    abi::ParamType,
+    types::{Address, Bytes, Selector, TransactionRequest, H256, U256},
+};
 
 use sputnik::{
     backend::{Backend, MemoryAccount},
@@ -71,6 +80,70 @@ where
         self.executor.expected_revert()
     }
 
+    fn unused_expectations(&self) -> Vec<String> {
+        self.executor.unused_expectations()
+    }
+
+    fn clear_expectations(&mut self) {
+        self.executor.clear_expectations()
+    }
+
+    fn gas_sections(&self) -> BTreeMap<String, u64> {
+        self.executor.gas_sections()
+    }
+
+    fn deprecations(&self) -> Vec<String> {
+        self.executor.deprecations()
+    }
+
+    fn set_return_types(&mut self, types: BTreeMap<Selector, Vec<ParamType>>) {
+        self.executor.set_return_types(types)
+    }
+
+    fn set_internal_jumps(&mut self, jumps: BTreeMap<H256, InternalJumps>) {
+        self.executor.set_internal_jumps(jumps)
+    }
+
+    fn revert_trace(&self) -> Vec<CallFrame> {
+        self.executor.revert_trace()
+    }
+
+    fn breakpoints(&self) -> Vec<Breakpoint> {
+        self.executor.breakpoints()
+    }
+
+    fn pranks(&self) -> Vec<PrankedCall> {
+        self.executor.pranks()
+    }
+
+    fn call_trace(&self) -> CallTrace {
+        self.executor.call_trace()
+    }
+
+    fn set_debug(&mut self, enabled: bool) {
+        self.executor.set_debug(enabled)
+    }
+
+    fn debug_trace(&self) -> DebugTrace {
+        self.executor.debug_trace()
+    }
+
+    fn set_coverage(&mut self, enabled: bool) {
+        self.executor.set_coverage(enabled)
+    }
+
+    fn coverage(&self) -> CoverageHits {
+        self.executor.coverage()
+    }
+
+    fn state_diff(&self, before: &S) -> StateDiff {
+        self.executor.state_diff(before)
+    }
+
+    fn broadcastable_transactions(&self) -> Vec<TransactionRequest> {
+        self.executor.broadcastable_transactions()
+    }
+
     fn is_success(reason: &Self::ReturnReason) -> bool {
         matches!(reason, ExitReason::Succeed(_))
     }
@@ -100,6 +173,10 @@ where
             .expect("could not transfer funds")
     }
 
+    fn code(&self, address: Address) -> Bytes {
+        self.executor.state().code(address).into()
+    }
+
     fn state(&self) -> &S {
         self.executor.state()
     }
@@ -171,12 +248,11 @@ pub mod helpers {
     use ethers::types::H160;
     use sputnik::backend::{MemoryBackend, MemoryVicinity};
 
-    use crate::{
-        fuzz::FuzzedExecutor,
-        sputnik::{
-            cheatcodes::cheatcode_handler::{CheatcodeStackExecutor, CheatcodeStackState},
-            PrecompileFn, PRECOMPILES_MAP,
-        },
+    #[cfg(feature = "fuzz")]
+    use crate::fuzz::FuzzedExecutor;
+    use crate::sputnik::{
+        cheatcodes::cheatcode_handler::{CheatcodeStackExecutor, CheatcodeStackState},
+        PrecompileFn, PRECOMPILES_MAP,
     };
     use once_cell::sync::Lazy;
 
@@ -199,6 +275,7 @@ pub mod helpers {
     }
 
     /// Instantiates a FuzzedExecutor over provided Sputnik EVM
+    #[cfg(feature = "fuzz")]
     pub fn fuzzvm<'a, B: Backend>(
         evm: &'a mut TestSputnikVM<'a, B>,
     ) -> FuzzedExecutor<'a, TestSputnikVM<'a, B>, CheatcodeStackState<'a, B>> {
@@ -21,7 +21,7 @@ use std::{
     },
 };
 
-use crate::blocking_provider::RuntimeOrHandle;
+use crate::runtime::{self, Throttle};
 
 /// A basic in memory cache (address -> Account)
 pub type MemCache = BTreeMap<H160, MemoryAccount>;
@@ -43,14 +43,15 @@ pub fn new_shared_cache<T>(cache: T) -> SharedCache<T> {
     Arc::new(RwLock::new(cache))
 }
 
-type AccountFuture<Err> =
-    Pin<Box<dyn Future<Output = (Result<(U256, U256, Bytes), Err>, Address)> + Send>>;
-type StorageFuture<Err> = Pin<Box<dyn Future<Output = (Result<H256, Err>, Address, H256)> + Send>>;
+type AccountFuture =
+    Pin<Box<dyn Future<Output = (eyre::Result<(U256, U256, Bytes)>, Address)> + Send>>;
+type StorageFuture = Pin<Box<dyn Future<Output = (eyre::Result<H256>, Address, H256)> + Send>>;
 
-/// Request variants that are executed by the provider
-enum ProviderRequest<Err> {
-    Account(AccountFuture<Err>),
-    Storage(StorageFuture<Err>),
+/// Request variants that are executed by the provider, with the timeouts and retries of
+/// [`runtime::retry`]
+enum ProviderRequest {
+    Account(AccountFuture),
+    Storage(StorageFuture),
 }
 
 /// The Request type the Backend listens for
@@ -76,10 +77,12 @@ enum AccountListener {
 #[must_use = "BackendHandler does nothing unless polled."]
 struct BackendHandler<M: Middleware> {
     provider: M,
+    /// The request budget of the provider's endpoint
+    throttle: Arc<Throttle>,
     /// Stores the state.
     cache: SharedCache<MemCache>,
     /// Requests currently in progress
-    pending_requests: Vec<ProviderRequest<M::Error>>,
+    pending_requests: Vec<ProviderRequest>,
     /// Listeners that wait for a `get_account` related response
     /// We also store the `get_storage_at` responses until the initial account info is fetched.
     /// The reason for that is because of the simple `address -> Account` model of the cache, so we
@@ -100,12 +103,14 @@ where
 {
     fn new(
         provider: M,
+        throttle: Arc<Throttle>,
         cache: SharedCache<MemCache>,
         rx: Receiver<BackendRequest>,
         block_id: Option<BlockId>,
     ) -> Self {
         Self {
             provider,
+            throttle,
             cache,
             pending_requests: Default::default(),
             account_requests: Default::default(),
@@ -210,9 +215,14 @@ where
             Entry::Vacant(entry) => {
                 entry.insert(vec![listener]);
                 let provider = self.provider.clone();
+                let throttle = self.throttle.clone();
                 let block_id = self.block_id;
                 let fut = Box::pin(async move {
-                    let storage = provider.get_storage_at(address, idx, block_id).await;
+                    let provider = &provider;
+                    let storage = runtime::retry(&throttle, || async move {
+                        provider.get_storage_at(address, idx, block_id).await
+                    })
+                    .await;
                     (storage, address, idx)
                 });
                 self.pending_requests.push(ProviderRequest::Storage(fut));
@@ -221,14 +231,19 @@ where
     }
 
     /// returns the future that fetches the account data
-    fn get_account_req(&self, address: Address) -> ProviderRequest<M::Error> {
+    fn get_account_req(&self, address: Address) -> ProviderRequest {
         let provider = self.provider.clone();
+        let throttle = self.throttle.clone();
         let block_id = self.block_id;
         let fut = Box::pin(async move {
-            let balance = provider.get_balance(address, block_id);
-            let nonce = provider.get_transaction_count(address, block_id);
-            let code = provider.get_code(address, block_id);
-            let resp = tokio::try_join!(balance, nonce, code);
+            let provider = &provider;
+            let resp = runtime::retry(&throttle, || async move {
+                let balance = provider.get_balance(address, block_id);
+                let nonce = provider.get_transaction_count(address, block_id);
+                let code = provider.get_code(address, block_id);
+                tokio::try_join!(balance, nonce, code)
+            })
+            .await;
             (resp, address)
         });
         ProviderRequest::Account(fut)
@@ -268,8 +283,8 @@ where
             match &mut request {
                 ProviderRequest::Account(fut) => {
                     if let Poll::Ready((resp, addr)) = fut.poll_unpin(cx) {
-                        let (balance, nonce, code) = resp.unwrap_or_else(|_| {
-                            tracing::trace!("Failed to get account for {}", addr);
+                        let (balance, nonce, code) = resp.unwrap_or_else(|err| {
+                            tracing::trace!("Failed to get account for {}: {}", addr, err);
                             Default::default()
                         });
                         let code = code.to_vec();
@@ -298,8 +313,13 @@ where
                 }
                 ProviderRequest::Storage(fut) => {
                     if let Poll::Ready((resp, addr, idx)) = fut.poll_unpin(cx) {
-                        let value = resp.unwrap_or_else(|_| {
-                            tracing::trace!("Failed to get storage for {} at {}", addr, idx);
+                        let value = resp.unwrap_or_else(|err| {
+                            tracing::trace!(
+                                "Failed to get storage for {} at {}: {}",
+                                addr,
+                                idx,
+                                err
+                            );
                             Default::default()
                         });
                         if let Some(acc) = pin.cache.write().get_mut(&addr) {
@@ -344,7 +364,7 @@ where
 /// A cloneable backend type that shares access to the backend data with all its clones.
 ///
 /// This backend type is connected to the `BackendHandler` via a mpsc channel. The `BackendHandlers`
-/// is spawned on a background thread and listens for incoming commands on the receiver half of the
+/// is spawned on the shared runtime and listens for incoming commands on the receiver half of the
 /// channel. A `SharedBackend` holds a sender for that channel, which is `Clone`, so their can be
 /// multiple `SharedBackend`s communicating with the same `BackendHandler`, hence this `Backend`
 /// type is thread safe.
@@ -379,17 +399,29 @@ impl SharedBackend {
         vicinity: MemoryVicinity,
         pin_block: Option<BlockId>,
     ) -> Self
+    where
+        M: Middleware + Unpin + 'static + Clone,
+    {
+        Self::new_throttled(provider, runtime::throttle(None), cache, vicinity, pin_block)
+    }
+
+    /// Same as [`SharedBackend::new`], with the requests sent within the budget of the provider's
+    /// endpoint
+    pub fn new_throttled<M>(
+        provider: M,
+        throttle: Arc<Throttle>,
+        cache: SharedCache<MemCache>,
+        vicinity: MemoryVicinity,
+        pin_block: Option<BlockId>,
+    ) -> Self
     where
         M: Middleware + Unpin + 'static + Clone,
     {
         let (tx, rx) = channel(1);
-        let handler = BackendHandler::new(provider, cache, rx, pin_block);
-        // spawn the provider handler to background
-        let rt = RuntimeOrHandle::new();
-        std::thread::spawn(move || match rt {
-            RuntimeOrHandle::Runtime(runtime) => runtime.block_on(handler),
-            RuntimeOrHandle::Handle(handle) => handle.block_on(handler),
-        });
+        let handler = BackendHandler::new(provider, throttle, cache, rx, pin_block);
+        // spawn the provider handler to the shared runtime, where it shares the connections of
+        // every other backend forking the same endpoint
+        runtime::spawn(handler);
 
         Self { inner: SharedBackendInner { vicinity: Arc::new(vicinity), backend: tx } }
     }
@@ -511,7 +543,6 @@ mod tests {
         types::Address,
     };
     use std::convert::TryFrom;
-    use tokio::runtime::Runtime;
 
     use super::*;
 
@@ -524,8 +555,7 @@ mod tests {
         // some rng contract from etherscan
         let address: Address = "63091244180ae240c87d1f528f5f269134cb07b3".parse().unwrap();
 
-        let rt = Runtime::new().unwrap();
-        let vicinity = rt.block_on(vicinity(&provider, None)).unwrap();
+        let vicinity = runtime::block_on(vicinity(&provider, None)).unwrap();
         let cache = new_shared_cache(MemCache::default());
 
         let backend = SharedBackend::new(Arc::new(provider), cache.clone(), vicinity, None);
@@ -0,0 +1,200 @@
+//! Persistent cache of the state fetched from forked chains, so that repeated runs against the
+//! same block don't fetch it again and work offline
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ethers::types::{Block, Bytes, TxHash, H160, H256, U256};
+use serde::{Deserialize, Serialize};
+use sputnik::backend::{MemoryAccount, MemoryVicinity};
+
+use super::MemCache;
+
+/// An account of a forked chain, with the storage slots fetched so far
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct CachedAccount {
+    nonce: U256,
+    balance: U256,
+    code: Bytes,
+    #[serde(default)]
+    storage: BTreeMap<H256, H256>,
+}
+
+/// The state of a forked chain at a block, stored as `<dir>/<chain id>/<block number>.json`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiskCache {
+    pub chain_id: U256,
+    pub gas_price: U256,
+    /// The forked block
+    pub block: Block<TxHash>,
+    /// The hashes of the preceding blocks fetched so far
+    #[serde(default)]
+    pub block_hashes: BTreeMap<U256, H256>,
+    #[serde(default)]
+    accounts: BTreeMap<H160, CachedAccount>,
+}
+
+impl DiskCache {
+    pub fn new(chain_id: U256, gas_price: U256, block: Block<TxHash>) -> Self {
+        Self {
+            chain_id,
+            gas_price,
+            block,
+            block_hashes: Default::default(),
+            accounts: Default::default(),
+        }
+    }
+
+    /// Returns the file the state of the chain at the block is cached in
+    pub fn path(dir: &Path, chain_id: U256, block: u64) -> PathBuf {
+        dir.join(chain_id.to_string()).join(format!("{}.json", block))
+    }
+
+    /// Reads the cached state of the chain at the block. If the chain id is not known, e.g.
+    /// because the endpoint is not reachable, the block must only be cached for a single chain.
+    pub fn read(dir: &Path, chain_id: Option<U256>, block: u64) -> Option<Self> {
+        let path = match chain_id {
+            Some(chain_id) => Self::path(dir, chain_id, block),
+            None => {
+                let mut paths = fs::read_dir(dir)
+                    .ok()?
+                    .filter_map(|entry| Some(entry.ok()?.path().join(format!("{}.json", block))))
+                    .filter(|path| path.is_file());
+                match (paths.next(), paths.next()) {
+                    (Some(path), None) => path,
+                    _ => return None,
+                }
+            }
+        };
+        let cache = fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&cache) {
+            Ok(cache) => Some(cache),
+            Err(err) => {
+                tracing::warn!(?err, path = ?path, "ignoring the corrupt fork cache");
+                None
+            }
+        }
+    }
+
+    /// Writes the state to its file in `dir`, along with anything another run cached there in
+    /// the meantime
+    pub fn write(&self, dir: &Path) -> eyre::Result<()> {
+        let block = self.block.number.map(|number| number.as_u64()).unwrap_or_default();
+        let path = Self::path(dir, self.chain_id, block);
+        let mut cache = self.clone();
+        if let Some(other) = Self::read(dir, Some(self.chain_id), block) {
+            for (address, account) in other.accounts {
+                let cached = cache.accounts.entry(address).or_insert_with(|| account.clone());
+                for (slot, value) in account.storage {
+                    cached.storage.entry(slot).or_insert(value);
+                }
+            }
+            for (number, hash) in other.block_hashes {
+                cache.block_hashes.entry(number).or_insert(hash);
+            }
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // written to a temporary file first, so that other runs never read half of it
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(&cache)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Returns the cached accounts
+    pub fn accounts(&self) -> MemCache {
+        self.accounts
+            .iter()
+            .map(|(address, account)| {
+                let account = MemoryAccount {
+                    nonce: account.nonce,
+                    balance: account.balance,
+                    code: account.code.to_vec(),
+                    storage: account.storage.clone(),
+                };
+                (*address, account)
+            })
+            .collect()
+    }
+
+    /// Caches the accounts
+    pub fn insert_accounts<'a>(
+        &mut self,
+        accounts: impl IntoIterator<Item = (&'a H160, &'a MemoryAccount)>,
+    ) {
+        for (address, account) in accounts {
+            let cached = CachedAccount {
+                nonce: account.nonce,
+                balance: account.balance,
+                code: account.code.clone().into(),
+                storage: account.storage.clone(),
+            };
+            self.accounts.insert(*address, cached);
+        }
+    }
+
+    /// Returns the environment of the forked block, to fork it without fetching it
+    pub fn vicinity(&self) -> MemoryVicinity {
+        MemoryVicinity {
+            origin: Default::default(),
+            chain_id: self.chain_id,
+            block_hashes: Vec::new(),
+            block_number: self.block.number.unwrap_or_default().as_u64().into(),
+            block_coinbase: self.block.author,
+            block_difficulty: self.block.difficulty,
+            block_gas_limit: self.block.gas_limit,
+            block_timestamp: self.block.timestamp,
+            block_base_fee_per_gas: self.block.base_fee_per_gas.unwrap_or_default(),
+            gas_price: self.gas_price,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persists_fetched_state() {
+        let dir = std::env::temp_dir().join("evm-adapters-fork-cache");
+        let _ = fs::remove_dir_all(&dir);
+        let block = Block { number: Some(42.into()), ..Default::default() };
+
+        let mut cache = DiskCache::new(1.into(), 7.into(), block.clone());
+        let account = MemoryAccount {
+            nonce: 1.into(),
+            balance: 2.into(),
+            code: vec![0x60, 0x00],
+            storage: BTreeMap::from([(H256::zero(), H256::from_low_u64_be(3))]),
+        };
+        cache.insert_accounts([(&H160::from_low_u64_be(1), &account)]);
+        cache.write(&dir).unwrap();
+
+        // another run caches another account of the same block
+        let mut other = DiskCache::new(1.into(), 7.into(), block);
+        other.insert_accounts([(
+            &H160::from_low_u64_be(2),
+            &MemoryAccount {
+                nonce: 0.into(),
+                balance: 5.into(),
+                code: Vec::new(),
+                storage: Default::default(),
+            },
+        )]);
+        other.write(&dir).unwrap();
+
+        let read = DiskCache::read(&dir, Some(1.into()), 42).unwrap();
+        assert_eq!(read.accounts()[&H160::from_low_u64_be(1)], account);
+        assert_eq!(read.accounts()[&H160::from_low_u64_be(2)].balance, 5.into());
+        assert_eq!(read.vicinity().block_number, 42.into());
+
+        // found without knowing the chain, as long as no other chain cached the block
+        assert_eq!(DiskCache::read(&dir, None, 42), Some(read));
+        assert_eq!(DiskCache::read(&dir, Some(5.into()), 42), None);
+    }
+}
@@ -1,4 +1,6 @@
 pub mod cache;
 pub use cache::{new_shared_cache, MemCache, SharedBackend, SharedCache};
+pub mod disk;
+pub use disk::DiskCache;
 pub mod rpc;
 pub use rpc::ForkMemoryBackend;
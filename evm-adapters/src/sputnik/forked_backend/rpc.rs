@@ -1,5 +1,10 @@
 //! Simple in-memory cache backend for use with forking providers
-use std::{cell::RefCell, collections::BTreeMap};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use ethers::{
     providers::Middleware,
@@ -7,7 +12,8 @@ use ethers::{
 };
 use sputnik::backend::{Backend, Basic, MemoryAccount};
 
-use crate::BlockingProvider;
+use super::DiskCache;
+use crate::{runtime::Throttle, BlockingProvider};
 
 /// Memory backend with ability to fork another chain from an HTTP provider, storing all cache
 /// values in a `BTreeMap` in memory.
@@ -24,9 +30,6 @@ pub struct ForkMemoryBackend<B, M> {
     /// The internal backend
     pub backend: B,
     /// cache state
-    // TODO: Actually cache values in memory.
-    // TODO: This should probably be abstracted away into something that efficiently
-    // also caches at disk etc.
     pub cache: RefCell<BTreeMap<H160, MemoryAccount>>,
     /// The block to fetch data from.
     // This is an `Option` so that we can have less code churn in the functions below
@@ -35,6 +38,14 @@ pub struct ForkMemoryBackend<B, M> {
     pin_block_meta: Block<TxHash>,
     /// The chain id of the forked chain
     chain_id: U256,
+    /// The hashes of the blocks before the fork block, fetched as `BLOCKHASH` asks for them
+    block_hashes: RefCell<BTreeMap<U256, H256>>,
+    /// Where the fetched state is persisted when the backend is dropped, if it is cached on disk
+    disk_cache: Option<(PathBuf, DiskCache)>,
+    /// The accounts of the initial cache, which are local state rather than fetched
+    local: BTreeSet<H160>,
+    /// The accounts which could not be fetched completely, and must not be persisted
+    failed: RefCell<BTreeSet<H160>>,
 }
 
 impl<B: Backend, M: Middleware> ForkMemoryBackend<B, M>
@@ -47,8 +58,66 @@ where
         pin_block: Option<u64>,
         init_cache: BTreeMap<H160, MemoryAccount>,
     ) -> Self {
-        let provider = BlockingProvider::new(provider);
+        Self::with_provider(BlockingProvider::new(provider), backend, pin_block, init_cache)
+    }
+
+    /// Same as [`ForkMemoryBackend::new`], with the requests sent within the budget of the
+    /// provider's endpoint
+    pub fn new_throttled(
+        provider: M,
+        throttle: Arc<Throttle>,
+        backend: B,
+        pin_block: Option<u64>,
+        init_cache: BTreeMap<H160, MemoryAccount>,
+    ) -> Self {
+        let provider = BlockingProvider::new_throttled(provider, throttle);
+        Self::with_provider(provider, backend, pin_block, init_cache)
+    }
+
+    /// Same as [`ForkMemoryBackend::new_throttled`] for a pinned block, with the fetched state
+    /// persisted in `dir` (see [`DiskCache`]). Later runs against the same block read it from
+    /// there, and work without the endpoint once everything they need was fetched.
+    pub fn new_cached(
+        provider: M,
+        throttle: Arc<Throttle>,
+        backend: B,
+        pin_block: u64,
+        init_cache: BTreeMap<H160, MemoryAccount>,
+        dir: PathBuf,
+    ) -> Self {
+        let provider = BlockingProvider::new_throttled(provider, throttle);
+        let disk_cache = match provider.block_and_chainid(Some(pin_block)) {
+            Ok((block, chain_id)) => DiskCache::read(&dir, Some(chain_id), pin_block)
+                .unwrap_or_else(|| DiskCache::new(chain_id, backend.gas_price(), block)),
+            Err(err) => DiskCache::read(&dir, None, pin_block).unwrap_or_else(|| {
+                panic!("could not get block meta and chain id, and block is not cached: {}", err)
+            }),
+        };
+
+        let local = init_cache.keys().copied().collect();
+        let mut cache = disk_cache.accounts();
+        cache.extend(init_cache);
+        Self {
+            provider,
+            backend,
+            cache: RefCell::new(cache),
+            pin_block: Some(pin_block.into()),
+            pin_block_meta: disk_cache.block.clone(),
+            chain_id: disk_cache.chain_id,
+            block_hashes: RefCell::new(disk_cache.block_hashes.clone()),
+            disk_cache: Some((dir, disk_cache)),
+            local,
+            failed: Default::default(),
+        }
+    }
 
+    fn with_provider(
+        provider: BlockingProvider<M>,
+        backend: B,
+        pin_block: Option<u64>,
+        init_cache: BTreeMap<H160, MemoryAccount>,
+    ) -> Self {
+        let init_cache_keys = init_cache.keys().copied().collect();
         // get the remaining block metadata
         let (block, chain_id) =
             provider.block_and_chainid(pin_block).expect("could not get block meta and chain id");
@@ -60,6 +129,45 @@ where
             pin_block: pin_block.map(Into::into),
             pin_block_meta: block,
             chain_id,
+            block_hashes: Default::default(),
+            disk_cache: None,
+            local: init_cache_keys,
+            failed: Default::default(),
+        }
+    }
+
+    /// Fetches the nonce, balance and code of the account at the fork block
+    fn fetch_account(&self, address: H160) -> MemoryAccount {
+        let (nonce, balance, code) = self
+            .provider
+            .get_account(address, self.pin_block)
+            .unwrap_or_else(|_| self.fetch_failed(address));
+        MemoryAccount { nonce, balance, code: code.to_vec(), storage: Default::default() }
+    }
+
+    /// Marks the account as not to be persisted, and returns a default value in place of the
+    /// value which could not be fetched
+    fn fetch_failed<T: Default>(&self, address: H160) -> T {
+        self.failed.borrow_mut().insert(address);
+        T::default()
+    }
+}
+
+impl<B, M> Drop for ForkMemoryBackend<B, M> {
+    fn drop(&mut self) {
+        let (dir, mut disk_cache) = match self.disk_cache.take() {
+            Some(disk_cache) => disk_cache,
+            None => return,
+        };
+        let (cache, failed) = (self.cache.borrow(), self.failed.borrow());
+        disk_cache.insert_accounts(
+            cache
+                .iter()
+                .filter(|(address, _)| !self.local.contains(address) && !failed.contains(address)),
+        );
+        disk_cache.block_hashes = self.block_hashes.borrow().clone();
+        if let Err(err) = disk_cache.write(&dir) {
+            tracing::warn!(?err, "could not write the fork cache");
         }
     }
 }
@@ -77,7 +185,21 @@ where
     }
 
     fn block_hash(&self, number: U256) -> H256 {
-        self.backend.block_hash(number)
+        // like on chain, only the hashes of the 256 most recent blocks are available
+        let current = self.block_number();
+        if number >= current || current - number > U256::from(256) {
+            return H256::zero()
+        }
+        if let Some(hash) = self.block_hashes.borrow().get(&number) {
+            return *hash
+        }
+        match self.provider.get_block_hash(number.as_u64()) {
+            Ok(Some(hash)) => {
+                self.block_hashes.borrow_mut().insert(number, hash);
+                hash
+            }
+            _ => self.backend.block_hash(number),
+        }
     }
 
     fn block_number(&self) -> U256 {
@@ -122,15 +244,7 @@ where
         // check non-zero balance
         if !exists {
             let mut cache = self.cache.borrow_mut();
-            let account = cache.entry(address).or_insert_with(|| {
-                let res = self.provider.get_account(address, self.pin_block).unwrap_or_default();
-                MemoryAccount {
-                    nonce: res.0,
-                    balance: res.1,
-                    code: res.2.to_vec(),
-                    storage: Default::default(),
-                }
-            });
+            let account = cache.entry(address).or_insert_with(|| self.fetch_account(address));
             exists = account.balance != U256::zero() ||
                 account.nonce != U256::zero() ||
                 !account.code.is_empty();
@@ -141,49 +255,26 @@ where
 
     fn basic(&self, address: H160) -> Basic {
         let mut cache = self.cache.borrow_mut();
-        let account = cache.entry(address).or_insert_with(|| {
-            let res = self.provider.get_account(address, self.pin_block).unwrap_or_default();
-            MemoryAccount {
-                nonce: res.0,
-                balance: res.1,
-                code: res.2.to_vec(),
-                storage: Default::default(),
-            }
-        });
+        let account = cache.entry(address).or_insert_with(|| self.fetch_account(address));
         Basic { balance: account.balance, nonce: account.nonce }
     }
 
     fn code(&self, address: H160) -> Vec<u8> {
         let mut cache = self.cache.borrow_mut();
-        let account = cache.entry(address).or_insert_with(|| {
-            // println!("didnt have account code {:?}", address);
-            let res = self.provider.get_account(address, self.pin_block).unwrap_or_default();
-            MemoryAccount {
-                nonce: res.0,
-                balance: res.1,
-                code: res.2.to_vec(),
-                storage: Default::default(),
-            }
-        });
+        let account = cache.entry(address).or_insert_with(|| self.fetch_account(address));
         account.code.clone()
     }
 
     fn storage(&self, address: H160, index: H256) -> H256 {
         let mut cache = self.cache.borrow_mut();
-        let account = cache.entry(address).or_insert_with(|| {
-            let res = self.provider.get_account(address, self.pin_block).unwrap_or_default();
-            MemoryAccount {
-                nonce: res.0,
-                balance: res.1,
-                code: res.2.to_vec(),
-                storage: Default::default(),
-            }
-        });
+        let account = cache.entry(address).or_insert_with(|| self.fetch_account(address));
         if let Some(val) = account.storage.get(&index) {
             *val
         } else {
-            let ret =
-                self.provider.get_storage_at(address, index, self.pin_block).unwrap_or_default();
+            let ret = self
+                .provider
+                .get_storage_at(address, index, self.pin_block)
+                .unwrap_or_else(|_| self.fetch_failed(address));
             account.storage.insert(index, ret);
             ret
         }
@@ -203,9 +294,9 @@ mod tests {
         types::Address,
     };
     use sputnik::Config;
-    use tokio::runtime::Runtime;
 
     use crate::{
+        runtime,
         sputnik::{helpers::new_backend, vicinity, Executor, PRECOMPILES_MAP},
         test_helpers::COMPILED,
         Evm,
@@ -222,9 +313,8 @@ mod tests {
             "https://mainnet.infura.io/v3/c60b0bb42f8a4c6481ecd229eddaca27",
         )
         .unwrap();
-        let rt = Runtime::new().unwrap();
         let blk = Some(13292465);
-        let vicinity = rt.block_on(vicinity(&provider, blk)).unwrap();
+        let vicinity = runtime::block_on(vicinity(&provider, blk)).unwrap();
         let backend = new_backend(&vicinity, Default::default());
         let backend = ForkMemoryBackend::new(provider, backend, blk, Default::default());
 
@@ -239,5 +329,44 @@ mod tests {
 
         // https://etherscan.io/block/13292465
         assert_eq!(res.as_u64(), 1632539668);
+
+        // the hashes of recent blocks are fetched, later ones do not exist yet
+        let parent = backend.block_hash(13292464.into());
+        assert_eq!(parent, backend.pin_block_meta.parent_hash);
+        assert_eq!(backend.block_hash(13292465.into()), H256::zero());
+    }
+
+    #[test]
+    #[ignore = "needs ETH_RPC_URL"]
+    fn fetches_recent_block_hashes_and_accounts() {
+        use crate::test_helpers::rpc_url;
+
+        let provider = Provider::<Http>::try_from(rpc_url()).unwrap();
+        let blk = Some(13292465);
+        let vicinity = runtime::block_on(vicinity(&provider, blk)).unwrap();
+        let backend = new_backend(&vicinity, Default::default());
+        let backend = ForkMemoryBackend::new(provider, backend, blk, Default::default());
+
+        // only the 256 most recent blocks have a hash, which is fetched once
+        let oldest = U256::from(13292465 - 256);
+        let hash = backend.block_hash(oldest);
+        assert_ne!(hash, H256::zero());
+        assert_eq!(backend.block_hashes.borrow().get(&oldest), Some(&hash));
+        assert_eq!(backend.block_hash(oldest), hash);
+        assert_eq!(backend.block_hash(oldest - 1), H256::zero());
+        assert_eq!(backend.block_hashes.borrow().len(), 1);
+
+        // WETH
+        let weth: Address = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".parse().unwrap();
+        let account = backend.fetch_account(weth);
+        assert!(!account.code.is_empty());
+        assert!(!account.balance.is_zero());
+        assert!(account.storage.is_empty());
+
+        // an account which was never used exists with its defaults
+        let unused = backend.fetch_account(Address::from_low_u64_be(0xdead_beef_1234));
+        assert_eq!(unused.nonce, U256::zero());
+        assert!(unused.code.is_empty());
+        assert!(backend.failed.borrow().is_empty());
     }
 }
@@ -0,0 +1,272 @@
+//! Gas costs which override the ones of the configured hard fork, for chains which changed them
+use sputnik::{gasometer::static_opcode_cost, Config, Opcode};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// The gas parameters of the sputnik [`Config`] which can be overridden, named after their field
+/// without the `gas_` prefix
+pub const GAS_PARAMS: &[&str] = &[
+    "transaction_call",
+    "transaction_create",
+    "transaction_zero_data",
+    "transaction_non_zero_data",
+    "access_list_address",
+    "access_list_storage_key",
+    "account_access_cold",
+    "storage_read_warm",
+    "sload",
+    "sload_cold",
+    "sstore_set",
+    "sstore_reset",
+    "balance",
+    "ext_code",
+    "ext_code_hash",
+    "call",
+    "expbyte",
+    "suicide",
+    "suicide_new_account",
+];
+
+/// A gas cost given as `<NAME>=<GAS>`, where the name is either one of the [`GAS_PARAMS`], e.g.
+/// `transaction_call=21000`, or the mnemonic of an opcode with a fixed cost, e.g. `ADD=5`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GasOverride {
+    /// The cost of executing the opcode, which replaces its fixed cost
+    Opcode(u8, u64),
+    /// One of the [`GAS_PARAMS`], which the costs of opcodes depending on the state and the
+    /// intrinsic cost of transactions are made of
+    Param(String, u64),
+}
+
+impl GasOverride {
+    /// Applies all parameter overrides to the config and returns the costs of the overridden
+    /// opcodes, which the executor has to charge instead of the fixed ones
+    pub fn apply(overrides: &[GasOverride], config: &mut Config) -> BTreeMap<u8, u64> {
+        let mut opcodes = BTreeMap::new();
+        for gas_override in overrides {
+            match gas_override {
+                GasOverride::Opcode(opcode, gas) => {
+                    opcodes.insert(*opcode, *gas);
+                }
+                GasOverride::Param(name, gas) => {
+                    let gas = *gas;
+                    match name.as_str() {
+                        "transaction_call" => config.gas_transaction_call = gas,
+                        "transaction_create" => config.gas_transaction_create = gas,
+                        "transaction_zero_data" => config.gas_transaction_zero_data = gas,
+                        "transaction_non_zero_data" => config.gas_transaction_non_zero_data = gas,
+                        "access_list_address" => config.gas_access_list_address = gas,
+                        "access_list_storage_key" => config.gas_access_list_storage_key = gas,
+                        "account_access_cold" => config.gas_account_access_cold = gas,
+                        "storage_read_warm" => config.gas_storage_read_warm = gas,
+                        "sload" => config.gas_sload = gas,
+                        "sload_cold" => config.gas_sload_cold = gas,
+                        "sstore_set" => config.gas_sstore_set = gas,
+                        "sstore_reset" => config.gas_sstore_reset = gas,
+                        "balance" => config.gas_balance = gas,
+                        "ext_code" => config.gas_ext_code = gas,
+                        "ext_code_hash" => config.gas_ext_code_hash = gas,
+                        "call" => config.gas_call = gas,
+                        "expbyte" => config.gas_expbyte = gas,
+                        "suicide" => config.gas_suicide = gas,
+                        "suicide_new_account" => config.gas_suicide_new_account = gas,
+                        _ => unreachable!("unknown gas parameter {}", name),
+                    }
+                }
+            }
+        }
+        opcodes
+    }
+}
+
+impl FromStr for GasOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, gas) =
+            s.split_once('=').ok_or_else(|| format!("expected `<NAME>=<GAS>`, got `{}`", s))?;
+        let gas =
+            gas.trim().parse::<u64>().map_err(|err| format!("invalid gas `{}`: {}", gas, err))?;
+        let name = name.trim();
+
+        // parameters are looked up first, because some of them are named like opcodes
+        if GAS_PARAMS.contains(&name) {
+            Ok(GasOverride::Param(name.to_string(), gas))
+        } else if let Some(opcode) = opcode(name) {
+            if static_opcode_cost(Opcode(opcode)).is_none() {
+                return Err(format!(
+                    "the cost of `{}` is not fixed, override one of the gas parameters instead: {}",
+                    name.to_uppercase(),
+                    GAS_PARAMS.join(", ")
+                ))
+            }
+            Ok(GasOverride::Opcode(opcode, gas))
+        } else {
+            Err(format!(
+                "`{}` is neither an opcode nor one of the gas parameters: {}",
+                name,
+                GAS_PARAMS.join(", ")
+            ))
+        }
+    }
+}
+
+impl fmt::Display for GasOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GasOverride::Opcode(opcode, gas) => write!(f, "0x{:02x}={}", opcode, gas),
+            GasOverride::Param(name, gas) => write!(f, "{}={}", name, gas),
+        }
+    }
+}
+
+/// Returns the opcode with the given mnemonic, which is matched case-insensitively
+fn opcode(name: &str) -> Option<u8> {
+    let name = name.to_uppercase();
+    // the numbered families of opcodes
+    let numbered = |prefix: &str, first: u8, count: u8| {
+        let n = name.strip_prefix(prefix)?.parse::<u8>().ok()?;
+        (1..=count).contains(&n).then(|| first + n - 1)
+    };
+    if let Some(opcode) = numbered("PUSH", 0x60, 32)
+        .or_else(|| numbered("DUP", 0x80, 16))
+        .or_else(|| numbered("SWAP", 0x90, 16))
+    {
+        return Some(opcode)
+    }
+    if let Some(n) = name.strip_prefix("LOG").and_then(|n| n.parse::<u8>().ok()) {
+        return (n <= 4).then(|| 0xa0 + n)
+    }
+
+    let opcode = match name.as_str() {
+        "STOP" => 0x00,
+        "ADD" => 0x01,
+        "MUL" => 0x02,
+        "SUB" => 0x03,
+        "DIV" => 0x04,
+        "SDIV" => 0x05,
+        "MOD" => 0x06,
+        "SMOD" => 0x07,
+        "ADDMOD" => 0x08,
+        "MULMOD" => 0x09,
+        "EXP" => 0x0a,
+        "SIGNEXTEND" => 0x0b,
+        "LT" => 0x10,
+        "GT" => 0x11,
+        "SLT" => 0x12,
+        "SGT" => 0x13,
+        "EQ" => 0x14,
+        "ISZERO" => 0x15,
+        "AND" => 0x16,
+        "OR" => 0x17,
+        "XOR" => 0x18,
+        "NOT" => 0x19,
+        "BYTE" => 0x1a,
+        "SHL" => 0x1b,
+        "SHR" => 0x1c,
+        "SAR" => 0x1d,
+        "SHA3" | "KECCAK256" => 0x20,
+        "ADDRESS" => 0x30,
+        "BALANCE" => 0x31,
+        "ORIGIN" => 0x32,
+        "CALLER" => 0x33,
+        "CALLVALUE" => 0x34,
+        "CALLDATALOAD" => 0x35,
+        "CALLDATASIZE" => 0x36,
+        "CALLDATACOPY" => 0x37,
+        "CODESIZE" => 0x38,
+        "CODECOPY" => 0x39,
+        "GASPRICE" => 0x3a,
+        "EXTCODESIZE" => 0x3b,
+        "EXTCODECOPY" => 0x3c,
+        "RETURNDATASIZE" => 0x3d,
+        "RETURNDATACOPY" => 0x3e,
+        "EXTCODEHASH" => 0x3f,
+        "BLOCKHASH" => 0x40,
+        "COINBASE" => 0x41,
+        "TIMESTAMP" => 0x42,
+        "NUMBER" => 0x43,
+        "DIFFICULTY" => 0x44,
+        "GASLIMIT" => 0x45,
+        "CHAINID" => 0x46,
+        "SELFBALANCE" => 0x47,
+        "BASEFEE" => 0x48,
+        "POP" => 0x50,
+        "MLOAD" => 0x51,
+        "MSTORE" => 0x52,
+        "MSTORE8" => 0x53,
+        "SLOAD" => 0x54,
+        "SSTORE" => 0x55,
+        "JUMP" => 0x56,
+        "JUMPI" => 0x57,
+        "PC" => 0x58,
+        "MSIZE" => 0x59,
+        "GAS" => 0x5a,
+        "JUMPDEST" => 0x5b,
+        "CREATE" => 0xf0,
+        "CALL" => 0xf1,
+        "CALLCODE" => 0xf2,
+        "RETURN" => 0xf3,
+        "DELEGATECALL" => 0xf4,
+        "CREATE2" => 0xf5,
+        "STATICCALL" => 0xfa,
+        "REVERT" => 0xfd,
+        "INVALID" => 0xfe,
+        "SELFDESTRUCT" | "SUICIDE" => 0xff,
+        _ => return None,
+    };
+    Some(opcode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sputnik::helpers::vm, test_helpers::COMPILED, Evm};
+    use ethers::types::Address;
+
+    #[test]
+    fn parses_gas_overrides() {
+        assert_eq!("add=5".parse(), Ok(GasOverride::Opcode(0x01, 5)));
+        assert_eq!("PUSH32=1".parse(), Ok(GasOverride::Opcode(0x7f, 1)));
+        assert_eq!("DUP16 = 1".parse(), Ok(GasOverride::Opcode(0x8f, 1)));
+        assert_eq!(
+            "transaction_call=0".parse(),
+            Ok(GasOverride::Param("transaction_call".to_string(), 0))
+        );
+        assert_eq!("call=700".parse(), Ok(GasOverride::Param("call".to_string(), 700)));
+        // SLOAD depends on the state, so it is overridden through `sload` and `sload_cold`
+        assert!("SLOAD=800".parse::<GasOverride>().is_err());
+        assert!("PUSH33=1".parse::<GasOverride>().is_err());
+        assert!("MSTORE".parse::<GasOverride>().is_err());
+        assert!("unknown=1".parse::<GasOverride>().is_err());
+    }
+
+    #[test]
+    fn applies_gas_overrides() {
+        let overrides: Vec<GasOverride> =
+            vec!["transaction_call=0".parse().unwrap(), "JUMPDEST=100".parse().unwrap()];
+        let mut config = Config::london();
+        let opcodes = GasOverride::apply(&overrides, &mut config);
+        assert_eq!(config.gas_transaction_call, 0);
+        assert_eq!(opcodes, BTreeMap::from([(0x5b, 100)]));
+    }
+
+    #[test]
+    fn charges_overridden_opcode_costs() {
+        let compiled = COMPILED.find("Greeter").expect("could not find contract");
+        let gas_used = |opcodes: BTreeMap<u8, u64>| {
+            let mut evm = vm().opcode_gas_costs(opcodes);
+            let (addr, _, _, _) = evm
+                .deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into())
+                .unwrap();
+            let (_, _, gas_used, _) =
+                evm.call::<(), _, _>(Address::zero(), addr, "greeting()", (), 0.into()).unwrap();
+            gas_used
+        };
+
+        let default = gas_used(BTreeMap::new());
+        // a `JUMPDEST` costs 1 gas by default
+        let expensive_jumpdest = gas_used(BTreeMap::from([(0x5b, 1001)]));
+        assert!(expensive_jumpdest > default);
+        assert_eq!((expensive_jumpdest - default) % 1000, 0);
+    }
+}
@@ -1,15 +1,19 @@
 mod evm;
 pub use evm::*;
 
+#[cfg(feature = "fork-backend")]
 mod forked_backend;
+#[cfg(feature = "fork-backend")]
 pub use forked_backend::*;
 
 pub mod cheatcodes;
+pub mod gas;
 pub mod state;
 
 use ethers::{
+    abi::ParamType,
     providers::Middleware,
-    types::{Address, H160, H256, U256},
+    types::{Address, Selector, TransactionRequest, H160, H256, U256},
 };
 
 use sputnik::{
@@ -21,29 +25,44 @@ use sputnik::{
 pub use sputnik as sputnik_evm;
 use sputnik_evm::executor::stack::PrecompileSet;
 
+use crate::{
+    trace::{
+        Breakpoint, CallFrame, CallTrace, CoverageHits, DebugTrace, InternalJumps, PrankedCall,
+        StateDiff,
+    },
+    ExecutorError,
+};
+
 /// Given an ethers provider and a block, it proceeds to construct a [`MemoryVicinity`] from
 /// the live chain data returned by the provider.
+#[cfg(feature = "fork-backend")]
 pub async fn vicinity<M: Middleware>(
     provider: &M,
     pin_block: Option<u64>,
-) -> Result<MemoryVicinity, M::Error> {
+) -> Result<MemoryVicinity, ExecutorError>
+where
+    M::Error: 'static,
+{
+    let provider_error = |err: M::Error| ExecutorError::Provider(Box::new(err));
     let block_number = if let Some(pin_block) = pin_block {
         pin_block
     } else {
-        provider.get_block_number().await?.as_u64()
+        provider.get_block_number().await.map_err(provider_error)?.as_u64()
     };
     let (gas_price, chain_id, block) = tokio::try_join!(
         provider.get_gas_price(),
         provider.get_chainid(),
         provider.get_block(block_number)
-    )?;
-    let block = block.expect("block not found");
+    )
+    .map_err(provider_error)?;
+    let block = block.ok_or(ExecutorError::BlockNotFound(block_number))?;
+    let number = block.number.ok_or(ExecutorError::PendingBlock(block_number))?;
 
     Ok(MemoryVicinity {
         origin: Default::default(),
         chain_id,
         block_hashes: Vec::new(),
-        block_number: block.number.expect("block number not found").as_u64().into(),
+        block_number: number.as_u64().into(),
         block_coinbase: block.author,
         block_difficulty: block.difficulty,
         block_gas_limit: block.gas_limit,
@@ -60,6 +79,59 @@ pub trait SputnikExecutor<S> {
     fn state(&self) -> &S;
     fn state_mut(&mut self) -> &mut S;
     fn expected_revert(&self) -> Option<&[u8]>;
+    // The cheatcode and tracing hooks below do nothing by default, like for the plain
+    // `StackExecutor`
+    /// Returns a description of every cheatcode expectation which has not been fulfilled
+    fn unused_expectations(&self) -> Vec<String> {
+        Vec::new()
+    }
+    /// Discards all pending cheatcode expectations
+    fn clear_expectations(&mut self) {}
+    /// Returns the gas used by every named section recorded via `snapshotGasStart/End`
+    fn gas_sections(&self) -> BTreeMap<String, u64> {
+        BTreeMap::new()
+    }
+    /// Returns a warning for every deprecated cheatcode which was called
+    fn deprecations(&self) -> Vec<String> {
+        Vec::new()
+    }
+    /// Sets the output types of the known functions, by their selector
+    fn set_return_types(&mut self, _types: BTreeMap<Selector, Vec<ParamType>>) {}
+    /// Sets the jumps into and out of internal functions of the known codes, by their hash
+    fn set_internal_jumps(&mut self, _jumps: BTreeMap<H256, InternalJumps>) {}
+    /// Returns the call frames leading to the revert of the last transaction, if any
+    fn revert_trace(&self) -> Vec<CallFrame> {
+        Vec::new()
+    }
+    /// Returns the breakpoints hit during the last transaction
+    fn breakpoints(&self) -> Vec<Breakpoint> {
+        Vec::new()
+    }
+    /// Returns the calls of the last transaction whose sender was replaced by a prank
+    fn pranks(&self) -> Vec<PrankedCall> {
+        Vec::new()
+    }
+    /// Returns the calls made by the last transaction and the storage it changed
+    fn call_trace(&self) -> CallTrace {
+        CallTrace::default()
+    }
+    fn set_debug(&mut self, _enabled: bool) {}
+    fn debug_trace(&self) -> DebugTrace {
+        DebugTrace::default()
+    }
+    fn set_coverage(&mut self, _enabled: bool) {}
+    /// Returns the opcodes and branches executed since coverage was enabled
+    fn coverage(&self) -> CoverageHits {
+        CoverageHits::default()
+    }
+    /// Returns the changes made to the accounts since the state was `before`
+    fn state_diff(&self, _before: &S) -> StateDiff {
+        StateDiff::default()
+    }
+    /// Returns the transactions collected by `broadcast` and `startBroadcast`
+    fn broadcastable_transactions(&self) -> Vec<TransactionRequest> {
+        Vec::new()
+    }
     fn gas_left(&self) -> U256;
     fn transact_call(
         &mut self,
@@ -199,6 +271,66 @@ pub static PRECOMPILES_MAP: Lazy<BTreeMap<Address, PrecompileFn>> = Lazy::new(||
     map
 });
 
+/// A precompile implemented by a closure, e.g. one which captures the state it emulates
+pub type CustomPrecompileFn = Box<
+    dyn Fn(
+            &[u8],
+            Option<u64>,
+            &sputnik::Context,
+            bool,
+        ) -> Result<PrecompileOutput, PrecompileFailure>
+        + Send
+        + Sync,
+>;
+
+/// A [`PrecompileSet`] which extends another one (usually [`PRECOMPILES_MAP`]) with custom
+/// precompiles, e.g. to emulate the precompiles of other chains (like Arbitrum's `ArbSys`) in
+/// tests. A custom precompile takes precedence over a precompile of the extended set at the same
+/// address.
+pub struct CustomPrecompiles<P> {
+    pub precompiles: P,
+    pub custom: BTreeMap<Address, CustomPrecompileFn>,
+}
+
+impl<P> CustomPrecompiles<P> {
+    pub fn new(precompiles: P) -> Self {
+        Self { precompiles, custom: BTreeMap::new() }
+    }
+
+    /// Registers the precompile at the address
+    #[must_use]
+    pub fn with<F>(mut self, address: Address, precompile: F) -> Self
+    where
+        F: Fn(&[u8], Option<u64>, &Context, bool) -> Result<PrecompileOutput, PrecompileFailure>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.custom.insert(address, Box::new(precompile));
+        self
+    }
+}
+
+impl<P: PrecompileSet> PrecompileSet for CustomPrecompiles<P> {
+    fn execute(
+        &self,
+        address: Address,
+        input: &[u8],
+        gas_limit: Option<u64>,
+        context: &Context,
+        is_static: bool,
+    ) -> Option<Result<PrecompileOutput, PrecompileFailure>> {
+        match self.custom.get(&address) {
+            Some(precompile) => Some(precompile(input, gas_limit, context, is_static)),
+            None => self.precompiles.execute(address, input, gas_limit, context, is_static),
+        }
+    }
+
+    fn is_precompile(&self, address: Address) -> bool {
+        self.custom.contains_key(&address) || self.precompiles.is_precompile(address)
+    }
+}
+
 /// Runs the provided precompile against the input data.
 pub fn exec(
     builtin: &revm_precompiles::Precompile,
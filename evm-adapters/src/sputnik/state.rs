@@ -218,7 +218,7 @@ where
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "fork-backend"))]
 mod tests {
     use super::*;
     use crate::sputnik::{
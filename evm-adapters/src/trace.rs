@@ -0,0 +1,700 @@
+//! Call stack tracking, used to explain where a revert originated and to render the calls of a
+//! transaction with the state it accessed, and breakpoints and opcodes recorded for debugging
+use ethers::{
+    abi::RawLog,
+    types::{Address, Bytes, Selector, H256, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// A call (or contract creation) frame
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CallFrame {
+    /// The address whose code was executed
+    pub address: Address,
+    /// The sender of the call, i.e. `msg.sender` of the frame
+    pub caller: Address,
+    /// The value sent with the call, i.e. `msg.value` of the frame
+    pub value: U256,
+    /// The calldata of the call, or the init code if this is a contract creation
+    pub input: Vec<u8>,
+    /// Whether this frame is a contract creation
+    pub create: bool,
+    /// The program counters of the entries of the internal functions the frame was executing
+    /// when it made its last call or exited, outermost first. Only followed in code whose
+    /// [`InternalJumps`] are known
+    pub internal_calls: Vec<usize>,
+}
+
+impl CallFrame {
+    /// Returns the selector of the called function, if this is a call with calldata
+    pub fn selector(&self) -> Option<Selector> {
+        if self.create {
+            return None
+        }
+        self.input.get(..4)?.try_into().ok()
+    }
+}
+
+/// The `JUMP`s of a contract's runtime code which enter or leave an internal function, as marked
+/// by the jump types of its source map
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InternalJumps {
+    /// The program counters of the jumps into an internal function
+    pub into: BTreeSet<usize>,
+    /// The program counters of the jumps returning from an internal function
+    pub out_of: BTreeSet<usize>,
+}
+
+/// A call recorded by the tracer, along with its outcome
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TracedCall {
+    /// The depth of the call, 0 for the top-level call
+    pub depth: usize,
+    pub frame: CallFrame,
+    /// Whether the call succeeded
+    pub success: bool,
+    /// The return or revert data of the call
+    pub output: Vec<u8>,
+    /// The gas used by the call and its subcalls, before refunds
+    pub gas_used: u64,
+    /// The accounts and storage slots accessed by the call's own code, in the order they were
+    /// accessed
+    pub accesses: Vec<StateAccess>,
+    /// The logs emitted by the call's own code, also if it reverted
+    pub logs: Vec<RawLog>,
+}
+
+/// An access of an account or a storage slot by an opcode. Under EIP-2929, the first access of
+/// each in a transaction is cold and costs more than the following, warm ones
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateAccess {
+    /// The mnemonic of the accessing opcode, e.g. `SLOAD` or `CALL`
+    pub opcode: String,
+    /// The accessed account, or the contract whose storage is accessed
+    pub address: Address,
+    /// The accessed storage slot, if this is a storage access
+    pub slot: Option<H256>,
+    /// Whether this was the first access in the transaction
+    pub cold: bool,
+}
+
+/// The number of cold and warm accesses made by a transaction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessSummary {
+    pub cold_slots: usize,
+    pub warm_slots: usize,
+    pub cold_accounts: usize,
+    pub warm_accounts: usize,
+}
+
+impl fmt::Display for AccessSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "slots: {} cold, {} warm; accounts: {} cold, {} warm",
+            self.cold_slots, self.warm_slots, self.cold_accounts, self.warm_accounts
+        )
+    }
+}
+
+/// Every call made by a transaction and the storage it changed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CallTrace {
+    /// The calls in the order they were made
+    pub calls: Vec<TracedCall>,
+    /// The value before and after the transaction of every storage slot it changed, by contract
+    /// address and slot
+    pub storage_diff: BTreeMap<(Address, H256), (H256, H256)>,
+    /// Where the first arithmetic overflow of the transaction happened, if any
+    pub overflow: Option<OverflowSite>,
+}
+
+/// The code which reverted with `Panic(0x11)`, i.e. a checked arithmetic operation which over-
+/// or underflowed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OverflowSite {
+    /// The contract whose code overflowed
+    pub address: Address,
+    /// The runtime code of the contract
+    pub code: Vec<u8>,
+    /// The program counters of the last opcodes the contract executed, most recent last. The
+    /// check which panics is generated by the compiler, so the overflowing expression is found
+    /// by walking them backwards
+    pub pcs: Vec<usize>,
+}
+
+impl CallTrace {
+    /// Returns the top-level calls, i.e. the roots of the call tree
+    pub fn roots(&self) -> impl Iterator<Item = (usize, &TracedCall)> {
+        self.calls.iter().enumerate().filter(|(_, call)| call.depth == 0)
+    }
+
+    /// Returns the calls made directly by the call at `index`, with their indices
+    pub fn children(&self, index: usize) -> impl Iterator<Item = (usize, &TracedCall)> {
+        let depth = self.calls.get(index).map(|call| call.depth);
+        self.calls
+            .iter()
+            .enumerate()
+            .skip(index + 1)
+            .take_while(move |(_, call)| Some(call.depth) > depth)
+            .filter(move |(_, call)| depth.map(|depth| depth + 1) == Some(call.depth))
+    }
+
+    /// Counts the cold and warm accesses of all calls
+    pub fn access_summary(&self) -> AccessSummary {
+        let mut summary = AccessSummary::default();
+        for access in self.calls.iter().flat_map(|call| &call.accesses) {
+            match (access.slot.is_some(), access.cold) {
+                (true, true) => summary.cold_slots += 1,
+                (true, false) => summary.warm_slots += 1,
+                (false, true) => summary.cold_accounts += 1,
+                (false, false) => summary.warm_accounts += 1,
+            }
+        }
+        summary
+    }
+}
+
+/// A point of execution marked with the `breakpoint` cheatcode, which a debugger can jump to
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Breakpoint {
+    /// The label passed to the cheatcode
+    pub label: String,
+    /// The contract which hit the breakpoint
+    pub address: Address,
+    /// The call depth of that contract
+    pub depth: usize,
+    /// The number of opcodes executed in the transaction before the breakpoint was hit
+    pub step: usize,
+}
+
+/// An opcode executed while debugging, with the state of the machine before it was executed
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugStep {
+    /// The call depth of the frame executing the opcode, 0 for the top-level call
+    pub depth: usize,
+    /// The contract whose code is executed, or which is being created
+    pub address: Address,
+    /// The index of the executed code in [`DebugTrace::codes`]
+    pub code: usize,
+    /// The program counter of the opcode
+    pub pc: usize,
+    /// The opcode
+    pub opcode: u8,
+    /// The gas left before the opcode was executed
+    pub gas_left: u64,
+    /// The stack, top last
+    pub stack: Vec<U256>,
+    /// The index of the memory of the frame in [`DebugTrace::memories`]
+    pub memory: usize,
+}
+
+impl DebugStep {
+    /// Returns the mnemonic of the opcode, e.g. `SSTORE` or `PUSH1`
+    pub fn mnemonic(&self) -> String {
+        let name = match self.opcode {
+            0x00 => "STOP",
+            0x01 => "ADD",
+            0x02 => "MUL",
+            0x03 => "SUB",
+            0x04 => "DIV",
+            0x05 => "SDIV",
+            0x06 => "MOD",
+            0x07 => "SMOD",
+            0x08 => "ADDMOD",
+            0x09 => "MULMOD",
+            0x0a => "EXP",
+            0x0b => "SIGNEXTEND",
+            0x10 => "LT",
+            0x11 => "GT",
+            0x12 => "SLT",
+            0x13 => "SGT",
+            0x14 => "EQ",
+            0x15 => "ISZERO",
+            0x16 => "AND",
+            0x17 => "OR",
+            0x18 => "XOR",
+            0x19 => "NOT",
+            0x1a => "BYTE",
+            0x1b => "SHL",
+            0x1c => "SHR",
+            0x1d => "SAR",
+            0x20 => "KECCAK256",
+            0x30 => "ADDRESS",
+            0x31 => "BALANCE",
+            0x32 => "ORIGIN",
+            0x33 => "CALLER",
+            0x34 => "CALLVALUE",
+            0x35 => "CALLDATALOAD",
+            0x36 => "CALLDATASIZE",
+            0x37 => "CALLDATACOPY",
+            0x38 => "CODESIZE",
+            0x39 => "CODECOPY",
+            0x3a => "GASPRICE",
+            0x3b => "EXTCODESIZE",
+            0x3c => "EXTCODECOPY",
+            0x3d => "RETURNDATASIZE",
+            0x3e => "RETURNDATACOPY",
+            0x3f => "EXTCODEHASH",
+            0x40 => "BLOCKHASH",
+            0x41 => "COINBASE",
+            0x42 => "TIMESTAMP",
+            0x43 => "NUMBER",
+            0x44 => "DIFFICULTY",
+            0x45 => "GASLIMIT",
+            0x46 => "CHAINID",
+            0x47 => "SELFBALANCE",
+            0x48 => "BASEFEE",
+            0x50 => "POP",
+            0x51 => "MLOAD",
+            0x52 => "MSTORE",
+            0x53 => "MSTORE8",
+            0x54 => "SLOAD",
+            0x55 => "SSTORE",
+            0x56 => "JUMP",
+            0x57 => "JUMPI",
+            0x58 => "PC",
+            0x59 => "MSIZE",
+            0x5a => "GAS",
+            0x5b => "JUMPDEST",
+            op @ 0x60..=0x7f => return format!("PUSH{}", op - 0x5f),
+            op @ 0x80..=0x8f => return format!("DUP{}", op - 0x7f),
+            op @ 0x90..=0x9f => return format!("SWAP{}", op - 0x8f),
+            op @ 0xa0..=0xa4 => return format!("LOG{}", op - 0xa0),
+            0xf0 => "CREATE",
+            0xf1 => "CALL",
+            0xf2 => "CALLCODE",
+            0xf3 => "RETURN",
+            0xf4 => "DELEGATECALL",
+            0xf5 => "CREATE2",
+            0xfa => "STATICCALL",
+            0xfd => "REVERT",
+            0xfe => "INVALID",
+            0xff => "SELFDESTRUCT",
+            op => return format!("0x{:02x}", op),
+        };
+        name.to_string()
+    }
+
+    /// Returns the immediate pushed by a `PUSH` opcode, read from the executed code
+    pub fn push_data<'a>(&self, code: &'a [u8]) -> Option<&'a [u8]> {
+        let len = match self.opcode {
+            op @ 0x60..=0x7f => (op - 0x5f) as usize,
+            _ => return None,
+        };
+        code.get(self.pc + 1..).map(|rest| &rest[..len.min(rest.len())])
+    }
+}
+
+/// Every opcode executed by a transaction, recorded while debugging
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugTrace {
+    /// The executed opcodes, in the order they were executed
+    pub steps: Vec<DebugStep>,
+    /// The code executed by every frame: the runtime code of called contracts and the init code
+    /// of created ones
+    pub codes: Vec<Vec<u8>>,
+    /// The memory of the steps. Most opcodes do not touch the memory, so a new snapshot is only
+    /// taken when it differs from the memory of the previous step
+    pub memories: Vec<Vec<u8>>,
+}
+
+impl DebugTrace {
+    /// Records a step, executed with the given memory
+    pub fn push(&mut self, mut step: DebugStep, memory: &[u8]) {
+        step.memory = match self.steps.last() {
+            Some(last) if self.memories[last.memory] == memory => last.memory,
+            _ => {
+                self.memories.push(memory.to_vec());
+                self.memories.len() - 1
+            }
+        };
+        self.steps.push(step);
+    }
+
+    /// Returns the memory the step was executed with
+    pub fn memory(&self, step: &DebugStep) -> &[u8] {
+        &self.memories[step.memory]
+    }
+}
+
+/// How often the opcodes and branches of every executed runtime code were executed, recorded for
+/// coverage over any number of transactions
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoverageHits {
+    /// Every executed runtime code by its hash, to find the compiled contract it belongs to
+    pub codes: BTreeMap<H256, Vec<u8>>,
+    /// The number of times the opcode at every program counter was executed, by the hash of the
+    /// runtime code
+    pub pcs: BTreeMap<H256, BTreeMap<usize, u64>>,
+    /// The number of times every `JUMPI` jumped and fell through, by the hash of the runtime code
+    /// and its program counter
+    pub branches: BTreeMap<H256, BTreeMap<usize, (u64, u64)>>,
+}
+
+impl CoverageHits {
+    /// Records the execution of the opcode at `pc`
+    pub fn hit(&mut self, code: H256, pc: usize) {
+        *self.pcs.entry(code).or_default().entry(pc).or_default() += 1;
+    }
+
+    /// Records which way the `JUMPI` at `pc` went
+    pub fn branch(&mut self, code: H256, pc: usize, jumped: bool) {
+        let (taken, not_taken) = self.branches.entry(code).or_default().entry(pc).or_default();
+        if jumped {
+            *taken += 1;
+        } else {
+            *not_taken += 1;
+        }
+    }
+
+    /// Adds the hits recorded by another executor
+    pub fn merge(&mut self, other: &CoverageHits) {
+        for (hash, code) in &other.codes {
+            self.codes.entry(*hash).or_insert_with(|| code.clone());
+        }
+        for (code, pcs) in &other.pcs {
+            let hits = self.pcs.entry(*code).or_default();
+            for (pc, count) in pcs {
+                *hits.entry(*pc).or_default() += count;
+            }
+        }
+        for (code, branches) in &other.branches {
+            let hits = self.branches.entry(*code).or_default();
+            for (pc, (taken, not_taken)) in branches {
+                let hit = hits.entry(*pc).or_default();
+                hit.0 += taken;
+                hit.1 += not_taken;
+            }
+        }
+    }
+}
+
+/// The changes made to an account, as the values before and after them
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountDiff {
+    pub balance: Option<(U256, U256)>,
+    pub nonce: Option<(U256, U256)>,
+    /// The size of the code, if it was deployed, replaced (e.g. with `etch`) or destroyed
+    pub code: Option<(usize, usize)>,
+    pub storage: BTreeMap<H256, (H256, H256)>,
+}
+
+impl AccountDiff {
+    pub fn is_empty(&self) -> bool {
+        self.balance.is_none() &&
+            self.nonce.is_none() &&
+            self.code.is_none() &&
+            self.storage.is_empty()
+    }
+}
+
+/// The accounts changed by a transaction, e.g. by a failed test
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub accounts: BTreeMap<Address, AccountDiff>,
+}
+
+/// A call whose sender was replaced by `prank` or `startPrank`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrankedCall {
+    /// The contract which made the call, i.e. which called `prank` or `startPrank`
+    pub pranker: Address,
+    /// The sender the call was made with instead
+    pub sender: Address,
+    /// The called contract
+    pub target: Address,
+    /// The calldata of the call
+    pub input: Bytes,
+    /// The call depth of the pranked call
+    pub depth: usize,
+    /// Whether the sender was set by `startPrank` rather than a single `prank`
+    pub persistent: bool,
+}
+
+/// Keeps track of the call stack during execution and records the chain of frames leading to
+/// the revert which made the top-level call fail, as well as every call that was made.
+///
+/// Without the `tracing` feature nothing is recorded, so that executors which do not need traces
+/// don't pay for them.
+#[derive(Clone, Debug, Default)]
+pub struct RevertTracer {
+    /// The frames currently being executed
+    stack: Vec<CallFrame>,
+    /// The frames from the top-level call to the revert site
+    trace: Vec<CallFrame>,
+    /// The data the innermost frame of `trace` reverted with
+    revert_data: Vec<u8>,
+    /// Every call made so far, in the order they were made
+    calls: Vec<TracedCall>,
+    /// The index in `calls` of every frame in `stack`
+    open: Vec<usize>,
+}
+
+impl RevertTracer {
+    /// Forgets about any previously recorded revert. Should be called before each top-level call
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.trace.clear();
+        self.revert_data.clear();
+        self.calls.clear();
+        self.open.clear();
+    }
+
+    /// Returns the frames from the top-level call to the revert site. Empty if nothing reverted
+    pub fn trace(&self) -> &[CallFrame] {
+        &self.trace
+    }
+
+    /// Returns every call made since the last reset, in the order they were made
+    pub fn calls(&self) -> &[TracedCall] {
+        &self.calls
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl RevertTracer {
+    /// Pushes a new frame on the call stack
+    pub fn enter(&mut self, frame: CallFrame) {
+        self.open.push(self.calls.len());
+        self.calls.push(TracedCall {
+            depth: self.stack.len(),
+            frame: frame.clone(),
+            ..Default::default()
+        });
+        self.stack.push(frame);
+    }
+
+    /// Pops the current frame from the call stack, which used `gas_used` gas.
+    ///
+    /// If it reverted, the call stack is recorded as the revert trace, unless the frame is just
+    /// bubbling up a revert of one of its subcalls. If it succeeded, any revert recorded inside
+    /// of it was caught and is discarded.
+    pub fn exit(&mut self, success: bool, retdata: &[u8], gas_used: u64) {
+        if let Some(call) = self.open.pop().and_then(|i| self.calls.get_mut(i)) {
+            call.success = success;
+            call.output = retdata.to_vec();
+            call.gas_used = gas_used;
+        }
+
+        let depth = self.stack.len();
+        if success {
+            if self.trace.len() >= depth {
+                self.trace.clear();
+                self.revert_data.clear();
+            }
+        } else if self.trace.len() <= depth || self.revert_data != retdata {
+            self.trace = self.stack.clone();
+            self.revert_data = retdata.to_vec();
+        }
+        self.stack.pop();
+    }
+
+    /// Records the internal functions the current frame is executing
+    pub fn set_internal_calls(&mut self, pcs: Vec<usize>) {
+        if let Some(frame) = self.stack.last_mut() {
+            frame.internal_calls = pcs;
+        }
+    }
+
+    /// Records an access made by the current frame
+    pub fn access(&mut self, access: StateAccess) {
+        if let Some(call) = self.open.last().and_then(|&i| self.calls.get_mut(i)) {
+            call.accesses.push(access);
+        }
+    }
+
+    /// Records a log emitted by the current frame
+    pub fn log(&mut self, log: RawLog) {
+        if let Some(call) = self.open.last().and_then(|&i| self.calls.get_mut(i)) {
+            call.logs.push(log);
+        }
+    }
+}
+
+/// Without the `tracing` feature nothing is recorded, so that the traces cost nothing
+#[cfg(not(feature = "tracing"))]
+impl RevertTracer {
+    /// Pushes a new frame on the call stack
+    pub fn enter(&mut self, _frame: CallFrame) {}
+
+    /// Pops the current frame from the call stack
+    pub fn exit(&mut self, _success: bool, _retdata: &[u8], _gas_used: u64) {}
+
+    /// Records the internal functions the current frame is executing
+    pub fn set_internal_calls(&mut self, _pcs: Vec<usize>) {}
+
+    /// Records an access made by the current frame
+    pub fn access(&mut self, _access: StateAccess) {}
+
+    /// Records a log emitted by the current frame
+    pub fn log(&mut self, _log: RawLog) {}
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_memory_only_when_it_changes() {
+        let mut trace = DebugTrace::default();
+        let step = |pc| DebugStep { pc, ..Default::default() };
+        trace.push(step(0), &[]);
+        trace.push(step(1), &[]);
+        trace.push(step(2), &[1; 32]);
+        trace.push(step(3), &[1; 32]);
+        // e.g. a call, whose frame starts with an empty memory
+        trace.push(step(4), &[]);
+
+        assert_eq!(trace.memories, vec![vec![], vec![1; 32], vec![]]);
+        let memories = trace.steps.iter().map(|step| trace.memory(step)).collect::<Vec<_>>();
+        assert_eq!(memories, [&[][..], &[], &[1; 32], &[1; 32], &[]]);
+    }
+
+    fn frame(n: u64) -> CallFrame {
+        CallFrame { address: Address::from_low_u64_be(n), ..Default::default() }
+    }
+
+    #[test]
+    fn records_innermost_revert() {
+        let mut tracer = RevertTracer::default();
+        tracer.enter(frame(1));
+        tracer.enter(frame(2));
+        tracer.enter(frame(3));
+        tracer.exit(false, b"boom", 0);
+        // the revert is bubbled up unchanged
+        tracer.exit(false, b"boom", 0);
+        tracer.exit(false, b"boom", 0);
+        assert_eq!(tracer.trace(), &[frame(1), frame(2), frame(3)]);
+    }
+
+    #[test]
+    fn discards_caught_reverts() {
+        let mut tracer = RevertTracer::default();
+        tracer.enter(frame(1));
+        tracer.enter(frame(2));
+        tracer.exit(false, b"caught", 0);
+        tracer.enter(frame(3));
+        tracer.exit(true, b"", 0);
+        tracer.exit(true, b"", 0);
+        assert!(tracer.trace().is_empty());
+    }
+
+    #[test]
+    fn records_internal_calls_of_the_reverting_frames() {
+        let mut tracer = RevertTracer::default();
+        tracer.enter(frame(1));
+        tracer.set_internal_calls(vec![10, 20]);
+        tracer.enter(frame(2));
+        tracer.set_internal_calls(vec![30]);
+        tracer.exit(false, b"boom", 0);
+        tracer.exit(false, b"boom", 0);
+
+        let internal_calls =
+            tracer.trace().iter().map(|frame| frame.internal_calls.clone()).collect::<Vec<_>>();
+        assert_eq!(internal_calls, vec![vec![10, 20], vec![30]]);
+    }
+
+    #[test]
+    fn records_new_revert_after_catching() {
+        let mut tracer = RevertTracer::default();
+        tracer.enter(frame(1));
+        tracer.enter(frame(2));
+        tracer.exit(false, b"caught", 0);
+        // the caller reverts with a different reason
+        tracer.exit(false, b"boom", 0);
+        assert_eq!(tracer.trace(), &[frame(1)]);
+    }
+
+    #[test]
+    fn records_accesses_of_the_current_call() {
+        let access = |n: u64, cold| StateAccess {
+            opcode: "SLOAD".to_string(),
+            address: frame(n).address,
+            slot: Some(H256::zero()),
+            cold,
+        };
+        let mut tracer = RevertTracer::default();
+        tracer.enter(frame(1));
+        tracer.access(access(1, true));
+        tracer.enter(frame(2));
+        tracer.access(access(2, true));
+        tracer.exit(true, b"", 0);
+        tracer.access(access(1, false));
+        tracer.exit(true, b"", 0);
+
+        let calls = tracer.calls();
+        assert_eq!(calls[0].accesses, vec![access(1, true), access(1, false)]);
+        assert_eq!(calls[1].accesses, vec![access(2, true)]);
+
+        let trace = CallTrace { calls: calls.to_vec(), ..Default::default() };
+        assert_eq!(
+            trace.access_summary(),
+            AccessSummary { cold_slots: 2, warm_slots: 1, ..Default::default() }
+        );
+    }
+
+    #[test]
+    fn records_every_call() {
+        let mut tracer = RevertTracer::default();
+        tracer.enter(frame(1));
+        tracer.enter(frame(2));
+        tracer.exit(false, b"caught", 0);
+        tracer.enter(frame(3));
+        tracer.exit(true, b"ok", 0);
+        tracer.exit(true, b"", 0);
+
+        let calls = tracer
+            .calls()
+            .iter()
+            .map(|call| (call.depth, call.frame.address, call.success, call.output.as_slice()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            calls,
+            vec![
+                (0, frame(1).address, true, &b""[..]),
+                (1, frame(2).address, false, &b"caught"[..]),
+                (1, frame(3).address, true, &b"ok"[..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn records_the_call_tree() {
+        let log = |n: u8| RawLog { topics: vec![H256::repeat_byte(n)], data: vec![n] };
+        let mut tracer = RevertTracer::default();
+        tracer.enter(CallFrame { input: vec![1, 2, 3, 4, 5], ..frame(1) });
+        tracer.log(log(1));
+        tracer.enter(CallFrame { caller: frame(1).address, value: 7.into(), ..frame(2) });
+        tracer.enter(frame(3));
+        tracer.log(log(3));
+        tracer.exit(true, b"", 100);
+        tracer.exit(false, b"caught", 300);
+        tracer.enter(CallFrame { create: true, input: vec![1, 2, 3, 4], ..frame(4) });
+        tracer.exit(true, b"", 200);
+        tracer.exit(true, b"", 1000);
+
+        let trace = CallTrace { calls: tracer.calls().to_vec(), ..Default::default() };
+        assert_eq!(trace.roots().map(|(index, _)| index).collect::<Vec<_>>(), vec![0]);
+        let children = trace.children(0).map(|(index, _)| index).collect::<Vec<_>>();
+        assert_eq!(children, vec![1, 3]);
+        assert_eq!(trace.children(1).map(|(index, _)| index).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(trace.children(3).count(), 0);
+
+        let calls = &trace.calls;
+        assert_eq!(calls[0].frame.selector(), Some([1, 2, 3, 4]));
+        assert_eq!(calls[3].frame.selector(), None);
+        assert_eq!((calls[1].frame.caller, calls[1].frame.value), (frame(1).address, 7.into()));
+        assert_eq!(
+            calls.iter().map(|call| call.gas_used).collect::<Vec<_>>(),
+            vec![1000, 300, 100, 200]
+        );
+        assert_eq!(calls[0].logs, vec![log(1)]);
+        assert!(calls[1].logs.is_empty());
+        assert_eq!(calls[2].logs, vec![log(3)]);
+    }
+}
@@ -0,0 +1,11 @@
+pragma solidity ^0.8.0;
+
+import "./DsTest.sol";
+
+contract Assertions is DSTest {
+    function testFailTwoAssertions() public {
+        assertEq(uint256(1), uint256(2));
+        assertTrue(false, "second");
+        emit log("unreachable");
+    }
+}
@@ -6,46 +6,331 @@ pragma experimental ABIEncoderV2;
 import "./DsTest.sol";
 
 interface Hevm {
+    // A read or write of a storage slot recorded by `startStateDiffRecording`
+    struct StorageAccess {
+        bytes32 slot;
+        bytes32 previousValue;
+        bytes32 newValue;
+        bool isWrite;
+    }
+    // An account called or created while recording a state diff, with the storage its code accessed
+    struct AccountAccess {
+        address account;
+        uint256 oldBalance;
+        uint256 newBalance;
+        bool reverted;
+        StorageAccess[] storageAccesses;
+    }
+    // The result of a command run by `tryFfi`
+    struct FfiResult {
+        int32 exitCode;
+        bytes stdout;
+        bytes stderr;
+    }
+
     // Set block.timestamp (newTimestamp)
     function warp(uint256) external;
     // Set block.height (newHeight)
     function roll(uint256) external;
+    // Advances block.timestamp by a number of seconds, on top of any earlier `warp`, (seconds)
+    function warpBy(uint256) external;
+    // Advances block.height by a number of blocks, on top of any earlier `roll`, (blocks)
+    function rollBy(uint256) external;
+    // Returns block.timestamp, including the override of `warp`
+    function getBlockTimestamp() external returns (uint256);
+    // Returns block.number, including the override of `roll`
+    function getBlockNumber() external returns (uint256);
     // Set block.basefee (newBasefee)
     function fee(uint256) external;
+    // Sets the hash blockhash returns for a block number, regardless of whether it is one of the 256 most recent blocks, (blockNumber, blockHash)
+    function setBlockhash(uint256,bytes32) external;
     // Loads a storage slot from an address (who, slot)
     function load(address,bytes32) external returns (bytes32);
     // Stores a value to an address' storage slot, (who, slot, value)
     function store(address,bytes32,bytes32) external;
     // Signs data, (privateKey, digest) => (r, v, s)
     function sign(uint256,bytes32) external returns (uint8,bytes32,bytes32);
+    // Signs the EIP-712 digest of typed data, (privateKey, domainSeparator, structHash) => (v, r, s)
+    function signTypedData(uint256,bytes32,bytes32) external returns (uint8,bytes32,bytes32);
+    // Signs data with an ed25519 key, (privateKey, message) => (publicKey, r, s)
+    function signEd25519(bytes32,bytes calldata) external returns (bytes32,bytes32,bytes32);
+    // Signs data with a BLS12-381 key, returning the compressed G1 public key and G2 signature, (privateKey, message) => (publicKey, signature)
+    function signBls(uint256,bytes calldata) external returns (bytes memory,bytes memory);
     // Gets address for a given private key, (privateKey) => (address)
     function addr(uint256) external returns (address);
+    // Derives a private key from a mnemonic at `m/44'/60'/0'/0/{index}`, (mnemonic, index) => (privateKey)
+    function deriveKey(string calldata, uint32) external returns (uint256);
     // Performs a foreign function call via terminal, (stringInputs) => (result)
     function ffi(string[] calldata) external returns (bytes memory);
+    // Like `ffi`, but returns the exit code and the raw stdout and stderr instead of failing, (args) => (result)
+    function tryFfi(string[] calldata) external returns (FfiResult memory);
     // Sets the *next* call's msg.sender to be the input address
     function prank(address) external;
     // Sets all subsequent calls' msg.sender to be the input address until `stopPrank` is called
     function startPrank(address) external;
+    // Like `startPrank`, optionally also for delegatecalls, whose msg.sender becomes the input address, and contract creations, which the input address deploys, (sender, delegateCalls, creates)
+    function startPrank(address, bool, bool) external;
     // Resets subsequent calls' msg.sender to be `address(this)`
     function stopPrank() external;
     // Sets an address' balance, (who, newBalance)
     function deal(address, uint256) external;
+    // Sets an address' ERC20 balance, finding the slot of its balance by probing the slots `balanceOf` reads, (token, who, newBalance)
+    function dealToken(address, address, uint256) external;
+    // Like `dealToken`, optionally adjusting `totalSupply` by the change of the balance, (token, who, newBalance, adjustTotalSupply)
+    function dealToken(address, address, uint256, bool) external;
+    // Like `dealToken`, with the slot of the token's balance mapping instead of probing, (token, who, newBalance, adjustTotalSupply, mappingSlot)
+    function dealToken(address, address, uint256, bool, uint256) external;
     // Sets an address' code, (who, newCode)
     function etch(address, bytes calldata) external;
     // Expects an error on next call
     function expectRevert(bytes calldata) external;
+    // Expects the next call to revert with a custom error with the given selector, whatever its arguments
+    function expectRevert(bytes4) external;
+    // Expects the next call to revert, whatever the revert data
+    function expectRevert() external;
+    // Expects an error on the next call of the current contract, from a call the given number of levels below it, (revertData, depth)
+    function expectRevert(bytes calldata, uint64) external;
+    // Expects the next call to emit the log emitted after this, comparing the selected topics and data, (checkTopic1, checkTopic2, checkTopic3, checkData)
+    function expectEmit(bool, bool, bool, bool) external;
+    // Like `expectEmit`, but the log has to be emitted by the given address, (checkTopic1, checkTopic2, checkTopic3, checkData, emitter)
+    function expectEmit(bool, bool, bool, bool, address) external;
+    // Expects the next call to emit the anonymous log emitted after this, whose topic0 is not the event selector, (checkTopic0, checkTopic1, checkTopic2, checkTopic3, checkData)
+    function expectEmitAnonymous(bool, bool, bool, bool, bool) external;
+    // Like `expectEmitAnonymous`, but the log has to be emitted by the given address, (checkTopic0, checkTopic1, checkTopic2, checkTopic3, checkData, emitter)
+    function expectEmitAnonymous(bool, bool, bool, bool, bool, address) external;
+    // Expects a call to an address whose calldata starts with the given calldata before the test ends, (where, data)
+    function expectCall(address, bytes calldata) external;
+    // Expects exactly `count` calls to an address whose calldata starts with the given calldata before the test ends, (where, data, count)
+    function expectCall(address, bytes calldata, uint64) external;
+    // Makes calls to an address whose calldata starts with the given calldata return the given data without executing, (where, data, retdata)
+    function mockCall(address, bytes calldata, bytes calldata) external;
+    // Like `mockCall`, but the calls revert with the given data, (where, data, revertData)
+    function mockCallRevert(address, bytes calldata, bytes calldata) external;
+    // Removes all mocked calls
+    function clearMockedCalls() external;
+    // Reads an environment variable and parses it as the given type, (name) => (value)
+    function envBool(string calldata) external returns (bool);
+    function envUint(string calldata) external returns (uint256);
+    function envInt(string calldata) external returns (int256);
+    function envAddress(string calldata) external returns (address);
+    function envBytes32(string calldata) external returns (bytes32);
+    function envString(string calldata) external returns (string memory);
+    function envBytes(string calldata) external returns (bytes memory);
+    // Starts measuring the gas used by a named section of the current call frame, (name)
+    function snapshotGasStart(string calldata) external;
+    // Stops measuring a named gas section and records it, (name) => (gasUsed)
+    function snapshotGasEnd(string calldata) external returns (uint256);
+    // Stops using gas for executed opcodes until `resumeGasMetering` is called
+    function pauseGasMetering() external;
+    // Uses gas for executed opcodes again
+    function resumeGasMetering() external;
+    // Stops the test and reports it as skipped if the condition is true, (skip)
+    function skip(bool) external;
+    // Records a labeled breakpoint which the debugger can jump to, (label)
+    function breakpoint(string calldata) external;
+    // Labels an address, (address, label)
+    function label(address, string calldata) external;
+    // Returns the label of an address, or the address itself if it has none, (address) => (label)
+    function getLabel(address) external returns (string memory);
+    // Only allows the calling frame to write to memory below 0x60 or within [min, max), (min, max)
+    function expectSafeMemory(uint64, uint64) external;
+    // Only allows the next call to write to memory below 0x60 or within [min, max), (min, max)
+    function expectSafeMemoryCall(uint64, uint64) external;
+    // Collects the next call or create as a transaction sent from the caller of the test, or
+    // from the given account, (sender)
+    function broadcast() external;
+    function broadcast(address) external;
+    // Collects every following call and create as a transaction until `stopBroadcast`, (sender)
+    function startBroadcast() external;
+    function startBroadcast(address) external;
+    // Stops collecting transactions
+    function stopBroadcast() external;
+    // Returns a random uint256, optionally within [min, max], (min, max) => (value)
+    function randomUint() external returns (uint256);
+    function randomUint(uint256,uint256) external returns (uint256);
+    // Returns a random address
+    function randomAddress() external returns (address);
+    // Converts the value to its canonical string representation, (value) => (string)
+    function toString(address) external returns (string memory);
+    function toString(bytes calldata) external returns (string memory);
+    function toString(bytes32) external returns (string memory);
+    function toString(bool) external returns (string memory);
+    function toString(uint256) external returns (string memory);
+    function toString(int256) external returns (string memory);
+    // Reads the value at a JSON-path-like key (e.g. `.a.b[0]`) and ABI-encodes it with an inferred type, (json, key) => (encoded)
+    function parseJson(string calldata, string calldata) external returns (bytes memory);
+    // Reads the value at a JSON-path-like key as the given type, (json, key) => (value)
+    function parseJsonBool(string calldata, string calldata) external returns (bool);
+    function parseJsonUint(string calldata, string calldata) external returns (uint256);
+    function parseJsonInt(string calldata, string calldata) external returns (int256);
+    function parseJsonAddress(string calldata, string calldata) external returns (address);
+    function parseJsonBytes32(string calldata, string calldata) external returns (bytes32);
+    function parseJsonString(string calldata, string calldata) external returns (string memory);
+    function parseJsonBytes(string calldata, string calldata) external returns (bytes memory);
+    // Reads the array at a JSON-path-like key as an array of the given type, (json, key) => (values)
+    function parseJsonBoolArray(string calldata, string calldata) external returns (bool[] memory);
+    function parseJsonUintArray(string calldata, string calldata) external returns (uint256[] memory);
+    function parseJsonIntArray(string calldata, string calldata) external returns (int256[] memory);
+    function parseJsonAddressArray(string calldata, string calldata) external returns (address[] memory);
+    function parseJsonBytes32Array(string calldata, string calldata) external returns (bytes32[] memory);
+    function parseJsonStringArray(string calldata, string calldata) external returns (string[] memory);
+    function parseJsonBytesArray(string calldata, string calldata) external returns (bytes[] memory);
+    // Returns the keys of the object at a JSON-path-like key, sorted, (json, key) => (keys)
+    function parseJsonKeys(string calldata, string calldata) external returns (string[] memory);
+    // Returns whether a JSON-path-like key exists in a JSON document, (json, key) => (exists)
+    function keyExists(string calldata, string calldata) external returns (bool);
+    // Reads the value at a JSON-path-like key of a TOML document and ABI-encodes it with an inferred type, (toml, key) => (encoded)
+    function parseToml(string calldata, string calldata) external returns (bytes memory);
+    // Reads the value at a JSON-path-like key of a TOML document as the given type, (toml, key) => (value)
+    function parseTomlBool(string calldata, string calldata) external returns (bool);
+    function parseTomlUint(string calldata, string calldata) external returns (uint256);
+    function parseTomlInt(string calldata, string calldata) external returns (int256);
+    function parseTomlAddress(string calldata, string calldata) external returns (address);
+    function parseTomlBytes32(string calldata, string calldata) external returns (bytes32);
+    function parseTomlString(string calldata, string calldata) external returns (string memory);
+    function parseTomlBytes(string calldata, string calldata) external returns (bytes memory);
+    // Reads the array at a JSON-path-like key of a TOML document as an array of the given type, (toml, key) => (values)
+    function parseTomlBoolArray(string calldata, string calldata) external returns (bool[] memory);
+    function parseTomlUintArray(string calldata, string calldata) external returns (uint256[] memory);
+    function parseTomlIntArray(string calldata, string calldata) external returns (int256[] memory);
+    function parseTomlAddressArray(string calldata, string calldata) external returns (address[] memory);
+    function parseTomlBytes32Array(string calldata, string calldata) external returns (bytes32[] memory);
+    function parseTomlStringArray(string calldata, string calldata) external returns (string[] memory);
+    function parseTomlBytesArray(string calldata, string calldata) external returns (bytes[] memory);
+    // Returns the keys of the table at a JSON-path-like key of a TOML document, sorted, (toml, key) => (keys)
+    function parseTomlKeys(string calldata, string calldata) external returns (string[] memory);
+    // Returns whether a JSON-path-like key exists in a TOML document, (toml, key) => (exists)
+    function keyExistsToml(string calldata, string calldata) external returns (bool);
+    // Forks the chain behind an RPC URL at a block, (rpcUrl, block) => (forkId)
+    function createFork(string calldata, uint256) external returns (uint256);
+    // Reads the chain state from a fork created with `createFork` from now on, (forkId)
+    function selectFork(uint256) external;
+    // Returns the id of the selected fork
+    function activeFork() external returns (uint256);
+    // Re-anchors the selected fork at a block, or at the start of the block of a transaction, (blockNumber) / (txHash)
+    function rollFork(uint256) external;
+    function rollFork(bytes32) external;
+    // Sends a raw JSON-RPC request to the selected fork (or the `--fork-url`) and returns the result, (method, params) => (result)
+    function rpc(string calldata, string calldata) external returns (bytes memory);
+    // Decodes a raw signed transaction and recovers its sender, with `to` being zero for contract creations
+    function decodeTx(bytes calldata) external returns (uint8 txType, address from, address to, uint256 nonce, uint256 gas, uint256 value, bytes memory data, uint256 chainId);
+    // Returns the creation bytecode of a compiled artifact, (artifact) => (code), e.g. `getCode("Contract.sol:Contract")`
+    function getCode(string calldata) external returns (bytes memory);
+    // Returns the runtime bytecode of a compiled artifact, (artifact) => (code)
+    function getDeployedCode(string calldata) external returns (bytes memory);
+    // Writes every account modified so far, with the storage slots written to, as a geth-style allocs JSON file, (path)
+    function dumpState(string calldata) external;
+    // Applies the balance, nonce, code and storage of every account in a geth-style allocs JSON file, (path)
+    function loadAllocs(string calldata) external;
+    // Returns the absolute path of the project's root directory
+    function projectRoot() external returns (string memory);
+    // Lists the entries of a directory sorted by path, each prefixed with the directory, (path) => (entries)
+    function readDir(string calldata) external returns (string[] memory);
+    // Whether a file or directory exists at the path, (path) => (exists)
+    function exists(string calldata) external returns (bool);
+    // Whether the path points to a file, (path) => (isFile)
+    function isFile(string calldata) external returns (bool);
+    // Whether the path points to a directory, (path) => (isDir)
+    function isDir(string calldata) external returns (bool);
+    // Replaces the storage of an account with a copy of another account's storage, (from, to)
+    function copyStorage(address, address) external;
+    // Asks for a value on the terminal, e.g. in an interactive script. Fails if not run interactively, (message) => (input)
+    function prompt(string calldata) external returns (string memory);
+    // Like `prompt`, but without echoing the input, e.g. for private keys
+    function promptSecret(string calldata) external returns (string memory);
+    // Like `prompt`, but parses the input as a decimal or 0x-prefixed hex number
+    function promptUint(string calldata) external returns (uint256);
+    // Wipes the storage of an account, (who)
+    function clearStorage(address) external;
+    // Wipes the storage, code and balance of an account, as if it self-destructed, (who)
+    function resetAccount(address) external;
+    // Marks an account and all of its storage as not accessed in the transaction, so the next access is cold (EIP-2929), (who)
+    function cool(address) external;
+    // Returns the version of the cheatcodes, which increases whenever cheatcodes are added or change
+    function cheatcodeVersion() external returns (uint256);
+    // Whether the cheatcode with the selector is available, e.g. `supports(Vm.cool.selector)`, (selector) => (supported)
+    function supports(bytes4) external returns (bool);
+    // Sleeps for the given number of milliseconds of wall-clock time, (ms). Requires --wall-clock
+    function sleep(uint256) external;
+    // Returns the host's current time in milliseconds since the Unix epoch. Requires --wall-clock
+    function unixTime() external returns (uint256);
+    // Starts recording the accounts every call and contract creation accesses, with their balances and storage accesses
+    function startStateDiffRecording() external;
+    // Stops recording and returns the accounts accessed since `startStateDiffRecording`, in the order they were called
+    function stopAndReturnStateDiff() external returns (AccountAccess[] memory);
+    // Builds a keccak256 Merkle tree over the leaves and returns its root, (leaves, sortPairs) => (root)
+    function getMerkleRoot(bytes32[] calldata, bool) external returns (bytes32);
+    // Returns the proof of the leaf at the given index, (leaves, index, sortPairs) => (proof)
+    function getMerkleProof(bytes32[] calldata, uint256, bool) external returns (bytes32[] memory);
+    // Verifies a Merkle proof, (root, proof, leaf, index, sortPairs) => (valid)
+    function verifyMerkleProof(bytes32, bytes32[] calldata, bytes32, uint256, bool) external returns (bool);
+}
+
+contract StateDiffTarget {
+    uint256 public value;
+
+    function set(uint256 _value) public payable {
+        value = _value;
+    }
 }
 
 contract HasStorage {
     uint public slot0 = 10;
 }
 
+// Runs against mainnet, see the tests which are ignored without `ETH_RPC_URL`
+contract ForkedCheatCodes is DSTest {
+    Hevm constant hevm = Hevm(HEVM_ADDRESS);
+    address constant WETH = 0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2;
+
+    function testCopyForkedStorage() public {
+        // the storage of a contract created in the test is not read from the fork otherwise
+        StateDiffTarget copy = new StateDiffTarget();
+        copy.set(7);
+
+        hevm.copyStorage(WETH, address(copy));
+        // WETH9's `decimals`, and its `name` in the slot which was written before
+        assertEq(uint256(hevm.load(address(copy), bytes32(uint256(2)))), 18);
+        assertEq(hevm.load(address(copy), bytes32(0)), hevm.load(WETH, bytes32(0)));
+        assertTrue(copy.value() != 0);
+    }
+
+    uint256 forkedCounter;
+
+    function testForkIsolation(string memory url) public {
+        uint256 first = hevm.createFork(url, 13292465);
+        uint256 second = hevm.createFork(url, 13292466);
+        bytes32 slot = bytes32(uint256(100));
+        address holder = address(uint160(uint256(keccak256("fork isolation"))));
+
+        hevm.selectFork(first);
+        hevm.store(WETH, slot, bytes32(uint256(1)));
+        hevm.deal(holder, 1 ether);
+        forkedCounter = 1;
+
+        // the writes made on the first fork are not seen on the second one
+        hevm.selectFork(second);
+        assertEq(hevm.load(WETH, slot), bytes32(0));
+        assertEq(holder.balance, 0);
+        hevm.store(WETH, slot, bytes32(uint256(2)));
+        // the test contract keeps its state on every fork
+        assertEq(forkedCounter, 1);
+
+        hevm.selectFork(first);
+        assertEq(hevm.load(WETH, slot), bytes32(uint256(1)));
+        assertEq(holder.balance, 1 ether);
+        hevm.selectFork(second);
+        assertEq(hevm.load(WETH, slot), bytes32(uint256(2)));
+    }
+}
+
 // We add `assertEq` tests as well to ensure that our test runner checks the
 // `failed` variable.
 contract CheatCodes is DSTest {
     address public store = address(new HasStorage());
     Hevm constant hevm = Hevm(HEVM_ADDRESS);
     address public who = hevm.addr(1);
+    uint256 public gasSectionSlot;
 
     // Warp
 
@@ -81,9 +366,32 @@ contract CheatCodes is DSTest {
         require(block.basefee == fee);
     }
 
+    // Blockhash
+
+    function testSetBlockhash() public {
+        hevm.roll(100);
+        hevm.setBlockhash(99, keccak256("block 99"));
+        require(blockhash(99) == keccak256("block 99"), "setBlockhash failed");
+        // other blocks are unaffected
+        require(blockhash(98) == bytes32(0), "setBlockhash changed another block");
+
+        hevm.setBlockhash(99, keccak256("reorg"));
+        require(blockhash(99) == keccak256("reorg"), "setBlockhash did not overwrite");
+    }
+
     // Roll
 
     // Underscore does not run the fuzz test?!
+    function testGetBlockTimestampAndNumber() public {
+        assertEq(hevm.getBlockTimestamp(), block.timestamp);
+        assertEq(hevm.getBlockNumber(), block.number);
+
+        hevm.warp(1337);
+        hevm.roll(42);
+        assertEq(hevm.getBlockTimestamp(), 1337);
+        assertEq(hevm.getBlockNumber(), 42);
+    }
+
     function testRoll(uint256 jump) public {
         uint pre = block.number;
         hevm.roll(block.number + jump);
@@ -96,6 +404,22 @@ contract CheatCodes is DSTest {
         assertEq(block.number, pre + jump + 1);
     }
 
+    function testWarpByRollBy() public {
+        hevm.warp(1000);
+        hevm.roll(10);
+        for (uint256 i = 0; i < 3; i++) {
+            hevm.warpBy(12);
+            hevm.rollBy(1);
+        }
+        assertEq(block.timestamp, 1036);
+        assertEq(block.number, 13);
+    }
+
+    function testFailWarpByOverflow() public {
+        hevm.warp(1);
+        hevm.warpBy(type(uint256).max);
+    }
+
     // function prove_warp_symbolic(uint128 jump) public {
     //     test_warp_concrete(jump);
     // }
@@ -149,6 +473,338 @@ contract CheatCodes is DSTest {
         assertEq(hevm.addr(sk), expected);
     }
 
+    function test_deriveKey() public {
+        string memory mnemonic = "test test test test test test test test test test test junk";
+
+        uint sk = hevm.deriveKey(mnemonic, 0);
+        assertEq(sk, 77814517325470205911140941194401928579557062014761831930645393041380819009408);
+        assertEq(hevm.addr(sk), 0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266);
+
+        assertEq(hevm.addr(hevm.deriveKey(mnemonic, 1)), 0x70997970C51812dc3A010C7d01b50e0d17dc79C8);
+    }
+
+    function testFail_deriveKey_invalid_mnemonic() public {
+        hevm.deriveKey("not a valid mnemonic", 0);
+    }
+
+    function testFailSelectUnknownFork() public {
+        hevm.selectFork(0);
+    }
+
+    function testFailActiveForkWithoutFork() public {
+        hevm.activeFork();
+    }
+
+    function testFailCreateForkInvalidUrl() public {
+        hevm.createFork("not a url", 1);
+    }
+
+    function testFailRollForkWithoutFork() public {
+        hevm.rollFork(1);
+    }
+
+    function testFailRpcWithoutEndpoint() public {
+        hevm.rpc("eth_chainId", "[]");
+    }
+
+    function testDecodeTx() public {
+        // EIP-1559 transfer of 1 wei signed by the private key 0x4646..46
+        bytes memory raw = hex"02f86b0180843b9aca0085174876e8008252089435353535353535353535353535353535353535350180c080a0bb50e2d89a4ed70663d080659fe0ad4b9bc3e06c17a227433966cb59ceee020da063e1ac797194ad423af7d885f881261c022ad9651e5f05034454df218baa7684";
+        (uint8 txType, address from, address to, uint256 nonce, uint256 gas, uint256 value, bytes memory data, uint256 chainId) = hevm.decodeTx(raw);
+        assertEq(uint256(txType), 2);
+        assertEq(from, hevm.addr(uint256(0x4646464646464646464646464646464646464646464646464646464646464646)));
+        assertEq(to, 0x3535353535353535353535353535353535353535);
+        assertEq(nonce, 0);
+        assertEq(gas, 21000);
+        assertEq(value, 1);
+        assertEq(data.length, 0);
+        assertEq(chainId, 1);
+    }
+
+    function testFailDecodeTxInvalid() public {
+        hevm.decodeTx(hex"02c0");
+    }
+
+    function testGetCode() public {
+        // `Greeter` is compiled with solc 0.7.6, so it cannot be imported here
+        bytes memory code = hevm.getCode("GreetTest.sol:Greeter");
+        address greeter;
+        assembly {
+            greeter := create(0, add(code, 0x20), mload(code))
+        }
+        (bool success, ) = greeter.call(abi.encodeWithSignature("greet(string)", "hi"));
+        assertTrue(success);
+        (, bytes memory greeting) = greeter.call(abi.encodeWithSignature("greeting()"));
+        assertEq(abi.decode(greeting, (string)), "hi");
+    }
+
+    function testFailGetCodeUnknownArtifact() public {
+        hevm.getCode("Unknown.sol:Unknown");
+    }
+
+    function testProjectRoot() public {
+        string memory path = string(abi.encodePacked(hevm.projectRoot(), "/testdata/CheatCodes.sol"));
+        assertTrue(hevm.isFile(path));
+    }
+
+    // called by the `dumps_state` test, which checks the written file
+    function dumpStateTo(string memory path) public {
+        hevm.deal(address(0x1337), 1 ether);
+        hevm.store(address(this), bytes32(uint256(1337)), bytes32(uint256(1)));
+        hevm.dumpState(path);
+    }
+
+    function testLoadAllocs() public {
+        hevm.loadAllocs("testdata/allocs.json");
+
+        address loaded = address(0x1338);
+        assertEq(loaded.balance, 1 ether);
+        assertEq(hevm.load(loaded, bytes32(uint256(1))), bytes32(uint256(42)));
+        (bool success, bytes memory ret) = loaded.call("");
+        assertTrue(success);
+        assertEq(abi.decode(ret, (uint256)), 42);
+
+        assertEq(address(0x1339).balance, 1000);
+    }
+
+    function testFailLoadAllocsMissing() public {
+        hevm.loadAllocs("testdata/missing.json");
+    }
+
+    function testReadDir() public {
+        // paths are relative to the directory the tests are run from
+        string[] memory entries = hevm.readDir("testdata");
+        bool found;
+        for (uint256 i = 0; i < entries.length; i++) {
+            if (keccak256(bytes(entries[i])) == keccak256("testdata/CheatCodes.sol")) {
+                found = true;
+            }
+        }
+        assertTrue(found);
+    }
+
+    function testFailReadDirMissing() public {
+        hevm.readDir("testdata/missing");
+    }
+
+    function testFileExists() public {
+        assertTrue(hevm.exists("testdata/CheatCodes.sol"));
+        assertTrue(hevm.isFile("testdata/CheatCodes.sol"));
+        assertTrue(!hevm.isDir("testdata/CheatCodes.sol"));
+
+        assertTrue(hevm.exists("testdata"));
+        assertTrue(hevm.isDir("testdata"));
+        assertTrue(!hevm.isFile("testdata"));
+
+        assertTrue(!hevm.exists("testdata/missing"));
+        assertTrue(!hevm.isFile("testdata/missing"));
+        assertTrue(!hevm.isDir("testdata/missing"));
+    }
+
+    function testCopyStorage() public {
+        StateDiffTarget original = new StateDiffTarget();
+        StateDiffTarget copy = new StateDiffTarget();
+        original.set(42);
+        copy.set(7);
+        hevm.store(address(original), bytes32(uint256(1)), bytes32(uint256(1337)));
+
+        hevm.copyStorage(address(original), address(copy));
+        assertEq(copy.value(), 42);
+        assertEq(hevm.load(address(copy), bytes32(uint256(1))), bytes32(uint256(1337)));
+
+        // the copies are independent
+        copy.set(1);
+        assertEq(original.value(), 42);
+    }
+
+    function testClearStorage() public {
+        StateDiffTarget target = new StateDiffTarget();
+        target.set(42);
+        hevm.store(address(target), bytes32(uint256(1)), bytes32(uint256(1337)));
+
+        hevm.clearStorage(address(target));
+        assertEq(target.value(), 0);
+        assertEq(hevm.load(address(target), bytes32(uint256(1))), bytes32(0));
+
+        // the code is kept
+        target.set(7);
+        assertEq(target.value(), 7);
+    }
+
+    function testResetAccount() public {
+        StateDiffTarget target = new StateDiffTarget();
+        hevm.deal(address(this), 1 ether);
+        target.set{value: 1 ether}(42);
+
+        hevm.resetAccount(address(target));
+        assertEq(address(target).balance, 0);
+        assertEq(getCode(address(target)).length, 0);
+        assertEq(hevm.load(address(target), bytes32(0)), bytes32(0));
+
+        // a contract can be deployed at the address again
+        hevm.etch(address(target), getCode(address(new StateDiffTarget())));
+        assertEq(target.value(), 0);
+        target.set(7);
+        assertEq(target.value(), 7);
+    }
+
+    function testCool() public {
+        StateDiffTarget target = new StateDiffTarget();
+        target.set(42);
+
+        uint256 gasBefore = gasleft();
+        target.value();
+        uint256 warm = gasBefore - gasleft();
+
+        hevm.cool(address(target));
+        gasBefore = gasleft();
+        target.value();
+        uint256 cold = gasBefore - gasleft();
+
+        // a cold account access costs 2500 and a cold slot access 2000 more than warm ones
+        assertEq(cold - warm, 4500);
+
+        // the accesses warmed them up again
+        gasBefore = gasleft();
+        target.value();
+        assertEq(gasBefore - gasleft(), warm);
+    }
+
+    function testSupports() public {
+        assertTrue(hevm.cheatcodeVersion() > 0);
+        assertTrue(hevm.supports(Hevm.cool.selector));
+        assertTrue(hevm.supports(Hevm.supports.selector));
+        assertTrue(!hevm.supports(bytes4(0xdeadbeef)));
+    }
+
+    function testUnknownCheatcode() public {
+        (bool success, bytes memory data) = address(hevm).call(abi.encodeWithSelector(bytes4(0xdeadbeef)));
+        assertTrue(!success);
+        // cheatcode errors are ABI-encoded strings without a selector
+        assertEq(
+            abi.decode(data, (string)),
+            "Unknown cheatcode 0xdeadbeef, use `supports` to check whether a cheatcode is available"
+        );
+    }
+
+    // tests are not run interactively
+    function testFailPrompt() public {
+        hevm.prompt("Continue?");
+    }
+
+    function testFailPromptSecret() public {
+        hevm.promptSecret("Private key:");
+    }
+
+    function testFailPromptUint() public {
+        hevm.promptUint("Amount:");
+    }
+
+    function testStateDiff() public {
+        StateDiffTarget target = new StateDiffTarget();
+        hevm.deal(address(this), 1 ether);
+
+        hevm.startStateDiffRecording();
+        target.set{value: 1}(42);
+        (bool success, ) = address(target).call(abi.encodeWithSignature("missing()"));
+        assertTrue(!success);
+        Hevm.AccountAccess[] memory accesses = hevm.stopAndReturnStateDiff();
+
+        assertEq(accesses.length, 2);
+        assertEq(accesses[0].account, address(target));
+        assertEq(accesses[0].oldBalance, 0);
+        assertEq(accesses[0].newBalance, 1);
+        assertTrue(!accesses[0].reverted);
+        Hevm.StorageAccess[] memory storageAccesses = accesses[0].storageAccesses;
+        Hevm.StorageAccess memory write = storageAccesses[storageAccesses.length - 1];
+        assertTrue(write.isWrite);
+        assertEq(write.slot, bytes32(0));
+        assertEq(write.previousValue, bytes32(0));
+        assertEq(write.newValue, bytes32(uint256(42)));
+
+        assertEq(accesses[1].account, address(target));
+        assertTrue(accesses[1].reverted);
+    }
+
+    function testFailStopStateDiffWithoutRecording() public {
+        hevm.stopAndReturnStateDiff();
+    }
+
+    function testSleep() public {
+        uint256 start = hevm.unixTime();
+        hevm.sleep(10);
+        assertGe(hevm.unixTime() - start, 10);
+    }
+
+    function testGetDeployedCode() public {
+        address greeter = address(0x1234);
+        hevm.etch(greeter, hevm.getDeployedCode("Greeter"));
+        (bool success, ) = greeter.call(abi.encodeWithSignature("gm()"));
+        assertTrue(success);
+        (, bytes memory greeting) = greeter.call(abi.encodeWithSignature("greeting()"));
+        assertEq(abi.decode(greeting, (string)), "gm");
+    }
+
+    function testMerkleProof() public {
+        bytes32[] memory leaves = new bytes32[](5);
+        for (uint256 i = 0; i < leaves.length; i++) {
+            leaves[i] = keccak256(abi.encode(i));
+        }
+        bytes32 root = hevm.getMerkleRoot(leaves, true);
+        bytes32[] memory proof = hevm.getMerkleProof(leaves, 4, true);
+        assertEq(proof.length, 3);
+
+        // the same algorithm as OpenZeppelin's `MerkleProof.verify`
+        bytes32 node = leaves[4];
+        for (uint256 i = 0; i < proof.length; i++) {
+            node = node <= proof[i]
+                ? keccak256(abi.encodePacked(node, proof[i]))
+                : keccak256(abi.encodePacked(proof[i], node));
+        }
+        assertEq(node, root);
+
+        assertTrue(hevm.verifyMerkleProof(root, proof, leaves[4], 4, true));
+        assertTrue(!hevm.verifyMerkleProof(root, proof, leaves[3], 3, true));
+
+        bytes32 unsortedRoot = hevm.getMerkleRoot(leaves, false);
+        proof = hevm.getMerkleProof(leaves, 1, false);
+        assertTrue(hevm.verifyMerkleProof(unsortedRoot, proof, leaves[1], 1, false));
+        assertTrue(!hevm.verifyMerkleProof(unsortedRoot, proof, leaves[1], 0, false));
+    }
+
+    function testSignTypedData() public {
+        uint256 sk = 1;
+        bytes32 domainSeparator = keccak256("domain");
+        bytes32 structHash = keccak256("struct");
+        (uint8 v, bytes32 r, bytes32 s) = hevm.signTypedData(sk, domainSeparator, structHash);
+        bytes32 digest = keccak256(abi.encodePacked("\x19\x01", domainSeparator, structHash));
+        assertEq(ecrecover(digest, v, r, s), hevm.addr(sk));
+    }
+
+    function testSignEd25519() public {
+        (bytes32 publicKey, bytes32 r, bytes32 s) = hevm.signEd25519(bytes32(uint256(1)), "hello");
+        (bytes32 publicKey2, bytes32 r2, bytes32 s2) = hevm.signEd25519(bytes32(uint256(1)), "hello");
+        // ed25519 signatures are deterministic
+        assertEq(publicKey, publicKey2);
+        assertEq(r, r2);
+        assertEq(s, s2);
+    }
+
+    function testSignBls() public {
+        (bytes memory publicKey, bytes memory signature) = hevm.signBls(1, "hello");
+        assertEq(publicKey.length, 48);
+        assertEq(signature.length, 96);
+    }
+
+    function testFailSignBlsZeroKey() public {
+        hevm.signBls(0, "hello");
+    }
+
+    function testFailGetMerkleProofOutOfBounds() public {
+        bytes32[] memory leaves = new bytes32[](2);
+        hevm.getMerkleProof(leaves, 2, false);
+    }
+
     function testFFI() public {
         string[] memory inputs = new string[](3);
         inputs[0] = "echo";
@@ -160,12 +816,73 @@ contract CheatCodes is DSTest {
         assertEq(output, "acab");
     }
 
+    function testTryFfi() public {
+        string[] memory inputs = new string[](3);
+        inputs[0] = "echo";
+        inputs[1] = "-n";
+        inputs[2] = "not hex";
+        Hevm.FfiResult memory res = hevm.tryFfi(inputs);
+        assertEq(int256(res.exitCode), 0);
+        assertEq(string(res.stdout), "not hex");
+        assertEq(res.stderr.length, 0);
+
+        inputs[0] = "sh";
+        inputs[1] = "-c";
+        inputs[2] = "printf oops >&2; exit 3";
+        res = hevm.tryFfi(inputs);
+        assertEq(int256(res.exitCode), 3);
+        assertEq(res.stdout.length, 0);
+        assertEq(string(res.stderr), "oops");
+    }
+
+    function testFailFFINonZeroExit() public {
+        string[] memory inputs = new string[](3);
+        inputs[0] = "sh";
+        inputs[1] = "-c";
+        inputs[2] = "exit 1";
+        hevm.ffi(inputs);
+    }
+
+    function testFFIString() public {
+        string[] memory inputs = new string[](2);
+        inputs[0] = "echo";
+        inputs[1] = "gm, not hex";
+        bytes memory res = hevm.ffi(inputs);
+        assertEq(abi.decode(res, (string)), "gm, not hex");
+
+        // hex without the 0x prefix is text as well
+        inputs[1] = "deadbeef";
+        res = hevm.ffi(inputs);
+        assertEq(abi.decode(res, (string)), "deadbeef");
+    }
+
     function testDeal() public {
         address addr = address(1337);
         hevm.deal(addr, 1337);
         assertEq(addr.balance, 1337);
     }
 
+    function testDealToken() public {
+        Token token = new Token();
+        address addr = address(1337);
+        hevm.dealToken(address(token), addr, 100);
+        assertEq(token.balanceOf(addr), 100);
+        assertEq(token.totalSupply(), 0);
+
+        hevm.dealToken(address(token), addr, 150, true);
+        assertEq(token.balanceOf(addr), 150);
+        assertEq(token.totalSupply(), 50);
+
+        // `balanceOf` is the second variable of the token
+        hevm.dealToken(address(token), address(this), 7, true, 1);
+        assertEq(token.balanceOf(address(this)), 7);
+        assertEq(token.totalSupply(), 57);
+    }
+
+    function testFailDealTokenWithoutBalance() public {
+        hevm.dealToken(address(new HasStorage()), address(1337), 100);
+    }
+
     function testPrank() public {
         Prank prank = new Prank();
         address new_sender = address(1337);
@@ -205,6 +922,31 @@ contract CheatCodes is DSTest {
         assertEq(ownerBalance, address(this).balance);
     }
 
+    function testPrankStartDelegateCall() public {
+        PrankDelegate delegate = new PrankDelegate();
+        bytes memory call = abi.encodeWithSelector(delegate.sender.selector);
+
+        hevm.startPrank(address(1337));
+        (, bytes memory data) = address(delegate).delegatecall(call);
+        hevm.stopPrank();
+        assertEq(abi.decode(data, (address)), msg.sender);
+
+        hevm.startPrank(address(1337), true, false);
+        (, data) = address(delegate).delegatecall(call);
+        hevm.stopPrank();
+        assertEq(abi.decode(data, (address)), address(1337));
+    }
+
+    function testPrankStartCreate() public {
+        hevm.startPrank(address(1337), false, true);
+        PrankDeployed deployed = new PrankDeployed();
+        hevm.stopPrank();
+        assertEq(deployed.deployer(), address(1337));
+
+        deployed = new PrankDeployed();
+        assertEq(deployed.deployer(), address(this));
+    }
+
     function testPrankStartComplex() public {
         // A -> B, B starts pranking, doesnt call stopPrank, A calls C calls D
         // C -> D would be pranked
@@ -239,6 +981,111 @@ contract CheatCodes is DSTest {
         target.customErr(99);
     }
 
+    function testExpectCustomRevertSelector() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert(InputAboveLimit.selector);
+        target.customErrWithArgs(101);
+        target.customErrWithArgs(99);
+    }
+
+    function testExpectRevertLargeOutput() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert("Value too large");
+        (uint256[12] memory values, string memory name) = target.largeOutputErr(101);
+        assertEq(values[11], 0);
+        assertEq(bytes(name).length, 0);
+    }
+
+    function testExpectRevertMalformedString() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert(hex"08c379a00000");
+        target.malformedStringErr();
+    }
+
+    function testFailExpectRevertMalformedString() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert("Value too large");
+        target.malformedStringErr();
+    }
+
+    function testFailExpectCustomRevertWrongSelector() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert(InputTooLarge.selector);
+        target.customErrWithArgs(101);
+    }
+
+    function testMockCall() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.mockCallRevert(
+            address(target),
+            abi.encodeWithSelector(target.stringErr.selector),
+            abi.encodeWithSelector(InputTooLarge.selector)
+        );
+        hevm.mockCall(
+            address(target),
+            abi.encodeWithSelector(target.stringErr.selector, 1),
+            abi.encode(42)
+        );
+        assertEq(target.stringErr(1), 42);
+
+        hevm.expectRevert(InputTooLarge.selector);
+        target.stringErr(5);
+
+        hevm.clearMockedCalls();
+        assertEq(target.stringErr(5), 5);
+    }
+
+    function testExpectCall() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectCall(address(target), abi.encodeWithSelector(target.stringErr.selector));
+        hevm.expectCall(address(target), abi.encodeWithSelector(target.stringErr.selector, 1), 2);
+        hevm.expectCall(address(target), abi.encodeWithSelector(target.stringErr2.selector), 0);
+        target.stringErr(1);
+        target.stringErr(1);
+        target.stringErr(2);
+    }
+
+    function testFailExpectCallCount() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectCall(address(target), abi.encodeWithSelector(target.stringErr.selector), 1);
+        target.stringErr(1);
+        target.stringErr(2);
+    }
+
+    function testFailExpectCallNotMade() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectCall(address(target), abi.encodeWithSelector(target.stringErr2.selector));
+        target.stringErr(1);
+    }
+
+    function testFailMockCallRevert() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.mockCallRevert(address(target), abi.encodeWithSelector(target.stringErr.selector), "");
+        target.stringErr(1);
+    }
+
+    function testFailUnusedMockCall() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.mockCall(address(target), abi.encodeWithSelector(target.stringErr.selector), abi.encode(42));
+        hevm.mockCallRevert(address(target), abi.encodeWithSelector(target.stringErr2.selector), "");
+        assertEq(target.stringErr(1), 42);
+    }
+
+    function testExpectAnyRevert() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert();
+        target.stringErr(101);
+        hevm.expectRevert();
+        target.customErrWithArgs(101);
+        target.stringErr(99);
+    }
+
+    function testFailExpectAnyRevertWithoutRevert() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert();
+        target.stringErr(99);
+    }
+
     function testCalleeExpectRevert() public {
         ExpectRevert target = new ExpectRevert();
         hevm.expectRevert("Value too largeCallee");
@@ -246,6 +1093,36 @@ contract CheatCodes is DSTest {
         target.stringErrCall(99);
     }
 
+    function testExpectNestedRevert() public {
+        ExpectRevert target = new ExpectRevert();
+        ExpectRevertRouter router = new ExpectRevertRouter();
+        hevm.expectRevert("Value too large", 1);
+        router.route(target, 101);
+        router.route(target, 99);
+    }
+
+    function testFailExpectNestedRevertMismatch() public {
+        ExpectRevert target = new ExpectRevert();
+        ExpectRevertRouter router = new ExpectRevertRouter();
+        hevm.expectRevert("Value too large2", 1);
+        router.route(target, 101);
+    }
+
+    function testFailExpectNestedRevertTooDeep() public {
+        ExpectRevert target = new ExpectRevert();
+        ExpectRevertRouter router = new ExpectRevertRouter();
+        hevm.expectRevert("Value too large", 2);
+        router.route(target, 101);
+    }
+
+    function testExpectTopLevelRevert() public {
+        ExpectRevert target = new ExpectRevert();
+        hevm.expectRevert("Value too large", 0);
+        // the call of the constructor does not consume the expectation
+        new ExpectRevertDeployer(target);
+        target.stringErr(101);
+    }
+
     function testFailExpectRevert() public {
         ExpectRevert target = new ExpectRevert();
         hevm.expectRevert("Value too large");
@@ -264,6 +1141,314 @@ contract CheatCodes is DSTest {
         hevm.expectRevert("revert");
     }  
 
+    event Transfer(address indexed from, address indexed to, uint256 amount);
+    event Ping(bytes32 indexed tag, uint256 value) anonymous;
+
+    function testExpectEmit() public {
+        Emitter emitter = new Emitter();
+        hevm.expectEmit(true, true, false, true);
+        emit Transfer(address(this), address(1337), 100);
+        emitter.transfer(address(1337), 100);
+
+        // the recipient is not checked
+        hevm.expectEmit(true, false, false, true);
+        emit Transfer(address(this), address(1), 100);
+        emitter.transfer(address(1337), 100);
+    }
+
+    function testFailExpectEmit() public {
+        Emitter emitter = new Emitter();
+        hevm.expectEmit(true, true, false, true);
+        emit Transfer(address(this), address(1337), 100);
+        emitter.transfer(address(1337), 99);
+    }
+
+    function testExpectEmitEmitter() public {
+        Emitter emitter = new Emitter();
+        EmitterCaller caller = new EmitterCaller();
+        hevm.expectEmit(true, true, false, true, address(emitter));
+        emit Transfer(address(caller), address(1337), 100);
+        caller.transfer(emitter, address(1337), 100);
+    }
+
+    function testFailExpectEmitWrongEmitter() public {
+        Emitter emitter = new Emitter();
+        EmitterCaller caller = new EmitterCaller();
+        hevm.expectEmit(true, true, false, true, address(caller));
+        emit Transfer(address(caller), address(1337), 100);
+        caller.transfer(emitter, address(1337), 100);
+    }
+
+    function testExpectEmitAnonymous() public {
+        Emitter emitter = new Emitter();
+        hevm.expectEmitAnonymous(true, false, false, false, true);
+        emit Ping("tag", 1);
+        emitter.ping("tag", 1);
+    }
+
+    function testFailExpectEmitAnonymousWrongTopic() public {
+        Emitter emitter = new Emitter();
+        hevm.expectEmitAnonymous(true, false, false, false, true);
+        emit Ping("tag", 1);
+        emitter.ping("other", 1);
+    }
+
+    // Test should fail if a prank is never applied
+    function testFailDanglingPrank() public {
+        hevm.prank(address(1337));
+    }
+
+    // Test should fail if `startPrank` is never stopped
+    function testFailDanglingStartPrank() public {
+        Prank prank = new Prank();
+        hevm.startPrank(address(1337));
+        prank.bar(address(1337));
+    }
+
+    function testEnv() public {
+        assertTrue(hevm.envBool("FOUNDRY_CHEATCODES_BOOL"));
+        assertEq(hevm.envUint("FOUNDRY_CHEATCODES_UINT"), 1337);
+        assertEq(hevm.envInt("FOUNDRY_CHEATCODES_INT"), -1337);
+        assertEq(hevm.envAddress("FOUNDRY_CHEATCODES_ADDRESS"), HEVM_ADDRESS);
+        assertEq(hevm.envBytes32("FOUNDRY_CHEATCODES_BYTES32"), bytes32(uint256(1337)));
+        assertEq(hevm.envString("FOUNDRY_CHEATCODES_STRING"), "hello world");
+        assertEq(string(hevm.envBytes("FOUNDRY_CHEATCODES_BYTES")), string(hex"1337"));
+    }
+
+    function testFailEnvMissing() public {
+        hevm.envUint("FOUNDRY_CHEATCODES_DOES_NOT_EXIST");
+    }
+
+    function testFailEnvMalformed() public {
+        hevm.envUint("FOUNDRY_CHEATCODES_MALFORMED");
+    }
+
+    function testSnapshotGas() public {
+        hevm.snapshotGasStart("cold");
+        gasSectionSlot = 1;
+        uint256 cold = hevm.snapshotGasEnd("cold");
+
+        hevm.snapshotGasStart("warm");
+        gasSectionSlot = 2;
+        uint256 warm = hevm.snapshotGasEnd("warm");
+
+        assertGt(cold, warm);
+    }
+
+    function testFailSnapshotGasNotStarted() public {
+        hevm.snapshotGasEnd("never started");
+    }
+
+    function testFailSnapshotGasNotEnded() public {
+        hevm.snapshotGasStart("never ended");
+    }
+
+    // `skip(true)` halts the test with a revert, which the test runner reports as skipped
+    function testFailSkip() public {
+        hevm.skip(true);
+    }
+
+    function testSkipFalse() public {
+        hevm.skip(false);
+    }
+
+    function testBreakpoint() public {
+        hevm.breakpoint("start");
+        gasSectionSlot = 1;
+        hevm.breakpoint("end");
+    }
+
+    function testLabel() public {
+        address alice = address(1337);
+        assertEq(hevm.getLabel(alice), "0x0000000000000000000000000000000000000539");
+        hevm.label(alice, "alice");
+        assertEq(hevm.getLabel(alice), "alice");
+        hevm.label(alice, "bob");
+        assertEq(hevm.getLabel(alice), "bob");
+    }
+
+    function testExpectSafeMemory() public {
+        hevm.expectSafeMemory(0x80, 0x100);
+        assembly {
+            mstore(0x40, 0x100)
+            mstore(0xe0, 1)
+        }
+    }
+
+    function testFailExpectSafeMemory() public {
+        hevm.expectSafeMemory(0x80, 0x100);
+        assembly {
+            mstore(0x100, 1)
+        }
+    }
+
+    function testExpectSafeMemoryCall() public {
+        MemoryWriter writer = new MemoryWriter();
+        hevm.expectSafeMemoryCall(0x80, 0xa0);
+        writer.write(0x80);
+        // only the next call is restricted
+        writer.write(0x200);
+    }
+
+    function testFailExpectSafeMemoryCall() public {
+        MemoryWriter writer = new MemoryWriter();
+        hevm.expectSafeMemoryCall(0x80, 0xa0);
+        writer.write(0xa0);
+    }
+
+    function testBroadcast() public {
+        Prank prank = new Prank();
+        address sender = address(1337);
+        hevm.broadcast(sender);
+        prank.bar(sender);
+        prank.bar(address(this));
+    }
+
+    function testStartBroadcast() public {
+        address sender = address(1337);
+        hevm.startBroadcast(sender);
+        Prank prank = new Prank();
+        prank.bar(sender);
+        hevm.stopBroadcast();
+        prank.bar(address(this));
+    }
+
+    function testRandomUint() public {
+        assertTrue(hevm.randomUint() != hevm.randomUint());
+        uint256 value = hevm.randomUint(10, 20);
+        assertTrue(value >= 10 && value <= 20);
+        assertEq(hevm.randomUint(5, 5), 5);
+        hevm.randomUint(0, type(uint256).max);
+    }
+
+    function testFailRandomUintInvalidRange() public {
+        hevm.randomUint(2, 1);
+    }
+
+    function testRandomAddress() public {
+        assertTrue(hevm.randomAddress() != hevm.randomAddress());
+    }
+
+    function randomValue() public returns (uint256) {
+        return hevm.randomUint();
+    }
+
+    function testPauseGasMetering() public {
+        hevm.pauseGasMetering();
+        uint256 gasBefore = gasleft();
+        for (uint256 i = 0; i < 100; i++) {
+            gasSectionSlot = i;
+        }
+        uint256 gasAfter = gasleft();
+        hevm.resumeGasMetering();
+        assertEq(gasBefore, gasAfter);
+
+        gasBefore = gasleft();
+        gasSectionSlot = 1337;
+        assertLt(gasleft(), gasBefore);
+    }
+
+    function testPauseGasMeteringCalls() public {
+        MemoryWriter writer = new MemoryWriter();
+        hevm.pauseGasMetering();
+        uint256 gasBefore = gasleft();
+        // only the memory the called frame expands is charged for while metering is paused
+        writer.write(0x1000);
+        uint256 gasAfter = gasleft();
+        hevm.resumeGasMetering();
+        assertLt(gasBefore - gasAfter, 1000);
+    }
+
+    function testToString() public {
+        assertEq(hevm.toString(address(0x7109709ECfa91a80626fF3989D68f67F5b1DD12D)), "0x7109709ECfa91a80626fF3989D68f67F5b1DD12D");
+        assertEq(hevm.toString(hex"1337"), "0x1337");
+        assertEq(hevm.toString(bytes32(uint256(1337))), "0x0000000000000000000000000000000000000000000000000000000000000539");
+        assertEq(hevm.toString(true), "true");
+        assertEq(hevm.toString(false), "false");
+        assertEq(hevm.toString(uint256(1337)), "1337");
+        assertEq(hevm.toString(int256(-1337)), "-1337");
+    }
+
+    string constant JSON =
+        '{"a":{"b":[1337,"0x7109709ECfa91a80626fF3989D68f67F5b1DD12D"]},"neg":-1337,"ok":true,"name":"forge","big":"1000000000000000000000000"}';
+
+    function testParseJson() public {
+        assertEq(abi.decode(hevm.parseJson(JSON, ".a.b[0]"), (uint256)), 1337);
+        assertEq(abi.decode(hevm.parseJson(JSON, "$.a.b[1]"), (address)), HEVM_ADDRESS);
+        assertEq(abi.decode(hevm.parseJson(JSON, ".neg"), (int256)), -1337);
+        assertEq(abi.decode(hevm.parseJson(JSON, ".name"), (string)), "forge");
+        assertTrue(abi.decode(hevm.parseJson(JSON, ".ok"), (bool)));
+    }
+
+    function testParseJsonTyped() public {
+        assertTrue(hevm.parseJsonBool(JSON, ".ok"));
+        assertEq(hevm.parseJsonUint(JSON, ".a.b[0]"), 1337);
+        assertEq(hevm.parseJsonUint(JSON, ".big"), 1e24);
+        assertEq(hevm.parseJsonInt(JSON, ".neg"), -1337);
+        assertEq(hevm.parseJsonAddress(JSON, ".a.b[1]"), HEVM_ADDRESS);
+        assertEq(hevm.parseJsonString(JSON, ".name"), "forge");
+    }
+
+    string constant JSON_ARRAYS =
+        '{"ids":[1,"2",1337],"owners":["0x7109709ECfa91a80626fF3989D68f67F5b1DD12D","0x0000000000000000000000000000000000000001"]}';
+
+    function testParseJsonArrays() public {
+        uint256[] memory ids = hevm.parseJsonUintArray(JSON_ARRAYS, ".ids");
+        assertEq(ids.length, 3);
+        assertEq(ids[1], 2);
+        assertEq(ids[2], 1337);
+
+        address[] memory owners = hevm.parseJsonAddressArray(JSON_ARRAYS, ".owners");
+        assertEq(owners.length, 2);
+        assertEq(owners[0], HEVM_ADDRESS);
+        assertEq(owners[1], address(1));
+    }
+
+    function testParseJsonKeys() public {
+        string[] memory keys = hevm.parseJsonKeys(JSON, ".");
+        assertEq(keys.length, 5);
+        assertEq(keys[0], "a");
+        assertEq(keys[4], "ok");
+        assertTrue(hevm.keyExists(JSON, ".a.b[1]"));
+        assertTrue(!hevm.keyExists(JSON, ".a.c"));
+    }
+
+    function testFailParseJsonArrayWrongType() public {
+        hevm.parseJsonUintArray(JSON_ARRAYS, ".owners");
+    }
+
+    function testFailParseJsonMissingKey() public {
+        hevm.parseJsonUint(JSON, ".missing");
+    }
+
+    function testFailParseJsonWrongType() public {
+        hevm.parseJsonUint(JSON, ".name");
+    }
+
+    string constant TOML =
+        'name = "forge"\nbig = "1000000000000000000000000"\n\n[mainnet]\nweth = "0x7109709ECfa91a80626fF3989D68f67F5b1DD12D"\nchainId = 1\nowners = ["0x0000000000000000000000000000000000000001"]\n';
+
+    function testParseToml() public {
+        assertEq(abi.decode(hevm.parseToml(TOML, ".mainnet.chainId"), (uint256)), 1);
+        assertEq(hevm.parseTomlAddress(TOML, ".mainnet.weth"), HEVM_ADDRESS);
+        assertEq(hevm.parseTomlUint(TOML, ".big"), 1e24);
+        assertEq(hevm.parseTomlString(TOML, ".name"), "forge");
+
+        address[] memory owners = hevm.parseTomlAddressArray(TOML, ".mainnet.owners");
+        assertEq(owners.length, 1);
+        assertEq(owners[0], address(1));
+
+        string[] memory keys = hevm.parseTomlKeys(TOML, ".mainnet");
+        assertEq(keys.length, 3);
+        assertEq(keys[0], "chainId");
+        assertTrue(hevm.keyExistsToml(TOML, ".mainnet.weth"));
+        assertTrue(!hevm.keyExistsToml(TOML, ".goerli"));
+    }
+
+    function testFailParseTomlInvalid() public {
+        hevm.parseTomlUint("name = ", ".name");
+    }
+
     function getCode(address who) internal returns (bytes memory o_code) {
         assembly {
             // retrieve the size of the code, this needs assembly
@@ -283,6 +1468,7 @@ contract CheatCodes is DSTest {
 
 
 error InputTooLarge();
+error InputAboveLimit(uint256 input, uint256 limit);
 contract ExpectRevert {
     function stringErrCall(uint256 a) public returns (uint256) {
         ExpectRevertCallee callee = new ExpectRevertCallee();
@@ -306,6 +1492,43 @@ contract ExpectRevert {
         }
         return a;
     }
+
+    function customErrWithArgs(uint256 a) public returns (uint256) {
+        if (a > 99) {
+            revert InputAboveLimit(a, 99);
+        }
+        return a;
+    }
+
+    function largeOutputErr(uint256 a) public returns (uint256[12] memory values, string memory name) {
+        require(a < 100, "Value too large");
+        values[0] = a;
+        name = "large";
+    }
+
+    function malformedStringErr() public pure {
+        // the `Error(string)` selector followed by a truncated offset
+        assembly {
+            mstore(0, 0x08c379a000000000000000000000000000000000000000000000000000000000)
+            revert(0, 6)
+        }
+    }
+}
+
+contract ExpectRevertRouter {
+    function route(ExpectRevert target, uint256 a) public returns (uint256) {
+        try target.stringErr(a) returns (uint256 amount) {
+            return amount;
+        } catch {
+            revert("Route failed");
+        }
+    }
+}
+
+contract ExpectRevertDeployer {
+    constructor(ExpectRevert target) {
+        target.stringErr(1);
+    }
 }
 
 contract ExpectRevertCallee {
@@ -320,6 +1543,30 @@ contract ExpectRevertCallee {
     }
 }
 
+contract Token {
+    uint256 public totalSupply;
+    mapping(address => uint256) public balanceOf;
+}
+
+contract Emitter {
+    event Transfer(address indexed from, address indexed to, uint256 amount);
+    event Ping(bytes32 indexed tag, uint256 value) anonymous;
+
+    function transfer(address to, uint256 amount) public {
+        emit Transfer(msg.sender, to, amount);
+    }
+
+    function ping(bytes32 tag, uint256 value) public {
+        emit Ping(tag, value);
+    }
+}
+
+contract EmitterCaller {
+    function transfer(Emitter emitter, address to, uint256 amount) public {
+        emitter.transfer(to, amount);
+    }
+}
+
 contract Prank {
     function bar(address expectedMsgSender) public {
         require(msg.sender == expectedMsgSender, "bad prank");
@@ -332,6 +1579,20 @@ contract Prank {
     }
 }
 
+contract PrankDelegate {
+    function sender() public view returns (address) {
+        return msg.sender;
+    }
+}
+
+contract PrankDeployed {
+    address public deployer;
+
+    constructor() {
+        deployer = msg.sender;
+    }
+}
+
 contract InnerPrank {
     function bar(address expectedMsgSender) public {
         require(msg.sender == expectedMsgSender, "bad prank");
@@ -353,3 +1614,11 @@ contract ComplexPrank {
 }
 
 
+
+contract MemoryWriter {
+    function write(uint256 offset) external pure {
+        assembly {
+            mstore(offset, 1)
+        }
+    }
+}
@@ -1,7 +1,24 @@
 pragma solidity ^0.8.10;
 
 contract FuzzTests {
+    enum Kind { A, B, C }
+
+    struct Order {
+        Kind kind;
+        uint24 amount;
+        int40 delta;
+        bytes3 tag;
+        uint8[][] levels;
+    }
+
     function testFuzzedRevert(uint256 x) public {
         require(x == 5, "fuzztest-revert");
     }
+
+    function testFuzzedOrder(Order memory order, Kind[] memory kinds) public pure {
+        require(order.kind <= Kind.C);
+        for (uint256 i = 0; i < kinds.length; i++) {
+            require(kinds[i] <= Kind.C);
+        }
+    }
 }
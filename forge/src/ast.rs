@@ -0,0 +1,296 @@
+//! Source spans of the external functions of compiled contracts, read from solc's AST and stored
+//! alongside the artifacts, so that frames can be pointed at the function they called, and the
+//! enums and structs declared by the sources, which the fuzzer needs to generate valid values
+use ethers::solc::{ArtifactOutput, CompilerOutput, Project};
+use evm_adapters::fuzz::TypeDefinitions;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+/// The key of the spans in an artifact
+const SPANS_KEY: &str = "functionSpans";
+
+/// The file in the artifacts directory which the type definitions of all sources are stored in
+const TYPES_FILE: &str = ".type-definitions.json";
+
+/// The source range of a function definition, or of a public state variable for its getter
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionSpan {
+    /// The path of the source file, relative to the project root
+    pub path: String,
+    /// The byte offset of the definition in the source file
+    pub offset: usize,
+    /// The length of the definition in bytes
+    pub length: usize,
+    /// The line the definition starts at, starting at 1
+    pub line: usize,
+}
+
+impl fmt::Display for FunctionSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path, self.line)
+    }
+}
+
+/// Mapping of every contract's name to the spans of its functions by their hex encoded selector
+pub type FunctionSpans = BTreeMap<String, BTreeMap<String, FunctionSpan>>;
+
+/// Collects the spans of all functions with a selector from the ASTs of the compiler output.
+/// Requires the `ast` output to have been selected
+pub fn function_spans(output: &CompilerOutput, root: &Path) -> FunctionSpans {
+    let output = serde_json::to_value(output).unwrap_or_default();
+    let mut spans = FunctionSpans::new();
+    for (path, source) in output["sources"].as_object().into_iter().flatten() {
+        let contracts = source["ast"]["nodes"].as_array().into_iter().flatten();
+        let contracts = contracts.filter(|node| node["nodeType"] == "ContractDefinition");
+        // the line numbers are only computed if there is anything to locate in the file
+        let mut text = None;
+        for contract in contracts {
+            let name = match contract["name"].as_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            for node in contract["nodes"].as_array().into_iter().flatten() {
+                let (selector, (offset, length)) = match (
+                    node["functionSelector"].as_str(),
+                    node["src"].as_str().and_then(parse_src),
+                ) {
+                    (Some(selector), Some(src)) => (selector, src),
+                    _ => continue,
+                };
+                let text: &String =
+                    text.get_or_insert_with(|| fs::read_to_string(path).unwrap_or_default());
+                let line = text.get(..offset).map(|s| s.matches('\n').count() + 1).unwrap_or(1);
+                let path = Path::new(path);
+                let span = FunctionSpan {
+                    path: path.strip_prefix(root).unwrap_or(path).display().to_string(),
+                    offset,
+                    length,
+                    line,
+                };
+                spans.entry(name.to_string()).or_default().insert(selector.to_string(), span);
+            }
+        }
+    }
+    spans
+}
+
+/// Parses the `offset:length:index` source range of an AST node
+fn parse_src(src: &str) -> Option<(usize, usize)> {
+    let mut fields = src.split(':');
+    Some((fields.next()?.parse().ok()?, fields.next()?.parse().ok()?))
+}
+
+/// Adds the spans to the artifacts of their contracts, which are written to
+/// `<artifacts>/<name>.json`
+pub fn write_function_spans<A: ArtifactOutput>(
+    project: &Project<A>,
+    spans: &FunctionSpans,
+) -> Result<()> {
+    if project.no_artifacts {
+        return Ok(())
+    }
+    for (name, functions) in spans {
+        let file = project.artifacts_path().join(format!("{}.json", name));
+        let mut artifact: Value = match fs::read_to_string(&file) {
+            Ok(artifact) => serde_json::from_str(&artifact)?,
+            Err(_) => continue,
+        };
+        if let Some(artifact) = artifact.as_object_mut() {
+            artifact.insert(SPANS_KEY.to_string(), serde_json::to_value(functions)?);
+            fs::write(&file, serde_json::to_string(&artifact)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the spans stored in the artifacts of the contracts
+pub fn read_function_spans<'a>(
+    artifacts: &Path,
+    names: impl IntoIterator<Item = &'a String>,
+) -> FunctionSpans {
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let artifact = fs::read_to_string(artifacts.join(format!("{}.json", name))).ok()?;
+            let mut artifact: Value = serde_json::from_str(&artifact).ok()?;
+            let functions = serde_json::from_value(artifact.get_mut(SPANS_KEY)?.take()).ok()?;
+            Some((name.clone(), functions))
+        })
+        .collect()
+}
+
+/// Collects the enums and structs declared at file level or in contracts from the ASTs of the
+/// compiler output. Requires the `ast` output to have been selected
+pub fn type_definitions(output: &CompilerOutput) -> TypeDefinitions {
+    let output = serde_json::to_value(output).unwrap_or_default();
+    let mut types = TypeDefinitions::default();
+    for source in output["sources"].as_object().into_iter().flat_map(|sources| sources.values()) {
+        let nodes = source["ast"]["nodes"].as_array().into_iter().flatten();
+        let contracts = nodes.clone().filter(|node| node["nodeType"] == "ContractDefinition");
+        let nested =
+            contracts.flat_map(|contract| contract["nodes"].as_array().into_iter().flatten());
+        for node in nodes.chain(nested) {
+            let name = match node["canonicalName"].as_str() {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let members = node["members"].as_array().into_iter().flatten();
+            match node["nodeType"].as_str() {
+                Some("EnumDefinition") => {
+                    types.enums.insert(name, members.count());
+                }
+                Some("StructDefinition") => {
+                    let members = members
+                        .map(|member| {
+                            member["typeDescriptions"]["typeString"]
+                                .as_str()
+                                .unwrap_or_default()
+                                .to_string()
+                        })
+                        .collect();
+                    types.structs.insert(name, members);
+                }
+                _ => {}
+            }
+        }
+    }
+    types
+}
+
+/// Adds the type definitions to the ones stored in the artifacts directory, so that they are
+/// still known when the sources declaring them are not recompiled
+pub fn write_type_definitions<A: ArtifactOutput>(
+    project: &Project<A>,
+    types: &TypeDefinitions,
+) -> Result<()> {
+    if project.no_artifacts {
+        return Ok(())
+    }
+    let mut stored = read_type_definitions(project.artifacts_path());
+    stored.extend(types.clone());
+    fs::create_dir_all(project.artifacts_path())?;
+    fs::write(project.artifacts_path().join(TYPES_FILE), serde_json::to_string(&stored)?)?;
+    Ok(())
+}
+
+/// Reads the type definitions stored in the artifacts directory
+pub fn read_type_definitions(artifacts: &Path) -> TypeDefinitions {
+    fs::read_to_string(artifacts.join(TYPES_FILE))
+        .ok()
+        .and_then(|types| serde_json::from_str(&types).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_function_spans_in_ast() {
+        let dir = std::env::temp_dir().join("forge-function-spans");
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("Foo.sol");
+        fs::write(&source, "contract Foo {\n    function bar() public {}\n}\n").unwrap();
+
+        let output: CompilerOutput = serde_json::from_value(serde_json::json!({
+            "sources": {
+                source.display().to_string(): {
+                    "id": 0,
+                    "ast": {
+                        "nodeType": "SourceUnit",
+                        "nodes": [{
+                            "nodeType": "ContractDefinition",
+                            "name": "Foo",
+                            "src": "0:45:0",
+                            "nodes": [
+                                {
+                                    "nodeType": "FunctionDefinition",
+                                    "name": "bar",
+                                    "functionSelector": "febb0f7e",
+                                    "src": "19:24:0"
+                                },
+                                {
+                                    "nodeType": "FunctionDefinition",
+                                    "name": "internalBar",
+                                    "src": "19:24:0"
+                                }
+                            ]
+                        }]
+                    }
+                }
+            },
+            "contracts": {}
+        }))
+        .unwrap();
+
+        let spans = function_spans(&output, &dir);
+        assert_eq!(
+            spans["Foo"],
+            BTreeMap::from([(
+                "febb0f7e".to_string(),
+                FunctionSpan { path: "Foo.sol".to_string(), offset: 19, length: 24, line: 2 }
+            )])
+        );
+    }
+
+    #[test]
+    fn finds_type_definitions_in_ast() {
+        let output: CompilerOutput = serde_json::from_value(serde_json::json!({
+            "sources": {
+                "Foo.sol": {
+                    "id": 0,
+                    "ast": {
+                        "nodeType": "SourceUnit",
+                        "nodes": [
+                            {
+                                "nodeType": "EnumDefinition",
+                                "canonicalName": "Side",
+                                "members": [{ "name": "Buy" }, { "name": "Sell" }]
+                            },
+                            {
+                                "nodeType": "ContractDefinition",
+                                "name": "Foo",
+                                "nodes": [
+                                    {
+                                        "nodeType": "EnumDefinition",
+                                        "canonicalName": "Foo.Kind",
+                                        "members": [
+                                            { "name": "A" },
+                                            { "name": "B" },
+                                            { "name": "C" }
+                                        ]
+                                    },
+                                    {
+                                        "nodeType": "StructDefinition",
+                                        "canonicalName": "Foo.Order",
+                                        "members": [
+                                            { "typeDescriptions": { "typeString": "enum Side" } },
+                                            { "typeDescriptions": { "typeString": "uint256[]" } }
+                                        ]
+                                    }
+                                ]
+                            }
+                        ]
+                    }
+                }
+            },
+            "contracts": {}
+        }))
+        .unwrap();
+
+        let types = type_definitions(&output);
+        assert_eq!(
+            types.enums,
+            BTreeMap::from([("Side".to_string(), 2), ("Foo.Kind".to_string(), 3)])
+        );
+        assert_eq!(
+            types.structs,
+            BTreeMap::from([(
+                "Foo.Order".to_string(),
+                vec!["enum Side".to_string(), "uint256[]".to_string()]
+            )])
+        );
+    }
+}
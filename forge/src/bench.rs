@@ -0,0 +1,97 @@
+//! Statistics over repeated executions of `bench*` functions
+use serde::{Deserialize, Serialize};
+
+/// Summary statistics over a set of samples
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub mean: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+impl Stats {
+    /// Computes the statistics of the provided samples. Returns the default (all zeroes) if there
+    /// are no samples.
+    pub fn new(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return Self::default()
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let len = sorted.len() as f64;
+        let mean = sorted.iter().sum::<f64>() / len;
+        let variance = sorted.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / len;
+
+        Self {
+            mean,
+            stddev: variance.sqrt(),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            p50: percentile(&sorted, 50.0),
+            p90: percentile(&sorted, 90.0),
+            p99: percentile(&sorted, 99.0),
+        }
+    }
+}
+
+/// Nearest-rank percentile of the already sorted samples
+fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    let rank = (pct / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.saturating_sub(1).min(sorted.len() - 1)]
+}
+
+/// The result of repeatedly executing a `bench*` function
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BenchResult {
+    /// Whether every iteration of the benchmark executed successfully
+    pub success: bool,
+    /// The revert reason of the first failing iteration, if any
+    pub reason: Option<String>,
+    /// The number of measured iterations (excluding warmup)
+    pub iterations: usize,
+    /// The gas used per iteration
+    pub gas: Stats,
+    /// The wall time per iteration in microseconds, which is mostly useful for benchmarks
+    /// hitting a forked backend
+    pub time_us: Stats,
+}
+
+impl BenchResult {
+    /// A benchmark which failed with the provided reason
+    pub fn failed(reason: Option<String>) -> Self {
+        Self { success: false, reason, ..Default::default() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_stats() {
+        let samples = (1..=100).map(|x| x as f64).collect::<Vec<_>>();
+        let stats = Stats::new(&samples);
+        assert_eq!(stats.mean, 50.5);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.max, 100.0);
+        assert_eq!(stats.p50, 50.0);
+        assert_eq!(stats.p90, 90.0);
+        assert_eq!(stats.p99, 99.0);
+        assert!((stats.stddev - 28.866).abs() < 0.001);
+    }
+
+    #[test]
+    fn stats_of_constant_samples() {
+        let stats = Stats::new(&[21000.0; 10]);
+        assert_eq!(stats.mean, 21000.0);
+        assert_eq!(stats.stddev, 0.0);
+        assert_eq!(stats.p99, 21000.0);
+        assert_eq!(Stats::new(&[]), Stats::default());
+    }
+}
@@ -0,0 +1,226 @@
+//! Line, branch and function coverage of the sources, computed from the program counters executed
+//! by the tests and the source maps of the compiled contracts
+use crate::source_map::{instruction_indices, SourceElement, SourceLocator};
+use evm_adapters::trace::CoverageHits;
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    ops::AddAssign,
+    path::{Path, PathBuf},
+};
+
+/// How many of the instrumented lines, branches or functions were executed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Hits {
+    pub hit: usize,
+    pub total: usize,
+}
+
+impl Hits {
+    fn new<'a>(counts: impl IntoIterator<Item = &'a u64>) -> Self {
+        counts.into_iter().fold(Self::default(), |hits, &count| Self {
+            hit: hits.hit + (count > 0) as usize,
+            total: hits.total + 1,
+        })
+    }
+}
+
+impl AddAssign for Hits {
+    fn add_assign(&mut self, other: Self) {
+        self.hit += other.hit;
+        self.total += other.total;
+    }
+}
+
+impl fmt::Display for Hits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.total == 0 {
+            return write!(f, "-")
+        }
+        let percent = self.hit as f64 * 100.0 / self.total as f64;
+        write!(f, "{:.2}% ({}/{})", percent, self.hit, self.total)
+    }
+}
+
+/// The coverage of a file or of all files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CoverageSummary {
+    pub lines: Hits,
+    pub branches: Hits,
+    pub functions: Hits,
+}
+
+impl AddAssign for CoverageSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.lines += other.lines;
+        self.branches += other.branches;
+        self.functions += other.functions;
+    }
+}
+
+/// The execution counts of a source file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceCoverage {
+    /// How often every line which instructions were generated from was executed, by line number
+    /// starting at 1
+    pub lines: BTreeMap<usize, u64>,
+    /// How often every branch (`JUMPI`) jumped and fell through, by its line and byte offset
+    pub branches: BTreeMap<(usize, usize), (u64, u64)>,
+    /// How often every function was entered, by the line of its definition and its name
+    pub functions: BTreeMap<(usize, String), u64>,
+}
+
+impl SourceCoverage {
+    pub fn summary(&self) -> CoverageSummary {
+        let branches = self.branches.values().flat_map(|(taken, not_taken)| [taken, not_taken]);
+        CoverageSummary {
+            lines: Hits::new(self.lines.values()),
+            branches: Hits::new(branches),
+            functions: Hits::new(self.functions.values()),
+        }
+    }
+}
+
+/// The coverage of every source file of the compiled contracts
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    pub files: BTreeMap<PathBuf, SourceCoverage>,
+}
+
+impl CoverageReport {
+    /// Attributes the hits of every executed code to the compiled contract it was deployed from
+    /// and maps them to the sources. Only runtime code is covered, constructors are not.
+    ///
+    /// A line counts as executed as often as its most executed instruction, since a single
+    /// statement usually compiles to many instructions.
+    pub fn new(locator: &SourceLocator, hits: &CoverageHits) -> Self {
+        let contracts = locator.contracts();
+        let mut executed = vec![(BTreeMap::new(), BTreeMap::new()); contracts.len()];
+        for (hash, code) in &hits.codes {
+            let (pcs, branches) = match locator.position(code) {
+                Some(index) => &mut executed[index],
+                None => continue,
+            };
+            for (&pc, &count) in hits.pcs.get(hash).into_iter().flatten() {
+                *pcs.entry(pc).or_insert(0u64) += count;
+            }
+            for (&pc, &(taken, not_taken)) in hits.branches.get(hash).into_iter().flatten() {
+                let branch = branches.entry(pc).or_insert((0u64, 0u64));
+                branch.0 += taken;
+                branch.1 += not_taken;
+            }
+        }
+
+        let mut sources = BTreeMap::new();
+        let mut files = BTreeMap::new();
+        for (contract, (pcs, branches)) in contracts.iter().zip(&executed) {
+            for (pc, index) in instruction_indices(&contract.code).into_iter().enumerate() {
+                let element = match index.and_then(|index| contract.source_map.get(index)) {
+                    Some(element) if element.length > 0 => element,
+                    _ => continue,
+                };
+                // instructions of generated helpers have no source file of the project
+                let path = match element.index.and_then(|id| locator.source_path(id)) {
+                    Some(path) => path,
+                    None => continue,
+                };
+                let source = match sources.entry(path).or_insert_with(|| SourceFile::read(path)) {
+                    Some(source) => source,
+                    None => continue,
+                };
+
+                let line = source.line(element.offset);
+                let count = pcs.get(&pc).copied().unwrap_or_default();
+                let file: &mut SourceCoverage = files.entry(path.clone()).or_default();
+                let line_hits = file.lines.entry(line).or_default();
+                *line_hits = (*line_hits).max(count);
+
+                // JUMPI
+                if contract.code[pc] == 0x57 {
+                    let (taken, not_taken) = branches.get(&pc).copied().unwrap_or_default();
+                    let branch = file.branches.entry((line, element.offset)).or_default();
+                    branch.0 += taken;
+                    branch.1 += not_taken;
+                }
+
+                if let Some(name) = source.function_name(element) {
+                    let function_hits = file.functions.entry((line, name)).or_default();
+                    *function_hits = (*function_hits).max(count);
+                }
+            }
+        }
+        Self { files }
+    }
+
+    /// Returns the coverage of all files together
+    pub fn summary(&self) -> CoverageSummary {
+        self.files.values().fold(CoverageSummary::default(), |mut summary, file| {
+            summary += file.summary();
+            summary
+        })
+    }
+
+    /// Renders the report in the LCOV tracefile format, e.g. for `genhtml` or coverage services
+    pub fn lcov(&self) -> String {
+        let mut out = String::new();
+        for (path, file) in &self.files {
+            let summary = file.summary();
+            out.push_str("TN:\n");
+            out.push_str(&format!("SF:{}\n", path.display()));
+            for (line, name) in file.functions.keys() {
+                out.push_str(&format!("FN:{},{}\n", line, name));
+            }
+            for ((_, name), hits) in &file.functions {
+                out.push_str(&format!("FNDA:{},{}\n", hits, name));
+            }
+            out.push_str(&format!("FNF:{}\n", summary.functions.total));
+            out.push_str(&format!("FNH:{}\n", summary.functions.hit));
+            for (block, ((line, _), (taken, not_taken))) in file.branches.iter().enumerate() {
+                // a branch which was never reached is marked with `-` instead of a count
+                let reached = taken + not_taken > 0;
+                for (branch, count) in [taken, not_taken].into_iter().enumerate() {
+                    let count = if reached { count.to_string() } else { "-".to_string() };
+                    out.push_str(&format!("BRDA:{},{},{},{}\n", line, block, branch, count));
+                }
+            }
+            out.push_str(&format!("BRF:{}\n", summary.branches.total));
+            out.push_str(&format!("BRH:{}\n", summary.branches.hit));
+            for (line, hits) in &file.lines {
+                out.push_str(&format!("DA:{},{}\n", line, hits));
+            }
+            out.push_str(&format!("LF:{}\n", summary.lines.total));
+            out.push_str(&format!("LH:{}\n", summary.lines.hit));
+            out.push_str("end_of_record\n");
+        }
+        out
+    }
+}
+
+/// A source file with the offset of every line
+struct SourceFile {
+    text: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    fn read(path: &Path) -> Option<Self> {
+        let text = fs::read_to_string(path).ok()?;
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        Some(Self { text, line_starts })
+    }
+
+    /// Returns the line of the byte offset, starting at 1
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset)
+    }
+
+    /// Returns the name of the function if the element is its definition, which the entry of a
+    /// function is mapped to
+    fn function_name(&self, element: &SourceElement) -> Option<String> {
+        let code = self.text.get(element.offset..element.offset + element.length)?;
+        let name = code.strip_prefix("function ")?.split('(').next()?.trim();
+        Some(name.to_string())
+    }
+}
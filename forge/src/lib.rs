@@ -1,8 +1,26 @@
 mod runner;
-pub use runner::{ContractRunner, TestKind, TestKindGas, TestResult};
+pub use runner::{ContractRunner, TestEvent, TestKind, TestKindGas, TestResult};
+
+mod bench;
+pub use bench::{BenchResult, Stats};
+
+mod trace;
+pub use trace::KnownContracts;
+
+mod ast;
+pub use ast::{
+    function_spans, read_function_spans, read_type_definitions, type_definitions,
+    write_function_spans, write_type_definitions, FunctionSpan, FunctionSpans,
+};
+
+mod source_map;
+pub use source_map::{parse_source_map, Jump, SourceElement, SourceLocation, SourceLocator};
+
+mod coverage;
+pub use coverage::{CoverageReport, CoverageSummary, Hits, SourceCoverage};
 
 mod multi_runner;
-pub use multi_runner::{MultiContractRunner, MultiContractRunnerBuilder};
+pub use multi_runner::{DeploymentCost, MultiContractRunner, MultiContractRunnerBuilder};
 
 use ethers::abi;
 use eyre::Result;
@@ -1,15 +1,24 @@
-use crate::{runner::TestResult, ContractRunner};
-use evm_adapters::Evm;
+use crate::{
+    ast::{
+        function_spans, read_function_spans, read_type_definitions, type_definitions,
+        write_function_spans, write_type_definitions,
+    },
+    runner::{TestEvent, TestResult},
+    BenchResult, ContractRunner, KnownContracts, SourceLocator,
+};
+use evm_adapters::{trace::CoverageHits, Evm};
+use foundry_utils::DisplayUnits;
 
 use ethers::{
     abi::Abi,
     prelude::ArtifactOutput,
     solc::{Artifact, Project},
-    types::{Address, U256},
+    types::{Address, Bytes, U256},
 };
 
 use proptest::test_runner::TestRunner;
 use regex::Regex;
+use serde::Serialize;
 
 use eyre::{Context, Result};
 use std::{collections::BTreeMap, marker::PhantomData};
@@ -24,6 +33,27 @@ pub struct MultiContractRunnerBuilder {
     pub sender: Option<Address>,
     /// The initial balance for each one of the deployed smart contracts
     pub initial_balance: U256,
+    /// Whether unused cheatcode expectations should be reported as warnings instead of
+    /// failing the test
+    pub allow_unused_expectations: bool,
+    /// Calldata recorded from real usage, whose arguments seed the fuzz tests
+    pub corpus: Vec<Bytes>,
+    /// Whether every opcode executed by the standard tests is recorded
+    pub debug: bool,
+    /// Whether the decoded call tree of every standard test is recorded
+    pub traces: bool,
+    /// Whether the breakpoints, pranks and state accesses of every standard test, and the
+    /// revert traces of the tests expected to fail, are recorded
+    pub details: bool,
+    /// Whether the state changed by every failed standard test is recorded
+    pub state_diffs: bool,
+    /// Whether the executed opcodes and branches of all tests are counted for coverage
+    pub coverage: bool,
+    /// Whether the contracts other than the tests are deployed on their own to measure their
+    /// cost for the gas report. The test contracts are always measured
+    pub gas_report: bool,
+    /// The unit in which the values of calls and balances are displayed in traces
+    pub display_units: DisplayUnits,
 }
 
 impl MultiContractRunnerBuilder {
@@ -38,6 +68,7 @@ impl MultiContractRunnerBuilder {
         // TODO: Can we remove the static? It's due to the `into_artifacts()` call below
         A: ArtifactOutput + 'static,
         E: Evm<S>,
+        S: Clone,
     {
         println!("compiling...");
         let output = project.compile()?;
@@ -50,44 +81,138 @@ impl MultiContractRunnerBuilder {
             println!("success.");
         }
 
+        // the spans of the contracts which were just compiled are stored with their artifacts,
+        // the ones of cached contracts are read back from them
+        let compiled = output.clone().output();
+        let spans = function_spans(&compiled, &project.paths.root);
+        write_function_spans(&project, &spans)?;
+        let types = type_definitions(&compiled);
+        write_type_definitions(&project, &types)?;
+
+        // internal functions can only be followed in the revert traces of contracts which were
+        // just compiled, since the cached artifacts do not include the source maps
+        let locator = SourceLocator::new(&compiled);
+        evm.set_internal_jumps(locator.internal_jumps());
+        evm.set_debug(self.debug);
+        evm.set_coverage(self.coverage);
+
         let sender = self.sender.unwrap_or_default();
         let initial_balance = self.initial_balance;
 
         // This is just the contracts compiled, but we need to merge this with the read cached
         // artifacts
-        let contracts = output.into_artifacts();
-        let contracts: BTreeMap<String, (Abi, Address, Vec<String>)> = contracts
+        let contracts = output
+            .into_artifacts()
             // only take contracts with valid abi and bytecode
             .filter_map(|(fname, contract)| {
                 let (abi, bytecode) = contract.into_inner();
                 abi.and_then(|abi| bytecode.map(|bytecode| (fname, abi, bytecode)))
             })
-            // Only take contracts with empty constructors.
-            .filter(|(_, abi, _)| {
-                abi.constructor.as_ref().map(|c| c.inputs.is_empty()).unwrap_or(true)
-            })
-            // Only take contracts which contain a `test` function
-            .filter(|(_, abi, _)| abi.functions().any(|func| func.name.starts_with("test")))
+            .collect::<Vec<_>>();
+
+        let mut known_contracts = KnownContracts::default();
+        for (name, abi, bytecode) in &contracts {
+            known_contracts.insert(name.clone(), abi.clone(), bytecode.clone());
+        }
+        if !project.no_artifacts {
+            known_contracts.insert_spans(read_function_spans(
+                project.artifacts_path(),
+                contracts.iter().map(|(name, _, _)| name),
+            ));
+        }
+        known_contracts.insert_spans(spans);
+        if !project.no_artifacts {
+            known_contracts
+                .insert_type_definitions(read_type_definitions(project.artifacts_path()));
+        }
+        known_contracts.insert_type_definitions(types);
+        known_contracts.insert_source_locator(locator, &project.paths.root);
+        known_contracts.set_display_units(self.display_units);
+
+        // calls whose revert is expected return the zero values of the called function's outputs
+        evm.set_return_types(
+            contracts
+                .iter()
+                .flat_map(|(_, abi, _)| abi.functions())
+                .map(|func| {
+                    let outputs = func.outputs.iter().map(|param| param.kind.clone()).collect();
+                    (func.short_signature(), outputs)
+                })
+                .collect(),
+        );
+
+        // contracts which require constructor arguments cannot be deployed on their own
+        let has_empty_constructor =
+            |abi: &Abi| abi.constructor.as_ref().map(|c| c.inputs.is_empty()).unwrap_or(true);
+        let is_test_contract = |abi: &Abi| {
+            abi.functions()
+                .any(|func| func.name.starts_with("test") || func.name.starts_with("bench"))
+        };
+
+        // The test contracts are measured when they are deployed below. For a gas report, all
+        // other contracts are deployed on a snapshot of the state which is restored afterwards,
+        // so that they do not affect the addresses or the state the tests run against
+        let mut deployments = BTreeMap::new();
+        let mut failed_deployments = BTreeMap::new();
+        if self.gas_report {
+            let snapshot = evm.state().clone();
+            for (name, abi, bytecode) in contracts.iter().filter(|(_, abi, bytecode)| {
+                !bytecode.as_ref().is_empty() &&
+                    has_empty_constructor(abi) &&
+                    !is_test_contract(abi)
+            }) {
+                // e.g. constructors which depend on the state set up by a test can not be
+                // deployed on their own, which should not stop the tests from running
+                match evm.deploy(sender, bytecode.clone(), 0.into()) {
+                    Ok((addr, _, gas, _)) => {
+                        let size = evm.code(addr).len();
+                        deployments.insert(name.clone(), DeploymentCost { gas, size });
+                    }
+                    Err(err) => {
+                        failed_deployments.insert(name.clone(), err.to_string());
+                    }
+                }
+                evm.reset(snapshot.clone());
+            }
+        }
+
+        let contracts: BTreeMap<String, (Abi, Address, Vec<String>)> = contracts
+            .into_iter()
+            // Only take contracts with empty constructors which contain a `test` or `bench`
+            // function
+            .filter(|(_, abi, _)| has_empty_constructor(abi) && is_test_contract(abi))
             // deploy the contracts
             .map(|(name, abi, bytecode)| {
                 let span = tracing::trace_span!("deploying", ?name);
                 let _enter = span.enter();
 
-                let (addr, _, _, logs) = evm
+                let (addr, _, gas, logs) = evm
                     .deploy(sender, bytecode, 0.into())
                     .wrap_err(format!("could not deploy {}", name))?;
+                deployments
+                    .insert(name.clone(), DeploymentCost { gas, size: evm.code(addr).len() });
 
                 evm.set_balance(addr, initial_balance);
+                known_contracts.insert_deployed(addr, name.clone());
                 Ok((name, (abi, addr, logs)))
             })
             .collect::<Result<BTreeMap<_, _>>>()?;
 
         Ok(MultiContractRunner {
             contracts,
+            known_contracts,
+            deployments,
+            failed_deployments,
             evm,
             state: PhantomData,
             sender: self.sender,
             fuzzer: self.fuzzer,
+            allow_unused_expectations: self.allow_unused_expectations,
+            corpus: self.corpus,
+            debug: self.debug,
+            traces: self.traces,
+            details: self.details,
+            state_diffs: self.state_diffs,
         })
     }
 
@@ -108,6 +233,69 @@ impl MultiContractRunnerBuilder {
         self.fuzzer = Some(fuzzer);
         self
     }
+
+    #[must_use]
+    pub fn allow_unused_expectations(mut self, allow: bool) -> Self {
+        self.allow_unused_expectations = allow;
+        self
+    }
+
+    #[must_use]
+    pub fn corpus(mut self, corpus: Vec<Bytes>) -> Self {
+        self.corpus = corpus;
+        self
+    }
+
+    #[must_use]
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    #[must_use]
+    pub fn traces(mut self, traces: bool) -> Self {
+        self.traces = traces;
+        self
+    }
+
+    #[must_use]
+    pub fn details(mut self, details: bool) -> Self {
+        self.details = details;
+        self
+    }
+
+    #[must_use]
+    pub fn state_diffs(mut self, state_diffs: bool) -> Self {
+        self.state_diffs = state_diffs;
+        self
+    }
+
+    #[must_use]
+    pub fn coverage(mut self, coverage: bool) -> Self {
+        self.coverage = coverage;
+        self
+    }
+
+    #[must_use]
+    pub fn gas_report(mut self, gas_report: bool) -> Self {
+        self.gas_report = gas_report;
+        self
+    }
+
+    #[must_use]
+    pub fn display_units(mut self, display_units: DisplayUnits) -> Self {
+        self.display_units = display_units;
+        self
+    }
+}
+
+/// The cost of deploying a contract
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DeploymentCost {
+    /// The gas used by the deployment, excluding the base transaction cost
+    pub gas: u64,
+    /// The size of the deployed runtime bytecode in bytes
+    pub size: usize,
 }
 
 /// A multi contract runner receives a set of contracts deployed in an EVM instance and proceeds
@@ -116,12 +304,32 @@ pub struct MultiContractRunner<E, S> {
     /// Mapping of contract name to compiled bytecode, deployed address and logs emitted during
     /// deployment
     contracts: BTreeMap<String, (Abi, Address, Vec<String>)>,
+    /// All compiled contracts, used for decoding revert traces
+    known_contracts: KnownContracts,
+    /// The cost of deploying each test contract, and with a gas report of each other contract
+    /// which has a constructor without arguments
+    deployments: BTreeMap<String, DeploymentCost>,
+    /// The contracts which could not be deployed on their own for the gas report, with the
+    /// reason why
+    failed_deployments: BTreeMap<String, String>,
     /// The EVM instance used in the test runner
     evm: E,
     /// The fuzzer which will be used to run parametric tests (w/ non-0 solidity args)
     fuzzer: Option<TestRunner>,
     /// The address which will be used as the `from` field in all EVM calls
     sender: Option<Address>,
+    /// Whether unused cheatcode expectations are downgraded to warnings
+    allow_unused_expectations: bool,
+    /// The calldata whose arguments seed the fuzz tests
+    corpus: Vec<Bytes>,
+    /// Whether every opcode executed by the standard tests is recorded
+    debug: bool,
+    /// Whether the decoded call tree of every standard test is recorded
+    traces: bool,
+    /// Whether the breakpoints, pranks and state accesses of every standard test are recorded
+    details: bool,
+    /// Whether the state changed by every failed standard test is recorded
+    state_diffs: bool,
     /// Market type for the EVM state being used
     state: PhantomData<S>,
 }
@@ -131,9 +339,35 @@ where
     E: Evm<S>,
     S: Clone,
 {
+    /// Returns the gas used and the code size of every contract's deployment, for a gas report
+    pub fn deployments(&self) -> &BTreeMap<String, DeploymentCost> {
+        &self.deployments
+    }
+
+    /// Returns the contracts left out of the gas report because their deployment failed, with
+    /// the reason why
+    pub fn failed_deployments(&self) -> &BTreeMap<String, String> {
+        &self.failed_deployments
+    }
+
+    /// Returns the opcodes and branches executed by all tests run so far, if coverage is enabled
+    pub fn coverage(&self) -> CoverageHits {
+        self.evm.coverage()
+    }
+
     pub fn test(
         &mut self,
         pattern: Regex,
+    ) -> Result<BTreeMap<String, BTreeMap<String, TestResult>>> {
+        self.test_streaming(pattern, &mut |_, _| {})
+    }
+
+    /// Runs the tests like [`MultiContractRunner::test`], calling `on_event` with the name of the
+    /// contract and every [`TestEvent`] while the tests are running
+    pub fn test_streaming(
+        &mut self,
+        pattern: Regex,
+        on_event: &mut dyn FnMut(&str, TestEvent<'_>),
     ) -> Result<BTreeMap<String, BTreeMap<String, TestResult>>> {
         // TODO: Convert to iterator, ideally parallel one?
         let contracts = std::mem::take(&mut self.contracts);
@@ -142,7 +376,8 @@ where
         let results = contracts
             .iter()
             .map(|(name, (abi, address, logs))| {
-                let result = self.run_tests(name, abi, *address, logs, &pattern, &init_state)?;
+                let result =
+                    self.run_tests(name, abi, *address, logs, &pattern, &init_state, on_event)?;
                 Ok((name.clone(), result))
             })
             .filter_map(|x: Result<_>| x.ok())
@@ -154,6 +389,31 @@ where
         Ok(results)
     }
 
+    /// Runs all `bench*` functions matching the pattern in every contract
+    pub fn bench(
+        &mut self,
+        pattern: Regex,
+        warmup: usize,
+        iterations: usize,
+    ) -> Result<BTreeMap<String, BTreeMap<String, BenchResult>>> {
+        let contracts = std::mem::take(&mut self.contracts);
+
+        let init_state: S = self.evm.state().clone();
+        let results = contracts
+            .iter()
+            .map(|(name, (abi, address, logs))| {
+                let mut runner =
+                    ContractRunner::new(&mut self.evm, abi, *address, self.sender, logs);
+                let result = runner.run_benches(&pattern, &init_state, warmup, iterations)?;
+                Ok((name.clone(), result))
+            })
+            .collect::<Result<Vec<_>>>();
+        // the contracts are put back before returning any error, to keep the runner usable
+        self.contracts = contracts;
+        let results = results?.into_iter().filter(|(_, res)| !res.is_empty()).collect();
+        Ok(results)
+    }
+
     // The _name field is unused because we only want it for tracing
     #[tracing::instrument(
         name = "contract",
@@ -161,17 +421,28 @@ where
         err,
         fields(name = %_name)
     )]
+    #[allow(clippy::too_many_arguments)]
     fn run_tests(
         &mut self,
-        _name: &str,
+        name: &str,
         contract: &Abi,
         address: Address,
         init_logs: &[String],
         pattern: &Regex,
         init_state: &S,
+        on_event: &mut dyn FnMut(&str, TestEvent<'_>),
     ) -> Result<BTreeMap<String, TestResult>> {
+        let mut on_contract_event = |event: TestEvent<'_>| on_event(name, event);
         let mut runner =
             ContractRunner::new(&mut self.evm, contract, address, self.sender, init_logs);
+        runner.allow_unused_expectations = self.allow_unused_expectations;
+        runner.known_contracts = Some(&self.known_contracts);
+        runner.corpus = &self.corpus;
+        runner.debug = self.debug;
+        runner.traces = self.traces;
+        runner.details = self.details;
+        runner.state_diffs = self.state_diffs;
+        runner.on_event = Some(&mut on_contract_event);
         runner.run_tests(pattern, self.fuzzer.as_mut(), init_state)
     }
 }
@@ -206,8 +477,20 @@ mod tests {
         let mut runner = runner(evm);
         let results = runner.test(Regex::new(".*").unwrap()).unwrap();
 
-        // 6 contracts being built
-        assert_eq!(results.keys().len(), 5);
+        // only the contracts with tests are run, not the contracts they test
+        assert_eq!(
+            results.keys().map(String::as_str).collect::<Vec<_>>(),
+            [
+                "DebugLogsTest",
+                "FooBar",
+                "FooTest",
+                "GmTest",
+                "GreeterTest",
+                "OverflowTest",
+                "RevertTraceTest",
+                "SkipTest"
+            ]
+        );
         for (_, contract_tests) in results {
             assert_ne!(contract_tests.keys().len(), 0);
             assert!(contract_tests.iter().all(|(_, result)| result.success));
@@ -247,6 +530,172 @@ mod tests {
             );
         }
 
+        #[test]
+        fn test_sputnik_streams_events() {
+            let mut runner = runner(vm());
+            let mut events = Vec::new();
+            let results = runner
+                .test_streaming(Regex::new("test1|test2").unwrap(), &mut |contract, event| {
+                    events.push((contract.to_owned(), serde_json::to_value(event).unwrap()))
+                })
+                .unwrap();
+            assert_eq!(results["DebugLogsTest"].len(), 2);
+
+            let events = events
+                .iter()
+                .filter(|(contract, _)| contract == "DebugLogsTest")
+                .map(|(_, event)| match event["event"].as_str().unwrap() {
+                    "log" => format!("log {}", event["log"].as_str().unwrap()),
+                    "suite_finished" => format!("suite_finished {}", event["passed"]),
+                    name => format!("{} {}", name, event["test"].as_str().unwrap()),
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                events,
+                vec![
+                    "test_started test1()",
+                    "log constructor",
+                    "log setUp",
+                    "log one",
+                    "test_finished test1()",
+                    "test_started test2()",
+                    "log constructor",
+                    "log setUp",
+                    "log two",
+                    "test_finished test2()",
+                    "suite_finished 2",
+                ]
+            );
+        }
+
+        #[test]
+        fn test_sputnik_revert_trace() {
+            // tests which are expected to revert only record it with the details
+            let mut runner = runner(vm());
+            let results = runner.test(Regex::new("testFailRevertTrace").unwrap()).unwrap();
+            let result = &results["RevertTraceTest"]["testFailRevertTrace()"];
+            assert!(result.success);
+            assert!(result.revert_trace.is_empty());
+            assert!(result.traces.is_empty());
+
+            let mut runner =
+                MultiContractRunnerBuilder::default().details(true).build(project(), vm()).unwrap();
+            let results = runner.test(Regex::new("testFailRevertTrace").unwrap()).unwrap();
+
+            let result = &results["RevertTraceTest"]["testFailRevertTrace()"];
+            assert!(result.success);
+            assert_eq!(
+                result.revert_trace,
+                vec![
+                    "RevertTraceTest::testFailRevertTrace()".to_owned(),
+                    "Middle::forward(1)".to_owned(),
+                    "Reverter::fail(1)".to_owned(),
+                    // the internal function which reverted, followed through the source map
+                    "↳ check (RevertTraceTest.sol:9)".to_owned()
+                ]
+            );
+        }
+
+        #[test]
+        fn test_sputnik_call_traces() {
+            let mut runner =
+                MultiContractRunnerBuilder::default().traces(true).build(project(), vm()).unwrap();
+            let results = runner.test(Regex::new("testFailRevertTrace").unwrap()).unwrap();
+            let traces = &results["RevertTraceTest"]["testFailRevertTrace()"].traces;
+
+            // the calls are decoded with the ABIs of the fixture contracts, nested by depth
+            assert!(traces[0].starts_with("[✗] RevertTraceTest::testFailRevertTrace()"));
+            assert!(traces[0].ends_with("→ revert: x is not zero"));
+            let calls =
+                traces.iter().filter(|line| line.trim_start().starts_with('[')).collect::<Vec<_>>();
+            assert_eq!(calls.len(), 3);
+            assert!(calls[1].starts_with("  [✗] Middle::forward(1)"));
+            assert!(calls[2].starts_with("    [✗] Reverter::fail(1)"));
+            assert!(calls[2].ends_with("→ revert: x is not zero"));
+        }
+
+        #[test]
+        fn test_sputnik_skip() {
+            let mut runner = runner(vm());
+            let results = runner.test(Regex::new("testSkip").unwrap()).unwrap();
+
+            let result = &results["SkipTest"]["testSkip()"];
+            assert!(result.success);
+            assert!(result.skipped);
+            assert_eq!(result.reason, None);
+        }
+
+        #[test]
+        fn test_sputnik_overflow_site() {
+            let mut runner = runner(vm());
+            let results = runner.test(Regex::new("testFailOverflow").unwrap()).unwrap();
+
+            let result = &results["OverflowTest"]["testFailOverflow()"];
+            assert!(result.success);
+            assert_eq!(
+                result.reason.as_deref(),
+                Some("Panic(0x11): arithmetic underflow or overflow")
+            );
+
+            let site = result.overflow.as_ref().expect("no overflow recorded");
+            let locator = crate::SourceLocator::compile(&project()).unwrap();
+            let location = locator.locate(site).expect("could not locate the overflow");
+            assert!(location.path.ends_with("OverflowTest.sol"));
+            assert_eq!(location.line, 8);
+            assert!(location.snippet.contains("count + amount"));
+        }
+
+        #[test]
+        fn test_sputnik_coverage() {
+            let mut runner = MultiContractRunnerBuilder::default()
+                .coverage(true)
+                .build(project(), vm())
+                .unwrap();
+            runner.test(Regex::new("testFailOverflow").unwrap()).unwrap();
+
+            let locator = crate::SourceLocator::compile(&project()).unwrap();
+            let report = crate::CoverageReport::new(&locator, &runner.coverage());
+            let (_, file) = report
+                .files
+                .iter()
+                .find(|(path, _)| path.ends_with("OverflowTest.sol"))
+                .expect("no coverage of the test file");
+
+            // `count = count + amount` ran twice, the `count()` getter never
+            assert_eq!(file.lines[&8], 2);
+            assert_eq!(file.lines[&5], 0);
+            assert_eq!(file.functions[&(7, "add".to_string())], 2);
+            assert!(report.lcov().contains("DA:8,2\n"));
+            let summary = report.summary();
+            assert!(summary.lines.hit > 0 && summary.lines.hit < summary.lines.total);
+        }
+
+        #[test]
+        fn test_sputnik_deployment_costs() {
+            // without a gas report only the test contracts are deployed
+            let runner = runner(vm());
+            assert!(runner.deployments().contains_key("GreeterTest"));
+            assert!(!runner.deployments().contains_key("Greeter"));
+
+            let runner = MultiContractRunnerBuilder::default()
+                .gas_report(true)
+                .build(project(), vm())
+                .unwrap();
+            let deployments = runner.deployments();
+
+            // both the contracts under test and the test contracts are measured
+            let greeter = deployments["Greeter"];
+            assert!(greeter.gas > 0);
+            assert!(greeter.size > 0);
+            assert!(deployments["GreeterTest"].size > greeter.size);
+            // constructors with arguments can not be simulated
+            assert!(!deployments.contains_key("Middle"));
+            assert!(deployments.contains_key("Reverter"));
+            // reverting constructors are left out instead of failing the run
+            assert!(!deployments.contains_key("ConstructorReverter"));
+            assert!(runner.failed_deployments().contains_key("ConstructorReverter"));
+        }
+
         #[test]
         fn test_sputnik_multi_runner() {
             test_multi_runner(vm());
@@ -3,9 +3,14 @@ use ethers::{
     types::{Address, Bytes},
 };
 
+use crate::{
+    bench::{BenchResult, Stats},
+    KnownContracts,
+};
 use evm_adapters::{
     fuzz::{FuzzTestResult, FuzzedCases, FuzzedExecutor},
-    Evm, EvmError,
+    trace::{AccessSummary, Breakpoint, CallTrace, DebugTrace, OverflowSite, PrankedCall},
+    Evm, EvmError, SKIP_REASON,
 };
 use eyre::{Context, Result};
 use regex::Regex;
@@ -16,10 +21,20 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CounterExample {
+    /// The ABI-encoded call of the test function with the failing inputs
     pub calldata: Bytes,
     // Token does not implement Serde (lol), so we just serialize the calldata
     #[serde(skip)]
     pub args: Vec<Token>,
+    /// The signature of the test function, e.g. `testDeposit(uint256)`
+    #[serde(default)]
+    pub signature: String,
+    /// A `cast send` command sending the failing call to the test contract
+    #[serde(default)]
+    pub cast: String,
+    /// A Solidity test function making the failing call, to be pasted into the test contract
+    #[serde(default)]
+    pub solidity: String,
 }
 
 impl fmt::Display for CounterExample {
@@ -28,6 +43,69 @@ impl fmt::Display for CounterExample {
     }
 }
 
+impl CounterExample {
+    /// Decodes the failing call of `func` on the test contract at `address`
+    pub fn new(func: &Function, address: Address, calldata: Bytes) -> Result<Self> {
+        // skip the function selector when decoding
+        let args = func.decode_input(&calldata.as_ref()[4..])?;
+        let signature = func.signature();
+
+        let cast_args = args.iter().map(|arg| shell_quote(&cast_arg(arg))).collect::<Vec<_>>();
+        let cast =
+            format!("cast send {:?} {} {}", address, shell_quote(&signature), cast_args.join(" "));
+
+        // the inputs are passed as literals if all of them can be written as such, otherwise the
+        // encoded call is made as is
+        let literals = args.iter().map(solidity_literal).collect::<Option<Vec<_>>>();
+        let call = match literals {
+            Some(literals) => format!("        this.{}({});", func.name, literals.join(", ")),
+            None => format!(
+                "        (bool success, bytes memory data) = address(this).call(hex\"{}\");\n        \
+                 if (!success) assembly {{ revert(add(data, 32), mload(data)) }}",
+                hex::encode(&calldata)
+            ),
+        };
+        // indented to be pasted into the body of the test contract
+        let solidity = format!("    function {}Repro() public {{\n{}\n    }}", func.name, call);
+
+        Ok(Self { calldata, args, signature, cast: cast.trim_end().to_string(), solidity })
+    }
+}
+
+/// Formats the token as an argument of `cast send`
+fn cast_arg(token: &Token) -> String {
+    match token {
+        Token::String(s) => s.clone(),
+        Token::FixedArray(tokens) | Token::Array(tokens) => {
+            format!("[{}]", tokens.iter().map(cast_arg).collect::<Vec<_>>().join(","))
+        }
+        Token::Tuple(tokens) => {
+            format!("({})", tokens.iter().map(cast_arg).collect::<Vec<_>>().join(","))
+        }
+        token => foundry_utils::format_token(token),
+    }
+}
+
+/// Quotes the argument for a POSIX shell
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Formats the token as a Solidity literal which converts implicitly to the token's type, if
+/// there is one. Byte strings, arrays and tuples have none
+fn solidity_literal(token: &Token) -> Option<String> {
+    match token {
+        Token::Address(_) | Token::Uint(_) | Token::Int(_) | Token::Bool(_) => {
+            Some(foundry_utils::format_token(token))
+        }
+        // string literals are restricted to printable ASCII without `unicode` prefix
+        Token::String(s) if s.chars().all(|c| c.is_ascii_graphic() || c == ' ') => {
+            Some(format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")))
+        }
+        _ => None,
+    }
+}
+
 /// The result of an executed solidity test
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TestResult {
@@ -53,6 +131,77 @@ pub struct TestResult {
     /// be printed to the user.
     pub logs: Vec<String>,
 
+    /// The gas used by every named section measured with `snapshotGasStart` / `snapshotGasEnd`.
+    ///
+    /// Only recorded for standard (non-fuzz) tests
+    pub gas_sections: BTreeMap<String, u64>,
+
+    /// Unused cheatcode expectations which did not fail the test because they were
+    /// downgraded to warnings
+    pub warnings: Vec<String>,
+
+    /// The warnings of the deprecated cheatcodes called by the test
+    #[serde(default)]
+    pub deprecations: Vec<String>,
+
+    /// If the test reverted, the call frames from the test function down to the frame where the
+    /// revert originated, formatted as `Contract::function(args)` followed by the source location
+    /// of the function if it is known.
+    ///
+    /// Only recorded for standard (non-fuzz) tests
+    pub revert_trace: Vec<String>,
+
+    /// The breakpoints hit during the test, recorded with the `breakpoint` cheatcode.
+    ///
+    /// Only recorded for standard (non-fuzz) tests
+    #[serde(default)]
+    pub breakpoints: Vec<Breakpoint>,
+
+    /// Every call of the test whose sender was replaced by `prank` or `startPrank`, to audit
+    /// which senders the test actually exercised.
+    ///
+    /// Only recorded for standard (non-fuzz) tests
+    #[serde(default)]
+    pub pranks: Vec<PrankedCall>,
+
+    /// The number of cold and warm account and storage accesses made by the test (EIP-2929).
+    ///
+    /// Only recorded for standard (non-fuzz) tests
+    #[serde(default)]
+    pub accesses: AccessSummary,
+
+    /// Every call made by the test as an indented tree, with the called contracts and functions,
+    /// their decoded arguments, results and events and the source location of the functions.
+    ///
+    /// Only recorded for standard (non-fuzz) tests, if traces are enabled
+    #[serde(default)]
+    pub traces: Vec<String>,
+
+    /// The balances, nonces, code and storage slots changed by the test, one account or slot
+    /// per line, to see what a failing test actually did.
+    ///
+    /// Only recorded for failed standard (non-fuzz) tests, if state diffs are enabled
+    #[serde(default)]
+    pub state_diff: Vec<String>,
+
+    /// Where the test overflowed, if it reverted with `Panic(0x11)`. Located in the sources with
+    /// a [`SourceLocator`](crate::SourceLocator).
+    ///
+    /// Only recorded for standard (non-fuzz) tests
+    #[serde(skip)]
+    pub overflow: Option<OverflowSite>,
+
+    /// Every opcode executed by the test, to step through it with `forge test --debug`.
+    ///
+    /// Only recorded for standard (non-fuzz) tests, if debugging is enabled
+    #[serde(skip)]
+    pub debug: DebugTrace,
+
+    /// Whether the test skipped itself with `skip(true)`. Skipped tests are successful, but
+    /// did not run to completion.
+    #[serde(default)]
+    pub skipped: bool,
+
     /// What kind of test this was
     pub kind: TestKind,
 }
@@ -118,6 +267,21 @@ impl TestKind {
     }
 }
 
+/// Progress of a contract's test run, reported while the tests are running
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TestEvent<'a> {
+    /// A test function is about to be run
+    TestStarted { test: &'a str },
+    /// A log emitted by a test. Logs are only known once the test's call returned, so they are
+    /// reported right before the test's `TestFinished` event
+    Log { test: &'a str, log: &'a str },
+    /// A test function finished
+    TestFinished { test: &'a str, result: &'a TestResult },
+    /// All matching tests of the contract finished
+    SuiteFinished { passed: usize, failed: usize, skipped: usize, duration_ms: u64 },
+}
+
 pub struct ContractRunner<'a, S, E> {
     /// Mutable reference to the EVM type.
     /// This is a temporary hack to work around the mutability restrictions of
@@ -134,6 +298,24 @@ pub struct ContractRunner<'a, S, E> {
     pub sender: Address,
     /// Any logs emitted in the constructor of the specific contract
     pub init_logs: &'a [String],
+    /// Whether unused cheatcode expectations (e.g. a dangling `expectRevert`) should only be
+    /// reported as warnings instead of failing the test
+    pub allow_unused_expectations: bool,
+    /// The compiled contracts, used to decode the frames of revert traces
+    pub known_contracts: Option<&'a KnownContracts>,
+    /// Calldata recorded from real usage, whose arguments seed the fuzz tests
+    pub corpus: &'a [Bytes],
+    /// Whether every opcode executed by the standard tests is recorded in their results
+    pub debug: bool,
+    /// Whether the decoded call tree of every standard test is recorded in its result
+    pub traces: bool,
+    /// Whether the breakpoints, pranks and state accesses of every standard test, and the revert
+    /// traces of the tests expected to fail, are recorded in their results
+    pub details: bool,
+    /// Whether the state changed by every failed standard test is recorded in its result
+    pub state_diffs: bool,
+    /// Called with every [`TestEvent`] as soon as it happens
+    pub on_event: Option<&'a mut dyn FnMut(TestEvent<'_>)>,
     // need to constrain the trait generic
     state: PhantomData<S>,
 }
@@ -151,6 +333,14 @@ impl<'a, S, E> ContractRunner<'a, S, E> {
             contract,
             address,
             init_logs,
+            allow_unused_expectations: false,
+            known_contracts: None,
+            corpus: &[],
+            debug: false,
+            traces: false,
+            details: false,
+            state_diffs: false,
+            on_event: None,
             state: PhantomData,
             sender: sender.unwrap_or_default(),
         }
@@ -181,10 +371,13 @@ impl<'a, S: Clone, E: Evm<S>> ContractRunner<'a, S, E> {
             .iter()
             .filter(|func| func.inputs.is_empty())
             .map(|func| {
+                let signature = func.signature();
+                self.emit(TestEvent::TestStarted { test: &signature });
                 // Before each test run executes, ensure we're at our initial state.
                 self.evm.reset(init_state.clone());
                 let result = self.run_test(func, needs_setup)?;
-                Ok((func.signature(), result))
+                self.emit_finished(&signature, &result);
+                Ok((signature, result))
             })
             .collect::<Result<BTreeMap<_, _>>>()?;
 
@@ -193,8 +386,11 @@ impl<'a, S: Clone, E: Evm<S>> ContractRunner<'a, S, E> {
                 .iter()
                 .filter(|func| !func.inputs.is_empty())
                 .map(|func| {
+                    let signature = func.signature();
+                    self.emit(TestEvent::TestStarted { test: &signature });
                     let result = self.run_fuzz_test(func, needs_setup, fuzzer.clone())?;
-                    Ok((func.signature(), result))
+                    self.emit_finished(&signature, &result);
+                    Ok((signature, result))
                 })
                 .collect::<Result<BTreeMap<_, _>>>()?;
 
@@ -207,12 +403,33 @@ impl<'a, S: Clone, E: Evm<S>> ContractRunner<'a, S, E> {
 
         if !map.is_empty() {
             let successful = map.iter().filter(|(_, tst)| tst.success).count();
+            let skipped = map.iter().filter(|(_, tst)| tst.skipped).count();
             let duration = Instant::now().duration_since(start);
             tracing::info!(?duration, "done. {}/{} successful", successful, map.len());
+            self.emit(TestEvent::SuiteFinished {
+                passed: successful - skipped,
+                failed: map.len() - successful,
+                skipped,
+                duration_ms: duration.as_millis() as u64,
+            });
         }
         Ok(map)
     }
 
+    fn emit(&mut self, event: TestEvent<'_>) {
+        if let Some(on_event) = self.on_event.as_mut() {
+            on_event(event);
+        }
+    }
+
+    /// Reports the logs of a finished test, followed by its result
+    fn emit_finished(&mut self, test: &str, result: &TestResult) {
+        for log in &result.logs {
+            self.emit(TestEvent::Log { test, log });
+        }
+        self.emit(TestEvent::TestFinished { test, result });
+    }
+
     #[tracing::instrument(name = "test", skip_all, fields(name = %func.signature()))]
     pub fn run_test(&mut self, func: &Function, setup: bool) -> Result<TestResult> {
         let start = Instant::now();
@@ -236,7 +453,10 @@ impl<'a, S: Clone, E: Evm<S>> ContractRunner<'a, S, E> {
             logs.extend_from_slice(&setup_logs);
         }
 
-        let (status, reason, gas_used, logs) = match self.evm.call::<(), _, _>(
+        // the state after `setUp()`, which the test's changes are diffed against
+        let before = self.state_diffs.then(|| self.evm.state().clone());
+
+        let (status, mut reason, gas_used, logs) = match self.evm.call::<(), _, _>(
             self.sender,
             self.address,
             func.clone(),
@@ -258,9 +478,81 @@ impl<'a, S: Clone, E: Evm<S>> ContractRunner<'a, S, E> {
                 }
             },
         };
-        let success = self.evm.check_success(self.address, &status, should_fail);
+
+        // `skip(true)` halts the test with a revert, but the test neither passes nor fails
+        let skipped = reason.as_deref() == Some(SKIP_REASON);
+        if skipped {
+            reason = None;
+        }
+
+        // grab the trace before `check_success` executes any other calls. The revert of a test
+        // expected to fail is usually its success, whose trace is only shown in detail
+        let revert_trace = if reason.is_some() && (!should_fail || self.details) {
+            let default = KnownContracts::default();
+            let known_contracts = self.known_contracts.unwrap_or(&default);
+            let mut lines = Vec::new();
+            for frame in self.evm.revert_trace() {
+                lines.push(known_contracts.format_located_frame(&frame));
+                if !frame.internal_calls.is_empty() {
+                    let code = self.evm.code(frame.address);
+                    lines.extend(known_contracts.format_internal_calls(&frame, code.as_ref()));
+                }
+            }
+            lines
+        } else {
+            Vec::new()
+        };
+
+        let gas_sections = self.evm.gas_sections();
+        let deprecations = self.evm.deprecations();
+        // breakpoints are also where the debugger stops
+        let breakpoints =
+            if self.details || self.debug { self.evm.breakpoints() } else { Vec::new() };
+        let pranks = if self.details { self.evm.pranks() } else { Vec::new() };
+        // the site of an arithmetic overflow is recorded with the calls, and only reverts
+        let call_trace = if self.traces || self.details || reason.is_some() {
+            self.evm.call_trace()
+        } else {
+            CallTrace::default()
+        };
+        let accesses = call_trace.access_summary();
+        let traces = match self.known_contracts.filter(|_| self.traces) {
+            Some(known_contracts) => {
+                known_contracts.format_call_trace(&call_trace).lines().map(String::from).collect()
+            }
+            None => Vec::new(),
+        };
+        let overflow = call_trace.overflow;
+        let debug = if self.debug { self.evm.debug_trace() } else { DebugTrace::default() };
+        let state_diff = before.map(|before| self.evm.state_diff(&before));
+        let unused_expectations = self.evm.unused_expectations();
+        let warnings = if self.allow_unused_expectations {
+            self.evm.clear_expectations();
+            unused_expectations
+        } else {
+            if !unused_expectations.is_empty() && reason.is_none() && !skipped {
+                reason = Some(unused_expectations.join(", "));
+            }
+            Vec::new()
+        };
+
+        let success = skipped || self.evm.check_success(self.address, &status, should_fail);
         let duration = Instant::now().duration_since(start);
-        tracing::debug!(?duration, %success, %gas_used);
+        tracing::debug!(?duration, %success, %skipped, %gas_used);
+
+        let state_diff = match state_diff.filter(|_| !success) {
+            Some(mut diff) => {
+                // the sender's nonce is incremented by the call of the test itself
+                if let Some(sender) = diff.accounts.get_mut(&self.sender) {
+                    sender.nonce = None;
+                }
+                diff.accounts.retain(|_, account| !account.is_empty());
+                let default = KnownContracts::default();
+                let known_contracts = self.known_contracts.unwrap_or(&default);
+                known_contracts.format_state_diff(&diff).lines().map(String::from).collect()
+            }
+            None => Vec::new(),
+        };
 
         Ok(TestResult {
             success,
@@ -268,10 +560,97 @@ impl<'a, S: Clone, E: Evm<S>> ContractRunner<'a, S, E> {
             gas_used,
             counterexample: None,
             logs,
+            gas_sections,
+            warnings,
+            deprecations,
+            revert_trace,
+            breakpoints,
+            pranks,
+            accesses,
+            traces,
+            state_diff,
+            overflow,
+            debug,
+            skipped,
             kind: TestKind::Standard(gas_used),
         })
     }
 
+    /// Runs all `bench*` functions without arguments whose names match the provided regular
+    /// expression, `warmup + iterations` times each
+    pub fn run_benches(
+        &mut self,
+        regex: &Regex,
+        init_state: &S,
+        warmup: usize,
+        iterations: usize,
+    ) -> Result<BTreeMap<String, BenchResult>> {
+        let needs_setup = self.contract.functions().any(|func| func.name == "setUp");
+        self.contract
+            .functions()
+            .filter(|func| func.name.starts_with("bench") && func.inputs.is_empty())
+            .filter(|func| regex.is_match(&func.name))
+            .map(|func| {
+                let result = self.run_bench(func, needs_setup, init_state, warmup, iterations)?;
+                Ok((func.signature(), result))
+            })
+            .collect()
+    }
+
+    /// Repeatedly executes the benchmark function. Every iteration starts from the state right
+    /// after `setUp()`, and the first `warmup` iterations are not measured (e.g. so that a
+    /// forked backend has its cache populated).
+    #[tracing::instrument(name = "bench", skip_all, fields(name = %func.signature()))]
+    pub fn run_bench(
+        &mut self,
+        func: &Function,
+        setup: bool,
+        init_state: &S,
+        warmup: usize,
+        iterations: usize,
+    ) -> Result<BenchResult> {
+        self.evm.reset(init_state.clone());
+        if setup {
+            self.evm.setup(self.address)?;
+        }
+        let bench_state = self.evm.state().clone();
+
+        let mut gas = Vec::with_capacity(iterations);
+        let mut time = Vec::with_capacity(iterations);
+        for i in 0..warmup + iterations {
+            self.evm.reset(bench_state.clone());
+
+            let start = Instant::now();
+            let res =
+                self.evm.call::<(), _, _>(self.sender, self.address, func.clone(), (), 0.into());
+            let elapsed = start.elapsed();
+
+            let (status, gas_used) = match res {
+                Ok((_, status, gas_used, _)) => (status, gas_used),
+                Err(EvmError::Execution { reason, .. }) => {
+                    return Ok(BenchResult::failed(Some(reason)))
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if !self.evm.check_success(self.address, &status, false) {
+                return Ok(BenchResult::failed(None))
+            }
+
+            if i >= warmup {
+                gas.push(gas_used as f64);
+                time.push(elapsed.as_secs_f64() * 1_000_000.0);
+            }
+        }
+
+        Ok(BenchResult {
+            success: true,
+            reason: None,
+            iterations,
+            gas: Stats::new(&gas),
+            time_us: Stats::new(&time),
+        })
+    }
+
     #[tracing::instrument(name = "fuzz-test", skip_all, fields(name = %func.signature()))]
     pub fn run_fuzz_test(
         &mut self,
@@ -289,20 +668,23 @@ impl<'a, S: Clone, E: Evm<S>> ContractRunner<'a, S, E> {
         }
 
         // instantiate the fuzzed evm in line
-        let evm = FuzzedExecutor::new(self.evm, runner, self.sender);
-        let FuzzTestResult { cases, test_error } = evm.fuzz(func, self.address, should_fail);
-
-        let success = test_error.is_none();
+        let types = self.known_contracts.map(|known| known.type_definitions().clone());
+        let evm = FuzzedExecutor::new(self.evm, runner, self.sender)
+            .allow_unused_expectations(self.allow_unused_expectations)
+            .type_definitions(types.unwrap_or_default())
+            .corpus(self.corpus.to_vec());
+        let FuzzTestResult { cases, test_error, warnings, deprecations } =
+            evm.fuzz(func, self.address, should_fail);
+
+        let skipped = test_error.as_ref().map_or(false, |err| err.revert_reason == SKIP_REASON);
+        let success = test_error.is_none() || skipped;
         let mut counterexample = None;
         let mut reason = None;
-        if let Some(err) = test_error {
+        if let Some(err) = test_error.filter(|_| !skipped) {
             match err.test_error {
                 TestError::Fail(_, value) => {
-                    // skip the function selector when decoding
-                    let args = func.decode_input(&value.as_ref()[4..])?;
-                    let counter = CounterExample { calldata: value.clone(), args };
-                    counterexample = Some(counter);
                     tracing::info!("Found minimal failing case: {}", hex::encode(&value));
+                    counterexample = Some(CounterExample::new(func, self.address, value)?);
                 }
                 result => panic!("Unexpected test result: {:?}", result),
             }
@@ -319,6 +701,18 @@ impl<'a, S: Clone, E: Evm<S>> ContractRunner<'a, S, E> {
             gas_used: cases.median_gas(),
             counterexample,
             logs: vec![],
+            gas_sections: BTreeMap::new(),
+            warnings,
+            deprecations,
+            revert_trace: Vec::new(),
+            breakpoints: Vec::new(),
+            pranks: Vec::new(),
+            accesses: Default::default(),
+            traces: Vec::new(),
+            state_diff: Vec::new(),
+            overflow: None,
+            debug: Default::default(),
+            skipped,
             kind: TestKind::Fuzz(cases),
         })
     }
@@ -374,6 +768,29 @@ mod tests {
             assert!(results["testGreeting(string,string)"].success);
         }
 
+        #[test]
+        fn test_benches() {
+            let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
+            let mut evm = vm();
+            let (addr, _, _, _) = evm
+                .deploy(Address::zero(), compiled.bytecode().unwrap().clone(), 0.into())
+                .unwrap();
+
+            let init_state = evm.state().clone();
+
+            let mut runner =
+                ContractRunner::new(&mut evm, compiled.abi.as_ref().unwrap(), addr, None, &[]);
+
+            let results =
+                runner.run_benches(&Regex::from_str(".*").unwrap(), &init_state, 1, 5).unwrap();
+            let result = &results["benchGreeting()"];
+            assert!(result.success);
+            assert_eq!(result.iterations, 5);
+            // every iteration starts from the same state, so the gas is deterministic
+            assert!(result.gas.mean > 0.0);
+            assert_eq!(result.gas.stddev, 0.0);
+        }
+
         #[test]
         fn test_fuzzing_counterexamples() {
             let compiled = COMPILED.find("GreeterTest").expect("could not find contract");
@@ -460,6 +877,30 @@ mod tests {
             let product_without_shrinking = args[0].saturating_mul(args[1]);
             assert!(product_without_shrinking > product_with_shrinking.into());
         }
+
+        #[test]
+        fn test_counterexample_reproduction() {
+            let func = get_func("function testShrinking(uint256 x, uint256 y) public").unwrap();
+            let calldata = func.encode_input(&[Token::Uint(3.into()), Token::Uint(5.into())]);
+            let counterexample =
+                CounterExample::new(&func, Address::zero(), calldata.unwrap().into()).unwrap();
+            assert_eq!(counterexample.signature, "testShrinking(uint256,uint256)");
+            assert_eq!(
+                counterexample.cast,
+                "cast send 0x0000000000000000000000000000000000000000 \
+                 'testShrinking(uint256,uint256)' '3' '5'"
+            );
+            assert_eq!(
+                counterexample.solidity,
+                "    function testShrinkingRepro() public {\n        this.testShrinking(3, 5);\n    }"
+            );
+
+            let func = get_func("function testBytes(bytes data) public").unwrap();
+            let calldata = func.encode_input(&[Token::Bytes(vec![1])]).unwrap();
+            let counterexample =
+                CounterExample::new(&func, Address::zero(), calldata.clone().into()).unwrap();
+            assert!(counterexample.solidity.contains(&hex::encode(&calldata)));
+        }
     }
 
     mod evmodin {
@@ -0,0 +1,307 @@
+//! Mapping of program counters back to the sources they were compiled from, used to point at the
+//! expression which made a test fail and to follow calls of internal functions
+use ethers::{
+    solc::{ArtifactOutput, CompilerOutput, Project},
+    types::H256,
+    utils::keccak256,
+};
+use evm_adapters::trace::{InternalJumps, OverflowSite};
+use eyre::Result;
+use std::{collections::BTreeMap, fmt, fs, path::PathBuf};
+
+/// An entry of a solc source map, i.e. the source range an instruction was generated from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SourceElement {
+    /// The byte offset of the range in the source file
+    pub offset: usize,
+    /// The length of the range in bytes
+    pub length: usize,
+    /// The id of the source file, `None` if the instruction was not generated from any source
+    pub index: Option<usize>,
+    /// Whether the instruction jumps into or out of an internal function
+    pub jump: Jump,
+}
+
+/// The jump type of a source map entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Jump {
+    /// A jump into an internal function (`i`)
+    In,
+    /// A jump returning from an internal function (`o`)
+    Out,
+    /// Any other instruction (`-`)
+    Regular,
+}
+
+impl Default for Jump {
+    fn default() -> Self {
+        Jump::Regular
+    }
+}
+
+/// Decodes a compressed source map, which has one `offset:length:index:jump:modifier_depth`
+/// entry per instruction, separated by `;`. Empty or missing fields repeat the previous entry's
+pub fn parse_source_map(map: &str) -> Vec<SourceElement> {
+    let mut element = SourceElement::default();
+    let mut index = -1i64;
+    map.split(';')
+        .map(|entry| {
+            let mut fields = entry.split(':');
+            if let Some(offset) = fields.next().and_then(|field| field.parse().ok()) {
+                element.offset = offset;
+            }
+            if let Some(length) = fields.next().and_then(|field| field.parse().ok()) {
+                element.length = length;
+            }
+            if let Some(idx) = fields.next().and_then(|field| field.parse().ok()) {
+                index = idx;
+            }
+            match fields.next() {
+                Some("i") => element.jump = Jump::In,
+                Some("o") => element.jump = Jump::Out,
+                Some("-") => element.jump = Jump::Regular,
+                _ => {}
+            }
+            element.index = usize::try_from(index).ok();
+            element
+        })
+        .collect()
+}
+
+/// Returns the index of the instruction starting at each program counter of the code, which is
+/// what source maps are indexed by. Immediates of `PUSH` opcodes are not instructions
+pub(crate) fn instruction_indices(code: &[u8]) -> Vec<Option<usize>> {
+    let mut indices = vec![None; code.len()];
+    let (mut pc, mut index) = (0, 0);
+    while pc < code.len() {
+        indices[pc] = Some(index);
+        let opcode = code[pc];
+        if (0x60..=0x7f).contains(&opcode) {
+            pc += (opcode - 0x5f) as usize;
+        }
+        pc += 1;
+        index += 1;
+    }
+    indices
+}
+
+/// The source range an instruction was generated from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub path: PathBuf,
+    /// The line of the start of the range, starting at 1
+    pub line: usize,
+    /// The first line of the source code in the range
+    pub snippet: String,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.snippet)
+    }
+}
+
+/// A compiled contract's runtime code and source map
+#[derive(Clone, Debug)]
+pub(crate) struct MappedContract {
+    pub(crate) code: Vec<u8>,
+    pub(crate) source_map: Vec<SourceElement>,
+}
+
+/// Finds the source code that instructions of the compiled contracts were generated from
+#[derive(Clone, Debug, Default)]
+pub struct SourceLocator {
+    contracts: Vec<MappedContract>,
+    /// The path of every source file by its id
+    sources: BTreeMap<usize, PathBuf>,
+}
+
+impl SourceLocator {
+    /// Reads the runtime code and source map of every contract of the compiler output
+    pub fn new(output: &CompilerOutput) -> Self {
+        // the source maps are only needed here, so they are read from their JSON representation
+        // instead of being carried around with every contract
+        let output = serde_json::to_value(output).unwrap_or_default();
+
+        let sources = output["sources"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(path, source)| {
+                Some((source["id"].as_u64()? as usize, PathBuf::from(path)))
+            })
+            .collect();
+
+        let contracts = output["contracts"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .flat_map(|(_, contracts)| contracts.as_object().into_iter().flatten())
+            .filter_map(|(_, contract)| {
+                let deployed = &contract["evm"]["deployedBytecode"];
+                let code = deployed["object"].as_str()?;
+                // unlinked code is not valid hex, and can not have been deployed as is anyway
+                let code = hex::decode(code.strip_prefix("0x").unwrap_or(code)).ok()?;
+                let source_map = parse_source_map(deployed["sourceMap"].as_str()?);
+                (!code.is_empty()).then(|| MappedContract { code, source_map })
+            })
+            .collect();
+
+        Self { contracts, sources }
+    }
+
+    /// Compiles the project and reads the source maps of its contracts. The project must not be
+    /// cached, since the cached artifacts do not include the source maps
+    pub fn compile<A: ArtifactOutput>(project: &Project<A>) -> Result<Self> {
+        let output = project.compile()?;
+        if output.has_compiler_errors() {
+            eyre::bail!(output.to_string())
+        }
+        Ok(Self::new(&output.output()))
+    }
+
+    /// Returns the source location of the expression which overflowed.
+    ///
+    /// solc's overflow checks live in generated helper functions, so the executed instructions
+    /// are walked back to the most recent one which was generated from a user source, i.e. the
+    /// jump into the helper which is mapped to the arithmetic expression.
+    pub fn locate(&self, site: &OverflowSite) -> Option<SourceLocation> {
+        let contract = self.find(&site.code)?;
+        let indices = instruction_indices(&contract.code);
+        site.pcs.iter().rev().find_map(|&pc| self.location(contract, &indices, pc))
+    }
+
+    /// Returns the source location of the instruction at `pc` of the deployed code, e.g. the
+    /// definition of the internal function which starts there
+    pub fn locate_pc(&self, code: &[u8], pc: usize) -> Option<SourceLocation> {
+        let contract = self.find(code)?;
+        self.location(contract, &instruction_indices(&contract.code), pc)
+    }
+
+    /// Returns the jumps into and out of internal functions of every contract's runtime code, by
+    /// the hash of the code
+    pub fn internal_jumps(&self) -> BTreeMap<H256, InternalJumps> {
+        self.contracts
+            .iter()
+            .filter_map(|contract| {
+                let mut jumps = InternalJumps::default();
+                for (pc, index) in instruction_indices(&contract.code).into_iter().enumerate() {
+                    // JUMP
+                    if contract.code[pc] != 0x56 {
+                        continue
+                    }
+                    match index.and_then(|index| contract.source_map.get(index)).map(|e| e.jump) {
+                        Some(Jump::In) => jumps.into.insert(pc),
+                        Some(Jump::Out) => jumps.out_of.insert(pc),
+                        _ => continue,
+                    };
+                }
+                let known = !jumps.into.is_empty();
+                known.then(|| (H256::from(keccak256(&contract.code)), jumps))
+            })
+            .collect()
+    }
+
+    fn location(
+        &self,
+        contract: &MappedContract,
+        indices: &[Option<usize>],
+        pc: usize,
+    ) -> Option<SourceLocation> {
+        let element = contract.source_map.get((*indices.get(pc)?)?)?;
+        if element.length == 0 {
+            return None
+        }
+        let path = self.sources.get(&element.index?)?;
+        let source = fs::read_to_string(path).ok()?;
+        let code = source.get(element.offset..element.offset + element.length)?;
+        let line = source[..element.offset].matches('\n').count() + 1;
+        let mut lines = code.lines();
+        let mut snippet = lines.next().unwrap_or_default().trim().to_string();
+        if lines.next().is_some() {
+            snippet.push_str(" ...");
+        }
+        Some(SourceLocation { path: path.clone(), line, snippet })
+    }
+
+    /// Returns the runtime code and source map of every compiled contract
+    pub(crate) fn contracts(&self) -> &[MappedContract] {
+        &self.contracts
+    }
+
+    /// Returns the path of the source file with the id
+    pub(crate) fn source_path(&self, id: usize) -> Option<&PathBuf> {
+        self.sources.get(&id)
+    }
+
+    /// Finds the contract with the deployed code. Immutables are only filled in on deployment,
+    /// so a contract with the same code length is used if there is no exact match and it is the
+    /// only one of that length
+    fn find(&self, code: &[u8]) -> Option<&MappedContract> {
+        self.position(code).map(|index| &self.contracts[index])
+    }
+
+    /// Like `find`, but returns the index of the contract in `contracts`
+    pub(crate) fn position(&self, code: &[u8]) -> Option<usize> {
+        if let Some(index) = self.contracts.iter().position(|contract| contract.code == code) {
+            return Some(index)
+        }
+        let mut same_len = self
+            .contracts
+            .iter()
+            .enumerate()
+            .filter(|(_, contract)| contract.code.len() == code.len())
+            .map(|(index, _)| index);
+        match (same_len.next(), same_len.next()) {
+            (Some(index), None) => Some(index),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_compressed_source_maps() {
+        let map = parse_source_map("1:2:1;:9;2:1:2:i;;-1::-1:o");
+        let element = |offset, length, index, jump| SourceElement { offset, length, index, jump };
+        assert_eq!(
+            map,
+            vec![
+                element(1, 2, Some(1), Jump::Regular),
+                element(1, 9, Some(1), Jump::Regular),
+                element(2, 1, Some(2), Jump::In),
+                element(2, 1, Some(2), Jump::In),
+                element(2, 1, None, Jump::Out),
+            ]
+        );
+    }
+
+    #[test]
+    fn collects_internal_jumps() {
+        // PUSH1 0x05 JUMP JUMPDEST PUSH1 0x03 JUMP JUMPDEST STOP
+        let code = vec![0x60, 0x05, 0x56, 0x5b, 0x60, 0x03, 0x56, 0x5b, 0x00];
+        let source_map = parse_source_map("0:1:0:-;:::i;:::-;;:::o;:::-;");
+        let locator = SourceLocator {
+            contracts: vec![MappedContract { code: code.clone(), source_map }],
+            sources: Default::default(),
+        };
+        let jumps = locator.internal_jumps();
+        assert_eq!(
+            jumps.get(&H256::from(keccak256(&code))),
+            Some(&InternalJumps {
+                into: [2].into_iter().collect(),
+                out_of: [6].into_iter().collect()
+            })
+        );
+    }
+
+    #[test]
+    fn skips_push_immediates() {
+        // PUSH1 0x80 PUSH2 0x0102 JUMPDEST
+        let indices = instruction_indices(&[0x60, 0x80, 0x61, 0x01, 0x02, 0x5b]);
+        assert_eq!(indices, vec![Some(0), None, Some(1), None, None, Some(2)]);
+    }
+}
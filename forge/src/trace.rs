@@ -0,0 +1,541 @@
+//! Decoding of call frames, e.g. the ones leading to a revert or every call of a transaction
+use crate::{
+    ast::{FunctionSpan, FunctionSpans},
+    SourceLocator,
+};
+use ethers::{
+    abi::{Abi, Function, RawLog},
+    types::{Address, Bytes},
+};
+use evm_adapters::{
+    fuzz::TypeDefinitions,
+    trace::{CallFrame, CallTrace, StateDiff, TracedCall},
+};
+use foundry_utils::{decode_revert, format_token, DisplayUnits};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// The compiled contracts of a project, used to make call frames human readable
+#[derive(Clone, Debug, Default)]
+pub struct KnownContracts {
+    /// Mapping of contract name to its ABI and creation bytecode
+    contracts: BTreeMap<String, (Abi, Bytes)>,
+    /// Mapping of the address of every deployed test contract to its name
+    deployed: BTreeMap<Address, String>,
+    /// Mapping of contract name to the source spans of its functions by selector
+    spans: FunctionSpans,
+    /// The source maps of the freshly compiled contracts, which name the internal functions
+    /// executed by a frame
+    locator: SourceLocator,
+    /// The project root, which the paths of internal functions are made relative to
+    root: PathBuf,
+    /// The enums and structs declared by the compiled sources
+    types: TypeDefinitions,
+    /// The unit in which the values of calls and balances are displayed
+    units: DisplayUnits,
+}
+
+impl KnownContracts {
+    /// Registers a compiled contract
+    pub fn insert(&mut self, name: String, abi: Abi, bytecode: Bytes) {
+        self.contracts.insert(name, (abi, bytecode));
+    }
+
+    /// Registers the address at which a contract was deployed
+    pub fn insert_deployed(&mut self, address: Address, name: String) {
+        self.deployed.insert(address, name);
+    }
+
+    /// Registers the source spans of the functions of compiled contracts
+    pub fn insert_spans(&mut self, spans: FunctionSpans) {
+        self.spans.extend(spans);
+    }
+
+    /// Registers the enums and structs declared by the compiled sources
+    pub fn insert_type_definitions(&mut self, types: TypeDefinitions) {
+        self.types.extend(types);
+    }
+
+    /// Returns the enums and structs declared by the compiled sources
+    pub fn type_definitions(&self) -> &TypeDefinitions {
+        &self.types
+    }
+
+    /// Sets the unit in which the values of calls and balances are displayed, e.g. the native
+    /// token of the chain the calls are made on
+    pub fn set_display_units(&mut self, units: DisplayUnits) {
+        self.units = units;
+    }
+
+    /// Registers the source maps of the compiled contracts, relative to the project root
+    pub fn insert_source_locator(&mut self, locator: SourceLocator, root: &Path) {
+        self.locator = locator;
+        self.root = root.to_path_buf();
+    }
+
+    /// Formats the internal functions the frame was executing, outermost first, as
+    /// `↳ function (src/Contract.sol:12)`. `code` is the runtime code executed by the frame.
+    ///
+    /// The called function itself is entered like an internal function by the dispatcher, and
+    /// is skipped since the frame already shows it.
+    pub fn format_internal_calls(&self, frame: &CallFrame, code: &[u8]) -> Vec<String> {
+        let called = self.called_function(frame).map(|func| func.name.as_str());
+        let (mut lines, mut first) = (Vec::new(), true);
+        for &pc in &frame.internal_calls {
+            // functions are entered at the start of their definition, everything else (e.g.
+            // generated helpers) is left out
+            let location = match self.locator.locate_pc(code, pc) {
+                Some(location) => location,
+                None => continue,
+            };
+            let name = match location.snippet.strip_prefix("function ") {
+                Some(definition) => definition.split('(').next().unwrap_or_default().trim(),
+                None => continue,
+            };
+            if std::mem::take(&mut first) && Some(name) == called {
+                continue
+            }
+            let path = location.path.strip_prefix(&self.root).unwrap_or(&location.path);
+            lines.push(format!("↳ {} ({}:{})", name, path.display(), location.line));
+        }
+        lines
+    }
+
+    /// Returns the source span of the function called by the frame, if it is known
+    pub fn locate_frame(&self, frame: &CallFrame) -> Option<&FunctionSpan> {
+        if frame.create || frame.input.len() < 4 {
+            return None
+        }
+        let selector = &frame.input[..4];
+        let contract = self.contract_name(frame.address, Some(selector));
+        self.spans.get(&contract)?.get(&hex::encode(selector))
+    }
+
+    /// Like `format_frame`, followed by the location of the called function if it is known, e.g.
+    /// `Contract::function(args) (src/Contract.sol:12)`
+    pub fn format_located_frame(&self, frame: &CallFrame) -> String {
+        match self.locate_frame(frame) {
+            Some(span) => format!("{} ({})", self.format_frame(frame), span),
+            None => self.format_frame(frame),
+        }
+    }
+
+    /// Formats a call frame as `Contract::function(args)`, or `new Contract(args)` for contract
+    /// creations.
+    ///
+    /// The contract is identified by its address if it is a deployed test contract, otherwise by
+    /// being the only known contract with a function of the called selector. If it cannot be
+    /// identified, its address is printed instead.
+    pub fn format_frame(&self, frame: &CallFrame) -> String {
+        if frame.create {
+            return self.format_create(&frame.input)
+        }
+
+        if frame.input.len() < 4 {
+            return format!("{}::fallback()", self.contract_name(frame.address, None))
+        }
+        let (selector, args) = frame.input.split_at(4);
+        let contract = self.contract_name(frame.address, Some(selector));
+
+        match self.called_function(frame) {
+            Some(func) => {
+                let args = match func.decode_input(args) {
+                    Ok(tokens) => tokens.iter().map(format_token).collect::<Vec<_>>().join(", "),
+                    Err(_) => format!("0x{}", hex::encode(args)),
+                };
+                format!("{}::{}({})", contract, func.name, args)
+            }
+            None => format!("{}::0x{}", contract, hex::encode(selector)),
+        }
+    }
+
+    /// Renders every call of a transaction as an indented tree with the source location of the
+    /// called function, its decoded result and events, and the accounts and storage slots it
+    /// accessed, marked as cold or warm, followed by the storage slots the transaction changed
+    pub fn format_call_trace(&self, trace: &CallTrace) -> String {
+        let mut lines = Vec::new();
+        for call in &trace.calls {
+            let indent = "  ".repeat(call.depth);
+            let status = if call.success { "✓" } else { "✗" };
+            let value = if call.frame.value.is_zero() {
+                String::new()
+            } else {
+                format!(" {{value: {}}}", self.units.format(call.frame.value))
+            };
+            lines.push(format!(
+                "{}[{}] {}{} → {}",
+                indent,
+                status,
+                self.format_located_frame(&call.frame),
+                value,
+                self.format_output(call)
+            ));
+            for log in &call.logs {
+                lines.push(format!("{}  emit {}", indent, self.format_log(log)));
+            }
+            for access in &call.accesses {
+                let target = match access.slot {
+                    Some(slot) => format!("{:?}", slot),
+                    None => self.contract_name(access.address, None),
+                };
+                let temperature = if access.cold { "cold" } else { "warm" };
+                lines.push(format!("{}  {} {} ({})", indent, access.opcode, target, temperature));
+            }
+        }
+
+        if !trace.storage_diff.is_empty() {
+            lines.push("Storage changes:".to_string());
+            for ((address, slot), (before, after)) in &trace.storage_diff {
+                lines.push(format!(
+                    "  {} @ {:?}: {:?} → {:?}",
+                    self.contract_name(*address, None),
+                    slot,
+                    before,
+                    after
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Renders the changed accounts with their changed balance, nonce, code and storage slots
+    pub fn format_state_diff(&self, diff: &StateDiff) -> String {
+        let mut lines = Vec::new();
+        for (address, account) in &diff.accounts {
+            lines.push(self.contract_name(*address, None));
+            if let Some((before, after)) = account.balance {
+                lines.push(format!(
+                    "  balance: {} → {}",
+                    self.units.format(before),
+                    self.units.format(after)
+                ));
+            }
+            if let Some((before, after)) = account.nonce {
+                lines.push(format!("  nonce: {} → {}", before, after));
+            }
+            if let Some((before, after)) = account.code {
+                lines.push(format!("  code: {} → {} bytes", before, after));
+            }
+            for (slot, (before, after)) in &account.storage {
+                lines.push(format!("  {:?}: {:?} → {:?}", slot, before, after));
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn format_output(&self, call: &TracedCall) -> String {
+        if !call.success {
+            return match decode_revert(&call.output) {
+                Ok(reason) => format!("revert: {}", reason),
+                Err(_) => format!("revert: 0x{}", hex::encode(&call.output)),
+            }
+        }
+        if call.frame.create {
+            return format!("{} bytes of code", call.output.len())
+        }
+        match self.called_function(&call.frame).map(|func| func.decode_output(&call.output)) {
+            Some(Ok(tokens)) => {
+                format!("({})", tokens.iter().map(format_token).collect::<Vec<_>>().join(", "))
+            }
+            _ => format!("0x{}", hex::encode(&call.output)),
+        }
+    }
+
+    /// Formats an event as `Event(name: value, ..)` if any known contract declares it, otherwise
+    /// as its raw topics and data
+    fn format_log(&self, log: &RawLog) -> String {
+        let event = log.topics.first().and_then(|topic| {
+            self.contracts
+                .values()
+                .find_map(|(abi, _)| abi.events().find(|event| event.signature() == *topic))
+        });
+        match event.map(|event| (event, event.parse_log(log.clone()))) {
+            Some((event, Ok(parsed))) => {
+                let params = parsed
+                    .params
+                    .iter()
+                    .map(|param| format!("{}: {}", param.name, format_token(&param.value)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{}({})", event.name, params)
+            }
+            _ => {
+                let topics = log.topics.iter().map(|topic| format!("{:?}", topic));
+                format!(
+                    "log(topics: [{}], data: 0x{})",
+                    topics.collect::<Vec<_>>().join(", "),
+                    hex::encode(&log.data)
+                )
+            }
+        }
+    }
+
+    /// Returns the function called by `frame`, looked up in the ABI of the called contract if it
+    /// is a deployed test contract, otherwise in any contract with a function of that selector
+    fn called_function(&self, frame: &CallFrame) -> Option<&Function> {
+        if frame.create || frame.input.len() < 4 {
+            return None
+        }
+        let selector = &frame.input[..4];
+        self.deployed
+            .get(&frame.address)
+            .and_then(|name| self.contracts.get(name))
+            .and_then(|(abi, _)| find_function(abi, selector))
+            .or_else(|| self.contracts.values().find_map(|(abi, _)| find_function(abi, selector)))
+    }
+
+    fn format_create(&self, init_code: &[u8]) -> String {
+        let created = self.contracts.iter().find(|(_, (_, bytecode))| {
+            !bytecode.as_ref().is_empty() && init_code.starts_with(bytecode.as_ref())
+        });
+        match created {
+            Some((name, (abi, bytecode))) => {
+                let args = &init_code[bytecode.as_ref().len()..];
+                let args = match abi.constructor() {
+                    Some(constructor) => {
+                        let params =
+                            constructor.inputs.iter().map(|p| p.kind.clone()).collect::<Vec<_>>();
+                        match ethers::abi::decode(&params, args) {
+                            Ok(tokens) => {
+                                tokens.iter().map(format_token).collect::<Vec<_>>().join(", ")
+                            }
+                            Err(_) => format!("0x{}", hex::encode(args)),
+                        }
+                    }
+                    None => String::new(),
+                };
+                format!("new {}({})", name, args)
+            }
+            None => "new <unknown>()".to_string(),
+        }
+    }
+
+    fn contract_name(&self, address: Address, selector: Option<&[u8]>) -> String {
+        if let Some(name) = self.deployed.get(&address) {
+            return name.clone()
+        }
+
+        if let Some(selector) = selector {
+            let mut candidates = self
+                .contracts
+                .iter()
+                .filter(|(_, (abi, _))| find_function(abi, selector).is_some())
+                .map(|(name, _)| name);
+            if let (Some(name), None) = (candidates.next(), candidates.next()) {
+                return name.clone()
+            }
+        }
+
+        format!("{:?}", address)
+    }
+}
+
+fn find_function<'a>(abi: &'a Abi, selector: &[u8]) -> Option<&'a Function> {
+    abi.functions().find(|func| func.short_signature()[..] == *selector)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        abi::{parse_abi, Token},
+        types::{H256, U256},
+    };
+    use evm_adapters::trace::{AccountDiff, StateAccess};
+    use foundry_utils::NativeToken;
+
+    fn known_contracts() -> KnownContracts {
+        let mut known = KnownContracts::default();
+        known.insert(
+            "Greeter".to_string(),
+            parse_abi(&["function greet(string)", "function gm()"]).unwrap(),
+            vec![0xaa, 0xbb].into(),
+        );
+        known.insert(
+            "GreeterTest".to_string(),
+            parse_abi(&["function testGreeting()", "function gm()"]).unwrap(),
+            vec![0xcc].into(),
+        );
+        known.insert_deployed(Address::from_low_u64_be(1), "GreeterTest".to_string());
+        known
+    }
+
+    fn call(address: u64, sig: &str, args: Vec<u8>) -> CallFrame {
+        let func = foundry_utils::get_func(sig).unwrap();
+        let mut input = func.short_signature().to_vec();
+        input.extend(args);
+        CallFrame { address: Address::from_low_u64_be(address), input, ..Default::default() }
+    }
+
+    #[test]
+    fn formats_frames() {
+        let known = known_contracts();
+
+        // deployed test contract
+        let frame = call(1, "function testGreeting()", vec![]);
+        assert_eq!(known.format_frame(&frame), "GreeterTest::testGreeting()");
+
+        // identified by a unique selector
+        let frame = call(
+            2,
+            "function greet(string)",
+            ethers::abi::encode(&[Token::String("yo".to_string())]),
+        );
+        assert_eq!(known.format_frame(&frame), "Greeter::greet(\"yo\")");
+
+        // ambiguous selector
+        let frame = call(2, "function gm()", vec![]);
+        assert_eq!(known.format_frame(&frame), format!("{:?}::gm()", Address::from_low_u64_be(2)));
+
+        // contract creation
+        let frame = CallFrame { input: vec![0xaa, 0xbb], create: true, ..Default::default() };
+        assert_eq!(known.format_frame(&frame), "new Greeter()");
+    }
+
+    #[test]
+    fn locates_frames() {
+        let mut known = known_contracts();
+        let span =
+            FunctionSpan { path: "src/Greeter.sol".to_string(), offset: 10, length: 5, line: 3 };
+        let greet = hex::encode(
+            foundry_utils::get_func("function greet(string)").unwrap().short_signature(),
+        );
+        known.insert_spans(BTreeMap::from([(
+            "Greeter".to_string(),
+            BTreeMap::from([(greet, span.clone())]),
+        )]));
+
+        let frame = call(
+            2,
+            "function greet(string)",
+            ethers::abi::encode(&[Token::String("yo".to_string())]),
+        );
+        assert_eq!(known.locate_frame(&frame), Some(&span));
+        assert_eq!(
+            known.format_located_frame(&frame),
+            "Greeter::greet(\"yo\") (src/Greeter.sol:3)"
+        );
+
+        // no span was recorded for the test contract
+        let frame = call(1, "function testGreeting()", vec![]);
+        assert_eq!(known.format_located_frame(&frame), "GreeterTest::testGreeting()");
+    }
+
+    #[test]
+    fn formats_call_traces() {
+        let mut known = known_contracts();
+        known.insert(
+            "Token".to_string(),
+            parse_abi(&[
+                "function balanceOf(address) returns (uint256)",
+                "event Approval(address indexed owner, uint256 amount)",
+            ])
+            .unwrap(),
+            vec![0xdd].into(),
+        );
+        let span =
+            FunctionSpan { path: "src/Token.sol".to_string(), offset: 0, length: 5, line: 7 };
+        let selector = foundry_utils::get_func("function balanceOf(address)").unwrap();
+        known.insert_spans(BTreeMap::from([(
+            "Token".to_string(),
+            BTreeMap::from([(hex::encode(selector.short_signature()), span)]),
+        )]));
+        let approval = RawLog {
+            topics: vec![
+                ethers::utils::id("Approval(address,uint256)").into(),
+                H256::from(Address::from_low_u64_be(1)),
+            ],
+            data: ethers::abi::encode(&[Token::Uint(5.into())]),
+        };
+
+        let mut balance_of = call(
+            2,
+            "function balanceOf(address)",
+            ethers::abi::encode(&[Token::Address(Address::from_low_u64_be(1))]),
+        );
+        balance_of.value = U256::exp10(18) * 3 / 2;
+        known.set_display_units(DisplayUnits::native(NativeToken::of(137)));
+        let trace = CallTrace {
+            calls: vec![
+                TracedCall {
+                    depth: 0,
+                    frame: call(1, "function testGreeting()", vec![]),
+                    success: false,
+                    output: ethers::abi::encode(&[Token::String("boom".to_string())]),
+                    accesses: vec![StateAccess {
+                        opcode: "CALL".to_string(),
+                        address: Address::from_low_u64_be(2),
+                        slot: None,
+                        cold: true,
+                    }],
+                    ..Default::default()
+                },
+                TracedCall {
+                    depth: 1,
+                    frame: balance_of,
+                    success: true,
+                    output: ethers::abi::encode(&[Token::Uint(1337.into())]),
+                    accesses: vec![StateAccess {
+                        opcode: "SLOAD".to_string(),
+                        address: Address::from_low_u64_be(2),
+                        slot: Some(Default::default()),
+                        cold: false,
+                    }],
+                    logs: vec![approval],
+                    ..Default::default()
+                },
+            ],
+            storage_diff: BTreeMap::from([(
+                (Address::from_low_u64_be(1), Default::default()),
+                (Default::default(), ethers::types::H256::from_low_u64_be(1)),
+            )]),
+            overflow: None,
+        };
+
+        let rendered = known.format_call_trace(&trace);
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "[✗] GreeterTest::testGreeting() → revert: boom");
+        assert_eq!(lines[1], format!("  CALL {:?} (cold)", Address::from_low_u64_be(2)));
+        assert_eq!(
+            lines[2],
+            format!(
+                "  [✓] Token::balanceOf({:?}) (src/Token.sol:7) {{value: 1.5 MATIC}} → (1337)",
+                Address::from_low_u64_be(1)
+            )
+        );
+        assert_eq!(
+            lines[3],
+            format!("    emit Approval(owner: {:?}, amount: 5)", Address::from_low_u64_be(1))
+        );
+        assert_eq!(lines[4], format!("    SLOAD {:?} (warm)", H256::zero()));
+        assert_eq!(lines[5], "Storage changes:");
+        assert!(lines[6].starts_with("  GreeterTest @ 0x0000"));
+    }
+
+    #[test]
+    fn formats_state_diffs() {
+        let known = known_contracts();
+        let account = AccountDiff {
+            balance: Some((1.into(), 0.into())),
+            code: Some((0, 2)),
+            storage: BTreeMap::from([(H256::zero(), (H256::zero(), H256::from_low_u64_be(1)))]),
+            ..Default::default()
+        };
+        let diff = StateDiff { accounts: BTreeMap::from([(Address::from_low_u64_be(1), account)]) };
+
+        let rendered = known.format_state_diff(&diff);
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert_eq!(lines[..3], ["GreeterTest", "  balance: 1 → 0", "  code: 0 → 2 bytes"]);
+
+        let mut known = known;
+        known.set_display_units("gwei".parse().unwrap());
+        let rendered = known.format_state_diff(&diff);
+        assert_eq!(rendered.lines().nth(1), Some("  balance: 0.000000001 gwei → 0 gwei"));
+        assert_eq!(
+            lines[3],
+            format!("  {:?}: {:?} → {:?}", H256::zero(), H256::zero(), H256::from_low_u64_be(1))
+        );
+    }
+}
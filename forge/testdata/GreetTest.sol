@@ -31,6 +31,10 @@ contract GreeterTest is GreeterTestSetup {
         greeter.greet(_greeting);
     }
 
+    function benchGreeting() public {
+        greeter.greet("gm");
+    }
+
     function testShrinking(uint256 x, uint256 y) public {
         require(x * y <= 100, "product greater than 100");
     }
@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.8.1;
+
+contract Counter {
+    uint256 public count;
+
+    function add(uint256 amount) public {
+        count = count + amount;
+    }
+}
+
+contract OverflowTest {
+    Counter counter;
+
+    function setUp() public {
+        counter = new Counter();
+    }
+
+    function testFailOverflow() public {
+        counter.add(1);
+        counter.add(type(uint256).max);
+    }
+}
@@ -0,0 +1,42 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+contract Reverter {
+    function fail(uint256 x) public pure {
+        check(x);
+    }
+
+    function check(uint256 x) internal pure {
+        require(x == 0, "x is not zero");
+    }
+}
+
+contract Middle {
+    Reverter reverter;
+
+    constructor(Reverter _reverter) {
+        reverter = _reverter;
+    }
+
+    function forward(uint256 x) public view {
+        reverter.fail(x);
+    }
+}
+
+contract RevertTraceTest {
+    Middle middle;
+
+    function setUp() public {
+        middle = new Middle(new Reverter());
+    }
+
+    function testFailRevertTrace() public {
+        middle.forward(1);
+    }
+}
+
+contract ConstructorReverter {
+    constructor() {
+        revert("can only be deployed by a test");
+    }
+}
@@ -0,0 +1,9 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+contract SkipTest {
+    function testSkip() public pure {
+        // what `skip(true)` reverts with
+        revert("FOUNDRY::SKIP");
+    }
+}
@@ -3,11 +3,16 @@ use ethers_core::{
     abi::{
         self, parse_abi,
         token::{LenientTokenizer, StrictTokenizer, Tokenizer},
-        AbiParser, Function, ParamType, Token,
+        AbiParser, Event, Function, ParamType, Token,
+    },
+    types::{transaction::eip2930::AccessListItem, *},
+    utils::{
+        keccak256,
+        rlp::{self, Rlp, RlpStream},
     },
-    types::*,
 };
 use eyre::{Result, WrapErr};
+use rustc_hex::ToHex;
 
 const BASE_TX_COST: u64 = 21000;
 
@@ -59,8 +64,14 @@ pub fn remove_extra_costs(gas: U256, calldata: &[u8]) -> U256 {
 }
 
 /// Given an ABI encoded error string with the function signature `Error(string)`, it decodes
-/// it and returns the revert error message.
+/// it and returns the revert error message. A `Panic(uint256)` is rendered as its code and what
+/// it stands for.
 pub fn decode_revert(error: &[u8]) -> std::result::Result<String, ethers_core::abi::Error> {
+    if let Some(code) = error.strip_prefix(&ethers_core::utils::id("Panic(uint256)")) {
+        let code = abi::decode(&[abi::ParamType::Uint(256)], code)?[0].clone().into_uint();
+        let code = code.unwrap_or_default();
+        return Ok(format!("Panic(0x{:x}): {}", code, panic_reason(code)))
+    }
     let error = error.strip_prefix(&ethers_core::utils::id("Error(string)")).unwrap_or(error);
     if !error.is_empty() {
         Ok(abi::decode(&[abi::ParamType::String], error)?[0].to_string())
@@ -69,6 +80,25 @@ pub fn decode_revert(error: &[u8]) -> std::result::Result<String, ethers_core::a
     }
 }
 
+/// Returns what the `Panic(uint256)` code which solc's runtime checks revert with stands for
+fn panic_reason(code: U256) -> &'static str {
+    if code > U256::from(u8::MAX) {
+        return "unknown panic"
+    }
+    match code.low_u64() {
+        0x01 => "assertion failed",
+        0x11 => "arithmetic underflow or overflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array encoding",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "memory allocation overflow",
+        0x51 => "call to uninitialized internal function",
+        _ => "unknown panic",
+    }
+}
+
 /// Given a k/v serde object, it pretty prints its keys and values as a table.
 pub fn to_table(value: serde_json::Value) -> String {
     match value {
@@ -95,6 +125,17 @@ pub fn get_func(sig: &str) -> Result<Function> {
     Ok(func.clone())
 }
 
+/// Given an event signature string, e.g. `Transfer(address indexed,address indexed,uint256)`, it
+/// tries to parse it as an `Event`. The `event` keyword is optional.
+pub fn get_event(sig: &str) -> Result<Event> {
+    let sig = sig.trim();
+    let sig = if sig.starts_with("event ") { sig.to_string() } else { format!("event {}", sig) };
+    let abi = parse_abi(&[&sig])?;
+    let (_, event) = abi.events.iter().next().ok_or_else(|| eyre::eyre!("event name not found"))?;
+    let event = event.get(0).ok_or_else(|| eyre::eyre!("events array empty"))?;
+    Ok(event.clone())
+}
+
 /// Parses string input as Token against the expected ParamType
 pub fn parse_tokens<'a, I: IntoIterator<Item = (&'a ParamType, &'a str)>>(
     params: I,
@@ -125,3 +166,483 @@ pub fn encode_args(func: &Function, args: &[impl AsRef<str>]) -> Result<Vec<u8>>
     let tokens = parse_tokens(params, true)?;
     Ok(func.encode_input(&tokens)?)
 }
+
+/// Pretty-prints a token the way it would be written in Solidity, e.g. `"gm"` for strings and
+/// `(1, [0x01, 0x02])` for tuples
+pub fn format_token(token: &Token) -> String {
+    match token {
+        Token::Address(addr) => ethers_core::utils::to_checksum(addr, None),
+        Token::FixedBytes(bytes) | Token::Bytes(bytes) => format!("0x{}", bytes.to_hex::<String>()),
+        Token::Int(num) => I256::from_raw(*num).to_string(),
+        Token::Uint(num) => num.to_string(),
+        Token::Bool(b) => b.to_string(),
+        Token::String(s) => format!("{:?}", s),
+        Token::FixedArray(tokens) | Token::Array(tokens) => {
+            format!("[{}]", tokens.iter().map(format_token).collect::<Vec<_>>().join(", "))
+        }
+        Token::Tuple(tokens) => {
+            format!("({})", tokens.iter().map(format_token).collect::<Vec<_>>().join(", "))
+        }
+    }
+}
+
+/// Returns the base URL of the block explorer of the chain with the given id, if it is known
+pub fn explorer_url(chain_id: u64) -> Option<&'static str> {
+    Some(match chain_id {
+        1 => "https://etherscan.io",
+        3 => "https://ropsten.etherscan.io",
+        4 => "https://rinkeby.etherscan.io",
+        5 => "https://goerli.etherscan.io",
+        42 => "https://kovan.etherscan.io",
+        10 => "https://optimistic.etherscan.io",
+        56 => "https://bscscan.com",
+        97 => "https://testnet.bscscan.com",
+        100 => "https://blockscout.com/xdai/mainnet",
+        137 => "https://polygonscan.com",
+        80001 => "https://mumbai.polygonscan.com",
+        250 => "https://ftmscan.com",
+        42161 => "https://arbiscan.io",
+        43114 => "https://snowtrace.io",
+        _ => return None,
+    })
+}
+
+/// Returns the block explorer URL of a transaction
+pub fn explorer_tx_url(chain_id: u64, hash: TxHash) -> Option<String> {
+    explorer_url(chain_id).map(|url| format!("{}/tx/{:?}", url, hash))
+}
+
+/// Returns the block explorer URL of an address
+pub fn explorer_address_url(chain_id: u64, address: Address) -> Option<String> {
+    explorer_url(chain_id).map(|url| format!("{}/address/{:?}", url, address))
+}
+
+/// The token in which a chain's balances, values and fees are denominated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NativeToken {
+    pub symbol: &'static str,
+    pub decimals: u32,
+}
+
+impl NativeToken {
+    /// Returns the native token of the chain with the given id, ether for unknown chains
+    pub fn of(chain_id: u64) -> Self {
+        let (symbol, decimals) = match chain_id {
+            25 => ("CRO", 18),
+            30 | 31 => ("RBTC", 18),
+            56 | 97 => ("BNB", 18),
+            100 => ("xDAI", 18),
+            137 | 80001 => ("MATIC", 18),
+            250 | 4002 => ("FTM", 18),
+            1284 => ("GLMR", 18),
+            1285 => ("MOVR", 18),
+            42220 | 44787 => ("CELO", 18),
+            43113 | 43114 => ("AVAX", 18),
+            1666600000 | 1666700000 => ("ONE", 18),
+            _ => ("ETH", 18),
+        };
+        Self { symbol, decimals }
+    }
+
+    /// Formats an amount given in the token's smallest unit, e.g. wei, as a decimal number
+    /// followed by the token's symbol, e.g. `1.5 MATIC`
+    pub fn format(&self, amount: U256) -> String {
+        format!("{} {}", format_units(amount, self.decimals, None), self.symbol)
+    }
+}
+
+/// The unit in which amounts of wei, e.g. values, balances and gas prices, are displayed, parsed
+/// from `wei`, `gwei` or `ether`, optionally followed by the number of fractional digits to round
+/// to, e.g. `gwei:2`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DisplayUnits {
+    pub name: &'static str,
+    pub decimals: u32,
+    /// The number of fractional digits to round to, `None` to show all non-zero ones
+    pub precision: Option<usize>,
+}
+
+impl Default for DisplayUnits {
+    fn default() -> Self {
+        Self { name: "wei", decimals: 0, precision: None }
+    }
+}
+
+impl DisplayUnits {
+    /// Displays amounts in the whole unit of the native token, e.g. `1.5 MATIC`
+    pub fn native(token: NativeToken) -> Self {
+        Self { name: token.symbol, decimals: token.decimals, precision: None }
+    }
+
+    /// Replaces `ether` by the native token of the chain with the given id, e.g. `MATIC` on
+    /// Polygon, keeping the precision. The other units are kept as they are
+    pub fn on_chain(self, chain_id: u64) -> Self {
+        if self.name == "ether" {
+            Self { precision: self.precision, ..Self::native(NativeToken::of(chain_id)) }
+        } else {
+            self
+        }
+    }
+
+    /// Formats an amount of wei in the unit, followed by the unit's name unless it is wei
+    pub fn format(&self, wei: U256) -> String {
+        let amount = format_units(wei, self.decimals, self.precision);
+        if self.decimals == 0 {
+            amount
+        } else {
+            format!("{} {}", amount, self.name)
+        }
+    }
+}
+
+impl std::str::FromStr for DisplayUnits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, precision) = match s.split_once(':') {
+            Some((name, precision)) => {
+                let precision =
+                    precision.parse().map_err(|_| format!("invalid precision `{}`", precision))?;
+                (name, Some(precision))
+            }
+            None => (s, None),
+        };
+        let (name, decimals) = match name.to_lowercase().as_str() {
+            "wei" => ("wei", 0),
+            "gwei" => ("gwei", 9),
+            "eth" | "ether" => ("ether", 18),
+            _ => return Err(format!("unknown unit `{}`, expected wei, gwei or ether", name)),
+        };
+        Ok(Self { name, decimals, precision })
+    }
+}
+
+/// Formats an amount of a unit with the given number of decimals as a decimal number, rounded to
+/// `precision` fractional digits if given, or with all non-zero ones otherwise
+pub fn format_units(amount: U256, decimals: u32, precision: Option<usize>) -> String {
+    let decimals = decimals as usize;
+    let digits = precision.unwrap_or(decimals).min(decimals);
+    // round half up to the kept digits
+    let dropped = U256::exp10(decimals - digits);
+    let amount = amount.saturating_add(dropped / 2) / dropped;
+
+    let unit = U256::exp10(digits);
+    let (whole, fraction) = (amount / unit, amount % unit);
+    let fraction = format!("{:0>width$}", fraction, width = digits);
+    let fraction = if precision.is_some() { &fraction } else { fraction.trim_end_matches('0') };
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// A proxy pattern, identified by where the proxy keeps the address it delegates to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProxyKind {
+    /// [EIP-1167](https://eips.ethereum.org/EIPS/eip-1167) minimal proxy, which has the
+    /// implementation in its code
+    Eip1167,
+    /// [EIP-1967](https://eips.ethereum.org/EIPS/eip-1967) transparent or UUPS proxy
+    Eip1967,
+    /// EIP-1967 beacon proxy, which asks its beacon for the implementation
+    Eip1967Beacon,
+    /// [EIP-1822](https://eips.ethereum.org/EIPS/eip-1822) universal upgradeable proxy
+    Eip1822,
+    /// OpenZeppelin (zos) proxy predating EIP-1967
+    Zeppelin,
+}
+
+impl ProxyKind {
+    /// Every proxy pattern storing its implementation in a slot, in the order they are checked
+    pub const SLOT_BASED: [ProxyKind; 4] =
+        [ProxyKind::Eip1967, ProxyKind::Eip1967Beacon, ProxyKind::Eip1822, ProxyKind::Zeppelin];
+
+    /// Returns the storage slot holding the implementation, or the beacon of beacon proxies
+    pub fn implementation_slot(&self) -> Option<H256> {
+        let slot = match self {
+            ProxyKind::Eip1167 => return None,
+            // keccak256("eip1967.proxy.implementation") - 1
+            ProxyKind::Eip1967 => {
+                "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"
+            }
+            // keccak256("eip1967.proxy.beacon") - 1
+            ProxyKind::Eip1967Beacon => {
+                "0xa3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50"
+            }
+            // keccak256("PROXIABLE")
+            ProxyKind::Eip1822 => {
+                "0xc5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7"
+            }
+            // keccak256("org.zeppelinos.proxy.implementation")
+            ProxyKind::Zeppelin => {
+                "0x7050c9e0f4ca769c69bd3a8ef740bc37934f8e2c036e5a723fd8ee048ed3f8c3"
+            }
+        };
+        Some(slot.parse().expect("valid slot"))
+    }
+
+    /// Returns the storage slot holding the admin of the proxy, if the pattern has one
+    pub fn admin_slot(&self) -> Option<H256> {
+        let slot = match self {
+            // keccak256("eip1967.proxy.admin") - 1
+            ProxyKind::Eip1967 | ProxyKind::Eip1967Beacon => {
+                "0xb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103"
+            }
+            // keccak256("org.zeppelinos.proxy.admin")
+            ProxyKind::Zeppelin => {
+                "0x10d6a54a4754c8869d6886b5f5d7fbfa5b4522237ea5c60d11bc4e7a1ff9390b"
+            }
+            ProxyKind::Eip1167 | ProxyKind::Eip1822 => return None,
+        };
+        Some(slot.parse().expect("valid slot"))
+    }
+}
+
+impl std::fmt::Display for ProxyKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ProxyKind::Eip1167 => "EIP-1167 minimal proxy",
+            ProxyKind::Eip1967 => "EIP-1967 proxy",
+            ProxyKind::Eip1967Beacon => "EIP-1967 beacon proxy",
+            ProxyKind::Eip1822 => "EIP-1822 proxy",
+            ProxyKind::Zeppelin => "OpenZeppelin legacy proxy",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Detects the proxy pattern of a contract from its runtime `code` and its storage, which is read
+/// through `storage`, and returns the address it delegates to. For beacon proxies, that is the
+/// address of the beacon, whose `implementation()` returns the actual implementation.
+pub fn detect_proxy(
+    code: &[u8],
+    mut storage: impl FnMut(H256) -> H256,
+) -> Option<(ProxyKind, Address)> {
+    if let Some(implementation) = minimal_proxy_implementation(code) {
+        return Some((ProxyKind::Eip1167, implementation))
+    }
+    ProxyKind::SLOT_BASED.iter().find_map(|kind| {
+        let slot = kind.implementation_slot()?;
+        slot_address(storage(slot)).map(|address| (*kind, address))
+    })
+}
+
+/// Returns the admin of a proxy and the pattern of the slot it was found in, reading its storage
+/// through `storage`
+pub fn detect_proxy_admin(mut storage: impl FnMut(H256) -> H256) -> Option<(ProxyKind, Address)> {
+    [ProxyKind::Eip1967, ProxyKind::Zeppelin].iter().find_map(|kind| {
+        let slot = kind.admin_slot()?;
+        slot_address(storage(slot)).map(|address| (*kind, address))
+    })
+}
+
+/// Returns the address stored in a proxy slot, if it holds a non-zero address and nothing else
+fn slot_address(value: H256) -> Option<Address> {
+    if value.is_zero() || value[..12].iter().any(|byte| *byte != 0) {
+        return None
+    }
+    Some(Address::from_slice(&value[12..]))
+}
+
+/// Returns the implementation of an EIP-1167 minimal proxy, given its runtime code
+fn minimal_proxy_implementation(code: &[u8]) -> Option<Address> {
+    const PREFIX: &[u8] = &[0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d, 0x3d, 0x36, 0x3d, 0x73];
+    const SUFFIX: &[u8] =
+        &[0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3];
+    if code.len() != PREFIX.len() + 20 + SUFFIX.len() ||
+        !code.starts_with(PREFIX) ||
+        !code.ends_with(SUFFIX)
+    {
+        return None
+    }
+    Some(Address::from_slice(&code[PREFIX.len()..PREFIX.len() + 20]))
+}
+
+/// A signed transaction decoded from its raw, RLP encoded form
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawTransaction {
+    /// The EIP-2718 transaction type, 0 for legacy transactions
+    pub tx_type: u8,
+    /// The chain id, `None` for legacy transactions without EIP-155 replay protection
+    pub chain_id: Option<U64>,
+    pub nonce: U256,
+    /// The gas price of legacy and EIP-2930 transactions
+    pub gas_price: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub max_fee_per_gas: Option<U256>,
+    pub gas: U256,
+    /// The recipient, `None` for contract creations
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Bytes,
+    pub access_list: AccessList,
+    pub signature: Signature,
+    /// The sender, recovered from the signature
+    pub from: Address,
+    pub hash: H256,
+}
+
+impl RawTransaction {
+    /// Returns the transaction with the field names of the JSON-RPC API
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": self.tx_type,
+            "chainId": self.chain_id,
+            "nonce": self.nonce,
+            "gasPrice": self.gas_price,
+            "maxPriorityFeePerGas": self.max_priority_fee_per_gas,
+            "maxFeePerGas": self.max_fee_per_gas,
+            "gas": self.gas,
+            "to": self.to,
+            "value": self.value,
+            "input": self.data,
+            "accessList": self.access_list,
+            "v": self.signature.v,
+            "r": self.signature.r,
+            "s": self.signature.s,
+            "from": self.from,
+            "hash": self.hash,
+        })
+    }
+}
+
+/// Decodes a raw signed legacy, EIP-2930 or EIP-1559 transaction and recovers its sender
+pub fn decode_raw_transaction(raw: &[u8]) -> Result<RawTransaction> {
+    let (tx_type, payload) = match raw.first() {
+        None => eyre::bail!("empty transaction"),
+        Some(&byte) if byte >= 0xc0 => (0, raw),
+        Some(&byte) if byte == 1 || byte == 2 => (byte, &raw[1..]),
+        Some(byte) => eyre::bail!("unsupported transaction type {}", byte),
+    };
+    let tx = Rlp::new(payload);
+    // the number of fields preceding the signature
+    let fields = match tx_type {
+        0 => 6,
+        1 => 8,
+        _ => 9,
+    };
+    let item_count = tx.item_count().wrap_err("invalid transaction")?;
+    if item_count != fields + 3 {
+        eyre::bail!("expected a transaction with {} fields, got {}", fields + 3, item_count)
+    }
+    decode_fields(tx_type, &tx, fields, raw).wrap_err("invalid transaction")
+}
+
+fn decode_fields(
+    tx_type: u8,
+    tx: &Rlp,
+    fields: usize,
+    raw: &[u8],
+) -> std::result::Result<RawTransaction, rlp::DecoderError> {
+    // typed transactions are prefixed with their chain id
+    let offset = if tx_type == 0 { 0 } else { 1 };
+    let nonce = tx.val_at(offset)?;
+    let (gas_price, max_priority_fee_per_gas, max_fee_per_gas, offset) = if tx_type == 2 {
+        (None, Some(tx.val_at(offset + 1)?), Some(tx.val_at(offset + 2)?), offset + 3)
+    } else {
+        (Some(tx.val_at(offset + 1)?), None, None, offset + 2)
+    };
+    let gas = tx.val_at(offset)?;
+    let to = if tx.at(offset + 1)?.is_empty() { None } else { Some(tx.val_at(offset + 1)?) };
+    let value = tx.val_at(offset + 2)?;
+    let data = tx.val_at::<Vec<u8>>(offset + 3)?.into();
+    let access_list = if tx_type == 0 {
+        Vec::new()
+    } else {
+        tx.at(offset + 4)?
+            .iter()
+            .map(|item| {
+                Ok(AccessListItem { address: item.val_at(0)?, storage_keys: item.list_at(1)? })
+            })
+            .collect::<std::result::Result<_, rlp::DecoderError>>()?
+    };
+
+    let signature =
+        Signature { v: tx.val_at(fields)?, r: tx.val_at(fields + 1)?, s: tx.val_at(fields + 2)? };
+    let chain_id = match tx_type {
+        // EIP-155: v = chain_id * 2 + 35 + y_parity
+        0 if signature.v >= 35 => Some(((signature.v - 35) / 2).into()),
+        0 => None,
+        _ => Some(tx.val_at(0)?),
+    };
+
+    // the signed payload is the transaction without its signature, replaced by
+    // `chain_id, 0, 0` for EIP-155 legacy transactions
+    let mut stream =
+        RlpStream::new_list(if tx_type == 0 && chain_id.is_some() { fields + 3 } else { fields });
+    for i in 0..fields {
+        stream.append_raw(tx.at(i)?.as_raw(), 1);
+    }
+    if let (0, Some(chain_id)) = (tx_type, chain_id) {
+        stream.append(&chain_id).append(&0u8).append(&0u8);
+    }
+    let mut preimage = if tx_type == 0 { Vec::new() } else { vec![tx_type] };
+    preimage.extend_from_slice(&stream.out());
+    let from = signature
+        .recover(H256::from(keccak256(preimage)))
+        .map_err(|_| rlp::DecoderError::Custom("invalid signature"))?;
+
+    Ok(RawTransaction {
+        tx_type,
+        chain_id,
+        nonce,
+        gas_price,
+        max_priority_fee_per_gas,
+        max_fee_per_gas,
+        gas,
+        to,
+        value,
+        data,
+        access_list: AccessList(access_list),
+        signature,
+        from,
+        hash: keccak256(raw).into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_native_tokens() {
+        assert_eq!(NativeToken::of(1), NativeToken { symbol: "ETH", decimals: 18 });
+        assert_eq!(NativeToken::of(137).symbol, "MATIC");
+        assert_eq!(NativeToken::of(80001).symbol, "MATIC");
+        assert_eq!(NativeToken::of(56).symbol, "BNB");
+        // unknown chains, e.g. local devnets, are assumed to use ether
+        assert_eq!(NativeToken::of(31337).symbol, "ETH");
+
+        let token = NativeToken::of(137);
+        assert_eq!(token.format(U256::exp10(18) * 3 / 2), "1.5 MATIC");
+        assert_eq!(token.format(U256::exp10(18) * 2), "2 MATIC");
+        assert_eq!(token.format(1.into()), "0.000000000000000001 MATIC");
+        assert_eq!(token.format(0.into()), "0 MATIC");
+
+        let units: DisplayUnits = "ether:2".parse().unwrap();
+        assert_eq!(units.on_chain(137).format(U256::exp10(18)), "1.00 MATIC");
+        let units: DisplayUnits = "gwei".parse().unwrap();
+        assert_eq!(units.on_chain(137), units);
+    }
+
+    #[test]
+    fn formats_display_units() {
+        let format =
+            |units: &str, wei: u64| units.parse::<DisplayUnits>().unwrap().format(wei.into());
+        assert_eq!(format("wei", 1_500), "1500");
+        assert_eq!(format("gwei", 1_500_000_000), "1.5 gwei");
+        assert_eq!(format("gwei:2", 1_234_567_890), "1.23 gwei");
+        assert_eq!(format("gwei:2", 1_000_000_000), "1.00 gwei");
+        // rounds half up, carrying into the whole part
+        assert_eq!(format("gwei:2", 1_235_000_000), "1.24 gwei");
+        assert_eq!(format("ether:2", 1_999_000_000_000_000_000), "2.00 ether");
+        assert_eq!(format("ether:0", 1_500_000_000_000_000_000), "2 ether");
+        // without a precision, whole amounts have no fraction
+        assert_eq!(format("ether", 2_000_000_000_000_000_000), "2 ether");
+        assert_eq!(format("eth", 0), "0 ether");
+
+        assert!("gwei:x".parse::<DisplayUnits>().is_err());
+        assert!("finney".parse::<DisplayUnits>().is_err());
+    }
+}